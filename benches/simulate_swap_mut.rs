@@ -0,0 +1,89 @@
+//! Applying swaps to a V3 pool in place vs cloning the state on every swap
+//!
+//! The clone baseline is what `simulate_swap_mut` used to do, its cost grows with the size of the tick maps
+
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hello_eth::alloy_primitives::{address, U256};
+use hello_eth::defi::amm::uniswap::v3::{PoolTick, State, UniswapV3Pool};
+use hello_eth::defi::currency::erc20::ERC20Token;
+
+/// A pool at price 1 with `words` bitmap words loaded
+fn synthetic_pool(words: i16) -> UniswapV3Pool {
+    let token0 = ERC20Token {
+        address: address!("0000000000000000000000000000000000000001"),
+        ..Default::default()
+    };
+    let token1 = ERC20Token {
+        address: address!("0000000000000000000000000000000000000002"),
+        ..Default::default()
+    };
+
+    let mut pool = UniswapV3Pool::new(
+        1,
+        address!("00000000000000000000000000000000000000aa"),
+        3000,
+        token0,
+        token1,
+    );
+
+    let tick_bitmap = (-words / 2..words / 2).map(|w| (w, U256::ZERO)).collect();
+
+    pool.update_state(State {
+        liquidity: 10u128.pow(24),
+        sqrt_price: U256::from(1) << 96,
+        tick: 0,
+        tick_spacing: 60,
+        tick_bitmap,
+        ticks: HashMap::new(),
+        pool_tick: PoolTick {
+            tick: 0,
+            liquidity_net: 0,
+            block: 0,
+        },
+        fee_protocol: 0,
+    });
+
+    pool
+}
+
+fn bench_simulate_swap_mut(c: &mut Criterion) {
+    let mut group = c.benchmark_group("v3_apply_swaps");
+    let amount_in = U256::from(10u128.pow(18));
+
+    for words in [16i16, 1_024, 16_384] {
+        let pool = synthetic_pool(words);
+        let token0 = pool.token0.address;
+        let token1 = pool.token1.address;
+
+        group.bench_with_input(BenchmarkId::new("in_place", words), &pool, |b, pool| {
+            let mut pool = pool.clone();
+            let mut zero_for_one = true;
+            b.iter(|| {
+                let token_in = if zero_for_one { token0 } else { token1 };
+                zero_for_one = !zero_for_one;
+                black_box(pool.simulate_swap_mut(token_in, amount_in).unwrap())
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("clone_state", words), &pool, |b, pool| {
+            let mut pool = pool.clone();
+            let mut zero_for_one = true;
+            b.iter(|| {
+                let token_in = if zero_for_one { token0 } else { token1 };
+                zero_for_one = !zero_for_one;
+
+                // the old behaviour cloned the whole state on every swap
+                black_box(pool.state().unwrap().clone());
+                let amount_out = pool.simulate_swap_mut(token_in, amount_in).unwrap();
+                black_box(amount_out)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_simulate_swap_mut);
+criterion_main!(benches);
@@ -0,0 +1,163 @@
+//! Benchmarks of the hot paths of the backtests over synthetic data
+//!
+//! * `simulate_swap` of V2 and V3 pools
+//! * `decode_swap` and `get_volume_from_logs` over a large number of Swap logs
+
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hello_eth::abi::uniswap::pool::v3::IUniswapV3Pool;
+use hello_eth::alloy_primitives::{address, Address, Signed, Uint, B256, I256, U256};
+use hello_eth::alloy_rpc_types::Log;
+use hello_eth::alloy_sol_types::SolEvent;
+use hello_eth::defi::amm::uniswap::{v2, v3};
+use hello_eth::defi::currency::erc20::ERC20Token;
+
+const POOL: Address = address!("00000000000000000000000000000000000000aa");
+
+fn tokens() -> (ERC20Token, ERC20Token) {
+    let token0 = ERC20Token {
+        address: address!("0000000000000000000000000000000000000001"),
+        ..Default::default()
+    };
+    let token1 = ERC20Token {
+        address: address!("0000000000000000000000000000000000000002"),
+        ..Default::default()
+    };
+    (token0, token1)
+}
+
+fn v2_pool() -> v2::UniswapV2Pool {
+    let (token0, token1) = tokens();
+    let mut pool = v2::UniswapV2Pool::new(1, POOL, token0, token1);
+    pool.update_state(v2::State {
+        reserve0: U256::from(10u128.pow(24)),
+        reserve1: U256::from(10u128.pow(24)),
+        block: 0,
+    });
+    pool
+}
+
+fn v3_pool() -> v3::UniswapV3Pool {
+    let (token0, token1) = tokens();
+    let mut pool = v3::UniswapV3Pool::new(1, POOL, 3000, token0, token1);
+    pool.update_state(v3::State {
+        liquidity: 10u128.pow(24),
+        sqrt_price: U256::from(1) << 96,
+        tick: 0,
+        tick_spacing: 60,
+        tick_bitmap: HashMap::new(),
+        ticks: HashMap::new(),
+        pool_tick: v3::PoolTick {
+            tick: 0,
+            liquidity_net: 0,
+            block: 0,
+        },
+        fee_protocol: 0,
+    });
+    pool
+}
+
+/// `count` Swap logs alternating between buys and sells
+fn swap_logs(count: u64) -> Vec<Log> {
+    (0..count)
+        .map(|i| {
+            let amount = I256::try_from(10u128.pow(18) + i as u128).unwrap();
+            let (amount0, amount1) = if i % 2 == 0 {
+                (amount, -amount)
+            } else {
+                (-amount, amount)
+            };
+
+            let event = IUniswapV3Pool::Swap {
+                sender: Address::ZERO,
+                recipient: Address::ZERO,
+                amount0,
+                amount1,
+                sqrtPriceX96: Uint::<160, 3>::from(1u128 << 96),
+                liquidity: 10u128.pow(24),
+                tick: Signed::<24, 1>::ZERO,
+            };
+
+            Log {
+                inner: hello_eth::alloy_primitives::Log {
+                    address: POOL,
+                    data: event.encode_log_data(),
+                },
+                block_number: Some(i / 10),
+                transaction_hash: Some(B256::from(U256::from(i))),
+                log_index: Some(i % 10),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+fn bench_simulate_swap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulate_swap");
+    let v2_pool = v2_pool();
+    let v3_pool = v3_pool();
+
+    for amount in [10u128.pow(15), 10u128.pow(18), 10u128.pow(22)] {
+        let amount_in = U256::from(amount);
+
+        group.bench_with_input(BenchmarkId::new("v2", amount), &amount_in, |b, amount_in| {
+            b.iter(|| {
+                v2_pool
+                    .simulate_swap(black_box(v2_pool.token0.address), *amount_in)
+                    .unwrap()
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("v3", amount), &amount_in, |b, amount_in| {
+            b.iter(|| {
+                v3_pool
+                    .simulate_swap(black_box(v3_pool.token0.address), *amount_in)
+                    .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_decode_swap(c: &mut Criterion) {
+    let pool = v3_pool();
+    let logs = swap_logs(1_000);
+
+    let mut group = c.benchmark_group("decode_swap");
+    group.throughput(Throughput::Elements(logs.len() as u64));
+    group.bench_function("v3_1000_logs", |b| {
+        b.iter(|| {
+            for log in &logs {
+                black_box(pool.decode_swap(log).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_get_volume_from_logs(c: &mut Criterion) {
+    let pool = v3_pool();
+
+    let mut group = c.benchmark_group("get_volume_from_logs");
+    group.sample_size(10);
+
+    for count in [10_000u64, 100_000] {
+        let logs = swap_logs(count);
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &logs, |b, logs| {
+            b.iter(|| pool.get_volume_from_logs(logs.clone()).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_simulate_swap,
+    bench_decode_swap,
+    bench_get_volume_from_logs
+);
+criterion_main!(benches);
@@ -4,7 +4,7 @@ use alloy_provider::{ProviderBuilder, Provider, WsConnect};
 use std::sync::Arc;
 
 use hello_eth::prelude::{UniswapV3Pool, BlockTime, ERC20Token, TokenKind};
-use hello_eth::defi::amm::uniswap::v3::lp_provider::{simulate_position, PositionArgs};
+use hello_eth::defi::amm::uniswap::v3::lp_provider::{simulate_position, PositionArgs, ReplayMode};
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
@@ -28,6 +28,8 @@ async fn main() -> Result<(), anyhow::Error> {
         price_assumption: 1.167293589301331,
         deposit_amount: 500_000.0,
         pool,
+        epoch_blocks: None,
+        replay_mode: ReplayMode::PoolReported,
     };
 
     // go back exactly 1 day from the current block
@@ -36,5 +38,15 @@ async fn main() -> Result<(), anyhow::Error> {
     let result = simulate_position(client, block_time, position).await?;
     println!("{}", result.pretty());
 
+    for epoch in &result.epochs {
+        println!(
+            "Epoch {} (blocks {} - {}): ${:.2}",
+            epoch.epoch,
+            epoch.start_block,
+            epoch.end_block,
+            epoch.total_usd()
+        );
+    }
+
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,23 @@
+use alloy_primitives::{address, Address};
+use alloy_sol_types::sol;
+
+/// The Balancer V2 Vault, same address on every chain
+pub const BALANCER_VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+
+sol! {
+
+    #[sol(rpc)]
+    contract IBalancerVault {
+        function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock);
+    }
+
+    /// A Balancer V2 pool, the pool contract is the BPT (LP token)
+    #[sol(rpc)]
+    contract IBalancerPool {
+        function getPoolId() external view returns (bytes32);
+        function totalSupply() external view returns (uint256);
+
+        /// The supply excluding the pre-minted BPT of composable stable pools and including the due protocol fees
+        function getActualSupply() external view returns (uint256);
+    }
+}
@@ -0,0 +1,17 @@
+use alloy_sol_types::sol;
+
+sol! {
+
+    /// A Curve StableSwap or CryptoSwap pool
+    ///
+    /// The old pools index the coins with int128, the newer ones with uint256, only the uint256 getters are declared
+    #[sol(rpc)]
+    contract ICurvePool {
+        function coins(uint256 i) external view returns (address);
+        function balances(uint256 i) external view returns (uint256);
+        function get_virtual_price() external view returns (uint256);
+
+        /// Only on the pools deployed before the LP token was merged into the pool contract
+        function lp_token() external view returns (address);
+    }
+}
@@ -25,4 +25,14 @@ sol! {
         function withdraw(uint256 amount) external;
 
 }
-}
\ No newline at end of file
+}
+
+sol! {
+    /// EIP-2612 gasless approvals
+    #[sol(rpc)]
+    contract IERC20Permit {
+        function permit(address owner, address spender, uint256 value, uint256 deadline, uint8 v, bytes32 r, bytes32 s) external;
+        function nonces(address owner) external view returns (uint256);
+        function DOMAIN_SEPARATOR() external view returns (bytes32);
+    }
+}
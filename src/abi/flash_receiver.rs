@@ -0,0 +1,61 @@
+//! A minimal contract that can receive flash swap / flash loan callbacks in a fork
+//!
+//! The contract has two entry points:
+//!
+//! * When called directly by an EOA it stores the repayments and forwards the rest of the calldata
+//!   to a target (eg. a Uniswap V2 pair or a Uniswap V3 pool), bubbling up any revert
+//!
+//! * When called by any contract (the callback) it transfers the stored repayments to the caller
+//!
+//! Calldata layout when called by an EOA:
+//!
+//! `target (32 bytes) | token0 (32) | amount0 (32) | token1 (32) | amount1 (32) | calldata`
+
+use alloy_primitives::{Address, Bytes, U256};
+use revm::primitives::Bytecode;
+
+/// Deployed Bytecode of the FlashReceiver contract
+const BYTECODE: &str = "0x33321461006c5760015480156100375763a9059cbb60e01b6000523360045280602452602060006044600060006000545af1156100b0575b5060035480156100695763a9059cbb60e01b6000523360045280602452602060006044600060006002545af1156100b0575b50005b60203560005560403560015560603560025560803560035560a036038060a060003760006000826000346000355af13d600060003e156100ab573d6000f35b3d6000fd5b60006000fd";
+
+/// A token and the amount the [FlashReceiver](self) pays back to the caller in the callback
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlashRepay {
+    pub token: Address,
+    pub amount: U256,
+}
+
+impl FlashRepay {
+    pub fn new(token: Address, amount: U256) -> Self {
+        Self { token, amount }
+    }
+}
+
+/// Encode a call to the flash receiver that will forward `calldata` to `target`
+///
+/// At most 2 repayments are supported
+pub fn encode_flash_call(
+    target: Address,
+    repay: &[FlashRepay],
+    calldata: Bytes,
+) -> Result<Bytes, anyhow::Error> {
+    if repay.len() > 2 {
+        return Err(anyhow::anyhow!("At most 2 repayments are supported"));
+    }
+
+    let mut data = Vec::with_capacity(160 + calldata.len());
+    data.extend_from_slice(target.into_word().as_slice());
+
+    for i in 0..2 {
+        let repay = repay.get(i).copied().unwrap_or_default();
+        data.extend_from_slice(repay.token.into_word().as_slice());
+        data.extend_from_slice(&repay.amount.to_be_bytes::<32>());
+    }
+
+    data.extend_from_slice(&calldata);
+    Ok(Bytes::from(data))
+}
+
+pub fn flash_receiver_bytecode() -> Result<Bytecode, anyhow::Error> {
+    let bytes: Bytes = BYTECODE.parse()?;
+    Ok(Bytecode::new_raw(bytes))
+}
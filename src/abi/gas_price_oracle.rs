@@ -0,0 +1,72 @@
+use alloy_contract::private::Network;
+use alloy_primitives::{address, Address, Bytes, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_sol_types::sol;
+use alloy_transport::Transport;
+
+/// The GasPriceOracle predeploy on OP-stack chains
+pub const GAS_PRICE_ORACLE: Address = address!("420000000000000000000000000000000000000F");
+
+sol! {
+    #[sol(rpc)]
+    contract IGasPriceOracle {
+        function getL1Fee(bytes memory _data) external view returns (uint256);
+        function getL1GasUsed(bytes memory _data) external view returns (uint256);
+        function l1BaseFee() external view returns (uint256);
+        function baseFeeScalar() external view returns (uint32);
+        function blobBaseFeeScalar() external view returns (uint32);
+        function isEcotone() external view returns (bool);
+        function isFjord() external view returns (bool);
+    }
+}
+
+/// Return the L1 data fee in wei for a transaction with the given RLP encoded data
+pub async fn get_l1_fee<T, P, N>(
+    client: P,
+    data: Bytes,
+    block_id: Option<BlockId>,
+) -> Result<U256, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = block_id.unwrap_or(BlockId::latest());
+    let oracle = IGasPriceOracle::new(GAS_PRICE_ORACLE, client);
+    let fee = oracle.getL1Fee(data).block(block).call().await?;
+    Ok(fee._0)
+}
+
+/// Return the amount of L1 gas used for a transaction with the given RLP encoded data
+pub async fn get_l1_gas_used<T, P, N>(
+    client: P,
+    data: Bytes,
+    block_id: Option<BlockId>,
+) -> Result<U256, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = block_id.unwrap_or(BlockId::latest());
+    let oracle = IGasPriceOracle::new(GAS_PRICE_ORACLE, client);
+    let gas_used = oracle.getL1GasUsed(data).block(block).call().await?;
+    Ok(gas_used._0)
+}
+
+/// Return the latest known L1 base fee
+pub async fn l1_base_fee<T, P, N>(
+    client: P,
+    block_id: Option<BlockId>,
+) -> Result<U256, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = block_id.unwrap_or(BlockId::latest());
+    let oracle = IGasPriceOracle::new(GAS_PRICE_ORACLE, client);
+    let base_fee = oracle.l1BaseFee().block(block).call().await?;
+    Ok(base_fee._0)
+}
@@ -0,0 +1,22 @@
+use alloy_sol_types::sol;
+
+sol! {
+
+    /// The common interface of the MasterChef forks
+    ///
+    /// `userInfo` and `poolInfo` return more fields on most forks, only the leading ones are declared
+    /// so the outputs should be decoded from the raw return data
+    #[sol(rpc)]
+    contract IMasterChef {
+        function poolLength() external view returns (uint256);
+
+        /// MasterChef V1, the LP token is the first field
+        function poolInfo(uint256 pid) external view returns (address lpToken);
+
+        /// MasterChef V2 and later, the LP tokens are stored apart from the pool info
+        function lpToken(uint256 pid) external view returns (address);
+
+        /// The staked amount is the first field
+        function userInfo(uint256 pid, address user) external view returns (uint256 amount);
+    }
+}
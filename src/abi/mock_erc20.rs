@@ -0,0 +1,83 @@
+//! A minimal ERC20 token that can be deployed into a fork
+//!
+//! Implements `balanceOf`, `transfer`, `transferFrom`, `approve`, `allowance`, `totalSupply`,
+//! `decimals`, `name` and `symbol` and emits the `Transfer` event.
+//! An allowance of `U256::MAX` is never decreased.
+//!
+//! The contract has no constructor, its state is set by writing directly to its storage:
+//!
+//! | Slot | Value |
+//! |------|-------|
+//! | 0 | `mapping(address => uint256)` balances |
+//! | 1 | `mapping(address => mapping(address => uint256))` allowances |
+//! | 2 | total supply |
+//! | 3, 4 | symbol (left aligned, at most 32 bytes) and its length |
+//! | 5, 6 | name (left aligned, at most 32 bytes) and its length |
+//! | 7 | decimals |
+
+use alloy_primitives::{keccak256, Address, Bytes, U256};
+use revm::primitives::Bytecode;
+
+/// Deployed Bytecode of the MockERC20 contract
+const BYTECODE: &str = "0x60003560e01c806370a082311461006f578063a9059cbb1461008957806323b872dd1461010e578063095ea7b3146101d0578063dd62ed3e146101fc57806318160ddd14610224578063313ce5671461023057806395d89b411461023c57806306fdde0314610253575b60006000fd5b600435600052600060205260406000205460005260206000f35b3360805260043560a05260243560c05260805160005260006020526040600020805460c051808210610069579003905560a05160005260006020526040600020805460c05101905560c05160e05260a0516080517fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef602060e0a3600160005260206000f35b60043560805260243560a05260443560c0526080516000526001602052604060002060205233600052604060002080548019156101585760c051808210610069579003905561015b565b50505b60805160005260006020526040600020805460c051808210610069579003905560a05160005260006020526040600020805460c05101905560c05160e05260a0516080517fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef602060e0a3600160005260206000f35b336000526001602052604060002060205260043560005260406000206024359055600160005260206000f35b6004356000526001602052604060002060205260243560005260406000205460005260206000f35b60025460005260206000f35b60075460005260206000f35b602060005260045460205260035460405260606000f35b602060005260065460205260055460405260606000f3";
+
+pub const BALANCES_SLOT: U256 = U256::from_limbs([0, 0, 0, 0]);
+pub const ALLOWANCES_SLOT: U256 = U256::from_limbs([1, 0, 0, 0]);
+pub const TOTAL_SUPPLY_SLOT: U256 = U256::from_limbs([2, 0, 0, 0]);
+pub const SYMBOL_SLOT: U256 = U256::from_limbs([3, 0, 0, 0]);
+pub const SYMBOL_LEN_SLOT: U256 = U256::from_limbs([4, 0, 0, 0]);
+pub const NAME_SLOT: U256 = U256::from_limbs([5, 0, 0, 0]);
+pub const NAME_LEN_SLOT: U256 = U256::from_limbs([6, 0, 0, 0]);
+pub const DECIMALS_SLOT: U256 = U256::from_limbs([7, 0, 0, 0]);
+
+pub fn mock_erc20_bytecode() -> Result<Bytecode, anyhow::Error> {
+    let bytes: Bytes = BYTECODE.parse()?;
+    Ok(Bytecode::new_raw(bytes))
+}
+
+/// The storage slot of the balance of `owner`
+pub fn balance_slot(owner: Address) -> U256 {
+    mapping_slot(owner, BALANCES_SLOT)
+}
+
+/// The storage slot of the allowance `owner` gave to `spender`
+pub fn allowance_slot(owner: Address, spender: Address) -> U256 {
+    mapping_slot(spender, mapping_slot(owner, ALLOWANCES_SLOT))
+}
+
+/// The initial storage of the token, with the whole supply owned by `holder`
+pub fn initial_storage(
+    name: &str,
+    symbol: &str,
+    decimals: u8,
+    total_supply: U256,
+    holder: Address,
+) -> Result<Vec<(U256, U256)>, anyhow::Error> {
+    Ok(vec![
+        (balance_slot(holder), total_supply),
+        (TOTAL_SUPPLY_SLOT, total_supply),
+        (SYMBOL_SLOT, short_string(symbol)?),
+        (SYMBOL_LEN_SLOT, U256::from(symbol.len())),
+        (NAME_SLOT, short_string(name)?),
+        (NAME_LEN_SLOT, U256::from(name.len())),
+        (DECIMALS_SLOT, U256::from(decimals)),
+    ])
+}
+
+fn mapping_slot(key: Address, slot: U256) -> U256 {
+    let mut data = [0u8; 64];
+    data[12..32].copy_from_slice(key.as_slice());
+    data[32..].copy_from_slice(&slot.to_be_bytes::<32>());
+    U256::from_be_bytes(keccak256(data).0)
+}
+
+fn short_string(value: &str) -> Result<U256, anyhow::Error> {
+    let bytes = value.as_bytes();
+    if bytes.len() > 32 {
+        return Err(anyhow::anyhow!("String longer than 32 bytes: {}", value));
+    }
+
+    let mut word = [0u8; 32];
+    word[..bytes.len()].copy_from_slice(bytes);
+    Ok(U256::from_be_bytes(word))
+}
@@ -1,3 +1,11 @@
 pub mod uniswap;
 pub mod erc20;
-pub mod swap_router;
\ No newline at end of file
+pub mod swap_router;
+pub mod flash_receiver;
+pub mod mock_erc20;
+pub mod gas_price_oracle;
+pub mod permit2;
+pub mod solidly;
+pub mod curve;
+pub mod balancer;
+pub mod masterchef;
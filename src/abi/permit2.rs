@@ -0,0 +1,74 @@
+use alloy_primitives::{
+    address, keccak256,
+    aliases::{U160, U48},
+    Address, U256,
+};
+use alloy_sol_types::{eip712_domain, sol, Eip712Domain};
+
+/// Permit2, same address on every chain
+pub const PERMIT2: Address = address!("000000000022D473030F116dDEE9F6B43aC78BA3");
+
+/// The storage slot of the `allowance` mapping, after the `nonceBitmap` of SignatureTransfer
+pub const ALLOWANCE_SLOT: U256 = U256::from_limbs([1, 0, 0, 0]);
+
+sol! {
+    #[sol(rpc)]
+    contract IPermit2 {
+        struct PermitDetails {
+            address token;
+            uint160 amount;
+            uint48 expiration;
+            uint48 nonce;
+        }
+
+        struct PermitSingle {
+            PermitDetails details;
+            address spender;
+            uint256 sigDeadline;
+        }
+
+        function allowance(address owner, address token, address spender) external view returns (uint160 amount, uint48 expiration, uint48 nonce);
+        function approve(address token, address spender, uint160 amount, uint48 expiration) external;
+        function permit(address owner, PermitSingle permitSingle, bytes signature) external;
+        function DOMAIN_SEPARATOR() external view returns (bytes32);
+    }
+}
+
+/// The EIP-712 domain of Permit2 on a chain, it has no version
+pub fn permit2_domain(chain_id: u64) -> Eip712Domain {
+    eip712_domain! {
+        name: "Permit2",
+        chain_id: chain_id,
+        verifying_contract: PERMIT2,
+    }
+}
+
+/// The storage slot of `allowance[owner][token][spender]`
+pub fn allowance_slot(owner: Address, token: Address, spender: Address) -> U256 {
+    let slot = |key: Address, slot: U256| {
+        let mut data = [0u8; 64];
+        data[..32].copy_from_slice(key.into_word().as_slice());
+        data[32..].copy_from_slice(&slot.to_be_bytes::<32>());
+        U256::from_be_bytes(keccak256(data).0)
+    };
+    slot(spender, slot(token, slot(owner, ALLOWANCE_SLOT)))
+}
+
+/// The `PackedAllowance` word: amount (uint160) | expiration (uint48) | nonce (uint48)
+pub fn pack_allowance(amount: U160, expiration: U48, nonce: U48) -> U256 {
+    U256::from(amount) | (U256::from(expiration) << 160) | (U256::from(nonce) << 208)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::storage::extract_bits;
+
+    #[test]
+    fn test_pack_allowance() {
+        let packed = pack_allowance(U160::from(1_000u64), U48::from(1_700_000_000u64), U48::from(3u64));
+        assert_eq!(extract_bits(packed, 0, 160), U256::from(1_000));
+        assert_eq!(extract_bits(packed, 160, 48), U256::from(1_700_000_000u64));
+        assert_eq!(extract_bits(packed, 208, 48), U256::from(3));
+    }
+}
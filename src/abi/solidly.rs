@@ -0,0 +1,38 @@
+use alloy_sol_types::sol;
+
+sol! {
+
+    /// The pool of Velodrome V2 and Aerodrome
+    #[sol(rpc)]
+    contract ISolidlyPool {
+
+        // * EVENTS *
+
+        event Swap(
+            address indexed sender,
+            address indexed to,
+            uint256 amount0In,
+            uint256 amount1In,
+            uint256 amount0Out,
+            uint256 amount1Out
+        );
+        event Sync(uint256 reserve0, uint256 reserve1);
+
+        // * VIEW FUNCTIONS *
+
+        function factory() external view returns (address);
+        function getReserves() external view returns (uint256 _reserve0, uint256 _reserve1, uint256 _blockTimestampLast);
+        function getAmountOut(uint256 amountIn, address tokenIn) external view returns (uint256);
+        function metadata() external view returns (uint256 dec0, uint256 dec1, uint256 r0, uint256 r1, bool st, address t0, address t1);
+        function stable() external view returns (bool);
+        function token0() external view returns (address);
+        function token1() external view returns (address);
+    }
+
+    /// The pool factory of Velodrome V2 and Aerodrome
+    #[sol(rpc)]
+    contract ISolidlyPoolFactory {
+        function getFee(address pool, bool _stable) external view returns (uint256);
+        function getPool(address tokenA, address tokenB, bool stable) external view returns (address);
+    }
+}
@@ -1,3 +1,4 @@
 pub mod factory;
 pub mod nft_position;
-pub mod pool;
\ No newline at end of file
+pub mod pool;
+pub mod staker;
\ No newline at end of file
@@ -4,6 +4,9 @@ use alloy_primitives::{U256, Bytes, Uint, address, Address};
 use INonfungiblePositionManager::MintParams;
 use anyhow::Context;
 
+/// The NonfungiblePositionManager on Ethereum, Optimism and Arbitrum
+///
+/// The other chains use a different address, see [UniswapDeployment](crate::defi::amm::uniswap::deployments::UniswapDeployment)
 pub const NFT_POSITION_CONTRACT: Address = address!("C36442b4a4522E871399CD717aBDD847Ab11FE88");
 
 
@@ -96,6 +99,14 @@ sol! {
             bytes32 s
         ) external payable;
 
+        function tokenURI(uint256 tokenId) external view returns (string memory);
+
+        function factory() external view returns (address);
+
+        function balanceOf(address owner) external view returns (uint256);
+
+        function tokenOfOwnerByIndex(address owner, uint256 index) external view returns (uint256);
+
         function safeTransferFrom(address from, address to, uint256 tokenId) external;
 
         function safeTransferFrom(address from, address to, uint256 tokenId, bytes calldata data) external;
@@ -168,6 +179,11 @@ pub fn encode_positions(token_id: U256) -> Bytes {
     Bytes::from(abi.abi_encode())
 }
 
+pub fn encode_token_uri(token_id: U256) -> Bytes {
+    let abi = INonfungiblePositionManager::tokenURICall{tokenId: token_id};
+    Bytes::from(abi.abi_encode())
+}
+
 pub fn encode_collect(params: INonfungiblePositionManager::CollectParams) -> Bytes {
     let abi = INonfungiblePositionManager::collectCall{params};
     Bytes::from(abi.abi_encode())
@@ -218,6 +234,11 @@ pub fn decode_decrease_liquidity(data: &Bytes) -> Result<(U256, U256), anyhow::E
     Ok((abi.amount0, abi.amount1))
 }
 
+pub fn decode_token_uri(data: &Bytes) -> Result<String, anyhow::Error> {
+    let abi = INonfungiblePositionManager::tokenURICall::abi_decode_returns(data, true)?;
+    Ok(abi._0)
+}
+
 pub fn decode_positions(data: &Bytes) -> Result<PositionsReturn, anyhow::Error> {
     let abi = INonfungiblePositionManager::positionsCall::abi_decode_returns(data, true)?;
 
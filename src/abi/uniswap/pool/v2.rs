@@ -6,6 +6,9 @@ use alloy_sol_types::{SolCall, sol};
 use alloy_transport::Transport;
 
 
+/// Storage slot of `reserve0`, `reserve1` and `blockTimestampLast`
+pub const RESERVES_SLOT: U256 = U256::from_limbs([8, 0, 0, 0]);
+
 sol! {
 
     #[sol(rpc)]
@@ -250,4 +253,31 @@ pub fn encode_token0() -> Bytes {
 pub fn encode_token1() -> Bytes {
     let abi = IUniswapV2Pair::token1Call {};
     Bytes::from(abi.abi_encode())
-}
\ No newline at end of file
+}
+
+/// Encode the function with signature `swap(uint256,uint256,address,bytes)` and selector `0x022c0d9f`
+///
+/// A non-empty `data` turns the swap into a flash swap
+pub fn encode_swap(amount0_out: U256, amount1_out: U256, to: Address, data: Bytes) -> Bytes {
+    let abi = IUniswapV2Pair::swapCall {
+        amount0Out: amount0_out,
+        amount1Out: amount1_out,
+        to,
+        data,
+    };
+    Bytes::from(abi.abi_encode())
+}
+
+/// Encode the function with signature `mint(address)` and selector `0x6a627842`
+pub fn encode_mint(to: Address) -> Bytes {
+    let abi = IUniswapV2Pair::mintCall { to };
+    Bytes::from(abi.abi_encode())
+}
+
+// ABI Decode the functions
+
+/// Decode the output of `getReserves()` into the reserves of token0 and token1
+pub fn decode_get_reserves(data: &Bytes) -> Result<(U256, U256), anyhow::Error> {
+    let abi = IUniswapV2Pair::getReservesCall::abi_decode_returns(data, true)?;
+    Ok((U256::from(abi.reserve0), U256::from(abi.reserve1)))
+}
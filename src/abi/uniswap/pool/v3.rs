@@ -9,6 +9,20 @@ use alloy_transport::Transport;
 
 use anyhow::Context;
 
+// * STORAGE LAYOUT *
+
+/// Storage slot of `slot0`
+pub const SLOT0_SLOT: U256 = U256::ZERO;
+
+/// Storage slot of `liquidity`
+pub const LIQUIDITY_SLOT: U256 = U256::from_limbs([4, 0, 0, 0]);
+
+/// Storage slot of the `ticks` mapping
+pub const TICKS_SLOT: U256 = U256::from_limbs([5, 0, 0, 0]);
+
+/// Storage slot of the `tickBitmap` mapping
+pub const TICK_BITMAP_SLOT: U256 = U256::from_limbs([6, 0, 0, 0]);
+
 sol! {
 
     #[sol(rpc)]
@@ -593,6 +607,17 @@ pub fn encode_token1() -> Bytes {
     Bytes::from(abi.abi_encode())
 }
 
+/// Encode the function with signature `flash(address,uint256,uint256,bytes)` and selector `0x490e6cbc`
+pub fn encode_flash(recipient: Address, amount0: U256, amount1: U256, data: Bytes) -> Bytes {
+    let abi = IUniswapV3Pool::flashCall {
+        recipient,
+        amount0,
+        amount1,
+        data,
+    };
+    Bytes::from(abi.abi_encode())
+}
+
 // ABI Decode the functions
 
 pub fn decode_positions(data: &Bytes) -> Result<(u128, U256, U256, u128, u128), anyhow::Error> {
@@ -605,3 +630,41 @@ pub fn decode_positions(data: &Bytes) -> Result<(u128, U256, U256, u128, u128),
         abi.tokensOwed1,
     ))
 }
+
+/// Decode the output of `slot0()` into the sqrtPriceX96 and the current tick
+pub fn decode_slot0(data: &Bytes) -> Result<(U256, i32), anyhow::Error> {
+    let abi = IUniswapV3Pool::slot0Call::abi_decode_returns(data, true)?;
+    let tick: i32 = abi._1.to_string().parse().context("Failed to parse tick")?;
+    Ok((U256::from(abi._0), tick))
+}
+
+/// Unpack the sqrtPriceX96 and the tick from the `slot0` storage word, see [SLOT0_SLOT]
+///
+/// Same values as [decode_slot0] but from `eth_getStorageAt` or a fork database instead of a `slot0()` call
+pub fn slot0_from_word(slot0: U256) -> (U256, i32) {
+    // sqrtPriceX96 occupies the lower 160 bits and the tick the next 24 bits
+    let sqrt_price_x96 = slot0 & ((U256::from(1) << 160) - U256::from(1));
+    let tick_bits = ((slot0 >> 160) & U256::from(0xFFFFFF)).to::<u32>();
+    // sign extend the int24
+    let tick = ((tick_bits << 8) as i32) >> 8;
+
+    (sqrt_price_x96, tick)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slot0_from_word() {
+        let sqrt_price_x96 = U256::from(1) << 96;
+        // the fee protocol and the unlocked flag above the tick are ignored
+        let high = U256::from(1) << 248;
+
+        let tick = U256::from((-887272_i32 as u32) & 0xFFFFFF) << 160;
+        assert_eq!(slot0_from_word(high | tick | sqrt_price_x96), (sqrt_price_x96, -887272));
+
+        let tick = U256::from(200_000) << 160;
+        assert_eq!(slot0_from_word(high | tick | sqrt_price_x96), (sqrt_price_x96, 200_000));
+    }
+}
@@ -0,0 +1,148 @@
+use alloy_contract::private::Network;
+use alloy_primitives::{address, keccak256, Address, B256, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_sol_types::{sol, SolValue};
+use alloy_transport::Transport;
+
+use IUniswapV3Staker::IncentiveKey;
+
+/// The canonical UniswapV3Staker, deployed at the same address on Ethereum, Optimism and Arbitrum
+pub const UNISWAP_V3_STAKER: Address = address!("e34139463bA50bD61336E0c446Bd8C0867c6fE65");
+
+sol! {
+    #[sol(rpc)]
+    contract IUniswapV3Staker {
+        struct IncentiveKey {
+            address rewardToken;
+            address pool;
+            uint256 startTime;
+            uint256 endTime;
+            address refundee;
+        }
+
+        event IncentiveCreated(
+            address indexed rewardToken,
+            address indexed pool,
+            uint256 startTime,
+            uint256 endTime,
+            address refundee,
+            uint256 reward
+        );
+        event IncentiveEnded(bytes32 indexed incentiveId, uint256 refund);
+        event TokenStaked(uint256 indexed tokenId, bytes32 indexed incentiveId, uint128 liquidity);
+        event TokenUnstaked(uint256 indexed tokenId, bytes32 indexed incentiveId);
+        event RewardClaimed(address indexed to, uint256 reward);
+
+        function incentives(bytes32 incentiveId)
+            external
+            view
+            returns (uint256 totalRewardUnclaimed, uint160 totalSecondsClaimedX128, uint96 numberOfStakes);
+
+        function deposits(uint256 tokenId)
+            external
+            view
+            returns (address owner, uint48 numberOfStakes, int24 tickLower, int24 tickUpper);
+
+        function stakes(uint256 tokenId, bytes32 incentiveId)
+            external
+            view
+            returns (uint160 secondsPerLiquidityInsideInitialX128, uint128 liquidity);
+
+        function rewards(address rewardToken, address owner) external view returns (uint256 rewardsOwed);
+
+        function getRewardInfo(IncentiveKey memory key, uint256 tokenId)
+            external
+            returns (uint256 reward, uint160 secondsInsideX128);
+
+        function createIncentive(IncentiveKey memory key, uint256 reward) external;
+        function endIncentive(IncentiveKey memory key) external returns (uint256 refund);
+        function stakeToken(IncentiveKey memory key, uint256 tokenId) external;
+        function unstakeToken(IncentiveKey memory key, uint256 tokenId) external;
+        function claimReward(address rewardToken, address to, uint256 amountRequested) external returns (uint256 reward);
+        function withdrawToken(uint256 tokenId, address to, bytes memory data) external;
+    }
+}
+
+/// The id of an incentive, `keccak256(abi.encode(key))`
+pub fn incentive_id(key: &IncentiveKey) -> B256 {
+    keccak256(key.abi_encode())
+}
+
+/// Return the state of an incentive
+///
+/// Returns `(totalRewardUnclaimed, totalSecondsClaimedX128, numberOfStakes)`
+pub async fn incentives<T, P, N>(
+    client: P,
+    staker: Address,
+    key: &IncentiveKey,
+    block_id: Option<BlockId>,
+) -> Result<(U256, U256, u128), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = block_id.unwrap_or(BlockId::latest());
+    let contract = IUniswapV3Staker::new(staker, client);
+    let incentive = contract
+        .incentives(incentive_id(key))
+        .block(block)
+        .call()
+        .await?;
+
+    Ok((
+        incentive.totalRewardUnclaimed,
+        U256::from(incentive.totalSecondsClaimedX128),
+        incentive.numberOfStakes.to::<u128>(),
+    ))
+}
+
+/// Return the reward a staked position has accrued for an incentive
+///
+/// Returns `(reward, secondsInsideX128)`
+pub async fn get_reward_info<T, P, N>(
+    client: P,
+    staker: Address,
+    key: IncentiveKey,
+    token_id: U256,
+    block_id: Option<BlockId>,
+) -> Result<(U256, U256), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = block_id.unwrap_or(BlockId::latest());
+    let contract = IUniswapV3Staker::new(staker, client);
+    let info = contract
+        .getRewardInfo(key, token_id)
+        .block(block)
+        .call()
+        .await?;
+
+    Ok((info.reward, U256::from(info.secondsInsideX128)))
+}
+
+/// Return the rewards owed to an owner for a reward token
+pub async fn rewards<T, P, N>(
+    client: P,
+    staker: Address,
+    reward_token: Address,
+    owner: Address,
+    block_id: Option<BlockId>,
+) -> Result<U256, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = block_id.unwrap_or(BlockId::latest());
+    let contract = IUniswapV3Staker::new(staker, client);
+    let rewards = contract
+        .rewards(reward_token, owner)
+        .block(block)
+        .call()
+        .await?;
+    Ok(rewards.rewardsOwed)
+}
@@ -0,0 +1,305 @@
+//! Command line access to the common analyses
+//!
+//! ```text
+//! hello-eth --rpc wss://eth.merkle.io quote --pool 0x88e6... --dex uniswap-v3 --token-in WETH --amount 10
+//! hello-eth --rpc wss://eth.merkle.io volume --pool 0x88e6... --days 1
+//! hello-eth simulate-position --config analysis.toml
+//! hello-eth --rpc wss://eth.merkle.io honeypot-check --token 0x...
+//! hello-eth --rpc wss://eth.merkle.io balances --account 0x... --tokens WETH,USDC
+//! ```
+//!
+//! The RPC endpoint is read from `--rpc`, the `ETH_RPC_URL` environment variable or the config file
+
+use alloy_network::Ethereum;
+use alloy_primitives::{
+    address,
+    utils::{format_units, parse_units},
+    Address, U256,
+};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::SolEvent;
+use alloy_transport::Transport;
+use clap::{Parser, Subcommand, ValueEnum};
+use revm::db::{CacheDB, EmptyDB};
+
+use std::path::PathBuf;
+
+use hello_eth::abi::uniswap::pool::v3::IUniswapV3Pool;
+use hello_eth::defi::amm::pool::{AnyPool, DexKind};
+use hello_eth::defi::amm::uniswap::v3::lp_provider::simulate_position;
+use hello_eth::prelude::*;
+use hello_eth::revm_utils::simulate::can_tranfer_erc20;
+use hello_eth::utils::config::{AnalysisConfig, PoolConfig};
+use hello_eth::ChainId;
+
+#[derive(Parser)]
+#[command(name = "hello-eth", about = "Common DeFi analyses from the command line")]
+struct Cli {
+    /// The RPC endpoint, HTTP or WebSocket
+    #[arg(long, env = "ETH_RPC_URL", global = true)]
+    rpc: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Dex {
+    UniswapV2,
+    UniswapV3,
+    Solidly,
+}
+
+impl From<Dex> for DexKind {
+    fn from(dex: Dex) -> Self {
+        match dex {
+            Dex::UniswapV2 => DexKind::UniswapV2,
+            Dex::UniswapV3 => DexKind::UniswapV3,
+            Dex::Solidly => DexKind::Solidly,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Quote a swap on a pool with its latest state
+    Quote {
+        #[arg(long)]
+        pool: Address,
+
+        #[arg(long, value_enum, default_value = "uniswap-v3")]
+        dex: Dex,
+
+        /// The token to sell, an address or a symbol (WETH, USDC, USDT, DAI)
+        #[arg(long)]
+        token_in: String,
+
+        /// The amount to sell, eg. 1.5
+        #[arg(long)]
+        amount: String,
+    },
+
+    /// The buy and sell volume of a Uniswap V3 pool
+    Volume {
+        #[arg(long)]
+        pool: Address,
+
+        #[arg(long, default_value_t = 1)]
+        days: u64,
+    },
+
+    /// Simulate a liquidity position described in a config file, see AnalysisConfig
+    SimulatePosition {
+        #[arg(long)]
+        config: PathBuf,
+    },
+
+    /// Check that a token can be transferred by a holder in a fork of the latest block
+    HoneypotCheck {
+        #[arg(long)]
+        token: Address,
+    },
+
+    /// The native and token balances of an account
+    Balances {
+        #[arg(long)]
+        account: Address,
+
+        /// Comma separated addresses or symbols
+        #[arg(long, value_delimiter = ',')]
+        tokens: Vec<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+
+    let config = match &cli.command {
+        Command::SimulatePosition { config } => Some(AnalysisConfig::from_file(config)?),
+        _ => None,
+    };
+
+    let url = cli
+        .rpc
+        .clone()
+        .or_else(|| config.as_ref().and_then(|c| c.rpc.url.clone()))
+        .ok_or_else(|| anyhow::anyhow!("No RPC endpoint, set --rpc or ETH_RPC_URL"))?;
+    let client = ProviderBuilder::new().on_builtin(&url).await?;
+    let chain_id = client.get_chain_id().await?;
+
+    match cli.command {
+        Command::Quote {
+            pool,
+            dex,
+            token_in,
+            amount,
+        } => quote(client, chain_id, pool, dex.into(), &token_in, &amount).await,
+        Command::Volume { pool, days } => volume(client, chain_id, pool, days).await,
+        Command::SimulatePosition { .. } => {
+            let config = config.expect("config is loaded above");
+            let args = config.position_args(client.clone()).await?;
+            let result = simulate_position(client, config.block_time()?, args).await?;
+            println!("{}", result.pretty());
+            Ok(())
+        }
+        Command::HoneypotCheck { token } => honeypot_check(client, chain_id, token).await,
+        Command::Balances { account, tokens } => balances(client, chain_id, account, &tokens).await,
+    }
+}
+
+/// Resolve an address or the symbol of a common token of the chain
+fn token_address(chain_id: u64, token: &str) -> Result<Address, anyhow::Error> {
+    if let Ok(address) = token.parse() {
+        return Ok(address);
+    }
+
+    let chain = ChainId::try_new(chain_id)?;
+    let address = match token.to_uppercase().as_str() {
+        "WETH" | "WBNB" => Some(chain.wrapped_native()),
+        "USDC" => Some(chain.usdc()),
+        "USDT" => chain.usdt(),
+        "DAI" => Some(chain.dai()),
+        _ => None,
+    };
+    address.ok_or_else(|| anyhow::anyhow!("Unknown token {} on chain {}", token, chain_id))
+}
+
+async fn quote<T, P>(
+    client: P,
+    chain_id: u64,
+    pool: Address,
+    dex: DexKind,
+    token_in: &str,
+    amount: &str,
+) -> Result<(), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone,
+{
+    let pool_config = PoolConfig {
+        name: None,
+        address: pool,
+        dex,
+        fee: None,
+        variant: Default::default(),
+    };
+    let mut pool = AnalysisConfig::new(chain_id)
+        .load_pool(client.clone(), &pool_config)
+        .await?;
+    pool.sync_state(client, None).await?;
+
+    let token_in = token_address(chain_id, token_in)?;
+    let (token0, token1) = pool.tokens();
+    let (token_in, token_out) = if token_in == token0.address {
+        (token0, token1)
+    } else if token_in == token1.address {
+        (token1, token0)
+    } else {
+        return Err(anyhow::anyhow!("The pool doesn't trade {}", token_in));
+    };
+
+    let amount_in = parse_units(amount, token_in.decimals)?.get_absolute();
+    let amount_out = pool.simulate_swap(token_in.address, amount_in)?;
+    println!(
+        "{} {} -> {} {}",
+        amount,
+        token_in.symbol,
+        format_units(amount_out, token_out.decimals)?,
+        token_out.symbol
+    );
+    Ok(())
+}
+
+async fn volume<T, P>(client: P, chain_id: u64, pool: Address, days: u64) -> Result<(), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    let pool_config = PoolConfig {
+        name: None,
+        address: pool,
+        dex: DexKind::UniswapV3,
+        fee: None,
+        variant: Default::default(),
+    };
+    let loaded = AnalysisConfig::new(chain_id)
+        .load_pool(client.clone(), &pool_config)
+        .await?;
+    let AnyPool::V3(mut pool) = loaded else {
+        unreachable!("loaded as a V3 pool");
+    };
+    pool.update_state(UniswapV3Pool::fetch_state(pool.address, client.clone(), None).await?);
+
+    let logs = get_logs_for(
+        client.clone(),
+        chain_id,
+        vec![pool.address],
+        vec![IUniswapV3Pool::Swap::SIGNATURE_HASH],
+        BlockTime::Days(days),
+    )
+    .await?;
+    let volume = pool.get_volume_from_logs(logs)?;
+
+    let (token0_usd, token1_usd) = pool.tokens_usd(client, None).await?;
+    println!("{} swaps over {} days", volume.swaps.len(), days);
+    println!(
+        "Buy volume: {} {} (${:.2})",
+        format_units(volume.buy_volume, pool.token1.decimals)?,
+        pool.token1.symbol,
+        volume.buy_volume_usd(token1_usd, pool.token1.decimals)?
+    );
+    println!(
+        "Sell volume: {} {} (${:.2})",
+        format_units(volume.sell_volume, pool.token0.decimals)?,
+        pool.token0.symbol,
+        volume.sell_volume_usd(token0_usd, pool.token0.decimals)?
+    );
+    Ok(())
+}
+
+async fn honeypot_check<T, P>(client: P, chain_id: u64, token: Address) -> Result<(), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    let token = ERC20Token::new(client.clone(), token, chain_id, TokenKind::Other).await?;
+    let amount = parse_units("1", token.decimals)?.get_absolute();
+
+    let holder = DummyAccount::new(AccountType::EOA, parse_units("1", 18)?.get_absolute());
+    let mut fork_factory = ForkFactory::new_sandbox_factory(client, CacheDB::new(EmptyDB::new()), None);
+    holder.insert(&mut fork_factory, token.clone(), amount)?;
+
+    let mut evm = new_evm(fork_factory.new_sandbox_fork(), None);
+    let dead = address!("000000000000000000000000000000000000dEaD");
+    let (success, reason) = can_tranfer_erc20(&mut evm, token.clone(), holder.address, dead, amount)?;
+
+    if success {
+        println!("{} can be transferred by a holder", token.symbol);
+    } else {
+        println!("{} CANNOT be transferred by a holder: {}", token.symbol, reason);
+    }
+    Ok(())
+}
+
+async fn balances<T, P>(
+    client: P,
+    chain_id: u64,
+    account: Address,
+    tokens: &[String],
+) -> Result<(), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone,
+{
+    let native = client.get_balance(account).await?;
+    println!("Native: {}", format_units(native, 18)?);
+
+    for token in tokens {
+        let address = token_address(chain_id, token)?;
+        let token = ERC20Token::new(client.clone(), address, chain_id, TokenKind::Other).await?;
+        let balance: U256 = token.balance_of(account, client.clone(), None).await?;
+        println!("{}: {}", token.symbol, format_units(balance, token.decimals)?);
+    }
+    Ok(())
+}
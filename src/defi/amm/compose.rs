@@ -0,0 +1,239 @@
+//! Quotes through a known path of two or three pools
+//!
+//! [quote](super::quote::quote) searches the registry for the best route, when the path is already known
+//! (eg. a triangular arbitrage WETH -> USDC -> WBTC -> WETH) a [Route] chains the swaps of its pools directly,
+//! each hop swapping the output of the previous one
+//!
+//! ```ignore
+//! let route = Route::new(weth, vec![&weth_usdc, &usdc_wbtc, &wbtc_weth])?;
+//! let quote = route.quote(amount_in)?;
+//! println!("{}", quote.pretty());
+//! ```
+
+use alloy_primitives::{Address, U256};
+
+use super::pool::AnyPool;
+use crate::defi::currency::erc20::ERC20Token;
+use crate::defi::utils::slippage;
+use crate::utils::format::to_f64;
+
+/// The maximum number of pools of a [Route]
+pub const MAX_HOPS: usize = 3;
+
+/// A path of pools, the output token of each pool is the input token of the next one
+#[derive(Debug, Clone)]
+pub struct Route<'a> {
+    pub pools: Vec<&'a AnyPool>,
+
+    /// The tokens of the path, `pools.len() + 1` of them starting with the input token
+    pub tokens: Vec<ERC20Token>,
+}
+
+/// One swap of a [RouteQuote]
+#[derive(Debug, Clone)]
+pub struct Hop {
+    pub pool: Address,
+    pub token_in: ERC20Token,
+    pub token_out: ERC20Token,
+    pub amount_in: U256,
+    pub amount_out: U256,
+
+    /// The price of `token_in` in `token_out` before the swap
+    pub mid_price: f64,
+}
+
+/// The result of [Route::quote]
+#[derive(Debug, Clone)]
+pub struct RouteQuote {
+    pub hops: Vec<Hop>,
+    pub amount_in: U256,
+    pub amount_out: U256,
+
+    /// The price of the input token in the output token before the swaps, the product of the mid prices of the hops
+    pub mid_price: f64,
+
+    /// The output amount over the input amount, formatted
+    pub execution_price: f64,
+
+    /// The combined price impact of the hops as a fraction (0.01 = 1%), fees included
+    pub price_impact: f64,
+}
+
+impl<'a> Route<'a> {
+    /// Build a route from `token_in` through the given pools in order
+    ///
+    /// Fails if there are more than [MAX_HOPS] pools or if a pool doesn't trade the output of the previous one
+    pub fn new(token_in: Address, pools: Vec<&'a AnyPool>) -> Result<Self, anyhow::Error> {
+        if pools.is_empty() || pools.len() > MAX_HOPS {
+            return Err(anyhow::anyhow!(
+                "A route needs between 1 and {} pools, got {}",
+                MAX_HOPS,
+                pools.len()
+            ));
+        }
+
+        let mut tokens = Vec::with_capacity(pools.len() + 1);
+        let mut current = token_in;
+        for pool in &pools {
+            let (token0, token1) = pool.tokens();
+            let (from, to) = if token0.address == current {
+                (token0, token1)
+            } else if token1.address == current {
+                (token1, token0)
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Pool {} doesn't trade {}",
+                    pool.address(),
+                    current
+                ));
+            };
+
+            if tokens.is_empty() {
+                tokens.push(from.clone());
+            }
+            tokens.push(to.clone());
+            current = to.address;
+        }
+
+        Ok(Self { pools, tokens })
+    }
+
+    pub fn token_in(&self) -> &ERC20Token {
+        &self.tokens[0]
+    }
+
+    pub fn token_out(&self) -> &ERC20Token {
+        &self.tokens[self.tokens.len() - 1]
+    }
+
+    /// Whether the route ends with the token it starts with, eg. a triangular arbitrage
+    pub fn is_cycle(&self) -> bool {
+        self.token_in().address == self.token_out().address
+    }
+
+    /// The combined price impact of the hops in percent, fees included
+    pub fn price_impact(&self, amount_in: U256) -> Result<f64, anyhow::Error> {
+        Ok(self.quote(amount_in)?.price_impact * 100.0)
+    }
+
+    /// Simulate the swaps of the route with the current state of the pools
+    ///
+    /// The pools are not changed, a pool that appears twice in the route is quoted with the same state both times
+    pub fn quote(&self, amount_in: U256) -> Result<RouteQuote, anyhow::Error> {
+        let mut hops = Vec::with_capacity(self.pools.len());
+        let mut amount = amount_in;
+        let mut mid_price = 1.0;
+
+        for (i, pool) in self.pools.iter().enumerate() {
+            let token_in = &self.tokens[i];
+            let token_out = &self.tokens[i + 1];
+
+            let hop_mid_price = pool.calculate_price(token_in.address)?;
+            let amount_out = pool.simulate_swap(token_in.address, amount)?;
+            mid_price *= hop_mid_price;
+
+            hops.push(Hop {
+                pool: pool.address(),
+                token_in: token_in.clone(),
+                token_out: token_out.clone(),
+                amount_in: amount,
+                amount_out,
+                mid_price: hop_mid_price,
+            });
+            amount = amount_out;
+        }
+
+        let formatted_in = to_f64(amount_in, self.token_in().decimals)?;
+        let formatted_out = to_f64(amount, self.token_out().decimals)?;
+        let execution_price = if formatted_in == 0.0 {
+            0.0
+        } else {
+            formatted_out / formatted_in
+        };
+
+        Ok(RouteQuote {
+            hops,
+            amount_in,
+            amount_out: amount,
+            mid_price,
+            execution_price,
+            price_impact: price_impact(mid_price, execution_price),
+        })
+    }
+}
+
+impl RouteQuote {
+    /// The minimum output with a slippage tolerance in percent, see [minimum_received](crate::defi::utils::slippage::minimum_received)
+    pub fn minimum_received(&self, slippage_percent: f64) -> Result<U256, anyhow::Error> {
+        slippage::minimum_received(self.amount_out, slippage_percent)
+    }
+
+    /// The output minus the input for a cycle, None if the route doesn't end with its input token
+    pub fn profit(&self) -> Option<i128> {
+        let first = self.hops.first()?;
+        let last = self.hops.last()?;
+        if first.token_in.address != last.token_out.address {
+            return None;
+        }
+        let amount_in = i128::try_from(self.amount_in).ok()?;
+        let amount_out = i128::try_from(self.amount_out).ok()?;
+        Some(amount_out - amount_in)
+    }
+
+    pub fn pretty(&self) -> String {
+        let mut lines = Vec::with_capacity(self.hops.len() + 1);
+        for hop in &self.hops {
+            lines.push(format!(
+                "{} {} -> {} {} via {}",
+                to_f64(hop.amount_in, hop.token_in.decimals).unwrap_or(0.0),
+                hop.token_in.symbol,
+                to_f64(hop.amount_out, hop.token_out.decimals).unwrap_or(0.0),
+                hop.token_out.symbol,
+                hop.pool
+            ));
+        }
+        lines.push(format!(
+            "Mid price {:.6}, execution price {:.6}, price impact {:.4}%",
+            self.mid_price,
+            self.execution_price,
+            self.price_impact * 100.0
+        ));
+        lines.join("\n")
+    }
+}
+
+/// The relative shortfall of the execution price from the mid price, 0 if the mid price is unknown
+pub(super) fn price_impact(mid_price: f64, execution_price: f64) -> f64 {
+    if mid_price == 0.0 {
+        return 0.0;
+    }
+    1.0 - execution_price / mid_price
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defi::amm::test_utils::v2_pool;
+    use alloy_primitives::address;
+
+    #[test]
+    fn test_route_chains_hops() {
+        let a = address!("0000000000000000000000000000000000000001");
+        let b = address!("0000000000000000000000000000000000000002");
+        let c = address!("0000000000000000000000000000000000000003");
+        let ab = v2_pool(address!("00000000000000000000000000000000000000ab"), a, b, (1_000_000, 2_000_000));
+        let bc = v2_pool(address!("00000000000000000000000000000000000000bc"), b, c, (4_000_000, 1_000_000));
+
+        let route = Route::new(a, vec![&ab, &bc]).unwrap();
+        assert_eq!(route.token_out().address, c);
+        assert!(!route.is_cycle());
+        assert!(Route::new(c, vec![&ab, &bc]).is_err());
+
+        let quote = route.quote(U256::from(1000)).unwrap();
+        assert_eq!(quote.hops[0].amount_out, ab.simulate_swap(a, U256::from(1000)).unwrap());
+        assert_eq!(quote.hops[1].amount_in, quote.hops[0].amount_out);
+        assert_eq!(quote.amount_out, quote.hops[1].amount_out);
+        assert!((quote.mid_price - 0.5).abs() < 1e-9);
+        assert!(quote.price_impact > 0.0 && quote.price_impact < 0.01);
+    }
+}
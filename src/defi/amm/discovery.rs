@@ -0,0 +1,462 @@
+//! Discover pools from the events of their factories
+//!
+//! [PoolDiscovery] scans the `PairCreated` (V2) and `PoolCreated` (V3) logs of a set of factories
+//! window by window, resolves the tokens of the new pools and reports each window to a callback.
+//!
+//! A window ends with a [DiscoveryCheckpoint], store it to resume the scan later with [PoolDiscovery::resume]
+//!
+//! ```ignore
+//! let discovery = PoolDiscovery::uniswap(chain_id)?;
+//! let pools = discovery
+//!     .scan(client.clone(), from_block, None, |progress| {
+//!         println!("{:.1}% {} pools", progress.fraction() * 100.0, progress.pools_found);
+//!         std::fs::write("checkpoint.json", serde_json::to_string(&progress.checkpoint).unwrap()).unwrap();
+//!     })
+//!     .await?;
+//! ```
+//!
+//! For a V2 factory [sync_all_pairs] is much faster: it walks `allPairs` with a batch contract
+//! and gets the tokens and reserves of many pairs per call
+
+use alloy_contract::private::Network;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, Log};
+use alloy_sol_types::SolEvent;
+use alloy_transport::Transport;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+use super::uniswap::{
+    deployments::UniswapDeployment, v2::UniswapV2Pool, v3::UniswapV3Pool, variant::DexVariant,
+};
+use crate::abi::uniswap::factory::{
+    v2::IUniswapV2Factory,
+    v3::IUniswapV3Factory,
+};
+use crate::defi::currency::erc20::{ERC20Token, TokenKind};
+use crate::utils::{
+    batch_request::{v2_pairs, V2PairData, V2_PAIRS_PER_CALL},
+    config::Config,
+    logs::query::get_logs_in_range,
+};
+use tracing::trace;
+
+/// The kind of pools a factory creates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FactoryKind {
+    V2,
+    V3,
+}
+
+/// A factory to discover the pools of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Factory {
+    pub address: Address,
+    pub kind: FactoryKind,
+
+    /// Set on the discovered pools
+    pub variant: DexVariant,
+}
+
+impl Factory {
+    pub fn v2(address: Address) -> Self {
+        Self {
+            address,
+            kind: FactoryKind::V2,
+            variant: DexVariant::default(),
+        }
+    }
+
+    pub fn v3(address: Address) -> Self {
+        Self {
+            address,
+            kind: FactoryKind::V3,
+            variant: DexVariant::default(),
+        }
+    }
+
+    pub fn with_variant(mut self, variant: DexVariant) -> Self {
+        self.variant = variant;
+        self
+    }
+}
+
+/// Where a scan stopped, the blocks before `next_block` are done
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveryCheckpoint {
+    pub chain_id: u64,
+    pub next_block: u64,
+
+    /// The last block of the scan
+    pub to_block: u64,
+}
+
+impl DiscoveryCheckpoint {
+    pub fn is_done(&self) -> bool {
+        self.next_block > self.to_block
+    }
+}
+
+/// The pools found in a window of a scan
+#[derive(Debug, Clone)]
+pub struct DiscoveryProgress {
+    pub from_block: u64,
+    pub to_block: u64,
+
+    /// The last block scanned so far
+    pub scanned_to: u64,
+
+    /// The pools found so far
+    pub pools_found: usize,
+
+    /// The pools found in this window
+    pub v2_pools: Vec<UniswapV2Pool>,
+    pub v3_pools: Vec<UniswapV3Pool>,
+
+    pub checkpoint: DiscoveryCheckpoint,
+}
+
+impl DiscoveryProgress {
+    /// The share of the blocks scanned from 0 to 1
+    pub fn fraction(&self) -> f64 {
+        let total = self.to_block - self.from_block + 1;
+        (self.scanned_to + 1 - self.from_block) as f64 / total as f64
+    }
+}
+
+/// The result of a scan
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveredPools {
+    pub v2_pools: Vec<UniswapV2Pool>,
+    pub v3_pools: Vec<UniswapV3Pool>,
+
+    /// The pools whose tokens could not be resolved (eg. a token without `decimals`)
+    pub skipped: Vec<Address>,
+}
+
+impl DiscoveredPools {
+    pub fn len(&self) -> usize {
+        self.v2_pools.len() + self.v3_pools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Scans factories for new pools, see the [module](self) docs
+#[derive(Debug, Clone)]
+pub struct PoolDiscovery {
+    pub chain_id: u64,
+    pub factories: Vec<Factory>,
+
+    /// `log_chunk_size * max_concurrency` blocks are scanned per window
+    pub config: Config,
+}
+
+impl PoolDiscovery {
+    pub fn new(chain_id: u64) -> Self {
+        Self {
+            chain_id,
+            factories: Vec::new(),
+            config: Config::default(),
+        }
+    }
+
+    /// The Uniswap V2 and V3 factories of a chain, see [UniswapDeployment]
+    pub fn uniswap(chain_id: u64) -> Result<Self, anyhow::Error> {
+        let deployment = UniswapDeployment::for_chain(chain_id)?;
+        Ok(Self::new(chain_id)
+            .with_factory(Factory::v2(deployment.v2_factory))
+            .with_factory(Factory::v3(deployment.v3_factory)))
+    }
+
+    pub fn with_factory(mut self, factory: Factory) -> Self {
+        self.factories.push(factory);
+        self
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Scan the factories from `from_block` to `to_block` (the latest block if None)
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - The provider
+    /// * `from_block` - The first block to scan, eg. the deployment block of the factories
+    /// * `to_block` - The last block to scan, the latest block if None
+    /// * `on_progress` - Called after each window with the pools it found and the checkpoint to resume from
+    pub async fn scan<T, P, N, F>(
+        &self,
+        client: P,
+        from_block: u64,
+        to_block: Option<u64>,
+        mut on_progress: F,
+    ) -> Result<DiscoveredPools, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone + 'static,
+        N: Network,
+        F: FnMut(&DiscoveryProgress),
+    {
+        let to_block = match to_block {
+            Some(block) => block,
+            None => self.config.timed(client.get_block_number()).await?,
+        };
+
+        let factories: HashMap<Address, Factory> =
+            self.factories.iter().map(|f| (f.address, *f)).collect();
+        let addresses: Vec<Address> = factories.keys().copied().collect();
+        let events = [
+            IUniswapV2Factory::PairCreated::SIGNATURE_HASH,
+            IUniswapV3Factory::PoolCreated::SIGNATURE_HASH,
+        ];
+
+        let window = self.config.log_chunk_size.max(1) * self.config.concurrency() as u64;
+        let mut tokens: HashMap<Address, Option<ERC20Token>> = HashMap::new();
+        let mut discovered = DiscoveredPools::default();
+        let mut start = from_block;
+
+        while start <= to_block {
+            let end = (start + window - 1).min(to_block);
+            let mut logs = get_logs_in_range(
+                client.clone(),
+                addresses.clone(),
+                events,
+                start,
+                end,
+                &self.config,
+            )
+            .await?;
+            trace!("{} factory logs in blocks {} - {}", logs.len(), start, end);
+
+            // the chunks are fetched concurrently, keep the pools in creation order
+            logs.sort_by_key(|log| (log.block_number, log.log_index));
+
+            let created: Vec<Created> = logs
+                .iter()
+                .filter_map(|log| decode_created(log, &factories))
+                .collect();
+
+            self.resolve_tokens(client.clone(), &created, &mut tokens).await;
+
+            let mut v2_pools = Vec::new();
+            let mut v3_pools = Vec::new();
+            for pool in created {
+                let (Some(Some(token0)), Some(Some(token1))) =
+                    (tokens.get(&pool.token0), tokens.get(&pool.token1))
+                else {
+                    discovered.skipped.push(pool.address);
+                    continue;
+                };
+
+                match pool.fee {
+                    None => v2_pools.push(
+                        UniswapV2Pool::new(self.chain_id, pool.address, token0.clone(), token1.clone())
+                            .with_variant(pool.factory.variant),
+                    ),
+                    Some(fee) => {
+                        // the fee comes from the factory itself, so the tier is not checked against the variant
+                        let mut v3_pool =
+                            UniswapV3Pool::new(self.chain_id, pool.address, fee, token0.clone(), token1.clone());
+                        v3_pool.variant = pool.factory.variant;
+                        v3_pools.push(v3_pool);
+                    }
+                }
+            }
+
+            discovered.v2_pools.extend(v2_pools.iter().cloned());
+            discovered.v3_pools.extend(v3_pools.iter().cloned());
+
+            on_progress(&DiscoveryProgress {
+                from_block,
+                to_block,
+                scanned_to: end,
+                pools_found: discovered.len(),
+                v2_pools,
+                v3_pools,
+                checkpoint: DiscoveryCheckpoint {
+                    chain_id: self.chain_id,
+                    next_block: end + 1,
+                    to_block,
+                },
+            });
+
+            start = end + 1;
+        }
+
+        Ok(discovered)
+    }
+
+    /// Continue a scan from a checkpoint
+    ///
+    /// The result only has the pools found after the checkpoint
+    pub async fn resume<T, P, N, F>(
+        &self,
+        client: P,
+        checkpoint: &DiscoveryCheckpoint,
+        on_progress: F,
+    ) -> Result<DiscoveredPools, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone + 'static,
+        N: Network,
+        F: FnMut(&DiscoveryProgress),
+    {
+        if checkpoint.chain_id != self.chain_id {
+            return Err(anyhow::anyhow!(
+                "The checkpoint is for chain {}, not {}",
+                checkpoint.chain_id,
+                self.chain_id
+            ));
+        }
+        if checkpoint.is_done() {
+            return Ok(DiscoveredPools::default());
+        }
+
+        self.scan(client, checkpoint.next_block, Some(checkpoint.to_block), on_progress)
+            .await
+    }
+
+    /// Fetch the tokens not in the cache, a token that fails is cached as None
+    async fn resolve_tokens<T, P, N>(
+        &self,
+        client: P,
+        created: &[Created],
+        tokens: &mut HashMap<Address, Option<ERC20Token>>,
+    ) where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let mut missing: Vec<Address> = created
+            .iter()
+            .flat_map(|pool| [pool.token0, pool.token1])
+            .filter(|token| !tokens.contains_key(token))
+            .collect();
+        missing.sort();
+        missing.dedup();
+
+        let chain_id = self.chain_id;
+        let resolved: Vec<(Address, Option<ERC20Token>)> = stream::iter(missing)
+            .map(|address| {
+                let client = client.clone();
+                async move {
+                    let token = ERC20Token::new(client, address, chain_id, TokenKind::Other).await;
+                    if let Err(e) = &token {
+                        trace!("Failed to resolve token {}: {:?}", address, e);
+                    }
+                    (address, token.ok())
+                }
+            })
+            .buffer_unordered(self.config.concurrency())
+            .collect()
+            .await;
+
+        tokens.extend(resolved);
+    }
+}
+
+/// Enumerate the pairs of a V2 factory from `start_index` to `allPairsLength` with their tokens and reserves
+///
+/// Each chunk of `batch_size` pairs is a single `eth_call` (see [v2_pairs]), all the chunks are read at the same block.
+/// Store the index of the last pair to only sync the new pairs next time.
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `factory` - The V2 factory
+/// * `start_index` - The index of the first pair, 0 for the whole factory
+/// * `batch_size` - The pairs per call, clamped to [V2_PAIRS_PER_CALL]
+pub async fn sync_all_pairs<T, P, N>(
+    client: P,
+    factory: Address,
+    start_index: u64,
+    batch_size: u64,
+) -> Result<Vec<V2PairData>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let config = Config::default();
+    let batch_size = batch_size.clamp(1, V2_PAIRS_PER_CALL);
+
+    let block = BlockId::number(config.timed(client.get_block_number()).await?);
+    let length: u64 = IUniswapV2Factory::new(factory, client.clone())
+        .allPairsLength()
+        .block(block)
+        .call()
+        .await?
+        .length
+        .saturating_to();
+    if start_index >= length {
+        return Ok(Vec::new());
+    }
+    trace!("Syncing pairs {} - {} of {}", start_index, length, factory);
+
+    let chunks: Vec<(u64, u64)> = (start_index..length)
+        .step_by(batch_size as usize)
+        .map(|start| (start, batch_size.min(length - start)))
+        .collect();
+
+    let results: Vec<Result<Vec<V2PairData>, anyhow::Error>> = stream::iter(chunks)
+        .map(|(start, count)| {
+            let client = client.clone();
+            async move { v2_pairs(client, factory, start, count, Some(block)).await }
+        })
+        .buffered(config.concurrency())
+        .collect()
+        .await;
+
+    let mut pairs = Vec::with_capacity((length - start_index) as usize);
+    for result in results {
+        pairs.extend(result?);
+    }
+
+    Ok(pairs)
+}
+
+/// A pool creation event
+struct Created {
+    factory: Factory,
+    address: Address,
+    token0: Address,
+    token1: Address,
+
+    /// None for V2
+    fee: Option<u32>,
+}
+
+fn decode_created(log: &Log, factories: &HashMap<Address, Factory>) -> Option<Created> {
+    let factory = *factories.get(&log.address())?;
+
+    match factory.kind {
+        FactoryKind::V2 => {
+            let event = log.log_decode::<IUniswapV2Factory::PairCreated>().ok()?.inner.data;
+            Some(Created {
+                factory,
+                address: event.pair,
+                token0: event.token0,
+                token1: event.token1,
+                fee: None,
+            })
+        }
+        FactoryKind::V3 => {
+            let event = log.log_decode::<IUniswapV3Factory::PoolCreated>().ok()?.inner.data;
+            Some(Created {
+                factory,
+                address: event.pool,
+                token0: event.token0,
+                token1: event.token1,
+                fee: Some(event.fee.to::<u32>()),
+            })
+        }
+    }
+}
@@ -1,2 +1,13 @@
+pub mod compose;
 pub mod consts;
-pub mod uniswap;
\ No newline at end of file
+pub mod discovery;
+pub mod pool;
+pub mod quote;
+pub mod quoter;
+pub mod registry;
+pub mod route;
+pub mod router;
+pub mod solidly;
+#[cfg(test)]
+pub mod test_utils;
+pub mod uniswap;
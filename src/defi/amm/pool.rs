@@ -0,0 +1,213 @@
+//! A pool of any supported DEX
+//!
+//! [AnyPool] wraps the pool types so downstream code can hold a mixed list of pools
+//! and call their common methods without matching on the variant
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, Log};
+use alloy_transport::Transport;
+use serde::{Deserialize, Serialize};
+
+use super::solidly::SolidlyPool;
+use super::uniswap::{v2::UniswapV2Pool, v3::UniswapV3Pool};
+use crate::defi::currency::erc20::ERC20Token;
+
+/// The DEX a pool belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DexKind {
+    UniswapV2,
+    UniswapV3,
+
+    /// Velodrome V2, Aerodrome
+    Solidly,
+}
+
+/// A Uniswap V2, Uniswap V3 or Solidly pool
+#[derive(Debug, Clone)]
+pub enum AnyPool {
+    V2(UniswapV2Pool),
+    V3(UniswapV3Pool),
+    Solidly(SolidlyPool),
+}
+
+impl From<UniswapV2Pool> for AnyPool {
+    fn from(pool: UniswapV2Pool) -> Self {
+        Self::V2(pool)
+    }
+}
+
+impl From<UniswapV3Pool> for AnyPool {
+    fn from(pool: UniswapV3Pool) -> Self {
+        Self::V3(pool)
+    }
+}
+
+impl From<SolidlyPool> for AnyPool {
+    fn from(pool: SolidlyPool) -> Self {
+        Self::Solidly(pool)
+    }
+}
+
+impl AnyPool {
+    pub fn address(&self) -> Address {
+        match self {
+            Self::V2(pool) => pool.address,
+            Self::V3(pool) => pool.address,
+            Self::Solidly(pool) => pool.address,
+        }
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            Self::V2(pool) => pool.chain_id,
+            Self::V3(pool) => pool.chain_id,
+            Self::Solidly(pool) => pool.chain_id,
+        }
+    }
+
+    pub fn dex_kind(&self) -> DexKind {
+        match self {
+            Self::V2(_) => DexKind::UniswapV2,
+            Self::V3(_) => DexKind::UniswapV3,
+            Self::Solidly(_) => DexKind::Solidly,
+        }
+    }
+
+    /// The fee of the pool in hundredths of a bip (eg. 3000 for 0.3%)
+    pub fn fee(&self) -> u32 {
+        match self {
+            Self::V2(pool) => pool.fee_bps * 100,
+            Self::V3(pool) => pool.fee,
+            Self::Solidly(pool) => pool.fee_bps * 100,
+        }
+    }
+
+    /// (token0, token1)
+    pub fn tokens(&self) -> (&ERC20Token, &ERC20Token) {
+        match self {
+            Self::V2(pool) => (&pool.token0, &pool.token1),
+            Self::V3(pool) => (&pool.token0, &pool.token1),
+            Self::Solidly(pool) => (&pool.token0, &pool.token1),
+        }
+    }
+
+    /// Whether the state of the pool is set
+    pub fn has_state(&self) -> bool {
+        match self {
+            Self::V2(pool) => pool.state().is_some(),
+            Self::V3(pool) => pool.state().is_some(),
+            Self::Solidly(pool) => pool.state().is_some(),
+        }
+    }
+
+    /// Fetch the state of the pool at the given block and set it
+    ///
+    /// If block is None, the latest block is used
+    pub async fn sync_state<T, P, N>(&mut self, client: P, block: Option<BlockId>) -> Result<(), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        match self {
+            Self::V2(pool) => {
+                let state = UniswapV2Pool::fetch_state(client, pool.address, block).await?;
+                pool.update_state(state);
+            }
+            Self::V3(pool) => {
+                let state = UniswapV3Pool::fetch_state(pool.address, client, block).await?;
+                pool.update_state(state);
+            }
+            Self::Solidly(pool) => {
+                let state = SolidlyPool::fetch_state(client, pool.address, block).await?;
+                pool.update_state(state);
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a log of the pool to its state, returns whether the state changed
+    ///
+    /// See [UniswapV2Pool::update_from_log], [UniswapV3Pool::apply_log] and [SolidlyPool::apply_log]
+    pub fn apply_log(&mut self, log: &Log) -> Result<bool, anyhow::Error> {
+        match self {
+            Self::V2(pool) => pool.update_from_log(log),
+            Self::V3(pool) => pool.apply_log(log),
+            Self::Solidly(pool) => pool.apply_log(log),
+        }
+    }
+
+    /// Simulate a swap with the current state of the pool
+    pub fn simulate_swap(&self, token_in: Address, amount_in: U256) -> Result<U256, anyhow::Error> {
+        match self {
+            Self::V2(pool) => pool.simulate_swap(token_in, amount_in),
+            Self::V3(pool) => pool.simulate_swap(token_in, amount_in),
+            Self::Solidly(pool) => pool.simulate_swap(token_in, amount_in),
+        }
+    }
+
+    /// Simulate a swap and update the state of the pool
+    pub fn simulate_swap_mut(&mut self, token_in: Address, amount_in: U256) -> Result<U256, anyhow::Error> {
+        match self {
+            Self::V2(pool) => pool.simulate_swap_mut(token_in, amount_in),
+            Self::V3(pool) => pool.simulate_swap_mut(token_in, amount_in),
+            Self::Solidly(pool) => pool.simulate_swap_mut(token_in, amount_in),
+        }
+    }
+
+    /// The price of `base_token` in terms of the other token
+    pub fn calculate_price(&self, base_token: Address) -> Result<f64, anyhow::Error> {
+        match self {
+            Self::V2(pool) => pool.calculate_price(base_token),
+            Self::V3(pool) => pool.calculate_price(base_token),
+            Self::Solidly(pool) => pool.calculate_price(base_token),
+        }
+    }
+
+    /// The shortfall of the execution price of a swap from the mid price in percent, fees included
+    pub fn price_impact(&self, token_in: Address, amount_in: U256) -> Result<f64, anyhow::Error> {
+        match self {
+            Self::V2(pool) => pool.price_impact(token_in, amount_in),
+            Self::V3(pool) => pool.price_impact(token_in, amount_in),
+            Self::Solidly(pool) => pool.price_impact(token_in, amount_in),
+        }
+    }
+
+    /// Switch token0 and token1
+    pub fn toggle_pair(&mut self) {
+        match self {
+            Self::V2(pool) => pool.toggle_pair(),
+            Self::V3(pool) => pool.toggle_pair(),
+            Self::Solidly(pool) => pool.toggle_pair(),
+        }
+    }
+
+    /// Whether one of the tokens has a known USD price
+    pub fn supports_usd(&self) -> Result<bool, anyhow::Error> {
+        match self {
+            Self::V2(pool) => pool.supports_usd(),
+            Self::V3(pool) => pool.supports_usd(),
+            Self::Solidly(pool) => pool.supports_usd(),
+        }
+    }
+
+    /// The USD prices of token0 and token1 with the current state of the pool
+    pub async fn tokens_usd<T, P, N>(
+        &self,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<(f64, f64), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        match self {
+            Self::V2(pool) => pool.tokens_usd(client, block).await,
+            Self::V3(pool) => pool.tokens_usd(client, block).await,
+            Self::Solidly(pool) => pool.tokens_usd(client, block).await,
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Swap quotes between [Currency]s
+//!
+//! Native currencies are quoted through their wrapped token, so callers don't have to special-case
+//! ETH or BNB. Wrapping and unwrapping are 1:1 and free
+
+use alloy_primitives::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+use super::registry::PoolRegistry;
+use super::router::Router;
+use crate::defi::currency::Currency;
+
+/// The result of [quote]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quote {
+    pub amount_out: U256,
+
+    /// The pools of the route in order, empty for a plain wrap or unwrap
+    pub pools: Vec<Address>,
+
+    /// The input is native and must be wrapped first
+    pub wrap: bool,
+
+    /// The output is native and must be unwrapped at the end
+    pub unwrap: bool,
+}
+
+/// Quote a swap of `amount` of `currency_in` to `currency_out`
+///
+/// The best route of at most two hops through the pools of the registry is used,
+/// the pools must have their state set, see [PoolRegistry::sync_states]
+///
+/// ## Arguments
+///
+/// * `registry` - The pools to route through
+/// * `currency_in` - The currency to sell
+/// * `currency_out` - The currency to buy
+/// * `amount` - The amount of `currency_in`
+pub fn quote(
+    registry: &PoolRegistry,
+    currency_in: &Currency,
+    currency_out: &Currency,
+    amount: U256,
+) -> Result<Quote, anyhow::Error> {
+    let token_in = currency_in.wrapped_address()?;
+    let token_out = currency_out.wrapped_address()?;
+
+    let mut quote = Quote {
+        amount_out: amount,
+        pools: Vec::new(),
+        wrap: currency_in.is_native(),
+        unwrap: currency_out.is_native(),
+    };
+
+    // ETH -> WETH or WETH -> ETH
+    if token_in == token_out {
+        if !(quote.wrap ^ quote.unwrap) {
+            return Err(anyhow::anyhow!("Cannot quote a currency against itself"));
+        }
+        return Ok(quote);
+    }
+
+    let (amount_out, pools) = best_route(registry, token_in, token_out, amount)
+        .ok_or_else(|| anyhow::anyhow!("No route from {} to {}", token_in, token_out))?;
+
+    quote.amount_out = amount_out;
+    quote.pools = pools;
+    Ok(quote)
+}
+
+/// The route of at most two hops with the highest output, see [Router::best_route]
+fn best_route(
+    registry: &PoolRegistry,
+    token_in: Address,
+    token_out: Address,
+    amount_in: U256,
+) -> Option<(U256, Vec<Address>)> {
+    let quote = Router::new(registry.pools())
+        .with_max_hops(2)
+        .best_route(token_in, token_out, amount_in)
+        .ok()?;
+
+    if quote.amount_out.is_zero() {
+        return None;
+    }
+    Some((quote.amount_out, quote.hops.iter().map(|hop| hop.pool).collect()))
+}
@@ -0,0 +1,337 @@
+//! Repeated quotes against cached pool states
+//!
+//! A [Quoter] owns its pools and remembers when and at which block the state of each one was set.
+//! Before a quote the state is refetched only if it's older than the [Staleness] policy allows,
+//! so a quoting loop neither hits the node on every quote nor quotes silently against an old state.
+//!
+//! The states can also be kept up to date from a log subscription: feed the logs of the pools to
+//! [Quoter::apply_log] and every new block to [Quoter::on_block], with [Quoter::with_streamed_logs]
+//! a pool without logs in a block is then known to be unchanged
+//!
+//! ```ignore
+//! let mut quoter = Quoter::new(Staleness::max_blocks(2));
+//! quoter.add_pool(pool);
+//! let amount_out = quoter.quote(client.clone(), pool_address, token_in, amount_in).await?;
+//! ```
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, Log};
+use alloy_transport::Transport;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+use super::compose::{Route, RouteQuote};
+use super::pool::AnyPool;
+
+/// How old the state of a pool can be before it's refetched
+///
+/// A state is stale if it breaks any of the limits, with no limit set it's never refetched once set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Staleness {
+    /// The maximum time since the state was set
+    pub max_age: Option<Duration>,
+
+    /// The maximum number of blocks the state can be behind the latest block seen by [Quoter::on_block]
+    pub max_blocks: Option<u64>,
+}
+
+impl Staleness {
+    /// Never refetch a state once set
+    pub fn never() -> Self {
+        Self::default()
+    }
+
+    pub fn max_age(max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            max_blocks: None,
+        }
+    }
+
+    pub fn max_blocks(max_blocks: u64) -> Self {
+        Self {
+            max_age: None,
+            max_blocks: Some(max_blocks),
+        }
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_max_blocks(mut self, max_blocks: u64) -> Self {
+        self.max_blocks = Some(max_blocks);
+        self
+    }
+}
+
+/// A pool of a [Quoter] and when its state was set
+#[derive(Debug, Clone)]
+pub struct CachedPool {
+    pub pool: AnyPool,
+
+    /// When the state was last fetched or updated, None if it was never set
+    pub updated_at: Option<Instant>,
+
+    /// The block the state is at, None if unknown
+    pub block: Option<u64>,
+}
+
+/// Pools with cached states, see the [module](self) docs
+#[derive(Debug, Clone, Default)]
+pub struct Quoter {
+    pools: HashMap<Address, CachedPool>,
+    pub staleness: Staleness,
+
+    /// The latest block seen by [Quoter::on_block]
+    pub latest_block: Option<u64>,
+
+    /// Every log of the pools is fed to [Quoter::apply_log]
+    pub streamed_logs: bool,
+}
+
+impl Quoter {
+    pub fn new(staleness: Staleness) -> Self {
+        Self {
+            staleness,
+            ..Default::default()
+        }
+    }
+
+    /// Whether every log of the pools is fed to [Quoter::apply_log]
+    ///
+    /// If so, [Quoter::on_block] moves all the states to the new block since the pools without logs didn't change
+    pub fn with_streamed_logs(mut self, streamed_logs: bool) -> Self {
+        self.streamed_logs = streamed_logs;
+        self
+    }
+
+    /// Add a pool, a pool that already has a state counts as just updated
+    ///
+    /// Its block is unknown though, so with [Staleness::max_blocks] it's refetched once a block is seen
+    pub fn add_pool(&mut self, pool: impl Into<AnyPool>) {
+        let pool = pool.into();
+        let updated_at = pool.has_state().then(Instant::now);
+        self.pools.insert(
+            pool.address(),
+            CachedPool {
+                pool,
+                updated_at,
+                block: None,
+            },
+        );
+    }
+
+    pub fn pool(&self, address: Address) -> Option<&CachedPool> {
+        self.pools.get(&address)
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = &CachedPool> {
+        self.pools.values()
+    }
+
+    /// A new block was seen
+    pub fn on_block(&mut self, block: u64) {
+        if self.latest_block.is_some_and(|latest| latest >= block) {
+            return;
+        }
+        self.latest_block = Some(block);
+
+        if self.streamed_logs {
+            let now = Instant::now();
+            for cached in self.pools.values_mut().filter(|c| c.pool.has_state()) {
+                cached.updated_at = Some(now);
+                cached.block = Some(block);
+            }
+        }
+    }
+
+    /// Apply a log to the state of its pool
+    ///
+    /// Returns whether a state changed, logs of unknown pools or pools without a state are ignored
+    pub fn apply_log(&mut self, log: &Log) -> Result<bool, anyhow::Error> {
+        let Some(cached) = self.pools.get_mut(&log.address()) else {
+            return Ok(false);
+        };
+        if !cached.pool.has_state() {
+            return Ok(false);
+        }
+
+        let changed = cached.pool.apply_log(log)?;
+        if changed {
+            cached.updated_at = Some(Instant::now());
+            cached.block = log.block_number.or(cached.block);
+        }
+        Ok(changed)
+    }
+
+    /// Whether the state of a pool must be refetched before quoting
+    pub fn is_stale(&self, address: Address) -> bool {
+        let Some(cached) = self.pools.get(&address) else {
+            return false;
+        };
+        let Some(updated_at) = cached.updated_at else {
+            return true;
+        };
+
+        if let Some(max_age) = self.staleness.max_age {
+            if updated_at.elapsed() > max_age {
+                return true;
+            }
+        }
+
+        if let (Some(max_blocks), Some(latest)) = (self.staleness.max_blocks, self.latest_block) {
+            match cached.block {
+                Some(block) if latest.saturating_sub(block) <= max_blocks => (),
+                _ => return true,
+            }
+        }
+
+        false
+    }
+
+    /// Fetch the state of a pool at the latest block
+    pub async fn refresh<T, P, N>(&mut self, client: P, address: Address) -> Result<(), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let cached = self
+            .pools
+            .get_mut(&address)
+            .ok_or_else(|| anyhow::anyhow!("Unknown pool {}", address))?;
+
+        let block = client.get_block_number().await?;
+        cached
+            .pool
+            .sync_state(client, Some(BlockId::number(block)))
+            .await?;
+        cached.updated_at = Some(Instant::now());
+        cached.block = Some(block);
+        trace!("Refreshed the state of {} at block {}", address, block);
+
+        if self.latest_block.map_or(true, |latest| latest < block) {
+            self.latest_block = Some(block);
+        }
+        Ok(())
+    }
+
+    /// Refetch the state of the pool if it's stale
+    pub async fn ensure_fresh<T, P, N>(&mut self, client: P, address: Address) -> Result<(), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        if self.is_stale(address) {
+            self.refresh(client, address).await?;
+        }
+        Ok(())
+    }
+
+    /// Quote a swap on a pool, refetching its state first if it's stale
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - The provider
+    /// * `pool` - The pool, must have been added with [Quoter::add_pool]
+    /// * `token_in` - The token to sell
+    /// * `amount_in` - The amount of `token_in`
+    pub async fn quote<T, P, N>(
+        &mut self,
+        client: P,
+        pool: Address,
+        token_in: Address,
+        amount_in: U256,
+    ) -> Result<U256, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        self.ensure_fresh(client, pool).await?;
+        let cached = self
+            .pools
+            .get(&pool)
+            .ok_or_else(|| anyhow::anyhow!("Unknown pool {}", pool))?;
+        cached.pool.simulate_swap(token_in, amount_in)
+    }
+
+    /// Quote a swap through a path of pools, refetching the stale ones first, see [Route]
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - The provider
+    /// * `token_in` - The token to sell
+    /// * `pools` - The pools of the path in order
+    /// * `amount_in` - The amount of `token_in`
+    pub async fn quote_route<T, P, N>(
+        &mut self,
+        client: P,
+        token_in: Address,
+        pools: &[Address],
+        amount_in: U256,
+    ) -> Result<RouteQuote, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        for pool in pools {
+            self.ensure_fresh(client.clone(), *pool).await?;
+        }
+
+        let path = pools
+            .iter()
+            .map(|address| {
+                self.pools
+                    .get(address)
+                    .map(|cached| &cached.pool)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown pool {}", address))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Route::new(token_in, path)?.quote(amount_in)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defi::amm::uniswap::v2::{State, UniswapV2Pool};
+    use crate::defi::currency::erc20::ERC20Token;
+    use alloy_primitives::address;
+
+    #[test]
+    fn test_staleness() {
+        let address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+        let mut pool = UniswapV2Pool::new(1, address, ERC20Token::default(), ERC20Token::default());
+
+        let mut quoter = Quoter::new(Staleness::max_blocks(2));
+        quoter.add_pool(pool.clone());
+        assert!(quoter.is_stale(address));
+
+        pool.update_state(State {
+            reserve0: U256::from(1000),
+            reserve1: U256::from(1000),
+            block: 100,
+        });
+        let mut quoter = Quoter::new(Staleness::max_blocks(2)).with_streamed_logs(true);
+        quoter.add_pool(pool);
+        quoter.on_block(100);
+        quoter.on_block(103);
+        assert!(!quoter.is_stale(address));
+
+        quoter.streamed_logs = false;
+        quoter.on_block(105);
+        assert!(!quoter.is_stale(address));
+        quoter.on_block(106);
+        assert!(quoter.is_stale(address));
+    }
+}
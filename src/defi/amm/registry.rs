@@ -0,0 +1,217 @@
+//! A set of known pools of a chain
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
+
+use super::pool::AnyPool;
+use super::uniswap::{
+    v2::UniswapV2Pool,
+    v3::{sqrt_price_to_f64, UniswapV3Pool},
+};
+use crate::utils::format::to_f64;
+
+/// The pools known to the caller, used to route prices through them
+///
+/// The pools must have their state set (see [PoolRegistry::sync_states]) to be used
+#[derive(Debug, Clone, Default)]
+pub struct PoolRegistry {
+    pub chain_id: u64,
+    pub v2_pools: Vec<UniswapV2Pool>,
+    pub v3_pools: Vec<UniswapV3Pool>,
+}
+
+/// One direction of a pool, used to price `base` in terms of `quote`
+#[derive(Debug, Clone)]
+pub struct PriceEdge {
+    pub pool: Address,
+    pub base: Address,
+    pub quote: Address,
+
+    /// The price of the base token in terms of the quote token
+    pub price: f64,
+
+    /// The (virtual for V3) reserve of the quote token in the pool, formatted
+    pub quote_reserve: f64,
+}
+
+impl PoolRegistry {
+    pub fn new(chain_id: u64) -> Self {
+        Self {
+            chain_id,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_v2_pool(&mut self, pool: UniswapV2Pool) {
+        self.v2_pools.push(pool);
+    }
+
+    pub fn add_v3_pool(&mut self, pool: UniswapV3Pool) {
+        self.v3_pools.push(pool);
+    }
+
+    /// All the tokens of the pools
+    pub fn tokens(&self) -> Vec<Address> {
+        let mut tokens: Vec<Address> = self
+            .v2_pools
+            .iter()
+            .flat_map(|p| [p.token0.address, p.token1.address])
+            .chain(
+                self.v3_pools
+                    .iter()
+                    .flat_map(|p| [p.token0.address, p.token1.address]),
+            )
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+        tokens
+    }
+
+    /// Fetch the state of every pool at the given block
+    ///
+    /// If block is None, the latest block is used
+    pub async fn sync_states<T, P, N>(
+        &mut self,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<(), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        for pool in &mut self.v2_pools {
+            let state = UniswapV2Pool::fetch_state(client.clone(), pool.address, block).await?;
+            pool.update_state(state);
+        }
+
+        for pool in &mut self.v3_pools {
+            let state = UniswapV3Pool::fetch_state(pool.address, client.clone(), block).await?;
+            pool.update_state(state);
+        }
+
+        Ok(())
+    }
+
+    /// (pool, token0, token1) of every pool
+    pub fn pairs(&self) -> Vec<(Address, Address, Address)> {
+        self.v2_pools
+            .iter()
+            .map(|p| (p.address, p.token0.address, p.token1.address))
+            .chain(
+                self.v3_pools
+                    .iter()
+                    .map(|p| (p.address, p.token0.address, p.token1.address)),
+            )
+            .collect()
+    }
+
+    /// Every pool as an [AnyPool]
+    pub fn pools(&self) -> Vec<AnyPool> {
+        self.v2_pools
+            .iter()
+            .cloned()
+            .map(AnyPool::from)
+            .chain(self.v3_pools.iter().cloned().map(AnyPool::from))
+            .collect()
+    }
+
+    /// Simulate a swap on one of the pools with its current state
+    pub fn simulate_swap(
+        &self,
+        pool: Address,
+        token_in: Address,
+        amount_in: U256,
+    ) -> Result<U256, anyhow::Error> {
+        if let Some(pool) = self.v2_pools.iter().find(|p| p.address == pool) {
+            return pool.simulate_swap(token_in, amount_in);
+        }
+
+        if let Some(pool) = self.v3_pools.iter().find(|p| p.address == pool) {
+            return pool.simulate_swap(token_in, amount_in);
+        }
+
+        Err(anyhow::anyhow!("Pool {} is not in the registry", pool))
+    }
+
+    /// Both directions of every pool that has a state
+    pub fn edges(&self) -> Vec<PriceEdge> {
+        let mut edges = Vec::new();
+
+        for pool in &self.v2_pools {
+            let Some(state) = pool.state() else {
+                continue;
+            };
+            let (Ok(reserve0), Ok(reserve1)) = (
+                to_f64(state.reserve0, pool.token0.decimals),
+                to_f64(state.reserve1, pool.token1.decimals),
+            ) else {
+                continue;
+            };
+
+            let (Ok(price0), Ok(price1)) = (
+                pool.calculate_price(pool.token0.address),
+                pool.calculate_price(pool.token1.address),
+            ) else {
+                continue;
+            };
+
+            edges.push(PriceEdge {
+                pool: pool.address,
+                base: pool.token0.address,
+                quote: pool.token1.address,
+                price: price0,
+                quote_reserve: reserve1,
+            });
+            edges.push(PriceEdge {
+                pool: pool.address,
+                base: pool.token1.address,
+                quote: pool.token0.address,
+                price: price1,
+                quote_reserve: reserve0,
+            });
+        }
+
+        for pool in &self.v3_pools {
+            let Some(state) = pool.state() else {
+                continue;
+            };
+
+            // virtual reserves x = L / sqrtP, y = L * sqrtP
+            let sqrt_price = sqrt_price_to_f64(state.sqrt_price);
+            if sqrt_price == 0.0 {
+                continue;
+            }
+            let liquidity = state.liquidity as f64;
+            let reserve0 = liquidity / sqrt_price / 10_f64.powi(pool.token0.decimals as i32);
+            let reserve1 = liquidity * sqrt_price / 10_f64.powi(pool.token1.decimals as i32);
+
+            let (Ok(price0), Ok(price1)) = (
+                pool.calculate_price(pool.token0.address),
+                pool.calculate_price(pool.token1.address),
+            ) else {
+                continue;
+            };
+
+            edges.push(PriceEdge {
+                pool: pool.address,
+                base: pool.token0.address,
+                quote: pool.token1.address,
+                price: price0,
+                quote_reserve: reserve1,
+            });
+            edges.push(PriceEdge {
+                pool: pool.address,
+                base: pool.token1.address,
+                quote: pool.token0.address,
+                price: price1,
+                quote_reserve: reserve0,
+            });
+        }
+
+        edges
+    }
+}
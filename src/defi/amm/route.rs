@@ -0,0 +1,204 @@
+//! Swaps through any number of pools
+//!
+//! A [SwapRoute] owns its pools, so unlike a [Route](super::compose::Route) it can be stored, sent across tasks
+//! and have as many hops as needed. V2, V3 and Solidly pools can be mixed in the same route
+//!
+//! ```ignore
+//! let route = SwapRoute::new(vec![weth_usdc_v3.into(), usdc_dai_v2.into()]);
+//! let quote = route.simulate_route(weth, amount_in)?;
+//! println!("{}", quote.pretty());
+//! ```
+
+use alloy_primitives::{Address, U256};
+
+use super::compose::{price_impact, Hop, RouteQuote};
+use super::pool::AnyPool;
+use crate::utils::format::to_f64;
+
+/// A list of pools to swap through in order
+#[derive(Debug, Clone, Default)]
+pub struct SwapRoute {
+    pub pools: Vec<AnyPool>,
+}
+
+impl SwapRoute {
+    pub fn new(pools: Vec<AnyPool>) -> Self {
+        Self { pools }
+    }
+
+    /// Append a pool to the end of the route
+    pub fn with_pool(mut self, pool: impl Into<AnyPool>) -> Self {
+        self.pools.push(pool.into());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// The tokens of the path starting from `token_in`, fails if a pool doesn't trade the output of the previous one
+    pub fn path(&self, token_in: Address) -> Result<Vec<Address>, anyhow::Error> {
+        let mut path = Vec::with_capacity(self.pools.len() + 1);
+        path.push(token_in);
+
+        for pool in &self.pools {
+            let current = path[path.len() - 1];
+            let (token0, token1) = pool.tokens();
+            let next = if token0.address == current {
+                token1.address
+            } else if token1.address == current {
+                token0.address
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Pool {} doesn't trade {}",
+                    pool.address(),
+                    current
+                ));
+            };
+            path.push(next);
+        }
+
+        Ok(path)
+    }
+
+    /// Simulate the swaps of the route with the local state of the pools, the output of each hop is the input of the next
+    ///
+    /// The route itself is not changed, but a pool that appears more than once is quoted with the state left by its previous swap
+    ///
+    /// ## Arguments
+    ///
+    /// * `token_in` - The token sold to the first pool
+    /// * `amount_in` - The amount of `token_in` to sell
+    pub fn simulate_route(&self, token_in: Address, amount_in: U256) -> Result<RouteQuote, anyhow::Error> {
+        self.clone().simulate_route_mut(token_in, amount_in)
+    }
+
+    /// Same as [Self::simulate_route] but the pools of the route are left with their state after the swaps
+    pub fn simulate_route_mut(
+        &mut self,
+        token_in: Address,
+        amount_in: U256,
+    ) -> Result<RouteQuote, anyhow::Error> {
+        if self.pools.is_empty() {
+            return Err(anyhow::anyhow!("The route has no pools"));
+        }
+
+        // fail before swapping if the path is broken
+        self.path(token_in)?;
+
+        let pools = &mut self.pools;
+        let mut hops = Vec::with_capacity(pools.len());
+        let mut current = token_in;
+        let mut amount = amount_in;
+        let mut mid_price = 1.0;
+
+        for i in 0..pools.len() {
+            // the other pools with the same address share the state of this one
+            let address = pools[i].address();
+            let pool = &mut pools[i];
+
+            let (token0, token1) = pool.tokens();
+            let (from, to) = if token0.address == current {
+                (token0.clone(), token1.clone())
+            } else {
+                (token1.clone(), token0.clone())
+            };
+
+            let hop_mid_price = pool.calculate_price(from.address)?;
+            let amount_out = pool.simulate_swap_mut(from.address, amount)?;
+            mid_price *= hop_mid_price;
+
+            let updated = pool.clone();
+            for (j, other) in pools.iter_mut().enumerate() {
+                if j != i && other.address() == address {
+                    *other = updated.clone();
+                }
+            }
+
+            hops.push(Hop {
+                pool: address,
+                token_in: from,
+                token_out: to.clone(),
+                amount_in: amount,
+                amount_out,
+                mid_price: hop_mid_price,
+            });
+            current = to.address;
+            amount = amount_out;
+        }
+
+        let formatted_in = to_f64(amount_in, hops[0].token_in.decimals)?;
+        let formatted_out = to_f64(amount, hops[hops.len() - 1].token_out.decimals)?;
+        let execution_price = if formatted_in == 0.0 {
+            0.0
+        } else {
+            formatted_out / formatted_in
+        };
+
+        Ok(RouteQuote {
+            hops,
+            amount_in,
+            amount_out: amount,
+            mid_price,
+            execution_price,
+            price_impact: price_impact(mid_price, execution_price),
+        })
+    }
+
+    /// Replace the pools with the same address as `pool`, eg. with the state left by a swap on another route
+    pub fn update_pool(&mut self, pool: &AnyPool) {
+        for existing in self.pools.iter_mut() {
+            if existing.address() == pool.address() {
+                *existing = pool.clone();
+            }
+        }
+    }
+
+    /// The combined price impact of the hops in percent, fees included
+    ///
+    /// Same as [RouteQuote::price_impact] times 100, for parity with [AnyPool::price_impact]
+    pub fn price_impact(&self, token_in: Address, amount_in: U256) -> Result<f64, anyhow::Error> {
+        Ok(self.simulate_route(token_in, amount_in)?.price_impact * 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defi::amm::test_utils::v2_pool;
+    use alloy_primitives::address;
+
+    #[test]
+    fn test_repeated_pool_uses_updated_state() {
+        let a = address!("0000000000000000000000000000000000000001");
+        let b = address!("0000000000000000000000000000000000000002");
+        let ab = v2_pool(address!("00000000000000000000000000000000000000ab"), a, b, (1_000_000, 1_000_000));
+
+        let route = SwapRoute::new(vec![ab.clone(), ab.clone(), ab.clone()]);
+        assert_eq!(route.path(a).unwrap(), vec![a, b, a, b]);
+
+        let amount_in = U256::from(10_000);
+        let quote = route.simulate_route(a, amount_in).unwrap();
+        assert_eq!(quote.hops.len(), 3);
+        assert_eq!(quote.hops[1].amount_in, quote.hops[0].amount_out);
+
+        // each hop sees the reserves left by the previous one, like a single pool swapped three times
+        let mut pool = ab.clone();
+        let out = pool.simulate_swap_mut(a, amount_in).unwrap();
+        let out = pool.simulate_swap_mut(b, out).unwrap();
+        let out = pool.simulate_swap_mut(a, out).unwrap();
+        assert_eq!(quote.amount_out, out);
+        assert!(quote.amount_out < amount_in);
+
+        // every copy of the pool is left with the state after the last swap
+        let mut moved = route.clone();
+        assert_eq!(moved.simulate_route_mut(a, amount_in).unwrap().amount_out, out);
+        for copy in &moved.pools {
+            assert_eq!(copy.simulate_swap(a, amount_in).unwrap(), pool.simulate_swap(a, amount_in).unwrap());
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! Find the best way to swap between two tokens through a set of pools
+//!
+//! The [Router] searches every path of at most [Router::max_hops] pools, quotes each of them as a [SwapRoute]
+//! with the local state of the pools and optionally splits the amount across up to [Router::max_splits] paths.
+//!
+//! The split is greedy: the amount is cut in [Router::split_parts] equal parts and each part goes to the path
+//! with the highest output given the swaps already made, so paths that share a pool see its moved price
+//!
+//! ```ignore
+//! let router = Router::new(pools).with_max_hops(2).with_max_splits(3);
+//! let quote = router.quote(weth, usdc, amount_in)?;
+//! println!("{}", quote.pretty());
+//! ```
+
+use alloy_primitives::{Address, U256};
+
+use std::collections::HashMap;
+
+use super::compose::{RouteQuote, MAX_HOPS};
+use super::pool::AnyPool;
+use super::route::SwapRoute;
+use crate::utils::format::to_f64;
+
+/// One of the routes of a [SplitQuote]
+#[derive(Debug, Clone)]
+pub struct Split {
+    /// The pools of the route in order
+    pub pools: Vec<Address>,
+    pub amount_in: U256,
+    pub amount_out: U256,
+}
+
+/// The result of [Router::quote]
+#[derive(Debug, Clone)]
+pub struct SplitQuote {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub amount_out: U256,
+
+    /// The routes the amount is split across, the largest input first
+    pub splits: Vec<Split>,
+}
+
+impl SplitQuote {
+    pub fn pretty(&self) -> String {
+        let mut lines = Vec::with_capacity(self.splits.len() + 1);
+        lines.push(format!(
+            "{} {} -> {} {} over {} routes",
+            self.amount_in,
+            self.token_in,
+            self.amount_out,
+            self.token_out,
+            self.splits.len()
+        ));
+
+        let total = to_f64(self.amount_in, 0).unwrap_or(0.0);
+        for split in &self.splits {
+            let share = if total == 0.0 {
+                0.0
+            } else {
+                to_f64(split.amount_in, 0).unwrap_or(0.0) / total * 100.0
+            };
+            let pools: Vec<String> = split.pools.iter().map(|p| p.to_string()).collect();
+            lines.push(format!(
+                "  {:.2}%: {} -> {} via {}",
+                share,
+                split.amount_in,
+                split.amount_out,
+                pools.join(" -> ")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Routes swaps through a set of pools, see the [module](self) docs
+#[derive(Debug, Clone)]
+pub struct Router {
+    pools: Vec<AnyPool>,
+
+    /// The maximum number of pools of a path, [MAX_HOPS] by default
+    pub max_hops: usize,
+
+    /// The maximum number of paths the amount can be split across, 1 (no split) by default
+    pub max_splits: usize,
+
+    /// The number of parts the amount is cut in when splitting
+    pub split_parts: usize,
+}
+
+impl Router {
+    /// A router over `pools`, the pools should have their state set
+    ///
+    /// A pool that appears twice (same address) is only kept once
+    pub fn new(pools: Vec<AnyPool>) -> Self {
+        let mut router = Self {
+            pools: Vec::with_capacity(pools.len()),
+            max_hops: MAX_HOPS,
+            max_splits: 1,
+            split_parts: 20,
+        };
+        for pool in pools {
+            router.add_pool(pool);
+        }
+        router
+    }
+
+    pub fn with_max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    pub fn with_max_splits(mut self, max_splits: usize) -> Self {
+        self.max_splits = max_splits;
+        self
+    }
+
+    pub fn with_split_parts(mut self, split_parts: usize) -> Self {
+        self.split_parts = split_parts;
+        self
+    }
+
+    /// Add a pool or replace the pool with the same address
+    pub fn add_pool(&mut self, pool: impl Into<AnyPool>) {
+        let pool = pool.into();
+        match self.pools.iter_mut().find(|p| p.address() == pool.address()) {
+            Some(existing) => *existing = pool,
+            None => self.pools.push(pool),
+        }
+    }
+
+    pub fn pools(&self) -> &[AnyPool] {
+        &self.pools
+    }
+
+    /// Every path from `token_in` to `token_out` of at most [Router::max_hops] pools
+    ///
+    /// A path doesn't use a pool twice nor goes through `token_in` or an intermediate token twice
+    pub fn paths(&self, token_in: Address, token_out: Address) -> Vec<SwapRoute> {
+        let mut edges: HashMap<Address, Vec<(usize, Address)>> = HashMap::new();
+        for (i, pool) in self.pools.iter().enumerate() {
+            let (token0, token1) = pool.tokens();
+            edges.entry(token0.address).or_default().push((i, token1.address));
+            edges.entry(token1.address).or_default().push((i, token0.address));
+        }
+
+        let mut paths = Vec::new();
+        let mut path = Vec::new();
+        let mut visited = vec![token_in];
+        self.search(&edges, token_in, token_out, &mut path, &mut visited, &mut paths);
+
+        paths
+            .into_iter()
+            .map(|path| SwapRoute::new(path.iter().map(|i| self.pools[*i].clone()).collect()))
+            .collect()
+    }
+
+    /// The single path with the highest output
+    ///
+    /// Paths that fail to simulate (eg. a pool without state) are skipped
+    pub fn best_route(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<RouteQuote, anyhow::Error> {
+        self.candidates(token_in, token_out, amount_in)
+            .into_iter()
+            .next()
+            .map(|(_, quote)| quote)
+            .ok_or_else(|| anyhow::anyhow!("No route from {} to {}", token_in, token_out))
+    }
+
+    /// The best way to swap `amount_in`, split across up to [Router::max_splits] paths
+    ///
+    /// ## Arguments
+    ///
+    /// * `token_in` - The token to sell
+    /// * `token_out` - The token to buy
+    /// * `amount_in` - The amount of `token_in` to sell
+    pub fn quote(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<SplitQuote, anyhow::Error> {
+        // the candidates are the paths with the best output for the whole amount
+        let candidates = self.candidates(token_in, token_out, amount_in);
+        let Some((best_route, best_quote)) = candidates.first() else {
+            return Err(anyhow::anyhow!("No route from {} to {}", token_in, token_out));
+        };
+
+        let single = SplitQuote {
+            token_in,
+            token_out,
+            amount_in,
+            amount_out: best_quote.amount_out,
+            splits: vec![Split {
+                pools: addresses(best_route),
+                amount_in,
+                amount_out: best_quote.amount_out,
+            }],
+        };
+
+        let max_splits = self.max_splits.min(candidates.len());
+        if max_splits <= 1 || self.split_parts <= 1 {
+            return Ok(single);
+        }
+
+        let routes: Vec<SwapRoute> = candidates
+            .into_iter()
+            .take(max_splits)
+            .map(|(route, _)| route)
+            .collect();
+        let split = self.split(routes, token_in, token_out, amount_in)?;
+
+        // rounding of the parts can make the split slightly worse than a single path
+        if split.amount_out > single.amount_out {
+            Ok(split)
+        } else {
+            Ok(single)
+        }
+    }
+
+    /// The paths that simulate with their quote for `amount_in`, the highest output first
+    fn candidates(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Vec<(SwapRoute, RouteQuote)> {
+        let mut candidates: Vec<(SwapRoute, RouteQuote)> = self
+            .paths(token_in, token_out)
+            .into_iter()
+            .filter_map(|route| {
+                let quote = route.simulate_route(token_in, amount_in).ok()?;
+                Some((route, quote))
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.amount_out.cmp(&a.1.amount_out));
+        candidates
+    }
+
+    /// Give each part of the amount to the route with the highest output on the pools moved by the previous parts
+    fn split(
+        &self,
+        mut routes: Vec<SwapRoute>,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<SplitQuote, anyhow::Error> {
+        let mut amounts = vec![(U256::ZERO, U256::ZERO); routes.len()];
+
+        let parts = U256::from(self.split_parts);
+        let part = amount_in / parts;
+        let remainder = amount_in - part * parts;
+
+        for i in 0..self.split_parts {
+            // the last part takes the remainder of the division
+            let amount = if i == self.split_parts - 1 {
+                part + remainder
+            } else {
+                part
+            };
+            if amount.is_zero() {
+                continue;
+            }
+
+            let mut best: Option<(usize, U256)> = None;
+            for (j, route) in routes.iter().enumerate() {
+                let Ok(quote) = route.simulate_route(token_in, amount) else {
+                    continue;
+                };
+                if best.map_or(true, |(_, best_out)| quote.amount_out > best_out) {
+                    best = Some((j, quote.amount_out));
+                }
+            }
+
+            let Some((j, _)) = best else {
+                return Err(anyhow::anyhow!("No route left for part {} of the split", i));
+            };
+            let out = routes[j].simulate_route_mut(token_in, amount)?.amount_out;
+            amounts[j].0 += amount;
+            amounts[j].1 += out;
+
+            // the other routes see the pools moved by this part
+            let moved = routes[j].pools.clone();
+            for (k, route) in routes.iter_mut().enumerate() {
+                if k != j {
+                    moved.iter().for_each(|pool| route.update_pool(pool));
+                }
+            }
+        }
+
+        let mut splits: Vec<Split> = routes
+            .iter()
+            .zip(amounts)
+            .filter(|(_, (amount_in, _))| !amount_in.is_zero())
+            .map(|(route, (amount_in, amount_out))| Split {
+                pools: addresses(route),
+                amount_in,
+                amount_out,
+            })
+            .collect();
+        splits.sort_by(|a, b| b.amount_in.cmp(&a.amount_in));
+
+        Ok(SplitQuote {
+            token_in,
+            token_out,
+            amount_in,
+            amount_out: splits.iter().map(|s| s.amount_out).sum(),
+            splits,
+        })
+    }
+
+    /// Depth first search of the paths as indices into `self.pools`
+    fn search(
+        &self,
+        edges: &HashMap<Address, Vec<(usize, Address)>>,
+        current: Address,
+        token_out: Address,
+        path: &mut Vec<usize>,
+        visited: &mut Vec<Address>,
+        paths: &mut Vec<Vec<usize>>,
+    ) {
+        if path.len() >= self.max_hops {
+            return;
+        }
+
+        for (pool, next) in edges.get(&current).into_iter().flatten() {
+            if path.contains(pool) {
+                continue;
+            }
+
+            if *next == token_out {
+                let mut found = path.clone();
+                found.push(*pool);
+                paths.push(found);
+                continue;
+            }
+
+            if visited.contains(next) {
+                continue;
+            }
+
+            path.push(*pool);
+            visited.push(*next);
+            self.search(edges, *next, token_out, path, visited, paths);
+            path.pop();
+            visited.pop();
+        }
+    }
+}
+
+fn addresses(route: &SwapRoute) -> Vec<Address> {
+    route.pools.iter().map(|pool| pool.address()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defi::amm::test_utils::v2_pool;
+    use alloy_primitives::address;
+
+    #[test]
+    fn test_router_finds_and_splits() {
+        let a = address!("0000000000000000000000000000000000000001");
+        let b = address!("0000000000000000000000000000000000000002");
+        let c = address!("0000000000000000000000000000000000000003");
+
+        // a direct pool and a two hop path through c with the same depth
+        let ab = v2_pool(address!("00000000000000000000000000000000000000ab"), a, b, (1_000_000, 1_000_000));
+        let ac = v2_pool(address!("00000000000000000000000000000000000000ac"), a, c, (10_000_000, 10_000_000));
+        let cb = v2_pool(address!("00000000000000000000000000000000000000cb"), c, b, (10_000_000, 10_000_000));
+
+        let router = Router::new(vec![ab.clone(), ac, cb]);
+        assert_eq!(router.paths(a, b).len(), 2);
+        assert_eq!(router.clone().with_max_hops(1).paths(a, b).len(), 1);
+
+        let amount_in = U256::from(500_000);
+        let best = router.best_route(a, b, amount_in).unwrap();
+        assert_eq!(best.hops.len(), 2);
+
+        let single = router.quote(a, b, amount_in).unwrap();
+        assert_eq!(single.splits.len(), 1);
+        assert_eq!(single.amount_out, best.amount_out);
+
+        let split = router.with_max_splits(2).quote(a, b, amount_in).unwrap();
+        assert_eq!(split.splits.len(), 2);
+        assert!(split.amount_out > single.amount_out);
+        assert_eq!(split.splits.iter().map(|s| s.amount_in).sum::<U256>(), amount_in);
+    }
+}
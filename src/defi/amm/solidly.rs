@@ -0,0 +1,476 @@
+//! Solidly pools (Velodrome V2 on Optimism, Aerodrome on Base)
+//!
+//! A volatile pool is a constant product pool like Uniswap V2, a stable pool follows the
+//! `x^3 * y + y^3 * x = k` curve which is much flatter around the 1:1 price.
+//! The fee is set per pool by the factory, usually 0.05% for stable pools and 0.3% for volatile ones
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, Log};
+use alloy_sol_types::SolEvent;
+use alloy_transport::Transport;
+
+use serde::{Deserialize, Serialize};
+
+use crate::abi::solidly::{ISolidlyPool, ISolidlyPoolFactory};
+use crate::defi::currency::erc20::{ERC20Token, TokenKind};
+use crate::defi::utils::chain_link::get_token_price;
+use crate::defi::utils::slippage;
+use crate::utils::{block_number, format::to_f64};
+use crate::ChainId;
+
+const E18: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+/// The fee denominator of the factory, fees are in basis points
+const FEE_DENOMINATOR: u64 = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolidlyPool {
+    pub chain_id: u64,
+    pub address: Address,
+    pub token0: ERC20Token,
+    pub token1: ERC20Token,
+
+    /// Whether the pool uses the stable curve
+    pub stable: bool,
+
+    /// The swap fee in basis points (5 = 0.05%)
+    pub fee_bps: u32,
+
+    #[serde(skip)]
+    state: Option<State>,
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+    pub reserve0: U256,
+    pub reserve1: U256,
+    pub block: u64,
+}
+
+impl SolidlyPool {
+    pub fn new(
+        chain_id: u64,
+        address: Address,
+        token0: ERC20Token,
+        token1: ERC20Token,
+        stable: bool,
+        fee_bps: u32,
+    ) -> Self {
+        // reorder tokens
+        let (token0, token1) = if token0.address < token1.address {
+            (token0, token1)
+        } else {
+            (token1, token0)
+        };
+
+        Self {
+            chain_id,
+            address,
+            token0,
+            token1,
+            stable,
+            fee_bps,
+            state: None,
+        }
+    }
+
+    /// Load a pool from its address, the tokens, the curve and the fee are read from the chain
+    ///
+    /// The state is not fetched, see [Self::fetch_state]
+    pub async fn from_address<T, P, N>(
+        client: P,
+        chain_id: u64,
+        address: Address,
+    ) -> Result<Self, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let pool = ISolidlyPool::new(address, client.clone());
+        let metadata = pool.metadata().call().await?;
+        let factory = pool.factory().call().await?._0;
+
+        let fee = ISolidlyPoolFactory::new(factory, client.clone())
+            .getFee(address, metadata.st)
+            .call()
+            .await?
+            ._0;
+
+        let token0 = ERC20Token::new(client.clone(), metadata.t0, chain_id, TokenKind::Other).await?;
+        let token1 = ERC20Token::new(client, metadata.t1, chain_id, TokenKind::Other).await?;
+
+        Ok(Self::new(chain_id, address, token0, token1, metadata.st, fee.to::<u32>()))
+    }
+
+    /// Switch token0 and token1
+    pub fn toggle_pair(&mut self) {
+        std::mem::swap(&mut self.token0, &mut self.token1);
+    }
+
+    /// Return a reference to the state of this pool
+    pub fn state(&self) -> Option<&State> {
+        self.state.as_ref()
+    }
+
+    /// Update the state for this pool
+    pub fn update_state(&mut self, state: State) {
+        self.state = Some(state);
+    }
+
+    /// Apply a `Sync` log of the pool to the state
+    ///
+    /// The pool emits `Sync` with the new reserves on every swap, mint and burn, the other logs are ignored
+    ///
+    /// Returns whether the state changed
+    pub fn apply_log(&mut self, log: &Log) -> Result<bool, anyhow::Error> {
+        if log.address() != self.address || log.topic0() != Some(&ISolidlyPool::Sync::SIGNATURE_HASH) {
+            return Ok(false);
+        }
+
+        let sync = log.log_decode::<ISolidlyPool::Sync>()?.inner.data;
+        let block = log
+            .block_number
+            .ok_or_else(|| anyhow::anyhow!("Block number is missing"))?;
+        if self.state.as_ref().is_some_and(|state| state.block > block) {
+            return Ok(false);
+        }
+
+        self.state = Some(State {
+            reserve0: sync.reserve0,
+            reserve1: sync.reserve1,
+            block,
+        });
+        Ok(true)
+    }
+
+    /// Fetch the state of the pool at a given block
+    /// If block is None, the latest block is used
+    ///
+    /// The state is tagged with the queried block number so [Self::apply_log] can order the logs after it
+    pub async fn fetch_state<T, P, N>(
+        client: P,
+        pool: Address,
+        block: Option<BlockId>,
+    ) -> Result<State, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let block = block_number(client.clone(), block).await?;
+        let contract = ISolidlyPool::new(pool, client);
+        let reserves = contract
+            .getReserves()
+            .call()
+            .block(BlockId::number(block))
+            .await?;
+
+        Ok(state_from_reserves(reserves, block))
+    }
+
+    pub fn simulate_swap(&self, token_in: Address, amount_in: U256) -> Result<U256, anyhow::Error> {
+        let state = self
+            .state
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+
+        self.get_amount_out(amount_in, token_in, state.reserve0, state.reserve1)
+    }
+
+    pub fn simulate_swap_mut(
+        &mut self,
+        token_in: Address,
+        amount_in: U256,
+    ) -> Result<U256, anyhow::Error> {
+        let mut state = self
+            .state
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+
+        let amount_out = self.get_amount_out(amount_in, token_in, state.reserve0, state.reserve1)?;
+
+        // the fee stays in the pool
+        if self.token0.address == token_in {
+            state.reserve0 += amount_in;
+            state.reserve1 -= amount_out;
+        } else {
+            state.reserve0 -= amount_out;
+            state.reserve1 += amount_in;
+        }
+        self.state = Some(state);
+
+        Ok(amount_out)
+    }
+
+    /// Same as `getAmountOut` of the pool
+    pub fn get_amount_out(
+        &self,
+        amount_in: U256,
+        token_in: Address,
+        reserve0: U256,
+        reserve1: U256,
+    ) -> Result<U256, anyhow::Error> {
+        if amount_in.is_zero() || reserve0.is_zero() || reserve1.is_zero() {
+            return Ok(U256::ZERO);
+        }
+
+        let amount_in = amount_in - amount_in * U256::from(self.fee_bps) / U256::from(FEE_DENOMINATOR);
+        let zero_for_one = token_in == self.token0.address;
+
+        if !self.stable {
+            let (reserve_in, reserve_out) = if zero_for_one {
+                (reserve0, reserve1)
+            } else {
+                (reserve1, reserve0)
+            };
+            return Ok(amount_in * reserve_out / (reserve_in + amount_in));
+        }
+
+        let decimals0 = U256::from(10).pow(U256::from(self.token0.decimals));
+        let decimals1 = U256::from(10).pow(U256::from(self.token1.decimals));
+
+        let xy = stable_k(reserve0, reserve1, decimals0, decimals1);
+        let reserve0 = reserve0 * E18 / decimals0;
+        let reserve1 = reserve1 * E18 / decimals1;
+
+        let (reserve_a, reserve_b, decimals_in, decimals_out) = if zero_for_one {
+            (reserve0, reserve1, decimals0, decimals1)
+        } else {
+            (reserve1, reserve0, decimals1, decimals0)
+        };
+
+        let amount_in = amount_in * E18 / decimals_in;
+        let y = reserve_b
+            .checked_sub(get_y(amount_in + reserve_a, xy, reserve_b)?)
+            .ok_or_else(|| anyhow::anyhow!("Output exceeds the reserve"))?;
+
+        Ok(y * decimals_out / E18)
+    }
+
+    /// Calculate the price of the base token in terms of the quote token
+    ///
+    /// For a stable pool this is the marginal price of the curve, not the ratio of the reserves
+    pub fn calculate_price(&self, base_token: Address) -> Result<f64, anyhow::Error> {
+        let state = self
+            .state
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+
+        let x = to_f64(state.reserve0, self.token0.decimals)?;
+        let y = to_f64(state.reserve1, self.token1.decimals)?;
+        if x == 0.0 || y == 0.0 {
+            return Err(anyhow::anyhow!("The pool has no liquidity"));
+        }
+
+        // price of token0 in token1 = -dy/dx along the curve
+        let price0 = if self.stable {
+            (3.0 * x * x * y + y * y * y) / (x * x * x + 3.0 * x * y * y)
+        } else {
+            y / x
+        };
+
+        if base_token == self.token0.address {
+            Ok(price0)
+        } else {
+            Ok(1.0 / price0)
+        }
+    }
+
+    /// The shortfall of the execution price of a swap from the mid price in percent, fees included
+    ///
+    /// See [price_impact](crate::defi::utils::slippage::price_impact)
+    pub fn price_impact(&self, token_in: Address, amount_in: U256) -> Result<f64, anyhow::Error> {
+        let amount_out = self.simulate_swap(token_in, amount_in)?;
+        let (decimals_in, decimals_out) = if token_in == self.token0.address {
+            (self.token0.decimals, self.token1.decimals)
+        } else {
+            (self.token1.decimals, self.token0.decimals)
+        };
+
+        Ok(slippage::price_impact(
+            self.calculate_price(token_in)?,
+            to_f64(amount_in, decimals_in)?,
+            to_f64(amount_out, decimals_out)?,
+        ))
+    }
+
+    /// Get the usd value of token0 and token1 at a given block
+    /// If block is None, the latest block is used
+    pub async fn tokens_usd<T, P, N>(
+        &self,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<(f64, f64), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let mut token0_usd =
+            get_token_price(client.clone(), block, self.chain_id, self.token0.address).await?;
+        let mut token1_usd = get_token_price(client, block, self.chain_id, self.token1.address).await?;
+
+        if token0_usd == 0.0 && token1_usd != 0.0 {
+            token0_usd = self.calculate_price(self.token0.address)? * token1_usd;
+        }
+        if token1_usd == 0.0 && token0_usd != 0.0 {
+            token1_usd = self.calculate_price(self.token1.address)? * token0_usd;
+        }
+
+        Ok((token0_usd, token1_usd))
+    }
+
+    /// Does pair support getting values in usd
+    ///
+    /// We check if at least one of the tokens is a stable coin or the wrapped native token
+    pub fn supports_usd(&self) -> Result<bool, anyhow::Error> {
+        let wrapped_native = ChainId::try_new(self.chain_id)?.wrapped_native();
+        let known = |token: &ERC20Token| token.is_stablecoin() || token.address == wrapped_native;
+        Ok(known(&self.token0) || known(&self.token1))
+    }
+}
+
+/// `_k` of a stable pool with the reserves in their own decimals
+fn stable_k(x: U256, y: U256, decimals0: U256, decimals1: U256) -> U256 {
+    let x = x * E18 / decimals0;
+    let y = y * E18 / decimals1;
+    f(x, y)
+}
+
+/// `x0^3 * y + y^3 * x0` in 18 decimals
+fn f(x0: U256, y: U256) -> U256 {
+    let a = x0 * y / E18;
+    let b = x0 * x0 / E18 + y * y / E18;
+    a * b / E18
+}
+
+/// The derivative of [f] with respect to `y`
+fn d(x0: U256, y: U256) -> U256 {
+    U256::from(3) * x0 * (y * y / E18) / E18 + x0 * x0 / E18 * x0 / E18
+}
+
+/// Solve `f(x0, y) = xy` for `y` with Newton's method, starting from `y`
+fn get_y(x0: U256, xy: U256, mut y: U256) -> Result<U256, anyhow::Error> {
+    for _ in 0..255 {
+        let k = f(x0, y);
+        let derivative = d(x0, y);
+        if derivative.is_zero() {
+            break;
+        }
+
+        if k < xy {
+            let mut dy = (xy - k) * E18 / derivative;
+            if dy.is_zero() {
+                if k == xy {
+                    return Ok(y);
+                }
+                if f(x0, y + U256::from(1)) > xy {
+                    return Ok(y + U256::from(1));
+                }
+                dy = U256::from(1);
+            }
+            y += dy;
+        } else {
+            let mut dy = (k - xy) * E18 / derivative;
+            if dy.is_zero() {
+                if k == xy || f(x0, y - U256::from(1)) < xy {
+                    return Ok(y);
+                }
+                dy = U256::from(1);
+            }
+            y -= dy;
+        }
+    }
+
+    Err(anyhow::anyhow!("The stable curve did not converge"))
+}
+
+/// The state of a pool from its `getReserves` at `block`
+fn state_from_reserves(reserves: ISolidlyPool::getReservesReturn, block: u64) -> State {
+    State {
+        reserve0: reserves._reserve0,
+        reserve1: reserves._reserve1,
+        block,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::address;
+
+    fn pool(stable: bool) -> SolidlyPool {
+        let token = |address, decimals| ERC20Token {
+            address,
+            decimals,
+            ..Default::default()
+        };
+        let mut pool = SolidlyPool::new(
+            10,
+            address!("0000000000000000000000000000000000000001"),
+            token(address!("0b2C639c533813f4Aa9D7837CAf62653d097Ff85"), 6),
+            token(address!("94b008aA00579c1307B0EF2c499aD98a8ce58e58"), 6),
+            stable,
+            if stable { 5 } else { 30 },
+        );
+        pool.update_state(State {
+            reserve0: U256::from(10_000_000_000_000u64),
+            reserve1: U256::from(10_000_000_000_000u64),
+            block: 1,
+        });
+        pool
+    }
+
+    #[test]
+    fn test_stable_curve_is_flatter() {
+        let token0 = pool(true).token0.address;
+        // 1M of a 10M/10M pool
+        let amount_in = U256::from(1_000_000_000_000u64);
+
+        let stable_out = pool(true).simulate_swap(token0, amount_in).unwrap();
+        let volatile_out = pool(false).simulate_swap(token0, amount_in).unwrap();
+
+        assert!(stable_out > volatile_out);
+        assert!(stable_out < amount_in);
+        // the stable pool loses little more than the fee on a 10% trade
+        assert!(stable_out > U256::from(998_000_000_000u64));
+        assert!((pool(true).calculate_price(token0).unwrap() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_fetched_state_then_logs() {
+        let mut pool = pool(true);
+        let reserves = ISolidlyPool::getReservesReturn {
+            _reserve0: U256::from(1000),
+            _reserve1: U256::from(2000),
+            _blockTimestampLast: U256::from(1_700_000_000),
+        };
+        let state = state_from_reserves(reserves, 100);
+        assert_eq!(state.block, 100);
+        pool.update_state(state);
+
+        let sync = |reserve: u64, block: Option<u64>| Log {
+            inner: alloy_primitives::Log {
+                address: pool.address,
+                data: ISolidlyPool::Sync {
+                    reserve0: U256::from(reserve),
+                    reserve1: U256::from(reserve),
+                }
+                .encode_log_data(),
+            },
+            block_number: block,
+            ..Default::default()
+        };
+
+        // a log before the fetched block is already in the state
+        assert!(!pool.apply_log(&sync(500, Some(99))).unwrap());
+        assert!(pool.apply_log(&sync(1500, Some(101))).unwrap());
+        let state = pool.state().unwrap();
+        assert_eq!((state.reserve0, state.block), (U256::from(1500), 101));
+
+        assert!(pool.apply_log(&sync(1600, None)).is_err());
+    }
+}
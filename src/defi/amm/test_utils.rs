@@ -0,0 +1,23 @@
+//! Fixtures shared by the tests of the AMM modules
+
+use alloy_primitives::{Address, U256};
+
+use super::pool::AnyPool;
+use super::uniswap::v2::{State, UniswapV2Pool};
+use crate::defi::currency::erc20::ERC20Token;
+
+/// A V2 pool of two 18 decimals tokens with the given reserves
+pub fn v2_pool(pool: Address, token0: Address, token1: Address, reserves: (u64, u64)) -> AnyPool {
+    let token = |address| ERC20Token {
+        address,
+        decimals: 18,
+        ..Default::default()
+    };
+    let mut pool = UniswapV2Pool::new(1, pool, token(token0), token(token1));
+    pool.update_state(State {
+        reserve0: U256::from(reserves.0),
+        reserve1: U256::from(reserves.1),
+        block: 1,
+    });
+    pool.into()
+}
@@ -0,0 +1,72 @@
+//! Addresses of the Uniswap contracts on each supported chain
+
+use alloy_primitives::{address, Address};
+
+/// The Uniswap contracts deployed on a chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UniswapDeployment {
+    pub chain_id: u64,
+    pub v2_factory: Address,
+    pub v3_factory: Address,
+
+    /// The NonfungiblePositionManager of V3
+    pub position_manager: Address,
+
+    /// SwapRouter02, routes both V2 and V3 swaps
+    pub swap_router: Address,
+    pub universal_router: Address,
+}
+
+pub const DEPLOYMENTS: [UniswapDeployment; 5] = [
+    UniswapDeployment {
+        chain_id: 1,
+        v2_factory: address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+        v3_factory: address!("1F98431c8aD98523631AE4a59f267346ea31F984"),
+        position_manager: address!("C36442b4a4522E871399CD717aBDD847Ab11FE88"),
+        swap_router: address!("68b3465833fb72A70ecDF485E0e4C7bD8665Fc45"),
+        universal_router: address!("3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD"),
+    },
+    UniswapDeployment {
+        chain_id: 10,
+        v2_factory: address!("0c3c1c532F1e39EdF36BE9Fe0bE1410313E074Bf"),
+        v3_factory: address!("1F98431c8aD98523631AE4a59f267346ea31F984"),
+        position_manager: address!("C36442b4a4522E871399CD717aBDD847Ab11FE88"),
+        swap_router: address!("68b3465833fb72A70ecDF485E0e4C7bD8665Fc45"),
+        universal_router: address!("Cb1355ff08Ab38bBCE60111F1bb2B784bE25D7e8"),
+    },
+    UniswapDeployment {
+        chain_id: 56,
+        v2_factory: address!("8909Dc15e40173Ff4699343b6eB8132c65e18eC6"),
+        v3_factory: address!("dB1d10011AD0Ff90774D0C6Bb92e5C5c8b4461F7"),
+        position_manager: address!("7b8A01B39D58278b5DE7e48c8449c9f4F5170613"),
+        swap_router: address!("B971eF87ede563556b2ED4b1C0b0019111Dd85d2"),
+        universal_router: address!("4Dae2f939ACf50408e13d58534Ff8c2776d45265"),
+    },
+    UniswapDeployment {
+        chain_id: 8453,
+        v2_factory: address!("8909Dc15e40173Ff4699343b6eB8132c65e18eC6"),
+        v3_factory: address!("33128a8fC17869897dcE68Ed026d694621f6FDfD"),
+        position_manager: address!("03a520b32C04BF3bEEf7BEb72E919cf822Ed34f1"),
+        swap_router: address!("2626664c2603336E57B271c5C0b26F421741e481"),
+        universal_router: address!("3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD"),
+    },
+    UniswapDeployment {
+        chain_id: 42161,
+        v2_factory: address!("f1D7CC64Fb4452F05c498126312eBE29f30Fbcf9"),
+        v3_factory: address!("1F98431c8aD98523631AE4a59f267346ea31F984"),
+        position_manager: address!("C36442b4a4522E871399CD717aBDD847Ab11FE88"),
+        swap_router: address!("68b3465833fb72A70ecDF485E0e4C7bD8665Fc45"),
+        universal_router: address!("5E325eDA8064b456f4781070C0738d849c824258"),
+    },
+];
+
+impl UniswapDeployment {
+    /// The deployment on the given chain
+    pub fn for_chain(chain_id: u64) -> Result<Self, anyhow::Error> {
+        DEPLOYMENTS
+            .iter()
+            .find(|d| d.chain_id == chain_id)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Unsupported chain id: {}", chain_id))
+    }
+}
@@ -1,3 +1,6 @@
 pub mod v2;
 pub mod v3;
-// pub mod router;
\ No newline at end of file
+pub mod router;
+pub mod deployments;
+pub mod replay;
+pub mod variant;
@@ -0,0 +1,251 @@
+//! Replay historical swaps through the off-chain swap math
+//!
+//! Every swap is simulated against the state of the pool right before it and the simulated output
+//! is compared to the output recorded in the swap log. This is a correctness check for the math
+//! after changes to state fetching or tick handling.
+//!
+//! After each swap the pool is moved to the recorded state, so one wrong swap doesn't make every
+//! following swap wrong as well. Mints and burns between the swaps are not replayed. For V3 only
+//! the active liquidity is corrected from the log, so keep the swaps to a short range of blocks.
+
+use alloy_primitives::{Address, U256};
+
+use crate::utils::logs::events::SwapData;
+
+use super::{v2::UniswapV2Pool, v3::UniswapV3Pool};
+
+/// The result of replaying one swap
+#[derive(Debug, Clone)]
+pub struct SwapCheck {
+    pub tx_hash: String,
+    pub block: u64,
+    pub token_in: Address,
+    pub amount_in: U256,
+
+    /// The output recorded in the swap log
+    pub expected_out: U256,
+
+    /// The output of the simulation, None if the simulation failed
+    pub simulated_out: Option<U256>,
+
+    /// The absolute difference between the simulated and the expected output in basis points of the expected output
+    pub error_bps: f64,
+
+    pub within_tolerance: bool,
+
+    /// Why the simulation failed
+    pub error: Option<String>,
+}
+
+/// The result of replaying a list of swaps, see [verify_v2_swaps] and [verify_v3_swaps]
+#[derive(Debug, Clone)]
+pub struct ReplayReport {
+    pub pool: Address,
+    pub tolerance_bps: f64,
+    pub checks: Vec<SwapCheck>,
+}
+
+impl ReplayReport {
+    /// Whether every swap is within the tolerance
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.within_tolerance)
+    }
+
+    /// The swaps that failed or are outside the tolerance
+    pub fn failed(&self) -> Vec<&SwapCheck> {
+        self.checks.iter().filter(|c| !c.within_tolerance).collect()
+    }
+
+    pub fn max_error_bps(&self) -> f64 {
+        self.checks
+            .iter()
+            .filter(|c| c.simulated_out.is_some())
+            .map(|c| c.error_bps)
+            .fold(0.0, f64::max)
+    }
+
+    pub fn mean_error_bps(&self) -> f64 {
+        let errors: Vec<f64> = self
+            .checks
+            .iter()
+            .filter(|c| c.simulated_out.is_some())
+            .map(|c| c.error_bps)
+            .collect();
+        if errors.is_empty() {
+            return 0.0;
+        }
+        errors.iter().sum::<f64>() / errors.len() as f64
+    }
+
+    pub fn pretty(&self) -> String {
+        let mut lines = vec![format!(
+            "Pool {}: {}/{} swaps within {} bps, max error {:.4} bps, mean error {:.4} bps",
+            self.pool,
+            self.checks.len() - self.failed().len(),
+            self.checks.len(),
+            self.tolerance_bps,
+            self.max_error_bps(),
+            self.mean_error_bps()
+        )];
+
+        for check in self.failed() {
+            let line = match (&check.simulated_out, &check.error) {
+                (Some(simulated), _) => format!(
+                    "  Block {} Tx {}: expected {} got {} ({:.4} bps)",
+                    check.block, check.tx_hash, check.expected_out, simulated, check.error_bps
+                ),
+                (None, error) => format!(
+                    "  Block {} Tx {}: simulation failed: {}",
+                    check.block,
+                    check.tx_hash,
+                    error.as_deref().unwrap_or("unknown error")
+                ),
+            };
+            lines.push(line);
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Replay swaps on a Uniswap V2 pool
+///
+/// ## Arguments
+///
+/// * `pool` - The pool with its state before the first swap
+/// * `swaps` - The decoded swaps of the pool, sorted by block
+/// * `tolerance_bps` - The allowed error of each swap in basis points
+pub fn verify_v2_swaps(
+    pool: &UniswapV2Pool,
+    swaps: &[SwapData],
+    tolerance_bps: f64,
+) -> Result<ReplayReport, anyhow::Error> {
+    let mut pool = pool.clone();
+    let mut checks = Vec::with_capacity(swaps.len());
+
+    for swap in swaps {
+        let token_in = swap.token_in.address;
+        let simulated = pool.simulate_swap(token_in, swap.amount_in);
+        checks.push(check_swap(swap, simulated, tolerance_bps));
+
+        // move the reserves by the recorded amounts
+        let mut state = pool
+            .state()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+        if token_in == pool.token0.address {
+            state.reserve0 += swap.amount_in;
+            state.reserve1 = state.reserve1.saturating_sub(swap.amount_out);
+        } else {
+            state.reserve1 += swap.amount_in;
+            state.reserve0 = state.reserve0.saturating_sub(swap.amount_out);
+        }
+        pool.update_state(state);
+    }
+
+    Ok(ReplayReport {
+        pool: pool.address,
+        tolerance_bps,
+        checks,
+    })
+}
+
+/// Replay swaps on a Uniswap V3 pool
+///
+/// The tick maps of the state must cover the ticks crossed by the swaps,
+/// see [UniswapV3Pool::fetch_state]
+///
+/// ## Arguments
+///
+/// * `pool` - The pool with its state before the first swap
+/// * `swaps` - The decoded swaps of the pool, sorted by block, see [UniswapV3Pool::decode_swap]
+/// * `tolerance_bps` - The allowed error of each swap in basis points
+pub fn verify_v3_swaps(
+    pool: &UniswapV3Pool,
+    swaps: &[SwapData],
+    tolerance_bps: f64,
+) -> Result<ReplayReport, anyhow::Error> {
+    let mut pool = pool.clone();
+    let mut checks = Vec::with_capacity(swaps.len());
+
+    for swap in swaps {
+        let simulated = pool.simulate_swap_mut(swap.token_in.address, swap.amount_in);
+        checks.push(check_swap(swap, simulated, tolerance_bps));
+
+        // the log has the price and liquidity after the swap, start the next swap from there
+        if let (Some(sqrt_price), Some(tick), Some(liquidity)) =
+            (swap.sqrt_price_x96, swap.tick, swap.liquidity)
+        {
+            let mut state = pool
+                .state()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+            state.sqrt_price = sqrt_price;
+            state.tick = tick;
+            state.liquidity = liquidity;
+            pool.update_state(state);
+        }
+    }
+
+    Ok(ReplayReport {
+        pool: pool.address,
+        tolerance_bps,
+        checks,
+    })
+}
+
+fn check_swap(
+    swap: &SwapData,
+    simulated: Result<U256, anyhow::Error>,
+    tolerance_bps: f64,
+) -> SwapCheck {
+    let (simulated_out, error_bps, error) = match simulated {
+        Ok(amount) => (Some(amount), error_bps(swap.amount_out, amount), None),
+        Err(e) => (None, f64::INFINITY, Some(e.to_string())),
+    };
+
+    SwapCheck {
+        tx_hash: swap.tx_hash.clone(),
+        block: swap.block,
+        token_in: swap.token_in.address,
+        amount_in: swap.amount_in,
+        expected_out: swap.amount_out,
+        simulated_out,
+        error_bps,
+        within_tolerance: error_bps <= tolerance_bps,
+        error,
+    }
+}
+
+/// The absolute difference between two amounts in basis points of `expected`
+pub fn error_bps(expected: U256, actual: U256) -> f64 {
+    if expected == actual {
+        return 0.0;
+    }
+    if expected.is_zero() {
+        return f64::INFINITY;
+    }
+
+    let diff = if actual > expected {
+        actual - expected
+    } else {
+        expected - actual
+    };
+
+    // scale first so the ratio keeps its precision for amounts beyond f64
+    let scaled = diff * U256::from(10_000u64) * U256::from(1_000_000u64) / expected;
+    scaled.to_string().parse::<f64>().unwrap_or(f64::INFINITY) / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_bps() {
+        assert_eq!(error_bps(U256::from(1000), U256::from(1000)), 0.0);
+        assert_eq!(error_bps(U256::from(10_000), U256::from(10_001)), 1.0);
+        assert_eq!(error_bps(U256::from(10_000), U256::from(9_990)), 10.0);
+        assert_eq!(error_bps(U256::ZERO, U256::from(1)), f64::INFINITY);
+    }
+}
@@ -4,15 +4,8 @@ use alloy_primitives::{Address, Bytes, bytes::BufMut, U256};
 use alloy_sol_types::{sol, SolCall, SolValue};
 
 
-use std::str::FromStr;
-
-// Deployed contract address
-
-/// Also on OP Base
-const ETH : &str = "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD";
-const OPTIMISM : &str = "0xCb1355ff08Ab38bBCE60111F1bb2B784bE25D7e8";
-const BSC : &str = "0x4Dae2f939ACf50408e13d58534Ff8c2776d45265";
-const ARBITRUM : &str = "0x5E325eDA8064b456f4781070C0738d849c824258";
+use super::deployments::DEPLOYMENTS;
+use crate::ChainId;
 
 
 sol! {
@@ -20,8 +13,7 @@ sol! {
     contract UniversalRouterContract {
         function execute(bytes calldata commands, bytes[] calldata inputs, uint256 deadline)
         external
-        payable
-        checkDeadline(deadline);
+        payable;
         function execute(bytes calldata commands, bytes[] calldata inputs) public payable;
     
 }
@@ -29,6 +21,7 @@ sol! {
 
 /// Universal Router Command Inputs
 #[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Input {
     /// 0x00
     V3_SWAP_EXACT_IN(
@@ -41,7 +34,25 @@ pub enum Input {
         // Minimum Received of token out
         U256,
 
-        // Encoded Token Path
+        // Encoded Token Path, see [encode_v3_path]
+        Bytes,
+
+        // Are funds coming through Permit2
+        bool
+    ),
+
+    /// 0x01
+    V3_SWAP_EXACT_OUT(
+        // Recipient
+        Address,
+
+        // Token out amount
+        U256,
+
+        // Maximum amount of token in to spend
+        U256,
+
+        // Encoded Token Path in reverse, see [encode_v3_path_exact_out]
         Bytes,
 
         // Are funds coming through Permit2
@@ -68,15 +79,36 @@ pub enum Input {
 }
 
 impl Input {
+    /// ## Arguments
+    ///
+    /// * `tokens` - The tokens in swap order, from token in to token out
+    /// * `fees` - The fee of the pool of each hop, one less than `tokens`
     pub fn swap_v3_exact_in(
         recipient: Address,
         token_in_amount: U256,
         min_received: U256,
-        token_path: Vec<Address>,
+        tokens: &[Address],
+        fees: &[u32],
         permit: bool
-    ) -> Self {
-        let encoded_path = encode_token_path(token_path);
-        Self::V3_SWAP_EXACT_IN(recipient, token_in_amount, min_received, encoded_path, permit)
+    ) -> Result<Self, anyhow::Error> {
+        let encoded_path = encode_v3_path(tokens, fees)?;
+        Ok(Self::V3_SWAP_EXACT_IN(recipient, token_in_amount, min_received, encoded_path, permit))
+    }
+
+    /// ## Arguments
+    ///
+    /// * `tokens` - The tokens in swap order, from token in to token out, they are reversed when encoded
+    /// * `fees` - The fee of the pool of each hop, one less than `tokens`
+    pub fn swap_v3_exact_out(
+        recipient: Address,
+        token_out_amount: U256,
+        max_spent: U256,
+        tokens: &[Address],
+        fees: &[u32],
+        permit: bool
+    ) -> Result<Self, anyhow::Error> {
+        let encoded_path = encode_v3_path_exact_out(tokens, fees)?;
+        Ok(Self::V3_SWAP_EXACT_OUT(recipient, token_out_amount, max_spent, encoded_path, permit))
     }
 
     pub fn swap_v2_exact_in(
@@ -89,39 +121,138 @@ impl Input {
         Self::V2_SWAP_EXACT_IN(recipient, token_in_amount, min_received, path, permit)
     }
 
+    /// The command byte of the input
+    pub fn command(&self) -> u8 {
+        match self {
+            Self::V3_SWAP_EXACT_IN(..) => 0x00,
+            Self::V3_SWAP_EXACT_OUT(..) => 0x01,
+            Self::V2_SWAP_EXACT_IN(..) => 0x08,
+        }
+    }
+
     /// ABI encode the command input
     pub fn encode(&self) -> Bytes {
-        let mut data = Vec::new();
-        match self {
-            Self::V3_SWAP_EXACT_IN(recipient, token_in_amount, min_received, encoded_path, permit) => {
-                data.extend_from_slice(&recipient.abi_encode());
-                data.extend_from_slice(&token_in_amount.abi_encode());
-                data.extend_from_slice(&min_received.abi_encode());
-                data.extend_from_slice(&encoded_path);
-                data.put_u8(*permit as u8);
+        let data = match self {
+            Self::V3_SWAP_EXACT_IN(recipient, amount, limit, path, permit)
+            | Self::V3_SWAP_EXACT_OUT(recipient, amount, limit, path, permit) => {
+                (*recipient, *amount, *limit, path.clone(), *permit).abi_encode_params()
             }
             Self::V2_SWAP_EXACT_IN(recipient, token_in_amount, min_received, path, permit) => {
-                data.extend_from_slice(&recipient.abi_encode());
-                data.extend_from_slice(&token_in_amount.abi_encode());
-                data.extend_from_slice(&min_received.abi_encode());
-                for address in path {
-                    data.extend_from_slice(&address.abi_encode());
-                }
-                data.put_u8(*permit as u8);
+                (*recipient, *token_in_amount, *min_received, path.clone(), *permit).abi_encode_params()
             }
-        }
+        };
         Bytes::from(data)
     }
+
+    /// Decode the input of a command, the reverse of [Input::encode]
+    pub fn decode(command: u8, data: &[u8]) -> Result<Self, anyhow::Error> {
+        // the upper bits are flags (eg. allow revert)
+        let input = match command & 0x3f {
+            0x00 => {
+                let (recipient, amount, limit, path, permit) =
+                    <(Address, U256, U256, Bytes, bool)>::abi_decode_params(data, true)?;
+                Self::V3_SWAP_EXACT_IN(recipient, amount, limit, path, permit)
+            }
+            0x01 => {
+                let (recipient, amount, limit, path, permit) =
+                    <(Address, U256, U256, Bytes, bool)>::abi_decode_params(data, true)?;
+                Self::V3_SWAP_EXACT_OUT(recipient, amount, limit, path, permit)
+            }
+            0x08 => {
+                let (recipient, amount, limit, path, permit) =
+                    <(Address, U256, U256, Vec<Address>, bool)>::abi_decode_params(data, true)?;
+                Self::V2_SWAP_EXACT_IN(recipient, amount, limit, path, permit)
+            }
+            _ => return Err(anyhow::anyhow!("Unsupported command: {:#04x}", command)),
+        };
+        Ok(input)
+    }
+
+    /// The tokens and fees of a V3 input in swap order, from token in to token out
+    pub fn v3_route(&self) -> Option<Result<(Vec<Address>, Vec<u32>), anyhow::Error>> {
+        match self {
+            Self::V3_SWAP_EXACT_IN(.., path, _) => Some(decode_v3_path(path)),
+            Self::V3_SWAP_EXACT_OUT(.., path, _) => Some(decode_v3_path_exact_out(path)),
+            Self::V2_SWAP_EXACT_IN(..) => None,
+        }
+    }
 }
 
+const ADDRESS_SIZE: usize = 20;
+const FEE_SIZE: usize = 3;
+
+/// Encode a V3 path `token0 | fee0 | token1 | fee1 | token2 ...` as used by exact input swaps
+///
+/// ## Arguments
+///
+/// * `tokens` - The tokens in swap order, from token in to token out
+/// * `fees` - The fee of the pool of each hop, one less than `tokens`
+pub fn encode_v3_path(tokens: &[Address], fees: &[u32]) -> Result<Bytes, anyhow::Error> {
+    if tokens.len() < 2 || fees.len() != tokens.len() - 1 {
+        return Err(anyhow::anyhow!(
+            "Invalid path: {} tokens and {} fees",
+            tokens.len(),
+            fees.len()
+        ));
+    }
 
-pub fn encode_token_path(path: Vec<Address>) -> Bytes {
-    let mut data = Vec::new();
-    for address in path {
-        let address: [u8; 20] = address.into(); 
-        data.extend_from_slice(&address);
+    let mut data = Vec::with_capacity(tokens.len() * ADDRESS_SIZE + fees.len() * FEE_SIZE);
+    for (i, token) in tokens.iter().enumerate() {
+        data.extend_from_slice(token.as_slice());
+        if let Some(fee) = fees.get(i) {
+            if *fee >= 1 << 24 {
+                return Err(anyhow::anyhow!("Fee {} doesn't fit in a uint24", fee));
+            }
+            data.put_uint(*fee as u64, FEE_SIZE);
+        }
     }
-    Bytes::from(data)
+    Ok(Bytes::from(data))
+}
+
+/// Encode a V3 path for an exact output swap
+///
+/// Exact output swaps are executed from the last hop backwards, so the path starts with the token out
+///
+/// ## Arguments
+///
+/// * `tokens` - The tokens in swap order, from token in to token out
+/// * `fees` - The fee of the pool of each hop, one less than `tokens`
+pub fn encode_v3_path_exact_out(tokens: &[Address], fees: &[u32]) -> Result<Bytes, anyhow::Error> {
+    let tokens: Vec<Address> = tokens.iter().rev().copied().collect();
+    let fees: Vec<u32> = fees.iter().rev().copied().collect();
+    encode_v3_path(&tokens, &fees)
+}
+
+/// Decode a V3 path into its tokens and fees, in the order they are encoded
+pub fn decode_v3_path(path: &[u8]) -> Result<(Vec<Address>, Vec<u32>), anyhow::Error> {
+    let hop = ADDRESS_SIZE + FEE_SIZE;
+    if path.len() < ADDRESS_SIZE + hop || (path.len() - ADDRESS_SIZE) % hop != 0 {
+        return Err(anyhow::anyhow!("Invalid path length: {}", path.len()));
+    }
+
+    let mut tokens = Vec::new();
+    let mut fees = Vec::new();
+    let mut offset = 0;
+    loop {
+        tokens.push(Address::from_slice(&path[offset..offset + ADDRESS_SIZE]));
+        offset += ADDRESS_SIZE;
+        if offset == path.len() {
+            break;
+        }
+        let fee = &path[offset..offset + FEE_SIZE];
+        fees.push(u32::from_be_bytes([0, fee[0], fee[1], fee[2]]));
+        offset += FEE_SIZE;
+    }
+
+    Ok((tokens, fees))
+}
+
+/// Decode the path of an exact output swap, the tokens and fees are returned in swap order
+pub fn decode_v3_path_exact_out(path: &[u8]) -> Result<(Vec<Address>, Vec<u32>), anyhow::Error> {
+    let (mut tokens, mut fees) = decode_v3_path(path)?;
+    tokens.reverse();
+    fees.reverse();
+    Ok((tokens, fees))
 }
 
 
@@ -134,37 +265,26 @@ pub struct UniversalRouter {
 
 impl UniversalRouter {
     pub fn new(chain_id: u64) -> Result<Self, anyhow::Error> {
-        let address = match chain_id {
-            1 => Address::from_str(ETH)?,
-            10 => Address::from_str(OPTIMISM)?,
-            56 => Address::from_str(BSC)?,
-            8453 => Address::from_str(ETH)?,
-            42161 => Address::from_str(ARBITRUM)?,
-            _ => return Err(anyhow::anyhow!("Unsupported chain id: {}", chain_id))
-        };
+        let address = ChainId::try_new(chain_id)?.universal_router();
         Ok(Self {
             chain_id,
             address
         })
     }
 
+    /// Is `address` a deployment of the Universal Router
+    ///
+    /// The Universal Router pulls the input tokens through [Permit2](crate::abi::permit2::PERMIT2)
+    pub fn is_universal_router(address: Address) -> bool {
+        DEPLOYMENTS.iter().any(|d| d.universal_router == address)
+    }
+
     /// Encode the execute function
     pub fn encode_execute(
         &self,
         inputs: Vec<Input>,
     ) -> Bytes {
-        let mut commands = Vec::new();
-
-        for input in &inputs {
-            match input {
-                Input::V3_SWAP_EXACT_IN(..) => {
-                    commands.push(0x00);
-                }
-                Input::V2_SWAP_EXACT_IN(..) => {
-                    commands.push(0x08);
-                }
-            }
-        }
+        let commands: Vec<u8> = inputs.iter().map(Input::command).collect();
 
         let contract = UniversalRouterContract::execute_1Call {
             commands: Bytes::from(commands),
@@ -174,4 +294,99 @@ impl UniversalRouter {
         Bytes::from(contract.abi_encode())
     }
 
+    /// Decode the calldata of either execute function into the supported inputs
+    ///
+    /// Fails on commands other than the [Input] variants
+    pub fn decode_execute(calldata: &[u8]) -> Result<Vec<Input>, anyhow::Error> {
+        let (commands, inputs) = match UniversalRouterContract::execute_0Call::abi_decode(calldata, true) {
+            Ok(call) => (call.commands, call.inputs),
+            Err(_) => {
+                let call = UniversalRouterContract::execute_1Call::abi_decode(calldata, true)?;
+                (call.commands, call.inputs)
+            }
+        };
+
+        if commands.len() != inputs.len() {
+            return Err(anyhow::anyhow!(
+                "{} commands but {} inputs",
+                commands.len(),
+                inputs.len()
+            ));
+        }
+
+        commands
+            .iter()
+            .zip(inputs.iter())
+            .map(|(command, input)| Input::decode(*command, input))
+            .collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::address;
+
+    const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+    const WBTC: Address = address!("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599");
+
+    #[test]
+    fn test_path_round_trip() {
+        let tokens = [WETH, USDC, WBTC];
+        let fees = [500, 3000];
+
+        let path = encode_v3_path(&tokens, &fees).unwrap();
+        assert_eq!(path.len(), 3 * 20 + 2 * 3);
+        assert_eq!(&path[..20], WETH.as_slice());
+        assert_eq!(&path[20..23], &[0x00, 0x01, 0xf4]);
+
+        let (decoded_tokens, decoded_fees) = decode_v3_path(&path).unwrap();
+        assert_eq!(decoded_tokens, tokens);
+        assert_eq!(decoded_fees, fees);
+    }
+
+    #[test]
+    fn test_exact_out_path_is_reversed() {
+        let tokens = [WETH, USDC, WBTC];
+        let fees = [500, 3000];
+
+        let path = encode_v3_path_exact_out(&tokens, &fees).unwrap();
+        assert_eq!(&path[..20], WBTC.as_slice());
+        assert_eq!(&path[path.len() - 20..], WETH.as_slice());
+        assert_eq!(path, encode_v3_path(&[WBTC, USDC, WETH], &[3000, 500]).unwrap());
+
+        let (decoded_tokens, decoded_fees) = decode_v3_path_exact_out(&path).unwrap();
+        assert_eq!(decoded_tokens, tokens);
+        assert_eq!(decoded_fees, fees);
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        assert!(encode_v3_path(&[WETH], &[]).is_err());
+        assert!(encode_v3_path(&[WETH, USDC], &[500, 3000]).is_err());
+        assert!(encode_v3_path(&[WETH, USDC], &[1 << 24]).is_err());
+        assert!(decode_v3_path(&[0u8; 20]).is_err());
+        assert!(decode_v3_path(&[0u8; 44]).is_err());
+    }
+
+    #[test]
+    fn test_execute_round_trip() {
+        let router = UniversalRouter::new(1).unwrap();
+        let inputs = vec![
+            Input::swap_v3_exact_in(WETH, U256::from(10), U256::from(9), &[WETH, USDC], &[500], true).unwrap(),
+            Input::swap_v3_exact_out(WETH, U256::from(10), U256::from(11), &[WETH, USDC, WBTC], &[500, 3000], false)
+                .unwrap(),
+            Input::swap_v2_exact_in(WETH, U256::from(10), U256::from(9), vec![USDC, WETH], false),
+        ];
+
+        let calldata = router.encode_execute(inputs.clone());
+        let decoded = UniversalRouter::decode_execute(&calldata).unwrap();
+        assert_eq!(decoded, inputs);
+
+        let (tokens, fees) = decoded[1].v3_route().unwrap().unwrap();
+        assert_eq!(tokens, [WETH, USDC, WBTC]);
+        assert_eq!(fees, [500, 3000]);
+    }
 }
\ No newline at end of file
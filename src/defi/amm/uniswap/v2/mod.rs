@@ -1,6 +1,7 @@
-use alloy_primitives::utils::parse_units;
-use alloy_primitives::{Address, U256};
-use alloy_rpc_types::BlockId;
+use alloy_primitives::utils::{format_units, parse_units};
+use alloy_primitives::{Address, I256, U256};
+use alloy_rpc_types::{BlockId, Log};
+use alloy_sol_types::SolEvent;
 
 use alloy_contract::private::Network;
 use alloy_provider::Provider;
@@ -8,11 +9,18 @@ use alloy_transport::Transport;
 
 use serde::{Deserialize, Serialize};
 
-use crate::abi::uniswap::pool::v2;
+use crate::abi::uniswap::pool::v2::{self, IUniswapV2Pair};
 use crate::defi::currency::erc20::ERC20Token;
 use crate::defi::utils::chain_link::get_token_price;
+use crate::defi::utils::slippage;
+use crate::utils::block_number;
+use crate::utils::format::to_f64;
+use crate::utils::logs::events::SwapData;
+use crate::utils::storage::{extract_bits, get_storage_batch};
 
 use super::super::consts::*;
+use super::v3::{volume_from_swaps, PoolVolume};
+use super::variant::DexVariant;
 use crate::defi::utils::common_addr::*;
 
 /// Represents a Uniswap V2 Pool
@@ -22,8 +30,29 @@ pub struct UniswapV2Pool {
     pub address: Address,
     pub token0: ERC20Token,
     pub token1: ERC20Token,
+
+    /// The DEX that deployed the pair
+    #[serde(default)]
+    pub variant: DexVariant,
+
+    /// The swap fee in basis points, 30 for Uniswap
+    #[serde(default = "default_fee_bps")]
+    pub fee_bps: u32,
+
     #[serde(skip)]
     state: Option<State>,
+
+    /// The last log applied by [UniswapV2Pool::update_from_log]
+    #[serde(skip)]
+    last_log: Option<AppliedLog>,
+}
+
+/// The position of a log applied to the state
+#[derive(Debug, Clone, Copy)]
+struct AppliedLog {
+    block: u64,
+    log_index: u64,
+    is_sync: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -31,10 +60,86 @@ pub struct UniswapV2Pool {
 pub struct State {
     pub reserve0: U256,
     pub reserve1: U256,
+
+    /// The block the state was fetched at or the block of the last applied log
     pub block: u64,
 }
 
+/// The changes between two [State]s of a pool, see [State::diff]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDiff {
+    pub reserve0_before: U256,
+    pub reserve0_after: U256,
+    pub reserve1_before: U256,
+    pub reserve1_after: U256,
+    pub block_before: u64,
+    pub block_after: u64,
+}
+
+impl State {
+    /// Compare this state (before) with `other` (after)
+    ///
+    /// Useful to find where a simulated state diverges from the state fetched on-chain
+    pub fn diff(&self, other: &State) -> StateDiff {
+        StateDiff {
+            reserve0_before: self.reserve0,
+            reserve0_after: other.reserve0,
+            reserve1_before: self.reserve1,
+            reserve1_after: other.reserve1,
+            block_before: self.block,
+            block_after: other.block,
+        }
+    }
+}
+
+impl StateDiff {
+    /// Whether both reserves are the same, the block is ignored
+    pub fn is_empty(&self) -> bool {
+        self.reserve0_before == self.reserve0_after && self.reserve1_before == self.reserve1_after
+    }
+
+    pub fn reserve0_delta(&self) -> I256 {
+        I256::from_raw(self.reserve0_after) - I256::from_raw(self.reserve0_before)
+    }
+
+    pub fn reserve1_delta(&self) -> I256 {
+        I256::from_raw(self.reserve1_after) - I256::from_raw(self.reserve1_before)
+    }
+
+    /// A human-readable rendering of the changes, only the reserves that changed are shown
+    pub fn pretty(&self, token0: &ERC20Token, token1: &ERC20Token) -> Result<String, anyhow::Error> {
+        if self.is_empty() {
+            return Ok("No changes".to_string());
+        }
+
+        let mut lines = Vec::new();
+        for (token, before, after, delta) in [
+            (token0, self.reserve0_before, self.reserve0_after, self.reserve0_delta()),
+            (token1, self.reserve1_before, self.reserve1_after, self.reserve1_delta()),
+        ] {
+            if before == after {
+                continue;
+            }
+            lines.push(format!(
+                "Reserve {}: {} -> {} ({}{})",
+                token.symbol,
+                format_units(before, token.decimals)?,
+                format_units(after, token.decimals)?,
+                if delta.is_negative() { "-" } else { "+" },
+                format_units(delta.unsigned_abs(), token.decimals)?,
+            ));
+        }
+
+        Ok(lines.join("\n"))
+    }
+}
+
+fn default_fee_bps() -> u32 {
+    DexVariant::Uniswap.v2_fee_bps()
+}
+
 impl UniswapV2Pool {
+    /// Create a Uniswap pair, see [Self::with_variant] for the forks
     pub fn new(chain_id: u64, address: Address, token0: ERC20Token, token1: ERC20Token) -> Self {
         // reorder tokens
         let (token0, token1) = if token0.address < token1.address {
@@ -48,10 +153,31 @@ impl UniswapV2Pool {
             address,
             token0,
             token1,
+            variant: DexVariant::Uniswap,
+            fee_bps: default_fee_bps(),
             state: None,
+            last_log: None,
         }
     }
 
+    /// Set the DEX of the pair and its default fee
+    pub fn with_variant(mut self, variant: DexVariant) -> Self {
+        self.variant = variant;
+        self.fee_bps = variant.v2_fee_bps();
+        self
+    }
+
+    /// Override the swap fee, eg. for a Camelot pair with a custom fee
+    pub fn with_fee_bps(mut self, fee_bps: u32) -> Self {
+        self.fee_bps = fee_bps;
+        self
+    }
+
+    /// Do both tokens of the pool track the same asset, see [is_stable_pair](crate::defi::currency::erc20::is_stable_pair)
+    pub fn is_stable_pair(&self) -> bool {
+        crate::defi::currency::erc20::is_stable_pair(&self.token0, &self.token1)
+    }
+
     /// Switch token0 and token1
     pub fn toggle_pair(&mut self) {
         std::mem::swap(&mut self.token0, &mut self.token1);
@@ -65,10 +191,109 @@ impl UniswapV2Pool {
     /// Update the state for this pool
     pub fn update_state(&mut self, state: State) {
         self.state = Some(state);
+        self.last_log = None;
+    }
+
+    /// Apply a `Sync`, `Swap`, `Mint` or `Burn` log of the pool to the state
+    ///
+    /// Keeps the state up to date from a log subscription without calling `getReserves`.
+    /// `Sync` sets the reserves, the other events move them by their amounts.
+    /// The pair emits `Sync` right before `Swap`, `Mint` and `Burn`, so when both are received
+    /// only the `Sync` is applied
+    ///
+    /// Logs must be applied in order, logs at or before the last applied one are ignored.
+    /// A fetched state already includes the logs of its block, so they are ignored as well
+    ///
+    /// Returns whether the state changed
+    pub fn update_from_log(&mut self, log: &Log) -> Result<bool, anyhow::Error> {
+        if log.address() != self.address {
+            return Ok(false);
+        }
+
+        let block = log
+            .block_number
+            .ok_or_else(|| anyhow::anyhow!("Block number is missing"))?;
+        let log_index = log
+            .log_index
+            .ok_or_else(|| anyhow::anyhow!("Log index is missing"))?;
+
+        match self.last_log {
+            Some(last) if (block, log_index) <= (last.block, last.log_index) => return Ok(false),
+            None if self.state.as_ref().is_some_and(|state| block <= state.block) => return Ok(false),
+            _ => {}
+        }
+        let follows_sync = self
+            .last_log
+            .is_some_and(|last| last.is_sync && last.block == block && last.log_index + 1 == log_index);
+
+        let mut state = self
+            .state
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+
+        let is_sync = match log.topic0() {
+            Some(&IUniswapV2Pair::Sync::SIGNATURE_HASH) => {
+                let sync = log.log_decode::<IUniswapV2Pair::Sync>()?.inner.data;
+                state.reserve0 = U256::from(sync.reserve0);
+                state.reserve1 = U256::from(sync.reserve1);
+                true
+            }
+            Some(&IUniswapV2Pair::Swap::SIGNATURE_HASH) => {
+                let swap = log.log_decode::<IUniswapV2Pair::Swap>()?.inner.data;
+                if !follows_sync {
+                    state.reserve0 = (state.reserve0 + swap.amount0In)
+                        .checked_sub(swap.amount0Out)
+                        .ok_or_else(|| anyhow::anyhow!("Reserve0 underflow"))?;
+                    state.reserve1 = (state.reserve1 + swap.amount1In)
+                        .checked_sub(swap.amount1Out)
+                        .ok_or_else(|| anyhow::anyhow!("Reserve1 underflow"))?;
+                }
+                false
+            }
+            Some(&IUniswapV2Pair::Mint::SIGNATURE_HASH) => {
+                let mint = log.log_decode::<IUniswapV2Pair::Mint>()?.inner.data;
+                if !follows_sync {
+                    state.reserve0 += mint.amount0;
+                    state.reserve1 += mint.amount1;
+                }
+                false
+            }
+            Some(&IUniswapV2Pair::Burn::SIGNATURE_HASH) => {
+                let burn = log.log_decode::<IUniswapV2Pair::Burn>()?.inner.data;
+                if !follows_sync {
+                    state.reserve0 = state
+                        .reserve0
+                        .checked_sub(burn.amount0)
+                        .ok_or_else(|| anyhow::anyhow!("Reserve0 underflow"))?;
+                    state.reserve1 = state
+                        .reserve1
+                        .checked_sub(burn.amount1)
+                        .ok_or_else(|| anyhow::anyhow!("Reserve1 underflow"))?;
+                }
+                false
+            }
+            _ => return Ok(false),
+        };
+
+        self.last_log = Some(AppliedLog {
+            block,
+            log_index,
+            is_sync,
+        });
+
+        if !is_sync && follows_sync {
+            return Ok(false);
+        }
+
+        state.block = block;
+        self.state = Some(state);
+        Ok(true)
     }
 
     /// Fetch the state of the pool at a given block
     /// If block is None, the latest block is used
+    ///
+    /// The state is tagged with the queried block number so [Self::update_from_log] can order the logs after it
     pub async fn fetch_state<T, P, N>(
         client: P,
         pool: Address,
@@ -79,14 +304,38 @@ impl UniswapV2Pool {
         P: Provider<T, N> + Clone,
         N: Network,
     {
-        let reserves = v2::get_reserves(pool, client, block).await?;
+        let block = block_number(client.clone(), block).await?;
+        let reserves = v2::get_reserves(pool, client, Some(BlockId::number(block))).await?;
         let reserve0 = U256::from(reserves.0);
         let reserve1 = U256::from(reserves.1);
 
         Ok(State {
             reserve0,
             reserve1,
-            block: reserves.2 as u64,
+            block,
+        })
+    }
+
+    /// Same as [Self::fetch_state] but reads the reserves directly with `eth_getStorageAt`
+    pub async fn fetch_state_from_storage<T, P, N>(
+        client: P,
+        pool: Address,
+        block: Option<BlockId>,
+    ) -> Result<State, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let block = block_number(client.clone(), block).await?;
+        let values = get_storage_batch(client, &[(pool, v2::RESERVES_SLOT)], Some(BlockId::number(block))).await?;
+        let reserves = values[0];
+
+        // reserve0 (uint112) | reserve1 (uint112) | blockTimestampLast (uint32)
+        Ok(State {
+            reserve0: extract_bits(reserves, 0, 112),
+            reserve1: extract_bits(reserves, 112, 112),
+            block,
         })
     }
 
@@ -133,14 +382,16 @@ impl UniswapV2Pool {
     }
 
     /// Calculates the amount received for a given `amount_in` `reserve_in` and `reserve_out`.
+    ///
+    /// The fee is [Self::fee_bps], eg. 30 => 9970 / 10000 of the input is swapped
     pub fn get_amount_out(&self, amount_in: U256, reserve_in: U256, reserve_out: U256) -> U256 {
         if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
             return U256::ZERO;
         }
-        let fee = (10000 - (300 / 10)) / 10; //Fee of 300 => (10,000 - 30) / 10  = 997
+        let fee = 10000 - self.fee_bps.min(10000);
         let amount_in_with_fee = amount_in * U256::from(fee);
         let numerator = amount_in_with_fee * reserve_out;
-        let denominator = reserve_in * U256::from(1000) + amount_in_with_fee;
+        let denominator = reserve_in * U256::from(10000) + amount_in_with_fee;
 
         numerator / denominator
     }
@@ -182,6 +433,30 @@ impl UniswapV2Pool {
         }
     }
 
+    /// Calculate the price of the base token in terms of the quote token
+    pub fn calculate_price(&self, base_token: Address) -> Result<f64, anyhow::Error> {
+        let price = self.calculate_price_64_x_64(base_token)?;
+        Ok(price as f64 / U128_0X10000000000000000 as f64)
+    }
+
+    /// The shortfall of the execution price of a swap from the mid price in percent, fees included
+    ///
+    /// See [price_impact](crate::defi::utils::slippage::price_impact)
+    pub fn price_impact(&self, token_in: Address, amount_in: U256) -> Result<f64, anyhow::Error> {
+        let amount_out = self.simulate_swap(token_in, amount_in)?;
+        let (decimals_in, decimals_out) = if token_in == self.token0.address {
+            (self.token0.decimals, self.token1.decimals)
+        } else {
+            (self.token1.decimals, self.token0.decimals)
+        };
+
+        Ok(slippage::price_impact(
+            self.calculate_price(token_in)?,
+            to_f64(amount_in, decimals_in)?,
+            to_f64(amount_out, decimals_out)?,
+        ))
+    }
+
     /// Get the usd value of token0 and token1 at a given block
     /// If block is None, the latest block is used
     pub async fn tokens_usd<T, P, N>(
@@ -226,6 +501,25 @@ impl UniswapV2Pool {
         Ok((token0_usd, token1_usd))
     }
 
+    /// The USD value of the reserves at a given block, priced like [Self::tokens_usd]
+    /// If block is None, the latest block is used
+    pub async fn tvl_usd<T, P, N>(&self, client: P, block: Option<BlockId>) -> Result<f64, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        // price from the reserves of the same block
+        let mut pool = self.clone();
+        pool.update_state(Self::fetch_state(client.clone(), self.address, block).await?);
+        let (token0_usd, token1_usd) = pool.tokens_usd(client, block).await?;
+
+        let state = pool.state().ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+        let reserve0 = to_f64(state.reserve0, self.token0.decimals)?;
+        let reserve1 = to_f64(state.reserve1, self.token1.decimals)?;
+        Ok(reserve0 * token0_usd + reserve1 * token1_usd)
+    }
+
     /// Does pair support getting values in usd
     ///
     /// We check if at least one of the tokens is a stable coin or WETH
@@ -241,6 +535,71 @@ impl UniswapV2Pool {
 
         Ok(b)
     }
+
+    /// Get the volume of the pool, like [UniswapV3Pool::get_volume_from_logs](super::v3::UniswapV3Pool::get_volume_from_logs)
+    pub fn get_volume_from_logs(&self, logs: Vec<Log>) -> Result<PoolVolume, anyhow::Error> {
+        let swaps = logs
+            .iter()
+            .map(|log| self.decode_swap(log))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(volume_from_swaps(swaps, self.token0.address, self.token1.address))
+    }
+
+    /// Decode a swap log against this pool
+    ///
+    /// The pair allows both tokens in and out of the same swap (flash swaps), the amounts are netted
+    /// so the token with more in than out is the token in
+    pub fn decode_swap(&self, log: &Log) -> Result<SwapData, anyhow::Error> {
+        let IUniswapV2Pair::Swap {
+            sender,
+            amount0In: amount0_in,
+            amount1In: amount1_in,
+            amount0Out: amount0_out,
+            amount1Out: amount1_out,
+            to,
+        } = log.log_decode()?.inner.data;
+
+        if log.address() != self.address {
+            return Err(anyhow::anyhow!("Pool Address mismatch"));
+        }
+
+        let block = log
+            .block_number
+            .ok_or_else(|| anyhow::anyhow!("Block number is missing"))?;
+        let tx_hash = log
+            .transaction_hash
+            .ok_or_else(|| anyhow::anyhow!("Transaction hash is missing"))?;
+
+        let (token_in, token_out, amount_in, amount_out) = if amount0_in > amount0_out {
+            (
+                self.token0.clone(),
+                self.token1.clone(),
+                amount0_in - amount0_out,
+                amount1_out.saturating_sub(amount1_in),
+            )
+        } else {
+            (
+                self.token1.clone(),
+                self.token0.clone(),
+                amount1_in.saturating_sub(amount1_out),
+                amount0_out - amount0_in,
+            )
+        };
+
+        let mut swap = SwapData::new(
+            Some(to),
+            token_in,
+            token_out,
+            amount_in,
+            amount_out,
+            block,
+            tx_hash.to_string(),
+        );
+        swap.sender = Some(sender);
+        swap.recipient = Some(to);
+        Ok(swap)
+    }
 }
 
 pub fn div_uu(x: U256, y: U256) -> Result<u128, anyhow::Error> {
@@ -323,3 +682,147 @@ pub fn div_uu(x: U256, y: U256) -> Result<u128, anyhow::Error> {
         Err(anyhow::anyhow!("Y is zero"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, aliases::U112};
+
+    fn log_of<E: SolEvent>(pool: Address, event: E, block: u64, log_index: u64) -> Log {
+        Log {
+            inner: alloy_primitives::Log {
+                address: pool,
+                data: event.encode_log_data(),
+            },
+            block_number: Some(block),
+            log_index: Some(log_index),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_update_from_log() {
+        let pool_address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+        let token = |address| ERC20Token {
+            address,
+            ..Default::default()
+        };
+        let mut pool = UniswapV2Pool::new(
+            1,
+            pool_address,
+            token(address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")),
+            token(address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")),
+        );
+        pool.update_state(State {
+            reserve0: U256::from(1000),
+            reserve1: U256::from(1000),
+            block: 1,
+        });
+
+        let swap = IUniswapV2Pair::Swap {
+            sender: Address::ZERO,
+            amount0In: U256::from(100),
+            amount1In: U256::ZERO,
+            amount0Out: U256::ZERO,
+            amount1Out: U256::from(90),
+            to: Address::ZERO,
+        };
+
+        // the fetched state already includes the logs of its block
+        assert!(!pool.update_from_log(&log_of(pool_address, swap.clone(), 1, 3)).unwrap());
+        assert_eq!(pool.state().unwrap().reserve0, U256::from(1000));
+
+        // a Swap alone moves the reserves
+        assert!(pool.update_from_log(&log_of(pool_address, swap.clone(), 2, 0)).unwrap());
+        let state = pool.state().unwrap();
+        assert_eq!((state.reserve0, state.reserve1), (U256::from(1100), U256::from(910)));
+
+        // the Sync before a Swap already has the reserves after it
+        let sync = IUniswapV2Pair::Sync {
+            reserve0: U112::from(1200),
+            reserve1: U112::from(828),
+        };
+        assert!(pool.update_from_log(&log_of(pool_address, sync, 3, 4)).unwrap());
+        assert!(!pool.update_from_log(&log_of(pool_address, swap.clone(), 3, 5)).unwrap());
+        let state = pool.state().unwrap();
+        assert_eq!((state.reserve0, state.reserve1), (U256::from(1200), U256::from(828)));
+
+        // already applied
+        assert!(!pool.update_from_log(&log_of(pool_address, swap, 2, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_decode_swap() {
+        let pool_address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+        let token = |address| ERC20Token {
+            address,
+            ..Default::default()
+        };
+        let (token0, token1) = (
+            address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+            address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"),
+        );
+        let pool = UniswapV2Pool::new(1, pool_address, token(token0), token(token1));
+        let trader = Address::repeat_byte(0xa);
+
+        let swap = |amounts: [u64; 4], block| {
+            let mut log = log_of(
+                pool_address,
+                IUniswapV2Pair::Swap {
+                    sender: Address::ZERO,
+                    amount0In: U256::from(amounts[0]),
+                    amount1In: U256::from(amounts[1]),
+                    amount0Out: U256::from(amounts[2]),
+                    amount1Out: U256::from(amounts[3]),
+                    to: trader,
+                },
+                block,
+                0,
+            );
+            log.transaction_hash = Some(Default::default());
+            log
+        };
+
+        let sell = pool.decode_swap(&swap([100, 0, 0, 90], 2)).unwrap();
+        assert_eq!(
+            (sell.token_in.address, sell.amount_in, sell.amount_out),
+            (token0, U256::from(100), U256::from(90))
+        );
+        assert_eq!(sell.account, Some(trader));
+
+        // a flash swap paying back more token1 than it took
+        let buy = pool.decode_swap(&swap([0, 50, 40, 10], 1)).unwrap();
+        assert_eq!(
+            (buy.token_in.address, buy.amount_in, buy.amount_out),
+            (token1, U256::from(40), U256::from(40))
+        );
+
+        let volume = pool
+            .get_volume_from_logs(vec![swap([100, 0, 0, 90], 2), swap([0, 50, 40, 10], 1)])
+            .unwrap();
+        assert_eq!((volume.buys, volume.sells), (1, 1));
+        assert_eq!((volume.buy_volume, volume.sell_volume), (U256::from(40), U256::from(40)));
+        assert_eq!(volume.swaps[0].block, 1);
+
+        // not a Swap
+        let sync = IUniswapV2Pair::Sync {
+            reserve0: U112::from(1),
+            reserve1: U112::from(1),
+        };
+        assert!(pool.decode_swap(&log_of(pool_address, sync, 1, 0)).is_err());
+    }
+
+    #[test]
+    fn test_fee_of_variant() {
+        let pool = UniswapV2Pool::new(56, Address::ZERO, ERC20Token::default(), ERC20Token::default());
+        let (amount_in, reserve) = (U256::from(1_000_000), U256::from(1_000_000_000));
+
+        // getAmountOut of the Uniswap and PancakeSwap routers
+        let uniswap = amount_in * U256::from(997) * reserve / (reserve * U256::from(1000) + amount_in * U256::from(997));
+        let pancake = amount_in * U256::from(9975) * reserve / (reserve * U256::from(10000) + amount_in * U256::from(9975));
+
+        assert_eq!(pool.get_amount_out(amount_in, reserve, reserve), uniswap);
+        let pool = pool.with_variant(DexVariant::PancakeSwap);
+        assert_eq!(pool.get_amount_out(amount_in, reserve, reserve), pancake);
+    }
+}
@@ -0,0 +1,131 @@
+use alloy_contract::private::Network;
+use alloy_network::{ReceiptResponse, TransactionBuilder};
+use alloy_primitives::{Address, TxHash, U256};
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+
+use crate::abi::uniswap::nft_position::{decode_create_pool, encode_create_pool};
+use crate::defi::currency::erc20::ERC20Token;
+
+/// Parameters to create and initialize a Uniswap V3 pool
+#[derive(Debug, Clone)]
+pub struct CreatePoolParams {
+    /// The token with the lower address
+    pub token0: ERC20Token,
+
+    /// The token with the higher address
+    pub token1: ERC20Token,
+
+    /// Fee tier of the pool (eg. 3000 for 0.3%)
+    pub fee: u32,
+
+    /// Initial price of token0 in terms of token1
+    pub price: f64,
+}
+
+impl CreatePoolParams {
+    /// Create new params from a human readable price
+    ///
+    /// The tokens are sorted and the price is inverted if needed
+    ///
+    /// ## Arguments
+    ///
+    /// * `token_a` - The first token
+    /// * `token_b` - The second token
+    /// * `fee` - Fee tier of the pool
+    /// * `price` - Initial price of `token_a` in terms of `token_b`
+    pub fn new(token_a: ERC20Token, token_b: ERC20Token, fee: u32, price: f64) -> Self {
+        if token_a.address < token_b.address {
+            Self {
+                token0: token_a,
+                token1: token_b,
+                fee,
+                price,
+            }
+        } else {
+            Self {
+                token0: token_b,
+                token1: token_a,
+                fee,
+                price: 1.0 / price,
+            }
+        }
+    }
+
+    /// The initial sqrtPriceX96 of the pool
+    pub fn sqrt_price_x96(&self) -> Result<U256, anyhow::Error> {
+        sqrt_price_x96_from_price(self.price, self.token0.decimals, self.token1.decimals)
+    }
+}
+
+/// Calculate the sqrtPriceX96 from a human readable price
+///
+/// ## Arguments
+///
+/// * `price` - Price of token0 in terms of token1
+/// * `decimals0` - Decimals of token0
+/// * `decimals1` - Decimals of token1
+pub fn sqrt_price_x96_from_price(
+    price: f64,
+    decimals0: u8,
+    decimals1: u8,
+) -> Result<U256, anyhow::Error> {
+    if !(price.is_finite() && price > 0.0) {
+        return Err(anyhow::anyhow!("Invalid price: {}", price));
+    }
+
+    // price in raw units of token1 per raw unit of token0
+    let raw_price = price * 10_f64.powi(decimals1 as i32 - decimals0 as i32);
+    let sqrt_price_x96 = raw_price.sqrt() * 2_f64.powi(96);
+
+    U256::try_from(sqrt_price_x96)
+        .map_err(|e| anyhow::anyhow!("Failed to convert sqrtPriceX96: {:?}", e))
+}
+
+/// Create and initialize a Uniswap V3 pool on-chain through the NonfungiblePositionManager
+///
+/// The client must be able to sign transactions (eg. a provider with a wallet)
+///
+/// Returns the pool address and the transaction hash
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `params` - See [CreatePoolParams]
+/// * `position_manager` - The address of the NonfungiblePositionManager
+pub async fn create_and_initialize_pool<T, P, N>(
+    client: P,
+    params: CreatePoolParams,
+    position_manager: Address,
+) -> Result<(Address, TxHash), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let call_data = encode_create_pool(
+        params.token0.address,
+        params.token1.address,
+        params.fee,
+        params.sqrt_price_x96()?,
+    )?;
+
+    let tx = N::TransactionRequest::default()
+        .with_to(position_manager)
+        .with_input(call_data);
+
+    // the pool address is deterministic so we can get it before sending the transaction
+    let output = client.call(&tx).await?;
+    let pool = decode_create_pool(&output)?;
+
+    let receipt = client.send_transaction(tx).await?.get_receipt().await?;
+
+    if !receipt.status() {
+        return Err(anyhow::anyhow!(
+            "Transaction {} reverted",
+            receipt.transaction_hash()
+        ));
+    }
+
+    Ok((pool, receipt.transaction_hash()))
+}
@@ -0,0 +1,332 @@
+//! Order-book style depth of a Uniswap V3 pool
+//!
+//! The liquidity between initialized ticks is converted into the amount of token0 offered above the current price (asks)
+//! and the amount of token1 offered below it (bids), the same way an order book shows resting orders
+//!
+//! Only the ticks present in [State::ticks] are used, so the depth is as complete as the tick map of the state.
+//! [UniswapV3Pool::liquidity_distribution] loads the tick map itself and buckets the liquidity per tick spacing
+
+use alloy_contract::private::Network;
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
+use serde::Serialize;
+use uniswap_v3_math::tick_math::{MAX_TICK, MIN_TICK};
+
+use super::range_order::tick_to_price;
+use super::{sqrt_price_to_f64, State, UniswapV3Pool};
+
+/// The liquidity of one tick spacing of a pool
+#[derive(Debug, Clone, Serialize)]
+pub struct LiquidityBucket {
+    /// The lower tick of the bucket, the bucket ends at `tick + tick_spacing`
+    pub tick: i32,
+
+    /// The price at `tick` (token0 in terms of token1)
+    pub price: f64,
+
+    /// The liquidity active when the price is inside the bucket
+    pub active_liquidity: u128,
+
+    /// The token0 held in the bucket, formatted
+    pub amount0: f64,
+
+    /// The token1 held in the bucket, formatted
+    pub amount1: f64,
+}
+
+/// A price level of the depth chart
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthLevel {
+    /// The tick where this level ends (going away from the current price)
+    pub tick: i32,
+
+    /// The price at `tick` (token0 in terms of token1)
+    pub price: f64,
+
+    /// The amount offered between the previous level and this one
+    ///
+    /// token0 for asks, token1 for bids
+    pub amount: f64,
+
+    /// The amount offered from the current price up to this level
+    pub cumulative: f64,
+}
+
+/// The cumulative bid/ask depth of a pool
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthChart {
+    /// The current price (token0 in terms of token1)
+    pub price: f64,
+
+    /// token1 offered below the current price, from the closest level to the furthest
+    pub bids: Vec<DepthLevel>,
+
+    /// token0 offered above the current price, from the closest level to the furthest
+    pub asks: Vec<DepthLevel>,
+}
+
+impl DepthChart {
+    /// Build the depth chart of a pool from its state
+    ///
+    /// ## Arguments
+    ///
+    /// * `pool` - The pool, used for the token decimals
+    /// * `state` - The state of the pool
+    /// * `max_levels` - The maximum number of levels on each side
+    pub fn from_state(pool: &UniswapV3Pool, state: &State, max_levels: usize) -> Self {
+        let d0 = pool.token0.decimals as i32;
+        let d1 = pool.token1.decimals as i32;
+
+        // raw token1 per raw token0 -> token1 per token0
+        let price_scale = 10f64.powi(d0 - d1);
+        let sqrt_price = sqrt_price_to_f64(state.sqrt_price);
+
+        let mut ticks: Vec<(i32, i128)> = state
+            .ticks
+            .iter()
+            .filter(|(_, info)| info.initialized && info.liquidity_gross > 0)
+            .map(|(tick, info)| (*tick, info.liquidity_net))
+            .collect();
+        ticks.sort_by_key(|(tick, _)| *tick);
+
+        // asks, walk up from the current price and add liquidity_net when crossing a tick
+        let mut asks = Vec::new();
+        let mut liquidity = state.liquidity as f64;
+        let mut sqrt_current = sqrt_price;
+        let mut cumulative = 0.0;
+        for (tick, liquidity_net) in ticks.iter().filter(|(tick, _)| *tick > state.tick) {
+            if asks.len() == max_levels {
+                break;
+            }
+            let sqrt_next = tick_to_price(*tick, 0, 0).sqrt();
+            let amount = liquidity * (1.0 / sqrt_current - 1.0 / sqrt_next) / 10f64.powi(d0);
+            cumulative += amount;
+            asks.push(DepthLevel {
+                tick: *tick,
+                price: sqrt_next * sqrt_next * price_scale,
+                amount,
+                cumulative,
+            });
+
+            liquidity = (liquidity + *liquidity_net as f64).max(0.0);
+            sqrt_current = sqrt_next;
+        }
+
+        // bids, walk down from the current price and subtract liquidity_net when crossing a tick
+        let mut bids = Vec::new();
+        let mut liquidity = state.liquidity as f64;
+        let mut sqrt_current = sqrt_price;
+        let mut cumulative = 0.0;
+        for (tick, liquidity_net) in ticks.iter().rev().filter(|(tick, _)| *tick <= state.tick) {
+            if bids.len() == max_levels {
+                break;
+            }
+            let sqrt_next = tick_to_price(*tick, 0, 0).sqrt();
+            let amount = liquidity * (sqrt_current - sqrt_next) / 10f64.powi(d1);
+            cumulative += amount;
+            bids.push(DepthLevel {
+                tick: *tick,
+                price: sqrt_next * sqrt_next * price_scale,
+                amount,
+                cumulative,
+            });
+
+            liquidity = (liquidity - *liquidity_net as f64).max(0.0);
+            sqrt_current = sqrt_next;
+        }
+
+        Self {
+            price: sqrt_price * sqrt_price * price_scale,
+            bids,
+            asks,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Export as CSV with the columns `side,tick,price,amount,cumulative`
+    ///
+    /// Bids are written from the furthest level to the closest followed by the asks,
+    /// so the rows are sorted by price
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("side,tick,price,amount,cumulative\n");
+        for level in self.bids.iter().rev() {
+            csv.push_str(&format!(
+                "bid,{},{},{},{}\n",
+                level.tick, level.price, level.amount, level.cumulative
+            ));
+        }
+        for level in &self.asks {
+            csv.push_str(&format!(
+                "ask,{},{},{},{}\n",
+                level.tick, level.price, level.amount, level.cumulative
+            ));
+        }
+        csv
+    }
+}
+
+impl UniswapV3Pool {
+    /// The [DepthChart] of the pool at its current state
+    pub fn depth_chart(&self, max_levels: usize) -> Result<DepthChart, anyhow::Error> {
+        let state = self
+            .state()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+        Ok(DepthChart::from_state(self, state, max_levels))
+    }
+
+    /// The liquidity of the pool per tick spacing, `tick_range` ticks below and above the current tick
+    ///
+    /// Fetches the state with the initialized ticks of the range, see [UniswapV3Pool::fetch_state_with_tick_range]
+    /// and [liquidity_distribution]
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - The provider
+    /// * `tick_range` - The number of ticks on each side of the current tick
+    /// * `block` - The block to read at, None for the latest
+    pub async fn liquidity_distribution<T, P, N>(
+        &self,
+        client: P,
+        tick_range: i32,
+        block: Option<BlockId>,
+    ) -> Result<Vec<LiquidityBucket>, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        // a bitmap word covers 256 tick spacings
+        let spacing = super::tick_spacing_for_fee(self.fee).unwrap_or(1);
+        let num_words = (tick_range.max(0) / (256 * spacing) + 1).min(u16::MAX as i32) as u16;
+
+        let state = Self::fetch_state_with_tick_range(self.address, client, block, num_words).await?;
+        Ok(liquidity_distribution(self, &state, tick_range))
+    }
+}
+
+/// Bucket the liquidity of a state per tick spacing, from the lowest tick to the highest
+///
+/// The active liquidity of each bucket is derived from the current liquidity and the `liquidity_net` of the
+/// ticks crossed to reach it, the amounts are what the liquidity holds at the current price:
+/// token0 above the price, token1 below it and both in the bucket of the current tick
+///
+/// ## Arguments
+///
+/// * `pool` - The pool, used for the token decimals
+/// * `state` - The state of the pool with the ticks of the range
+/// * `tick_range` - The number of ticks on each side of the current tick
+pub fn liquidity_distribution(pool: &UniswapV3Pool, state: &State, tick_range: i32) -> Vec<LiquidityBucket> {
+    let spacing = state.tick_spacing.max(1);
+    let d0 = pool.token0.decimals as i32;
+    let d1 = pool.token1.decimals as i32;
+    let price_scale = 10f64.powi(d0 - d1);
+    let sqrt_price = sqrt_price_to_f64(state.sqrt_price);
+
+    let liquidity_net = |tick: i32| {
+        state
+            .ticks
+            .get(&tick)
+            .filter(|info| info.initialized)
+            .map_or(0, |info| info.liquidity_net)
+    };
+
+    let current = state.tick.div_euclid(spacing) * spacing;
+    let first = ((state.tick - tick_range).div_euclid(spacing) * spacing).max(MIN_TICK.div_euclid(spacing) * spacing);
+    let last = ((state.tick + tick_range).div_euclid(spacing) * spacing).min(MAX_TICK.div_euclid(spacing) * spacing);
+
+    // the active liquidity of each bucket, walking away from the current one
+    let mut liquidity = Vec::new();
+    let mut active = state.liquidity as i128;
+    let mut tick = current;
+    while tick > first {
+        // leaving the bucket downwards crosses its lower tick
+        active -= liquidity_net(tick);
+        tick -= spacing;
+        liquidity.push((tick, active.max(0) as u128));
+    }
+    liquidity.reverse();
+    liquidity.push((current, state.liquidity));
+
+    let mut active = state.liquidity as i128;
+    let mut tick = current;
+    while tick + spacing <= last {
+        tick += spacing;
+        active += liquidity_net(tick);
+        liquidity.push((tick, active.max(0) as u128));
+    }
+
+    liquidity
+        .into_iter()
+        .map(|(tick, active_liquidity)| {
+            let sqrt_lower = tick_to_price(tick, 0, 0).sqrt();
+            let sqrt_upper = tick_to_price(tick + spacing, 0, 0).sqrt();
+            let l = active_liquidity as f64;
+
+            let (amount0, amount1) = if sqrt_price <= sqrt_lower {
+                (l * (1.0 / sqrt_lower - 1.0 / sqrt_upper), 0.0)
+            } else if sqrt_price >= sqrt_upper {
+                (0.0, l * (sqrt_upper - sqrt_lower))
+            } else {
+                (l * (1.0 / sqrt_price - 1.0 / sqrt_upper), l * (sqrt_price - sqrt_lower))
+            };
+
+            LiquidityBucket {
+                tick,
+                price: sqrt_lower * sqrt_lower * price_scale,
+                active_liquidity,
+                amount0: amount0 / 10f64.powi(d0),
+                amount1: amount1 / 10f64.powi(d1),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::PoolTick;
+    use crate::defi::currency::erc20::ERC20Token;
+    use alloy_primitives::{Address, U256};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_liquidity_distribution() {
+        let mut state = State {
+            liquidity: 0,
+            sqrt_price: U256::from(1) << 96,
+            tick: 0,
+            tick_spacing: 60,
+            tick_bitmap: HashMap::from([(0, U256::ZERO), (-1, U256::ZERO)]),
+            ticks: HashMap::new(),
+            pool_tick: PoolTick {
+                tick: 0,
+                liquidity_net: 0,
+                block: 0,
+            },
+            fee_protocol: 0,
+        };
+        state.update_position(-120, 120, 1_000_000).unwrap();
+        state.update_position(60, 180, 500_000).unwrap();
+
+        let token = ERC20Token {
+            decimals: 0,
+            ..Default::default()
+        };
+        let pool = UniswapV3Pool::new(1, Address::ZERO, 3000, token.clone(), token);
+
+        let buckets = liquidity_distribution(&pool, &state, 240);
+        let ticks: Vec<i32> = buckets.iter().map(|b| b.tick).collect();
+        assert_eq!(ticks, vec![-240, -180, -120, -60, 0, 60, 120, 180, 240]);
+
+        let liquidity: Vec<u128> = buckets.iter().map(|b| b.active_liquidity).collect();
+        assert_eq!(liquidity, vec![0, 0, 1_000_000, 1_000_000, 1_000_000, 1_500_000, 500_000, 0, 0]);
+
+        // token1 below the price, token0 above it
+        assert!(buckets[3].amount0 == 0.0 && buckets[3].amount1 > 0.0);
+        assert!(buckets[5].amount0 > 0.0 && buckets[5].amount1 == 0.0);
+    }
+}
@@ -1,14 +1,30 @@
 // Credits: https://github.com/normdoow/uniswap.fish
 
-use alloy_primitives::U256;
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_sol_types::SolCall;
+use alloy_transport::Transport;
 use bigdecimal::{BigDecimal, FromPrimitive};
-use uniswap_v3_math::sqrt_price_math::Q96;
+use uniswap_v3_math::{
+    full_math::mul_div,
+    sqrt_price_math::Q96,
+    tick_math::{MAX_TICK, MIN_TICK},
+};
 use std::str::FromStr;
+use serde::{Deserialize, Serialize};
 
-use super::PoolTick;
+use super::{PoolTick, UniswapV3Pool};
+use crate::abi::uniswap::nft_position::{decode_positions, encode_positions};
+use crate::abi::uniswap::pool::v3::{
+    decode_slot0, encode_fee_growth_global0_x128, encode_fee_growth_global1_x128, encode_slot0, encode_tick,
+    IUniswapV3Pool,
+};
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepositAmounts {
     /// Amount of token0 to deposit
     pub amount0: f64,
@@ -20,19 +36,70 @@ pub struct DepositAmounts {
     pub liquidity_delta: f64,
 }
 
+/// A single known USD price used to derive the USD price of the other token of a pool
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UsdAnchor {
+    /// USD price of token0
+    Token0(f64),
+
+    /// USD price of token1
+    Token1(f64),
+}
+
+impl UsdAnchor {
+    /// Get the USD prices of token0 and token1
+    ///
+    /// ## Arguments
+    ///
+    /// * `price` - Price of token0 in terms of token1
+    pub fn token_prices(&self, price: f64) -> (f64, f64) {
+        match *self {
+            UsdAnchor::Token0(usd) => {
+                let token1_usd = if price == 0.0 { 0.0 } else { usd / price };
+                (usd, token1_usd)
+            }
+            UsdAnchor::Token1(usd) => (usd * price, usd),
+        }
+    }
+}
+
+/// The share of the swap fees that goes to the LPs for a `feeProtocol` of slot0
+///
+/// ## Arguments
+///
+/// * `fee_protocol` - The `feeProtocol` of the pool, see [State::fee_protocol](super::State::fee_protocol)
+/// * `zero_for_one` - Whether the fees are paid in token0
+pub fn lp_fee_share(fee_protocol: u8, zero_for_one: bool) -> f64 {
+    let fee_protocol = if zero_for_one {
+        fee_protocol % 16
+    } else {
+        fee_protocol >> 4
+    };
+
+    if fee_protocol == 0 {
+        1.0
+    } else {
+        1.0 - 1.0 / fee_protocol as f64
+    }
+}
+
 /// Estimate the earned fees in USD value
 ///
+/// The volume is assumed to be split evenly between both directions when the protocol fee differs per token
+///
 /// ## Arguments
 ///
 /// * `liquidity_delta` - Liquidity delta
 /// * `liquidity` - Liquidity
 /// * `volume_usd` - Volume in USD
 /// * `fee` - Fee tier
+/// * `fee_protocol` - The `feeProtocol` of the pool, 0 if the protocol fee is off
 pub fn estimate_fees_usd(
     liquidity_delta: U256,
     liquidity: U256,
     volume_usd: f64,
     fee: u32,
+    fee_protocol: u8,
 ) -> BigDecimal {
     let fee_percentage = match fee {
         100 => BigDecimal::from_f64(0.01 / 100.0).unwrap(), // 0.0001
@@ -50,7 +117,10 @@ pub fn estimate_fees_usd(
 
     let volume_usd_decimal = BigDecimal::from_f64(volume_usd).unwrap();
 
-    let earned_fees = fee_percentage * (volume_usd_decimal * liquidity_percentage);
+    let lp_share = (lp_fee_share(fee_protocol, true) + lp_fee_share(fee_protocol, false)) / 2.0;
+    let lp_share_decimal = BigDecimal::from_f64(lp_share).unwrap();
+
+    let earned_fees = fee_percentage * (volume_usd_decimal * liquidity_percentage) * lp_share_decimal;
 
     earned_fees
 }
@@ -84,6 +154,9 @@ pub fn estimate_fees_in_tokens(
 
 /// Get the amount of tokens to deposit
 ///
+/// A lower price of 0 and an infinite upper price give a full range position.
+/// If `p` is outside the range the deposit is single-sided, all in token0 below the range and all in token1 above it
+///
 /// # Arguments
 ///
 /// * `p` - Most active price assumption
@@ -100,34 +173,136 @@ pub fn get_tokens_deposit_amount(
     token_b_price: f64,
     deposit_amount: f64,
 ) -> DepositAmounts {
-    let delta_l = deposit_amount
-        / ((p.sqrt() - pl.sqrt()) * token_b_price
-            + (1.0 / p.sqrt() - 1.0 / pu.sqrt()) * token_a_price);
+    let (_, amount0, amount1) = amounts_per_liquidity(p, pl, pu);
+
+    // the USD value of one unit of liquidity
+    let value = amount0 * token_a_price + amount1 * token_b_price;
+    if !(value.is_finite() && value > 0.0) {
+        return DepositAmounts {
+            amount0: 0.0,
+            amount1: 0.0,
+            liquidity_delta: 0.0,
+        };
+    }
 
-    let mut delta_y = delta_l * (p.sqrt() - pl.sqrt());
+    let delta_l = deposit_amount / value;
 
-    if delta_y * token_b_price < 0.0 {
-        delta_y = 0.0;
+    DepositAmounts {
+        amount0: delta_l * amount0,
+        amount1: delta_l * amount1,
+        liquidity_delta: delta_l,
     }
+}
 
-    if delta_y * token_b_price > deposit_amount {
-        delta_y = deposit_amount / token_b_price;
-    }
+/// Which tokens a position needs at the current price
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepositSide {
+    /// The price is inside the range
+    Both,
+
+    /// The price is at or below the lower price
+    Token0Only,
 
-    let mut delta_x = delta_l * (1.0 / p.sqrt() - 1.0 / pu.sqrt());
+    /// The price is at or above the upper price
+    Token1Only,
+}
 
-    if delta_x * token_a_price < 0.0 {
-        delta_x = 0.0;
+/// The token amounts a range needs at the current price, see [deposit_ratio]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepositRatio {
+    pub side: DepositSide,
+
+    /// Amount of token0 per unit of liquidity
+    pub amount0: f64,
+
+    /// Amount of token1 per unit of liquidity
+    pub amount1: f64,
+
+    /// The share of the deposit value in token0 at the current price, 0 to 1
+    pub value_share0: f64,
+}
+
+impl DepositRatio {
+    /// The amount of token1 to deposit along with `amount0` of token0
+    ///
+    /// None if the range takes token1 only
+    pub fn amount1_for(&self, amount0: f64) -> Option<f64> {
+        match self.side {
+            DepositSide::Token1Only => None,
+            DepositSide::Token0Only => Some(0.0),
+            DepositSide::Both => Some(amount0 * self.amount1 / self.amount0),
+        }
     }
 
-    if delta_x * token_a_price > deposit_amount {
-        delta_x = deposit_amount / token_a_price;
+    /// The amount of token0 to deposit along with `amount1` of token1
+    ///
+    /// None if the range takes token0 only
+    pub fn amount0_for(&self, amount1: f64) -> Option<f64> {
+        match self.side {
+            DepositSide::Token0Only => None,
+            DepositSide::Token1Only => Some(0.0),
+            DepositSide::Both => Some(amount1 * self.amount0 / self.amount1),
+        }
     }
+}
 
-    DepositAmounts {
-        amount0: delta_x,
-        amount1: delta_y,
-        liquidity_delta: delta_l,
+/// Get the token0:token1 ratio a range needs at the current price of the pool
+///
+/// The pool must have its state set, the prices are token0 in terms of token1
+///
+/// ## Arguments
+///
+/// * `pool` - The pool
+/// * `lower` - Lower price of the range
+/// * `upper` - Upper price of the range
+pub fn deposit_ratio(
+    pool: &UniswapV3Pool,
+    lower: f64,
+    upper: f64,
+) -> Result<DepositRatio, anyhow::Error> {
+    let price = pool.calculate_price(pool.token0.address)?;
+    get_deposit_ratio(price, lower, upper)
+}
+
+/// Same as [deposit_ratio] with the price given
+///
+/// ## Arguments
+///
+/// * `p` - Price of token0 in terms of token1
+/// * `pl` - Lower price range
+/// * `pu` - Upper price range
+pub fn get_deposit_ratio(p: f64, pl: f64, pu: f64) -> Result<DepositRatio, anyhow::Error> {
+    if !(pl >= 0.0 && pl < pu && p > 0.0) {
+        return Err(anyhow::anyhow!("Invalid price range: {} - {}", pl, pu));
+    }
+
+    let (side, amount0, amount1) = amounts_per_liquidity(p, pl, pu);
+
+    let value0 = amount0 * p;
+    let value_share0 = value0 / (value0 + amount1);
+
+    Ok(DepositRatio {
+        side,
+        amount0,
+        amount1,
+        value_share0,
+    })
+}
+
+/// The amounts of token0 and token1 per unit of liquidity in a range at a price
+///
+/// An infinite upper price works as is, `1 / sqrt(pu)` is 0
+fn amounts_per_liquidity(p: f64, pl: f64, pu: f64) -> (DepositSide, f64, f64) {
+    if p <= pl {
+        (DepositSide::Token0Only, 1.0 / pl.sqrt() - 1.0 / pu.sqrt(), 0.0)
+    } else if p >= pu {
+        (DepositSide::Token1Only, 0.0, pu.sqrt() - pl.sqrt())
+    } else {
+        (
+            DepositSide::Both,
+            1.0 / p.sqrt() - 1.0 / pu.sqrt(),
+            p.sqrt() - pl.sqrt(),
+        )
     }
 }
 
@@ -157,7 +332,7 @@ pub fn get_liquidity_delta(p: f64, pl: f64, pu: f64, amount0: U256, amount1: U25
 }
 
 /// Function to calculate liquidity for a given amount of token0
-fn get_liquidity_for_amount0(
+pub fn get_liquidity_for_amount0(
     sqrt_ratio_lower_x96: U256,
     sqrt_ratio_upper_x96: U256,
     amount0: U256,
@@ -177,7 +352,8 @@ fn get_liquidity_for_amount0(
     numerator.checked_div(denominator).unwrap()
 }
 
-fn get_liquidity_for_amount1(sqrt_ratio_ax96: U256, sqrt_ratio_bx96: U256, amount1: U256) -> U256 {
+/// Function to calculate liquidity for a given amount of token1
+pub fn get_liquidity_for_amount1(sqrt_ratio_ax96: U256, sqrt_ratio_bx96: U256, amount1: U256) -> U256 {
     let numerator = amount1
         .checked_mul(Q96)
         .unwrap();
@@ -219,4 +395,206 @@ pub fn get_tick_from_price(price: f64) -> i32 {
     let tick = (sqrt_price.ln() / (1.0001_f64).sqrt().ln()).round() as i32;
 
     tick
-}
\ No newline at end of file
+}
+
+/// The ticks of a price range, rounded to the nearest multiple of the tick spacing
+///
+/// A lower price of 0 and an infinite upper price map to the lowest and highest usable ticks (full range)
+///
+/// ## Arguments
+///
+/// * `pl` - Lower price range
+/// * `pu` - Upper price range
+/// * `tick_spacing` - The tick spacing of the pool
+pub fn get_range_ticks(pl: f64, pu: f64, tick_spacing: i32) -> Result<(i32, i32), anyhow::Error> {
+    if !(pl >= 0.0 && pl < pu) || tick_spacing <= 0 {
+        return Err(anyhow::anyhow!("Invalid price range: {} - {}", pl, pu));
+    }
+
+    let min_tick = MIN_TICK / tick_spacing * tick_spacing;
+    let max_tick = MAX_TICK / tick_spacing * tick_spacing;
+    let align = |tick: i32| {
+        let tick = (tick as f64 / tick_spacing as f64).round() as i32 * tick_spacing;
+        tick.clamp(min_tick, max_tick)
+    };
+
+    let lower = if pl == 0.0 { min_tick } else { align(get_tick_from_price(pl)) };
+    let upper = if pu.is_infinite() { max_tick } else { align(get_tick_from_price(pu)) };
+
+    if lower >= upper {
+        return Err(anyhow::anyhow!(
+            "The price range {} - {} is narrower than the tick spacing {}",
+            pl,
+            pu,
+            tick_spacing
+        ));
+    }
+    Ok((lower, upper))
+}
+
+/// The fee growth per unit of liquidity inside a tick range, like `Tick.getFeeGrowthInside`
+///
+/// The subtractions wrap like in the pool, only the difference between two values is meaningful
+///
+/// ## Arguments
+///
+/// * `tick` - The current tick of the pool
+/// * `tick_lower` - The lower tick of the range
+/// * `tick_upper` - The upper tick of the range
+/// * `fee_growth_global` - `feeGrowthGlobal0X128` or `feeGrowthGlobal1X128` of the pool
+/// * `lower_outside` - `feeGrowthOutside` of the lower tick for the same token
+/// * `upper_outside` - `feeGrowthOutside` of the upper tick for the same token
+pub fn fee_growth_inside(
+    tick: i32,
+    tick_lower: i32,
+    tick_upper: i32,
+    fee_growth_global: U256,
+    lower_outside: U256,
+    upper_outside: U256,
+) -> U256 {
+    let below = if tick >= tick_lower {
+        lower_outside
+    } else {
+        fee_growth_global.wrapping_sub(lower_outside)
+    };
+    let above = if tick < tick_upper {
+        upper_outside
+    } else {
+        fee_growth_global.wrapping_sub(upper_outside)
+    };
+    fee_growth_global.wrapping_sub(below).wrapping_sub(above)
+}
+
+/// The `tokensOwed` of a position once updated with the current fee growth, like `Position.update`
+///
+/// ## Arguments
+///
+/// * `liquidity` - The liquidity of the position
+/// * `fee_growth_inside` - The current fee growth inside the range, see [fee_growth_inside]
+/// * `fee_growth_inside_last` - `feeGrowthInside{0,1}LastX128` of the position
+/// * `tokens_owed` - `tokensOwed{0,1}` of the position
+pub fn tokens_owed(
+    liquidity: u128,
+    fee_growth_inside: U256,
+    fee_growth_inside_last: U256,
+    tokens_owed: u128,
+) -> Result<U256, anyhow::Error> {
+    let growth = fee_growth_inside.wrapping_sub(fee_growth_inside_last);
+    let accrued = mul_div(growth, U256::from(liquidity), U256::from(1) << 128)?;
+    Ok(U256::from(tokens_owed) + accrued)
+}
+
+/// The fees of a position NFT not collected yet, in token0 and token1
+///
+/// Same as the amounts a `collect` would return without withdrawing liquidity, without simulating it
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `pool` - The pool of the position
+/// * `position_manager` - The NonfungiblePositionManager
+/// * `token_id` - The id of the position
+/// * `block` - The block to read at, None for the latest block
+pub async fn get_uncollected_fees<T, P, N>(
+    client: P,
+    pool: Address,
+    position_manager: Address,
+    token_id: U256,
+    block: Option<BlockId>,
+) -> Result<(U256, U256), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut batch = EthCallBatch::new(block);
+    let positions = batch.add(position_manager, encode_positions(token_id));
+    let slot0 = batch.add(pool, encode_slot0());
+    let global0 = batch.add(pool, encode_fee_growth_global0_x128());
+    let global1 = batch.add(pool, encode_fee_growth_global1_x128());
+    let mut results = batch.send(client.clone()).await?;
+
+    let position = decode_positions(&take_result(&mut results, positions)?)?;
+    let (_, tick) = decode_slot0(&take_result(&mut results, slot0)?)?;
+    let global0 = IUniswapV3Pool::feeGrowthGlobal0X128Call::abi_decode_returns(&take_result(&mut results, global0)?, true)?._0;
+    let global1 = IUniswapV3Pool::feeGrowthGlobal1X128Call::abi_decode_returns(&take_result(&mut results, global1)?, true)?._0;
+
+    // the boundary ticks come from the position
+    let mut batch = EthCallBatch::new(block);
+    let lower = batch.add(pool, encode_tick(position.tick_lower)?);
+    let upper = batch.add(pool, encode_tick(position.tick_upper)?);
+    let mut results = batch.send(client).await?;
+
+    let lower = IUniswapV3Pool::ticksCall::abi_decode_returns(&take_result(&mut results, lower)?, true)?;
+    let upper = IUniswapV3Pool::ticksCall::abi_decode_returns(&take_result(&mut results, upper)?, true)?;
+
+    let (tick_lower, tick_upper) = (position.tick_lower, position.tick_upper);
+    let inside0 = fee_growth_inside(tick, tick_lower, tick_upper, global0, lower._2, upper._2);
+    let inside1 = fee_growth_inside(tick, tick_lower, tick_upper, global1, lower._3, upper._3);
+
+    Ok((
+        tokens_owed(position.liquidity, inside0, position.fee_growth_inside0_last_x128, position.tokens_owed0)?,
+        tokens_owed(position.liquidity, inside1, position.fee_growth_inside1_last_x128, position.tokens_owed1)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_owed() {
+        let q128 = U256::from(1) << 128;
+        let growth = |n: u64| q128 * U256::from(n);
+
+        // in range: inside = global - below - above
+        assert_eq!(fee_growth_inside(0, -10, 10, growth(10), growth(2), growth(3)), growth(5));
+        // below the range: the lower tick outside is counted from the other side
+        assert_eq!(fee_growth_inside(-20, -10, 10, growth(10), growth(8), growth(1)), growth(7));
+
+        // 100 liquidity with 2 of growth per liquidity since the last update, plus 7 owed
+        assert_eq!(tokens_owed(100, growth(5), growth(3), 7).unwrap(), U256::from(207));
+
+        // the growth inside wrapped around since the last update
+        let last = U256::MAX - growth(1) + U256::from(1);
+        assert_eq!(tokens_owed(10, growth(1), last, 0).unwrap(), U256::from(20));
+    }
+
+    #[test]
+    fn test_tokens_deposit_amount() {
+        // full range at a price of 4, half of the value in each token
+        let deposit = get_tokens_deposit_amount(4.0, 0.0, f64::INFINITY, 4.0, 1.0, 100.0);
+        assert!((deposit.amount0 - 12.5).abs() < 1e-9);
+        assert!((deposit.amount1 - 50.0).abs() < 1e-9);
+
+        // below the range all in token0, above it all in token1
+        let below = get_tokens_deposit_amount(0.5, 1.0, 16.0, 2.0, 1.0, 100.0);
+        assert!((below.amount0 - 50.0).abs() < 1e-9 && below.amount1 == 0.0);
+        let above = get_tokens_deposit_amount(20.0, 1.0, 16.0, 2.0, 1.0, 100.0);
+        assert!(above.amount0 == 0.0 && (above.amount1 - 100.0).abs() < 1e-9);
+
+        assert_eq!(get_range_ticks(0.0, f64::INFINITY, 60).unwrap(), (-887220, 887220));
+        assert!(get_range_ticks(1.0, 1.0001, 60).is_err());
+    }
+
+    #[test]
+    fn test_deposit_ratio() {
+        // sqrt(p) = 2 is the geometric middle of sqrt(pl) = 1 and sqrt(pu) = 4
+        let ratio = get_deposit_ratio(4.0, 1.0, 16.0).unwrap();
+        assert!((ratio.amount0 - 0.25).abs() < 1e-12);
+        assert!((ratio.amount1 - 1.0).abs() < 1e-12);
+        assert!((ratio.value_share0 - 0.5).abs() < 1e-12);
+        assert!((ratio.amount1_for(2.0).unwrap() - 8.0).abs() < 1e-12);
+
+        let below = get_deposit_ratio(0.5, 1.0, 16.0).unwrap();
+        assert_eq!(below.side, DepositSide::Token0Only);
+        assert_eq!(below.value_share0, 1.0);
+        assert_eq!(below.amount0_for(1.0), None);
+
+        let above = get_deposit_ratio(20.0, 1.0, 16.0).unwrap();
+        assert_eq!(above.side, DepositSide::Token1Only);
+        assert_eq!(above.value_share0, 0.0);
+
+        assert!(get_deposit_ratio(4.0, 16.0, 1.0).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! Compare liquidity ranges beyond their APR
+//!
+//! A narrow range earns more fees per dollar while the price is inside it but earns nothing outside.
+//! [PositionHealth] puts both sides in numbers: the share of the volume that traded in range and how much
+//! more liquidity the range provides than a full-range position of the same value
+
+use serde::Serialize;
+
+/// How well a range captured the volume of a pool, see [position_health]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PositionHealth {
+    /// The fraction of the volume (in USD) that traded while the position was in range, 0 to 1
+    pub range_utilization: f64,
+
+    /// The liquidity of the range relative to a full-range position of the same value at the entry price
+    ///
+    /// 1 for a full range, higher for narrower ranges
+    pub capital_efficiency: f64,
+
+    /// `range_utilization * capital_efficiency`, the fees earned relative to a full-range position
+    pub score: f64,
+}
+
+/// Score a range against the swaps it saw
+///
+/// ## Arguments
+///
+/// * `swaps` - The USD volume of each swap and whether the position was in range
+/// * `lower_range` - The lower price of the range (token0 in terms of token1)
+/// * `upper_range` - The upper price of the range (token0 in terms of token1)
+/// * `price` - The price the position was entered at (token0 in terms of token1)
+pub fn position_health(swaps: &[(f64, bool)], lower_range: f64, upper_range: f64, price: f64) -> PositionHealth {
+    let range_utilization = range_utilization(swaps);
+    let capital_efficiency = capital_efficiency(lower_range, upper_range, price);
+
+    PositionHealth {
+        range_utilization,
+        capital_efficiency,
+        score: range_utilization * capital_efficiency,
+    }
+}
+
+/// The fraction of the volume that traded in range, 0 without volume
+pub fn range_utilization(swaps: &[(f64, bool)]) -> f64 {
+    let total: f64 = swaps.iter().map(|(volume, _)| volume).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    let in_range: f64 = swaps.iter().filter(|(_, in_range)| *in_range).map(|(volume, _)| volume).sum();
+    in_range / total
+}
+
+/// The liquidity of a range per unit of value relative to the full range, at `price`
+///
+/// A full-range position of liquidity L is worth `2L√P` (in token1), the same L in `[pa, pb]` is worth
+/// `L(2√P - √pa - P/√pb)` in range, only token0 below it and only token1 above it.
+/// A range from 0 to infinity is the full range
+pub fn capital_efficiency(lower_range: f64, upper_range: f64, price: f64) -> f64 {
+    if lower_range < 0.0 || upper_range <= lower_range || price <= 0.0 {
+        return 1.0;
+    }
+
+    // an infinite upper price works as is, its terms go to 0
+    let (sqrt_price, sqrt_lower, sqrt_upper) = (price.sqrt(), lower_range.sqrt(), upper_range.sqrt());
+    let value = if sqrt_price <= sqrt_lower {
+        (1.0 / sqrt_lower - 1.0 / sqrt_upper) * price
+    } else if sqrt_price >= sqrt_upper {
+        sqrt_upper - sqrt_lower
+    } else {
+        2.0 * sqrt_price - sqrt_lower - price / sqrt_upper
+    };
+
+    if value <= 0.0 {
+        return 1.0;
+    }
+    2.0 * sqrt_price / value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_health() {
+        let health = position_health(&[(100.0, true), (300.0, false)], 0.81, 1.21, 1.0);
+        assert_eq!(health.range_utilization, 0.25);
+        // 2 / (2 - 0.9 - 1 / 1.1)
+        assert!((health.capital_efficiency - 10.476).abs() < 1e-3);
+        assert!((health.score - health.capital_efficiency / 4.0).abs() < 1e-9);
+
+        // a very wide range is close to the full range
+        assert!((capital_efficiency(1e-12, 1e12, 1.0) - 1.0).abs() < 1e-5);
+        assert_eq!(capital_efficiency(0.0, f64::INFINITY, 2.0), 1.0);
+        assert_eq!(range_utilization(&[]), 0.0);
+    }
+}
@@ -1,6 +1,6 @@
 use alloy_primitives::{
-    utils::{format_units, parse_units},
-    Signed, Uint, U256,
+    utils::parse_units,
+    Address, Bytes, Signed, Uint, U256,
 };
 
 use alloy_rpc_types::BlockId;
@@ -10,28 +10,168 @@ use alloy_network::Ethereum;
 use alloy_provider::Provider;
 use alloy_transport::Transport;
 
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::{Mutex, Semaphore};
 use tokio::task::JoinHandle;
 
 use crate::{
-    defi::currency::erc20::ERC20Token,
-    revm_utils::{dummy_account::*, fork_db::fork_factory::ForkFactory, simulate::*, utils::*},
+    ChainId,
+    defi::currency::erc20::{ERC20Token, TokenKind},
+    revm_utils::{
+        contracts::swap_router::*,
+        fork_db::{fork_db::ForkDB, fork_factory::ForkFactory},
+        scenario::{Scenario, ScenarioAccount, Spender},
+        simulate::*,
+        utils::*,
+    },
+};
+use revm::{
+    db::{CacheDB, EmptyDB},
+    Database,
 };
-use revm::db::{CacheDB, EmptyDB};
 
-use super::{fee_math::*, UniswapV3Pool};
+use super::{fee_math::*, health::{position_health, PositionHealth}, staker::*, UniswapV3Pool};
+use crate::defi::amm::uniswap::deployments::UniswapDeployment;
 use crate::{
     abi::{
-        swap_router::*,
-        uniswap::{nft_position::*, pool::v3::*},
+        uniswap::{nft_position::*, pool::v3::*, staker::{incentives, UNISWAP_V3_STAKER}},
+    },
+    defi::utils::{
+        chain_link::get_token_price,
+        common_addr::{wbnb, weth},
+        op_stack::suggest_tx_fee,
+    },
+    utils::{
+        arbitrum::is_arbitrum,
+        config::Config,
+        deadline::{deadline_after, DEFAULT_DEADLINE_SECS},
+        format::to_f64,
+        logs::query::get_logs_for,
+        logs::events::SwapData,
+        average_block_secs, BlockTime,
     },
-    utils::{logs::query::get_logs_for, BlockTime},
 };
 
 use anyhow::Context;
 use tracing::trace;
 
+/// How many swaps are replayed per batch
+const REPLAY_BATCH_SIZE: usize = 100;
+
+/// How many of the largest swaps are kept in [PositionResult::big_swaps]
+pub const BIG_SWAPS: usize = 10;
+
+/// How the historical swaps are replayed in [simulate_position]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ReplayMode {
+    /// Only replay the swaps, the price path is whatever the fork computes
+    #[default]
+    Swaps,
+
+    /// After each replayed swap overwrite the pool's sqrtPriceX96, tick and active liquidity
+    /// with the values recorded in the Swap event, keeping the backtest on the true price path
+    ///
+    /// Ticks are not crossed when the price is reconciled, so fee growth outside of ticks
+    /// that were jumped over is not updated
+    PoolReported,
+}
+
+/// What happens to a historical swap that fails to replay in [simulate_position]
+///
+/// Swaps can fail because the fork state drifts from the chain (eg. our position changes the liquidity)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetryMode {
+    /// The swap is dropped and counted in [PositionResult::failed_swaps]
+    #[default]
+    Skip,
+
+    /// The swap is replayed in smaller chunks against the current fork state
+    ///
+    /// The chunk size is halved up to `max_halvings` times, at the first size that goes through
+    /// as many chunks as possible are replayed and the rest of the input is dropped
+    Split { max_halvings: u32 },
+}
+
+/// Swap the fees collected at the end of [simulate_position] to a stable coin, like an LP harvesting would
+///
+/// The swaps run in the fork after the position is exited, so the realized amount includes the price impact
+/// and the swap fees of the harvest
+#[derive(Debug, Clone)]
+pub struct Harvest {
+    /// The stable coin to harvest into
+    pub stable: ERC20Token,
+
+    /// The pools used to swap the fee tokens that are not the stable
+    ///
+    /// Not needed for a fee token paired with the stable in the simulated pool, that pool is used
+    pub pools: Vec<HarvestPool>,
+}
+
+/// A pool that swaps one of the fee tokens to the stable of a [Harvest]
+#[derive(Debug, Clone, Copy)]
+pub struct HarvestPool {
+    /// The fee token sold in this pool
+    pub token: Address,
+
+    pub pool: Address,
+    pub variant: PoolVariant,
+
+    /// The fee tier of a V3 pool, ignored for V2
+    pub fee: u32,
+}
+
+impl Harvest {
+    pub fn new(stable: ERC20Token) -> Self {
+        Self {
+            stable,
+            pools: Vec::new(),
+        }
+    }
+
+    /// Swap `token` to the stable through `pool`
+    pub fn with_pool(mut self, token: Address, pool: Address, variant: PoolVariant, fee: u32) -> Self {
+        self.pools.push(HarvestPool {
+            token,
+            pool,
+            variant,
+            fee,
+        });
+        self
+    }
+}
+
+/// How the USD prices of the pool tokens are obtained in [simulate_position]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DepositPricing {
+    /// Both tokens are priced from external sources, see [UniswapV3Pool::tokens_usd]
+    #[default]
+    External,
+
+    /// Only one token needs a known USD price, the relative price comes from the pool itself
+    ///
+    /// Useful for long-tail pools where one of the tokens has no external price
+    PoolDerived,
+}
+
+impl DepositPricing {
+    async fn tokens_usd<T, P>(
+        &self,
+        pool: &UniswapV3Pool,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<(f64, f64), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone,
+    {
+        match self {
+            DepositPricing::External => pool.tokens_usd(client, block).await,
+            DepositPricing::PoolDerived => pool.tokens_usd_from_pool(client, block).await,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PositionArgs {
     /// Lower price range (token0 in terms of token1)
@@ -48,6 +188,33 @@ pub struct PositionArgs {
 
     /// The Uniswap V3 pool
     pub pool: UniswapV3Pool,
+
+    /// Size of each earnings epoch in blocks
+    ///
+    /// If None, earnings are bucketed per day
+    pub epoch_blocks: Option<u64>,
+
+    /// How the historical swaps are replayed, see [ReplayMode]
+    pub replay_mode: ReplayMode,
+
+    /// How the swaps that fail to replay are handled, see [RetryMode]
+    pub retry_mode: RetryMode,
+
+    /// How the USD prices of the tokens are obtained, see [DepositPricing]
+    pub deposit_pricing: DepositPricing,
+
+    /// UniswapV3Staker incentives of the pool the position would be staked in
+    ///
+    /// The expected rewards are included in the [PositionResult]
+    pub incentives: Vec<IncentiveKey>,
+
+    /// Swap the earned fees to a stable coin at the end, see [Harvest]
+    ///
+    /// If None, the fees are only valued at the latest prices
+    pub harvest: Option<Harvest>,
+
+    /// Read the price of the fork after each replayed swap, see [PositionResult::trajectory]
+    pub record_trajectory: bool,
 }
 
 impl PositionArgs {
@@ -64,11 +231,18 @@ impl PositionArgs {
             price_assumption,
             deposit_amount,
             pool,
+            epoch_blocks: None,
+            replay_mode: ReplayMode::default(),
+            retry_mode: RetryMode::default(),
+            deposit_pricing: DepositPricing::default(),
+            incentives: Vec::new(),
+            harvest: None,
+            record_trajectory: false,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PositionResult {
     pub token0: ERC20Token,
     pub token1: ERC20Token,
@@ -113,6 +287,9 @@ pub struct PositionResult {
     /// The total number of failed swaps (for debugging purposes)
     pub failed_swaps: u64,
 
+    /// The number of swaps that were only partially replayed, see [RetryMode::Split]
+    pub partial_swaps: u64,
+
     /// The total number of times that our position was out of the range
     pub out_of_range: usize,
 
@@ -120,12 +297,92 @@ pub struct PositionResult {
     pub in_range: usize,
 
     pub apr: f64,
+
+    /// The cost in USD of minting the position and collecting the fees at the latest gas price
+    ///
+    /// Includes the L1 data fee on OP-stack chains
+    pub gas_cost_usd: f64,
+
+    /// The APR after subtracting the gas costs
+    pub net_apr: f64,
+
+    /// The earnings broken down by epoch (per day by default)
+    pub epochs: Vec<EpochEarnings>,
+
+    /// Amount of Token0 deposited when the position was minted
+    pub principal_in0: f64,
+
+    /// Amount of Token1 deposited when the position was minted
+    pub principal_in1: f64,
+
+    /// Amount of Token0 returned by removing all the liquidity at the end, fees excluded
+    pub principal_out0: f64,
+
+    /// Amount of Token1 returned by removing all the liquidity at the end, fees excluded
+    pub principal_out1: f64,
+
+    /// The deposited amounts valued at the latest prices, what holding them would be worth
+    pub principal_in_usd: f64,
+
+    /// The withdrawn amounts valued at the latest prices
+    pub principal_out_usd: f64,
+
+    /// The expected rewards of each incentive in [PositionArgs::incentives]
+    pub incentive_rewards: Vec<IncentiveReward>,
+
+    /// The total expected incentive rewards in USD
+    pub incentive_rewards_usd: f64,
+
+    /// The APR of the incentive rewards alone
+    pub incentive_apr: f64,
+
+    /// The APR of incentives distributed off-chain (eg. Merkl), not included in [Self::apr]
+    ///
+    /// Zero unless set by [merkl::apply_to_position](crate::defi::utils::merkl)
+    pub external_incentive_apr: f64,
+
+    /// Amount of the stable received by swapping the earned fees, see [PositionArgs::harvest]
+    pub harvested: Option<f64>,
+
+    /// The harvested stable in USD at the latest price, the earnings after slippage
+    pub harvested_usd: Option<f64>,
+
+    /// The lower price of the range, see [PositionArgs::lower_range]
+    pub lower_range: f64,
+
+    /// The upper price of the range, see [PositionArgs::upper_range]
+    pub upper_range: f64,
+
+    /// The price of token0 in token1 after each replayed swap, empty if the swaps don't report the pool price
+    pub prices: Vec<PricePoint>,
+
+    /// The [BIG_SWAPS] largest swaps by USD value at the latest prices, largest first
+    pub big_swaps: Vec<BigSwap>,
+
+    /// The price of the fork after each replayed swap, empty unless [PositionArgs::record_trajectory] is set
+    pub trajectory: Vec<TrajectoryPoint>,
+
+    /// The share of the volume traded in range and the capital efficiency of the range, see [PositionHealth]
+    pub health: PositionHealth,
 }
 
 impl PositionResult {
+    /// The value of the withdrawn principal minus the value of holding the deposit, both at the latest prices
+    ///
+    /// Negative when the price moved (range-exit or impermanent loss), the fees are not included
+    pub fn exit_pnl_usd(&self) -> f64 {
+        self.principal_out_usd - self.principal_in_usd
+    }
+
+    /// The earned fees at the latest prices minus what harvesting them realized
+    pub fn harvest_slippage_usd(&self) -> Option<f64> {
+        self.harvested_usd
+            .map(|harvested| self.earned0_usd + self.earned1_usd - harvested)
+    }
+
     /// Create a pretty string representation of the result
     pub fn pretty(&self) -> String {
-        format!(
+        let mut pretty = format!(
             "\nPast Price of {}: ${:.2}
              Past Price of {}: ${:.2}
              Latest Price of {}: ${:.2}
@@ -134,13 +391,24 @@ impl PositionResult {
              Earned1: {:.2} {} (${:.2})
              Total Earned: ${:.2}
              APR: {:.2}%
+             Gas Cost: ${:.2}
+             Net APR: {:.2}%
+             Principal In: ${:.2}
+             Principal Out: ${:.2}
+             Exit PnL: ${:.2}
+             Incentive Rewards: ${:.2}
+             Incentive APR: {:.2}%
+             External Incentive APR: {:.2}%
              Buy Volume USD: {:.2}
              Sell Volume USD: {:.2}
              Total Fee0: {:.2}
              Total Fee1: {:.2}
              Failed Swaps: {}
+             Partial Swaps: {}
              Out of Range: {}
-             In Range: {}",
+             In Range: {}
+             Range Utilization: {:.2}%
+             Capital Efficiency: {:.2}x",
             self.token0.symbol,
             self.past_token0_usd,
             self.token1.symbol,
@@ -157,17 +425,136 @@ impl PositionResult {
             self.earned1_usd,
             self.earned0_usd + self.earned1_usd,
             self.apr,
+            self.gas_cost_usd,
+            self.net_apr,
+            self.principal_in_usd,
+            self.principal_out_usd,
+            self.exit_pnl_usd(),
+            self.incentive_rewards_usd,
+            self.incentive_apr,
+            self.external_incentive_apr,
             self.buy_volume_usd,
             self.sell_volume_usd,
             self.total_fee0,
             self.total_fee1,
             self.failed_swaps,
+            self.partial_swaps,
             self.out_of_range,
-            self.in_range
-        )
+            self.in_range,
+            self.health.range_utilization * 100.0,
+            self.health.capital_efficiency
+        );
+
+        if let (Some(harvested_usd), Some(slippage)) =
+            (self.harvested_usd, self.harvest_slippage_usd())
+        {
+            pretty.push_str(&format!(
+                "\n             Harvested: ${:.2}\n             Harvest Slippage: ${:.2}",
+                harvested_usd, slippage
+            ));
+        }
+
+        pretty
+    }
+}
+
+/// The expected reward of a UniswapV3Staker incentive
+#[derive(Debug, Clone, Serialize)]
+pub struct IncentiveReward {
+    #[serde(serialize_with = "serialize_incentive_key")]
+    pub key: IncentiveKey,
+    pub reward_token: ERC20Token,
+
+    /// Amount of the reward token
+    pub amount: f64,
+
+    /// Amount in USD at the latest price
+    pub amount_usd: f64,
+}
+
+/// The fees earned by the position during an epoch
+#[derive(Debug, Clone, Serialize)]
+pub struct EpochEarnings {
+    /// The index of the epoch starting from 0
+    pub epoch: u64,
+
+    /// First block of the epoch
+    pub start_block: u64,
+
+    /// Last block of the epoch
+    pub end_block: u64,
+
+    /// Amount of Token0 earned in this epoch
+    pub earned0: f64,
+
+    /// Amount of Token1 earned in this epoch
+    pub earned1: f64,
+
+    /// Amount of Token0 earned in USD in this epoch
+    pub earned0_usd: f64,
+
+    /// Amount of Token1 earned in USD in this epoch
+    pub earned1_usd: f64,
+}
+
+impl EpochEarnings {
+    /// Total earned in USD in this epoch
+    pub fn total_usd(&self) -> f64 {
+        self.earned0_usd + self.earned1_usd
     }
 }
 
+fn serialize_incentive_key<S: serde::Serializer>(key: &IncentiveKey, serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeStruct;
+
+    let mut state = serializer.serialize_struct("IncentiveKey", 5)?;
+    state.serialize_field("reward_token", &key.rewardToken)?;
+    state.serialize_field("pool", &key.pool)?;
+    state.serialize_field("start_time", &key.startTime)?;
+    state.serialize_field("end_time", &key.endTime)?;
+    state.serialize_field("refundee", &key.refundee)?;
+    state.end()
+}
+
+/// The price of the pool after a swap
+#[derive(Debug, Clone, Serialize)]
+pub struct PricePoint {
+    pub block: u64,
+
+    /// Token0 in terms of token1
+    pub price: f64,
+}
+
+/// The state of the fork after a replayed swap
+#[derive(Debug, Clone, Serialize)]
+pub struct TrajectoryPoint {
+    pub block: u64,
+
+    /// Token0 in terms of token1, from the `slot0` of the pool in the fork
+    pub price: f64,
+    pub tick: i32,
+
+    /// Whether the position earned fees from the swap, what [PositionResult::in_range] counts
+    pub in_range: bool,
+}
+
+/// One of the largest swaps replayed during the simulation
+#[derive(Debug, Clone, Serialize)]
+pub struct BigSwap {
+    pub block: u64,
+    pub tx_hash: String,
+    pub token_in: ERC20Token,
+    pub token_out: ERC20Token,
+    pub amount_in: f64,
+    pub amount_out: f64,
+
+    /// The input amount in USD at the latest price
+    pub amount_usd: f64,
+
+    /// Whether the position earned fees from this swap
+    pub in_range: bool,
+}
+
 /// Keep track in which block the price is in the range or not
 #[derive(Debug, Clone)]
 pub struct PriceRange {
@@ -186,19 +573,36 @@ impl PriceRange {
 /// It works by quering and forking the historically required chain state and simulate all the swaps that occured in the past
 /// Because of that it may be slow and not suitable for some usecases
 ///
+/// The position manager is resolved from the pool's chain id, see [UniswapDeployment].
+/// The swaps are replayed with the [SwapRouter] which only supports the V3 factory of Ethereum, Optimism and Arbitrum
+///
 /// ## Arguments
 ///
 /// * `client` - The provided client
 /// * `block_time` - Simulate the position based on the past time (x days or x hours ago)
 /// * `args` - See [PositionArgs]
+#[cfg_attr(
+    feature = "telemetry",
+    tracing::instrument(
+        name = "simulate_position",
+        skip_all,
+        fields(
+            pool = %args.pool.address,
+            fork_block = tracing::field::Empty,
+            swaps = tracing::field::Empty,
+            failed_swaps = tracing::field::Empty,
+            partial_swaps = tracing::field::Empty
+        )
+    )
+)]
 pub async fn simulate_position<T, P>(
     client: P,
     block_time: BlockTime,
     args: PositionArgs,
 ) -> Result<PositionResult, anyhow::Error>
 where
-    T: Transport + Clone + Unpin,
-    P: Provider<T, Ethereum> + Clone + 'static + Unpin,
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
 {
     let full_block = client
         .get_block(BlockId::latest(), false.into())
@@ -207,8 +611,17 @@ where
     let chain_id = client.get_chain_id().await?;
 
     let latest_block = full_block.clone().header.number.clone();
-    let fork_block = block_time.go_back(chain_id, latest_block)?;
-    let fork_block = BlockId::number(fork_block);
+    let fork_block_number = block_time
+        .go_back_with_client(client.clone(), chain_id, latest_block)
+        .await?;
+    let fork_block = BlockId::number(fork_block_number);
+    let fork_full_block = client
+        .get_block(fork_block, false.into())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Fork block {} not found", fork_block_number))?;
+
+    #[cfg(feature = "telemetry")]
+    tracing::Span::current().record("fork_block", fork_block_number);
 
     let mut pool = args.pool.clone();
 
@@ -230,14 +643,33 @@ where
         UniswapV3Pool::fetch_state(args.pool.address, client.clone(), Some(fork_block.clone()))
             .await?;
     pool.update_state(state);
+    let fork_liquidity = pool.state().map(|s| s.liquidity).unwrap_or(0);
 
     // get token0 and token1 prices in USD at the fork block
-    let (past_token0_usd, past_token1_usd) = pool
-        .tokens_usd(client.clone(), Some(fork_block.clone()))
+    let (past_token0_usd, past_token1_usd) = args
+        .deposit_pricing
+        .tokens_usd(&pool, client.clone(), Some(fork_block.clone()))
         .await?;
 
+    // a lower range of 0 and an infinite upper range give the full range
+    let (fork_tick, tick_spacing) = pool
+        .state()
+        .map(|s| (s.tick, s.tick_spacing))
+        .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+    let (lower_tick, upper_tick) =
+        get_range_ticks(args.lower_range, args.upper_range, tick_spacing)?;
+
+    // the mint only takes token0 below the range and token1 above it, whatever the price assumption
+    let deposit_price = if fork_tick < lower_tick {
+        args.lower_range
+    } else if fork_tick >= upper_tick {
+        args.upper_range
+    } else {
+        price_assumption
+    };
+
     let deposit = get_tokens_deposit_amount(
-        price_assumption,
+        deposit_price,
         args.lower_range,
         args.upper_range,
         past_token0_usd,
@@ -250,43 +682,50 @@ where
     let amount1 =
         parse_units(&deposit.amount1.to_string(), args.pool.token1.decimals)?.get_absolute();
 
-    let lower_tick = get_tick_from_price(args.lower_range);
-    let upper_tick = get_tick_from_price(args.upper_range);
+    #[cfg(feature = "telemetry")]
+    let setup_start = std::time::Instant::now();
+
+    // the position manager of the pool's chain
+    let position_manager = UniswapDeployment::for_chain(args.pool.chain_id)?.position_manager;
 
     // prepare the fork enviroment
     let db = CacheDB::new(EmptyDB::default());
     let mut fork_factory = ForkFactory::new_sandbox_factory(client.clone(), db, Some(fork_block));
 
+    let tokens = [args.pool.token0.clone(), args.pool.token1.clone()];
+
     // a simple router to simulate uniswap swaps
-    let swap_router = DummyAccount::new(AccountType::Contract(swap_router_bytecode()?), U256::ZERO);
+    let mut scenario = Scenario::new()
+        .with_chain(ChainId::new(chain_id))
+        .with_block(fork_full_block.clone())
+        .with_account(ScenarioAccount::contract("swap_router", swap_router_bytecode()?));
 
     // a dummy account that act as the swapper
-    let swapper = DummyAccount::new(AccountType::EOA, U256::ZERO);
-
-    // a dummy account that act as the lp provider
-    let lp_provider = DummyAccount::new(AccountType::EOA, U256::ZERO);
-
-    let amount_to_fund_0 = args.pool.token0.total_supply;
-    let amount_to_fund_1 = args.pool.token1.total_supply;
+    let mut swapper = ScenarioAccount::eoa("swapper");
 
-    swap_router.insert(&mut fork_factory, args.pool.token0.clone(), U256::from(1))?;
-    swapper.insert(
-        &mut fork_factory,
-        args.pool.token0.clone(),
-        amount_to_fund_0,
-    )?;
-    swapper.insert(
-        &mut fork_factory,
-        args.pool.token1.clone(),
-        amount_to_fund_1,
-    )?;
+    // a dummy account that act as the lp provider, we give it just as much to create the position
+    let mut lp_provider = ScenarioAccount::eoa("lp_provider")
+        .with_token(tokens[0].clone(), amount0)
+        .with_token(tokens[1].clone(), amount1);
 
-    // we give the lp provider just as much to create the position
-    lp_provider.insert(&mut fork_factory, args.pool.token0.clone(), amount0)?;
-    lp_provider.insert(&mut fork_factory, args.pool.token1.clone(), amount1)?;
+    // aprove the nft and swapper contract to spent the tokens
+    for token in &tokens {
+        let router = Spender::Account("swap_router".to_string());
+        swapper = swapper
+            .with_token(token.clone(), token.total_supply)
+            .with_approval(token.clone(), router, U256::MAX);
+        lp_provider = lp_provider.with_approval(
+            token.clone(),
+            Spender::Address(position_manager),
+            U256::MAX,
+        );
+    }
+    scenario = scenario.with_account(swapper).with_account(lp_provider);
 
-    let fork_db = fork_factory.new_sandbox_fork();
-    let mut evm = new_evm(fork_db, Some(full_block.clone()));
+    let (mut evm, handles) = scenario.build(&mut fork_factory)?;
+    let swap_router = handles.address("swap_router")?;
+    let swapper = handles.address("swapper")?;
+    let lp_provider = handles.address("lp_provider")?;
 
     let fee: Uint<24, 1> = args
         .pool
@@ -313,131 +752,292 @@ where
         amount1Desired: amount1,
         amount0Min: U256::ZERO,
         amount1Min: U256::ZERO,
-        recipient: lp_provider.address,
-        deadline: U256::from(full_block.header.timestamp),
+        recipient: lp_provider,
+        deadline: deadline_after(fork_full_block.header.timestamp, DEFAULT_DEADLINE_SECS),
     };
 
-    // aprove the nft and swapper contract to spent the tokens
-    let tokens = vec![args.pool.token0.clone(), args.pool.token1.clone()];
-    for token in tokens {
-        approve_token(
-            &mut evm,
-            token.clone(),
-            lp_provider.address,
-            NFT_POSITION_CONTRACT,
-            U256::MAX,
-        )?;
-        approve_token(
-            &mut evm,
-            token.clone(),
-            swapper.address,
-            swap_router.address,
-            U256::MAX,
-        )?;
-    }
+    // measure the gas of the mint so we can account for it in the net APR
+    let mint_call_data = Bytes::from(encode_mint(mint_params.clone()));
+    let mint_gas = estimate_gas(
+        &mut evm,
+        lp_provider,
+        position_manager,
+        mint_call_data.clone(),
+    )?;
 
     // create the position
     let mint_res = mint_position(
         &mut evm,
         mint_params,
-        lp_provider.address,
-        NFT_POSITION_CONTRACT,
+        lp_provider,
+        position_manager,
         true,
     )?;
     let token_id = mint_res.0;
+    let position_liquidity = mint_res.1;
+    let (principal_in0, principal_in1) = (mint_res.2, mint_res.3);
+    let lower_tick_i32 = lower_tick.to_string().parse::<i32>()?;
+    let upper_tick_i32 = upper_tick.to_string().parse::<i32>()?;
+
+    #[cfg(feature = "telemetry")]
+    tracing::debug!(
+        elapsed_ms = setup_start.elapsed().as_millis() as u64,
+        "Fork setup finished"
+    );
 
     let mut price_ranges = Vec::new();
+    let mut prices = Vec::new();
+    let mut trajectory = Vec::new();
+
+    // the replayed swaps with whether they were in range, to pick the big swaps once the prices are known
+    let mut replayed_swaps = Vec::new();
 
     // keep track of the amounts we have collected
     let mut collected0 = U256::ZERO;
     let mut collected1 = U256::ZERO;
 
+    // keep track of the cumulative fees after each swap (block, amount0, amount1)
+    let mut fee_checkpoints = Vec::new();
+
     // keep track how many times we failed to swap
     let mut failed_swaps = 0;
+    let mut partial_swaps = 0;
+
+    // the swaps only have a block number, their timestamp is estimated from the fork block
+    let block_secs = average_block_secs(chain_id);
+    let fork_timestamp = fork_full_block.header.timestamp;
 
     // simulate all the swaps that occured
     trace!("Simulating {} swaps", volume.swaps.len());
-    for pool_swap in &volume.swaps {
-        let swap_params = SwapRouter::Params {
-            input_token: pool_swap.token_in.address,
-            output_token: pool_swap.token_out.address,
-            amount_in: pool_swap.amount_in,
-            pool: args.pool.address,
-            pool_variant: U256::from(1),
-            fee,
-            minimum_received: U256::ZERO,
-        };
+    for (_batch, swaps) in volume.swaps.chunks(REPLAY_BATCH_SIZE).enumerate() {
+        #[cfg(feature = "telemetry")]
+        let _span = tracing::debug_span!("swap_replay_batch", batch = _batch, swaps = swaps.len())
+            .entered();
+
+        for pool_swap in swaps {
+            // move the env to the block of the swap so the pool oracle records observations over time
+            if let Some(block_secs) = block_secs {
+                let elapsed = pool_swap.block.saturating_sub(fork_block_number) as f64 * block_secs;
+                // on Arbitrum the NUMBER opcode returns the L1 block, which the swaps don't have
+                let number = (!is_arbitrum(chain_id)).then_some(pool_swap.block);
+                advance_block(&mut evm, number, fork_timestamp + elapsed as u64);
+            }
 
-        if let Err(e) = swap(
-            &mut evm,
-            swap_params,
-            swapper.address,
-            swap_router.address,
-            true,
-        ) {
-            failed_swaps += 1;
-            trace!("Failed to swap: {:?}", e);
-            continue;
-        }
+            let swap_params = SwapRouter::Params {
+                input_token: pool_swap.token_in.address,
+                output_token: pool_swap.token_out.address,
+                amount_in: pool_swap.amount_in,
+                pool: args.pool.address,
+                pool_variant: PoolVariant::UniswapV3.into(),
+                fee,
+                // historical swaps are replayed as they happened, no slippage protection
+                minimum_received: U256::ZERO,
+            };
+
+            let swap_res = replay_swap(
+                &mut evm,
+                swap_params,
+                swapper,
+                swap_router,
+                args.retry_mode,
+            );
+
+            if args.replay_mode == ReplayMode::PoolReported {
+                if let (Some(sqrt_price_x96), Some(tick), Some(liquidity)) =
+                    (pool_swap.sqrt_price_x96, pool_swap.tick, pool_swap.liquidity)
+                {
+                    // the reported liquidity does not include our position
+                    let in_range = lower_tick_i32 <= tick && tick < upper_tick_i32;
+                    let liquidity = if in_range {
+                        liquidity.saturating_add(position_liquidity)
+                    } else {
+                        liquidity
+                    };
+
+                    write_v3_pool_state(
+                        evm.db_mut(),
+                        args.pool.address,
+                        sqrt_price_x96,
+                        tick,
+                        Some(liquidity),
+                    )?;
+                }
+            }
 
-        // collect the fees
-        let collect_params = INonfungiblePositionManager::CollectParams {
-            tokenId: token_id,
-            recipient: lp_provider.address,
-            amount0Max: u128::MAX,
-            amount1Max: u128::MAX,
-        };
+            match swap_res {
+                Ok(replayed) if replayed < pool_swap.amount_in => partial_swaps += 1,
+                Ok(_) => {}
+                Err(e) => {
+                    failed_swaps += 1;
+                    trace!("Failed to swap: {:?}", e);
+                    continue;
+                }
+            }
 
-        let (amount0, amount1) = collect_fees(
-            &mut evm,
-            collect_params,
-            lp_provider.address,
-            NFT_POSITION_CONTRACT,
-            false,
-        )?;
+            // collect the fees
+            let collect_params = INonfungiblePositionManager::CollectParams {
+                tokenId: token_id,
+                recipient: lp_provider,
+                amount0Max: u128::MAX,
+                amount1Max: u128::MAX,
+            };
+
+            let (amount0, amount1) = collect_fees(
+                &mut evm,
+                collect_params,
+                lp_provider,
+                position_manager,
+                false,
+            )?;
+
+            // compare the amount0 and amount1 with the collected amounts
+            let is_in_range = if amount0 > collected0 || amount1 > collected1 {
+                collected0 = amount0;
+                collected1 = amount1;
+                true
+            } else {
+                false
+            };
+
+            if let Some(price) = pool_price(pool_swap, &args.pool) {
+                prices.push(PricePoint {
+                    block: pool_swap.block,
+                    price,
+                });
+            }
+            if args.record_trajectory {
+                let (sqrt_price_x96, tick) = read_v3_pool_state(evm.db_mut(), args.pool.address)?;
+                trajectory.push(TrajectoryPoint {
+                    block: pool_swap.block,
+                    price: args.pool.price_at_sqrt_price(sqrt_price_x96, args.pool.token0.address),
+                    tick,
+                    in_range: is_in_range,
+                });
+            }
+            replayed_swaps.push((pool_swap, is_in_range));
 
-        // compare the amount0 and amount1 with the collected amounts
-        let is_in_range = if amount0 > collected0 || amount1 > collected1 {
-            collected0 = amount0;
-            collected1 = amount1;
-            true
-        } else {
-            false
-        };
+            price_ranges.push(PriceRange::new(is_in_range, pool_swap.block));
+            fee_checkpoints.push((pool_swap.block, amount0, amount1));
+        }
 
-        // TODO: store big swaps in a separate struct
+        #[cfg(feature = "telemetry")]
+        tracing::debug!(failed_swaps, "Swap replay batch finished");
+    }
 
-        price_ranges.push(PriceRange::new(is_in_range, pool_swap.block));
+    #[cfg(feature = "telemetry")]
+    {
+        let span = tracing::Span::current();
+        span.record("swaps", volume.swaps.len());
+        span.record("failed_swaps", failed_swaps);
+        span.record("partial_swaps", partial_swaps);
     }
 
     // Collect all the fees earned
     let collect_params = INonfungiblePositionManager::CollectParams {
         tokenId: token_id,
-        recipient: swapper.address,
+        recipient: swapper,
         amount0Max: u128::MAX,
         amount1Max: u128::MAX,
     };
 
+    let collect_call_data = encode_collect(collect_params.clone());
+    let collect_gas = estimate_gas(
+        &mut evm,
+        lp_provider,
+        position_manager,
+        collect_call_data.clone(),
+    )?;
+
     let (amount0, amount1) = collect_fees(
         &mut evm,
         collect_params,
-        lp_provider.address,
-        NFT_POSITION_CONTRACT,
+        lp_provider,
+        position_manager,
+        true,
+    )?;
+
+    let earned0 = to_f64(amount0, args.pool.token0.decimals)?;
+    let earned1 = to_f64(amount1, args.pool.token1.decimals)?;
+
+    // exit the position, the fees are already collected so what's owed now is the principal only
+    let decrease_params = INonfungiblePositionManager::DecreaseLiquidityParams {
+        tokenId: token_id,
+        liquidity: position_liquidity,
+        amount0Min: U256::ZERO,
+        amount1Min: U256::ZERO,
+        deadline: deadline_after(evm.block().timestamp.to::<u64>(), DEFAULT_DEADLINE_SECS),
+    };
+    decrease_liquidity(
+        &mut evm,
+        decrease_params,
+        lp_provider,
+        position_manager,
+        true,
+    )?;
+
+    let collect_params = INonfungiblePositionManager::CollectParams {
+        tokenId: token_id,
+        recipient: lp_provider,
+        amount0Max: u128::MAX,
+        amount1Max: u128::MAX,
+    };
+    let (principal_out0, principal_out1) = collect_fees(
+        &mut evm,
+        collect_params,
+        lp_provider,
+        position_manager,
         true,
     )?;
 
-    let earned0 = format_units(amount0, args.pool.token0.decimals)?.parse::<f64>()?;
-    let earned1 = format_units(amount1, args.pool.token1.decimals)?.parse::<f64>()?;
+    // swap the fees to the stable after the exit, so the harvest doesn't move the exit price
+    let harvested = match &args.harvest {
+        Some(harvest) => {
+            let fees = [
+                (args.pool.token0.address, amount0),
+                (args.pool.token1.address, amount1),
+            ];
+            let out = harvest_fees(
+                &mut evm,
+                harvest,
+                &args.pool,
+                fees,
+                swapper,
+                swap_router,
+            )?;
+            Some(to_f64(out, harvest.stable.decimals)?)
+        }
+        None => None,
+    };
+
+    let principal_in0 = to_f64(principal_in0, args.pool.token0.decimals)?;
+    let principal_in1 = to_f64(principal_in1, args.pool.token1.decimals)?;
+    let principal_out0 = to_f64(principal_out0, args.pool.token0.decimals)?;
+    let principal_out1 = to_f64(principal_out1, args.pool.token1.decimals)?;
 
     // get the current usd price of token0 and token1
     let state = UniswapV3Pool::fetch_state(args.pool.address, client.clone(), None).await?;
     pool.update_state(state);
 
-    let (latest_token0_usd, latest_token1_usd) = pool.tokens_usd(client.clone(), None).await?;
+    let (latest_token0_usd, latest_token1_usd) = args
+        .deposit_pricing
+        .tokens_usd(&pool, client.clone(), None)
+        .await?;
 
     let earned0_usd = latest_token0_usd * earned0;
     let earned1_usd = latest_token1_usd * earned1;
 
+    let harvested_usd = match (&args.harvest, harvested) {
+        (Some(harvest), Some(harvested)) => {
+            let stable_usd =
+                get_token_price(client.clone(), None, chain_id, harvest.stable.address).await?;
+            Some(harvested * stable_usd)
+        }
+        _ => None,
+    };
+
+    let principal_in_usd = latest_token0_usd * principal_in0 + latest_token1_usd * principal_in1;
+    let principal_out_usd = latest_token0_usd * principal_out0 + latest_token1_usd * principal_out1;
+
     // not sure what's most correct but calculate the volume based on the latest prices
     let buy_volume_usd = volume.buy_volume_usd(latest_token0_usd, pool.token0.decimals)?;
     let sell_volume_usd = volume.sell_volume_usd(latest_token1_usd, pool.token1.decimals)?;
@@ -449,22 +1049,100 @@ where
     let out_of_range = price_ranges.iter().filter(|r| !r.is_in_range).count();
     let in_range = price_ranges.iter().filter(|r| r.is_in_range).count();
 
+    // the cost of minting the position and collecting the fees at the latest gas price
+    // on OP-stack chains this includes the L1 data fee
+    let native = if chain_id == 56 { wbnb(chain_id)? } else { weth(chain_id)? };
+    let native_usd = get_token_price(client.clone(), None, chain_id, native).await?;
+    let mint_fee =
+        suggest_tx_fee(client.clone(), chain_id, position_manager, mint_call_data, mint_gas).await?;
+    let collect_fee =
+        suggest_tx_fee(client.clone(), chain_id, position_manager, collect_call_data, collect_gas)
+            .await?;
+    let gas_cost_usd = mint_fee.total_usd(native_usd)? + collect_fee.total_usd(native_usd)?;
+
     // calculate the APR of the position
     let total_earned = earned0_usd + earned1_usd;
     let mut apr = 0.0;
+    let mut net_apr = 0.0;
 
-    match block_time {
-        BlockTime::Days(days) => {
-            apr = (total_earned / args.deposit_amount) * (365.0 / days as f64) * 100.0;
-        }
-        BlockTime::Hours(hours) => {
-            apr = (total_earned / args.deposit_amount) * (8760.0 / hours as f64) * 100.0;
-        }
+    let periods_per_year = match block_time {
+        BlockTime::Days(days) => Some(365.0 / days as f64),
+        BlockTime::Hours(hours) => Some(8760.0 / hours as f64),
         BlockTime::Block(_) => {
             // TODO
+            None
         }
+        BlockTime::Period(..) => block_time
+            .seconds()
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| 365.0 * 86400.0 / seconds as f64),
+    };
+
+    // the expected staker rewards, the position gets its share of the active liquidity while in range
+    let liquidity_share =
+        position_liquidity as f64 / fork_liquidity.saturating_add(position_liquidity) as f64;
+    let in_range_ratio = if price_ranges.is_empty() {
+        0.0
+    } else {
+        in_range as f64 / price_ranges.len() as f64
+    };
+
+    let incentive_rewards = estimate_incentive_rewards(
+        client.clone(),
+        chain_id,
+        &args.incentives,
+        fork_block,
+        full_block.header.timestamp,
+        liquidity_share,
+        in_range_ratio,
+    )
+    .await?;
+    let incentive_rewards_usd: f64 = incentive_rewards.iter().map(|r| r.amount_usd).sum();
+    let mut incentive_apr = 0.0;
+
+    if let Some(periods_per_year) = periods_per_year {
+        apr = (total_earned / args.deposit_amount) * periods_per_year * 100.0;
+        net_apr = ((total_earned - gas_cost_usd) / args.deposit_amount) * periods_per_year * 100.0;
+        incentive_apr = (incentive_rewards_usd / args.deposit_amount) * periods_per_year * 100.0;
     }
 
+    // bucket the earned fees into epochs
+    let epoch_blocks = match args.epoch_blocks {
+        Some(blocks) => blocks,
+        None if is_arbitrum(chain_id) => {
+            let day_ago = BlockTime::Days(1)
+                .go_back_with_client(client.clone(), chain_id, latest_block)
+                .await?;
+            latest_block - day_ago
+        }
+        None => BlockTime::Days(1).go_forward(chain_id, 0)?,
+    };
+
+    let epochs = bucket_earnings(
+        &fee_checkpoints,
+        fork_block_number,
+        latest_block,
+        epoch_blocks,
+        &args.pool,
+        latest_token0_usd,
+        latest_token1_usd,
+    )?;
+
+    let big_swaps = big_swaps(
+        &replayed_swaps,
+        &args.pool,
+        latest_token0_usd,
+        latest_token1_usd,
+    )?;
+
+    let swap_volumes = swap_volumes_usd(
+        &replayed_swaps,
+        &args.pool,
+        latest_token0_usd,
+        latest_token1_usd,
+    )?;
+    let health = position_health(&swap_volumes, args.lower_range, args.upper_range, price_assumption);
+
     let result = PositionResult {
         token0: args.pool.token0.clone(),
         token1: args.pool.token1.clone(),
@@ -482,14 +1160,378 @@ where
         total_fee0,
         total_fee1,
         failed_swaps,
+        partial_swaps,
         out_of_range,
         in_range,
         apr,
+        gas_cost_usd,
+        net_apr,
+        epochs,
+        principal_in0,
+        principal_in1,
+        principal_out0,
+        principal_out1,
+        principal_in_usd,
+        principal_out_usd,
+        incentive_rewards,
+        incentive_rewards_usd,
+        incentive_apr,
+        external_incentive_apr: 0.0,
+        harvested,
+        harvested_usd,
+        lower_range: args.lower_range,
+        upper_range: args.upper_range,
+        prices,
+        big_swaps,
+        trajectory,
+        health,
     };
 
     Ok(result)
 }
 
+/// The price of token0 in token1 reported by a swap, None if the swap has no sqrtPriceX96
+fn pool_price(swap: &SwapData, pool: &UniswapV3Pool) -> Option<f64> {
+    Some(pool.price_at_sqrt_price(swap.sqrt_price_x96?, pool.token0.address))
+}
+
+/// The input amount in USD of each swap at the latest prices and whether the position was in range
+fn swap_volumes_usd(
+    swaps: &[(&SwapData, bool)],
+    pool: &UniswapV3Pool,
+    token0_usd: f64,
+    token1_usd: f64,
+) -> Result<Vec<(f64, bool)>, anyhow::Error> {
+    swaps
+        .iter()
+        .map(|(swap, in_range)| {
+            let token_in_usd = if swap.token_in.address == pool.token0.address {
+                token0_usd
+            } else {
+                token1_usd
+            };
+            Ok((to_f64(swap.amount_in, swap.token_in.decimals)? * token_in_usd, *in_range))
+        })
+        .collect()
+}
+
+/// The [BIG_SWAPS] largest swaps by USD value, largest first
+fn big_swaps(
+    swaps: &[(&SwapData, bool)],
+    pool: &UniswapV3Pool,
+    token0_usd: f64,
+    token1_usd: f64,
+) -> Result<Vec<BigSwap>, anyhow::Error> {
+    let mut big_swaps = Vec::with_capacity(swaps.len());
+
+    for (swap, in_range) in swaps {
+        let token_in_usd = if swap.token_in.address == pool.token0.address {
+            token0_usd
+        } else {
+            token1_usd
+        };
+        let amount_in = to_f64(swap.amount_in, swap.token_in.decimals)?;
+
+        big_swaps.push(BigSwap {
+            block: swap.block,
+            tx_hash: swap.tx_hash.clone(),
+            token_in: swap.token_in.clone(),
+            token_out: swap.token_out.clone(),
+            amount_in,
+            amount_out: to_f64(swap.amount_out, swap.token_out.decimals)?,
+            amount_usd: amount_in * token_in_usd,
+            in_range: *in_range,
+        });
+    }
+
+    big_swaps.sort_by(|a, b| b.amount_usd.total_cmp(&a.amount_usd));
+    big_swaps.truncate(BIG_SWAPS);
+    Ok(big_swaps)
+}
+
+/// Swap the collected fees to the stable of `harvest` and return the amount of the stable
+///
+/// The caller must hold the fees and have approved `contract` to spend them
+fn harvest_fees<DB>(
+    evm: &mut revm::Evm<'static, (), DB>,
+    harvest: &Harvest,
+    pool: &UniswapV3Pool,
+    fees: [(Address, U256); 2],
+    caller: Address,
+    contract: Address,
+) -> Result<U256, anyhow::Error>
+where
+    DB: Database + revm::DatabaseCommit,
+{
+    let stable = harvest.stable.address;
+    let mut harvested = U256::ZERO;
+
+    for (token, amount) in fees {
+        if amount.is_zero() {
+            continue;
+        }
+
+        if token == stable {
+            harvested += amount;
+            continue;
+        }
+
+        let params = match harvest.pools.iter().find(|p| p.token == token) {
+            Some(p) => Params::new(token, stable, amount, p.pool, p.variant, p.fee),
+            None if pool.token0.address == stable || pool.token1.address == stable => Params::new(
+                token,
+                stable,
+                amount,
+                pool.address,
+                PoolVariant::UniswapV3,
+                pool.fee,
+            ),
+            None => return Err(anyhow::anyhow!("No harvest pool for token {}", token)),
+        };
+
+        harvested += swap(evm, params, caller, contract, true)
+            .with_context(|| format!("Failed to harvest token {}", token))?;
+    }
+
+    Ok(harvested)
+}
+
+/// Estimate the rewards of the given incentives between the fork block and `to_timestamp`
+async fn estimate_incentive_rewards<T, P>(
+    client: P,
+    chain_id: u64,
+    keys: &[IncentiveKey],
+    fork_block: BlockId,
+    to_timestamp: u64,
+    liquidity_share: f64,
+    in_range_ratio: f64,
+) -> Result<Vec<IncentiveReward>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone,
+{
+    let mut rewards = Vec::new();
+    if keys.is_empty() {
+        return Ok(rewards);
+    }
+
+    let from_timestamp = client
+        .get_block(fork_block, false.into())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Fork block not found"))?
+        .header
+        .timestamp;
+
+    for key in keys {
+        let (unclaimed, seconds_claimed_x128, _) =
+            incentives(client.clone(), UNISWAP_V3_STAKER, key, Some(fork_block)).await?;
+
+        let amount = estimate_incentive_reward(
+            key,
+            unclaimed,
+            seconds_claimed_x128,
+            from_timestamp,
+            to_timestamp,
+            liquidity_share,
+            in_range_ratio,
+        )?;
+
+        let reward_token =
+            ERC20Token::new(client.clone(), key.rewardToken, chain_id, TokenKind::Other).await?;
+        let amount = amount / 10f64.powi(reward_token.decimals as i32);
+        let price = get_token_price(client.clone(), None, chain_id, key.rewardToken).await?;
+
+        rewards.push(IncentiveReward {
+            key: key.clone(),
+            reward_token,
+            amount,
+            amount_usd: amount * price,
+        });
+    }
+
+    Ok(rewards)
+}
+
+/// Replay a historical swap and return the amount of the input that went through
+///
+/// On failure the swap is retried according to `retry_mode`
+fn replay_swap<DB>(
+    evm: &mut revm::Evm<'static, (), DB>,
+    params: SwapRouter::Params,
+    caller: Address,
+    contract: Address,
+    retry_mode: RetryMode,
+) -> Result<U256, anyhow::Error>
+where
+    DB: Database + revm::DatabaseCommit,
+{
+    let amount_in = params.amount_in;
+    let err = match swap(evm, params.clone(), caller, contract, true) {
+        Ok(_) => return Ok(amount_in),
+        Err(e) => e,
+    };
+
+    let RetryMode::Split { max_halvings } = retry_mode else {
+        return Err(err);
+    };
+
+    let mut remaining = amount_in;
+    let mut chunk = amount_in;
+    for _ in 0..max_halvings {
+        chunk /= U256::from(2);
+        if chunk.is_zero() {
+            break;
+        }
+
+        while remaining >= chunk {
+            let mut chunk_params = params.clone();
+            chunk_params.amount_in = chunk;
+            if swap(evm, chunk_params, caller, contract, true).is_err() {
+                break;
+            }
+            remaining -= chunk;
+        }
+
+        if remaining < amount_in {
+            break;
+        }
+    }
+
+    if remaining == amount_in {
+        return Err(err);
+    }
+
+    trace!("Replayed {} of {} after splitting the swap", amount_in - remaining, amount_in);
+    Ok(amount_in - remaining)
+}
+
+/// Execute a call without committing and return the gas it used
+fn estimate_gas<DB>(
+    evm: &mut revm::Evm<'static, (), DB>,
+    caller: Address,
+    contract: Address,
+    call_data: Bytes,
+) -> Result<u64, anyhow::Error>
+where
+    DB: Database,
+    DB::Error: std::fmt::Debug,
+{
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = call_data;
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = revm::primitives::TransactTo::Call(contract);
+
+    let res = evm
+        .transact()
+        .map_err(|e| anyhow::anyhow!("Failed to estimate gas: {:?}", e))?;
+    Ok(res.result.gas_used())
+}
+
+/// Read the sqrtPriceX96 and the tick of a Uniswap V3 pool from the `slot0` of the fork
+pub fn read_v3_pool_state(db: &mut ForkDB, pool: Address) -> Result<(U256, i32), anyhow::Error> {
+    let slot0 = db.storage(pool, SLOT0_SLOT)?;
+    Ok(slot0_from_word(slot0))
+}
+
+/// Overwrite the sqrtPriceX96, tick and optionally the active liquidity of a Uniswap V3 pool in the fork
+///
+/// The rest of `slot0` (observation index, cardinality, feeProtocol, unlocked) is kept intact
+pub fn write_v3_pool_state(
+    db: &mut ForkDB,
+    pool: Address,
+    sqrt_price_x96: U256,
+    tick: i32,
+    liquidity: Option<u128>,
+) -> Result<(), anyhow::Error> {
+    let slot0 = db.storage(pool, SLOT0_SLOT)?;
+
+    // sqrtPriceX96 occupies the lower 160 bits and the tick the next 24 bits
+    let price_mask = (U256::from(1) << 160) - U256::from(1);
+    let state_mask = (U256::from(1) << 184) - U256::from(1);
+    let tick_bits = U256::from((tick as u32) & 0xFFFFFF);
+
+    let new_slot0 = (slot0 & !state_mask) | (sqrt_price_x96 & price_mask) | (tick_bits << 160);
+    db.insert_account_storage(pool, SLOT0_SLOT, new_slot0)?;
+
+    if let Some(liquidity) = liquidity {
+        db.insert_account_storage(pool, LIQUIDITY_SLOT, U256::from(liquidity))?;
+    }
+
+    Ok(())
+}
+
+/// Bucket the cumulative collected fees into epochs of `epoch_blocks` size
+///
+/// ## Arguments
+///
+/// * `checkpoints` - The cumulative fees (block, amount0, amount1) sorted by block
+/// * `start_block` - The block the simulation started from
+/// * `end_block` - The block the simulation ended
+/// * `epoch_blocks` - The size of each epoch in blocks
+/// * `pool` - The pool the position was simulated on
+/// * `token0_usd` - The USD price of token0 used to value the earnings
+/// * `token1_usd` - The USD price of token1 used to value the earnings
+pub fn bucket_earnings(
+    checkpoints: &[(u64, U256, U256)],
+    start_block: u64,
+    end_block: u64,
+    epoch_blocks: u64,
+    pool: &UniswapV3Pool,
+    token0_usd: f64,
+    token1_usd: f64,
+) -> Result<Vec<EpochEarnings>, anyhow::Error> {
+    if epoch_blocks == 0 {
+        return Err(anyhow::anyhow!("Epoch size must be greater than 0"));
+    }
+
+    let mut epochs = Vec::new();
+    let mut epoch_start = start_block;
+    let mut epoch = 0;
+
+    // cumulative amounts at the end of the previous epoch
+    let mut prev0 = U256::ZERO;
+    let mut prev1 = U256::ZERO;
+
+    // cumulative amounts seen so far
+    let mut last0 = U256::ZERO;
+    let mut last1 = U256::ZERO;
+
+    let mut checkpoints = checkpoints.iter().peekable();
+
+    while epoch_start <= end_block {
+        let epoch_end = (epoch_start + epoch_blocks - 1).min(end_block);
+
+        while let Some((block, amount0, amount1)) = checkpoints.peek() {
+            if *block > epoch_end {
+                break;
+            }
+            last0 = *amount0;
+            last1 = *amount1;
+            checkpoints.next();
+        }
+
+        let earned0 = to_f64(last0.saturating_sub(prev0), pool.token0.decimals)?;
+        let earned1 = to_f64(last1.saturating_sub(prev1), pool.token1.decimals)?;
+
+        epochs.push(EpochEarnings {
+            epoch,
+            start_block: epoch_start,
+            end_block: epoch_end,
+            earned0,
+            earned1,
+            earned0_usd: earned0 * token0_usd,
+            earned1_usd: earned1 * token1_usd,
+        });
+
+        prev0 = last0;
+        prev1 = last1;
+        epoch += 1;
+        epoch_start = epoch_end + 1;
+    }
+
+    Ok(epochs)
+}
+
 pub fn divide_by_fee(fee: u32, amount: f64) -> f64 {
     let fee_percent = match fee {
         fee if fee == 100 => 0.01 / 100.0,
@@ -538,6 +1580,25 @@ pub async fn get_average_price<T, P>(
     step: usize,
     pool: UniswapV3Pool,
 ) -> Result<AvgPrice, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    let config = Config::default().with_max_concurrency(10);
+    get_average_price_with_config(client, chain_id, latest_block, block_time, step, pool, &config)
+        .await
+}
+
+/// Get the average price of a Uniswap V3 pool (token0 in terms of token1) using the given [Config]
+pub async fn get_average_price_with_config<T, P>(
+    client: P,
+    chain_id: u64,
+    latest_block: u64,
+    block_time: BlockTime,
+    step: usize,
+    pool: UniswapV3Pool,
+    config: &Config,
+) -> Result<AvgPrice, anyhow::Error>
 where
     T: Transport + Clone,
     P: Provider<T, Ethereum> + Clone + 'static,
@@ -545,21 +1606,26 @@ where
     let pool_address = pool.address.clone();
     let prices = Arc::new(Mutex::new(Vec::new()));
     let pool = Arc::new(Mutex::new(pool));
-    let semaphore = Arc::new(Semaphore::new(10));
+    let semaphore = Arc::new(Semaphore::new(config.concurrency()));
     let mut tasks: Vec<JoinHandle<Result<(), anyhow::Error>>> = Vec::new();
 
-    let from_block = block_time.go_back(chain_id, latest_block)?;
+    let (from_block, to_block) = block_time
+        .block_range_with_client(client.clone(), chain_id, latest_block)
+        .await?;
 
-    for block in (from_block..latest_block).step_by(step) {
+    for block in (from_block..to_block).step_by(step) {
         let client = client.clone();
         let prices = prices.clone();
         let pool = pool.clone();
         let semaphore = semaphore.clone();
+        let config = config.clone();
 
         let task = tokio::spawn(async move {
             let _permit = semaphore.acquire_owned().await.unwrap();
             let block_id = BlockId::number(block);
-            let state = UniswapV3Pool::fetch_state(pool_address, client, Some(block_id)).await?;
+            let state = config
+                .timed(UniswapV3Pool::fetch_state(pool_address, client, Some(block_id)))
+                .await?;
 
             let mut pool = pool.lock().await;
             pool.update_state(state);
@@ -1,8 +1,17 @@
 pub mod fee_math;
 pub mod lp_provider;
-
-use alloy_primitives::{Address, I256, U256, utils::format_units};
+pub mod create_pool;
+pub mod staker;
+pub mod depth;
+pub mod position_nft;
+pub mod range_order;
+pub mod position_report;
+pub mod position;
+pub mod health;
+
+use alloy_primitives::{Address, I256, U256};
 use alloy_rpc_types::{BlockId, Log};
+use alloy_sol_types::{SolCall, SolEvent};
 
 use alloy_contract::private::Network;
 use alloy_provider::Provider;
@@ -12,13 +21,22 @@ use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::str::FromStr;
-use tokio::try_join;
 use uniswap_v3_math::{tick_bitmap::position, tick_math::*};
 
 use super::super::consts::*;
+use super::variant::DexVariant;
 use crate::defi::utils::common_addr::*;
-use crate::defi::utils::chain_link::get_token_price;
+use crate::defi::utils::chain_link::get_token_prices;
+use crate::defi::utils::slippage;
+use crate::defi::analytics::ohlc::{build_candles, Candle, Trade};
+use crate::defi::analytics::tvl::pool_balances;
+use crate::utils::block_number;
 use crate::utils::logs::events::SwapData;
+use crate::utils::format::to_f64;
+use crate::utils::batch_request::v3_ticks;
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
+use crate::utils::storage::{extract_bits, get_storage_batch, mapping_slot, signed_key};
+use fee_math::UsdAnchor;
 use crate::{
     abi::uniswap::pool::v3::{self, *},
     defi::currency::erc20::ERC20Token,
@@ -32,8 +50,17 @@ pub struct UniswapV3Pool {
     pub fee: u32,
     pub token0: ERC20Token,
     pub token1: ERC20Token,
+
+    /// The DEX that deployed the pool
+    #[serde(default)]
+    pub variant: DexVariant,
+
     #[serde(skip)]
     state: Option<State>,
+
+    /// The position (block, log index) of the last log applied by [UniswapV3Pool::apply_log]
+    #[serde(skip)]
+    last_log: Option<(u64, u64)>,
 }
 
 /// Represents the volume of a pool that occured at some point
@@ -42,18 +69,125 @@ pub struct PoolVolume {
     pub buy_volume: U256,
     pub sell_volume: U256,
     pub swaps: Vec<SwapData>,
+
+    /// The number of swaps buying token0 (token1 in)
+    pub buys: usize,
+
+    /// The number of swaps selling token0 (token0 in)
+    pub sells: usize,
+}
+
+/// The swaps of an account in a pool, see [PoolVolume::by_account]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraderVolume {
+    pub account: Address,
+
+    /// The number of swaps buying token0
+    pub buys: usize,
+
+    /// The number of swaps selling token0
+    pub sells: usize,
+
+    /// The token0 bought and sold
+    pub volume0: U256,
+
+    /// The token1 paid and received
+    pub volume1: U256,
 }
 
 impl PoolVolume {
     pub fn buy_volume_usd(&self, usd_value: f64, decimals: u8) -> Result<f64, anyhow::Error> {
-        let formatted = format_units(self.buy_volume, decimals)?.parse::<f64>()?;
+        let formatted = to_f64(self.buy_volume, decimals)?;
         Ok(formatted * usd_value)
     }
 
     pub fn sell_volume_usd(&self, usd_value: f64, decimals: u8) -> Result<f64, anyhow::Error> {
-        let formatted = format_units(self.sell_volume, decimals)?.parse::<f64>()?;
+        let formatted = to_f64(self.sell_volume, decimals)?;
         Ok(formatted * usd_value)
+    }
+
+    /// The volume of every account, swaps without an account are skipped
+    ///
+    /// Sorted by the volume in token1, highest first
+    ///
+    /// ## Arguments
+    ///
+    /// * `token1` - The current token1 of the pool, a swap paying it buys token0 like in [UniswapV3Pool::get_volume_from_logs]
+    pub fn by_account(&self, token1: Address) -> Vec<TraderVolume> {
+        let mut traders: HashMap<Address, TraderVolume> = HashMap::new();
+
+        for swap in &self.swaps {
+            let Some(account) = swap.account else {
+                continue;
+            };
+            let trader = traders.entry(account).or_insert_with(|| TraderVolume {
+                account,
+                buys: 0,
+                sells: 0,
+                volume0: U256::ZERO,
+                volume1: U256::ZERO,
+            });
+
+            if swap.token_in.address == token1 {
+                trader.buys += 1;
+                trader.volume0 += swap.amount_out;
+                trader.volume1 += swap.amount_in;
+            } else {
+                trader.sells += 1;
+                trader.volume0 += swap.amount_in;
+                trader.volume1 += swap.amount_out;
+            }
+        }
+
+        let mut traders: Vec<TraderVolume> = traders.into_values().collect();
+        traders.sort_by(|a, b| b.volume1.cmp(&a.volume1).then(a.account.cmp(&b.account)));
+        traders
+    }
+
+    /// The `n` accounts with the highest volume in token1, see [Self::by_account]
+    pub fn top_traders(&self, token1: Address, n: usize) -> Vec<TraderVolume> {
+        let mut traders = self.by_account(token1);
+        traders.truncate(n);
+        traders
+    }
 }
+
+/// Build the [PoolVolume] of decoded swaps, shared by the V2 and V3 pools
+///
+/// A swap paying `token1` buys token0, `token0` and `token1` are the current tokens of the pool so a toggled pair is classified the same way
+///
+/// ## Arguments
+///
+/// * `swaps` - The decoded swaps of the pool
+/// * `token0` - The current token0 of the pool
+/// * `token1` - The current token1 of the pool
+pub fn volume_from_swaps(mut swaps: Vec<SwapData>, token0: Address, token1: Address) -> PoolVolume {
+    let mut buy_volume = U256::ZERO;
+    let mut sell_volume = U256::ZERO;
+    let (mut buys, mut sells) = (0, 0);
+
+    for swap in &swaps {
+        if swap.token_in.address == token1 {
+            buy_volume += swap.amount_in;
+            buys += 1;
+        } else {
+            sells += 1;
+        }
+
+        if swap.token_out.address == token0 {
+            sell_volume += swap.amount_out;
+        }
+    }
+
+    swaps.sort_by(|a, b| a.block.cmp(&b.block));
+
+    PoolVolume {
+        buy_volume,
+        sell_volume,
+        swaps,
+        buys,
+        sells,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -66,8 +200,228 @@ pub struct State {
     pub tick_bitmap: HashMap<i16, U256>,
     pub ticks: HashMap<i32, TickInfo>,
     pub pool_tick: PoolTick,
+
+    /// `feeProtocol` of slot0, the lower 4 bits for token0 and the upper 4 bits for token1
+    ///
+    /// A non-zero value `n` sends `1/n` of the swap fees to the protocol instead of the LPs
+    pub fee_protocol: u8,
+}
+
+/// The changes between two [State]s of a pool, see [State::diff]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDiff {
+    pub sqrt_price_before: U256,
+    pub sqrt_price_after: U256,
+    pub tick_before: i32,
+    pub tick_after: i32,
+    pub liquidity_before: u128,
+    pub liquidity_after: u128,
+    pub fee_protocol_before: u8,
+    pub fee_protocol_after: u8,
+
+    /// The ticks whose net liquidity changed, were added or were removed, sorted by tick
+    pub ticks: Vec<TickChange>,
+
+    /// The tick bitmap words that differ, sorted
+    pub bitmap_words: Vec<i16>,
+}
+
+/// The net liquidity of a tick before and after, None if the tick is not in the state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickChange {
+    pub tick: i32,
+    pub liquidity_net_before: Option<i128>,
+    pub liquidity_net_after: Option<i128>,
+}
+
+/// The swap fee of a simulated swap, in the input token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SwapFees {
+    /// The part of the fee that goes to the liquidity providers
+    pub lp_fee: U256,
+
+    /// The part of the fee that goes to the protocol
+    pub protocol_fee: U256,
+}
+
+
+impl State {
+    /// Add `liquidity_delta` to a position from `tick_lower` to `tick_upper`, like a Mint (positive) or a Burn (negative)
+    ///
+    /// The active liquidity changes if the current tick is in the range. The ticks are only updated
+    /// if their bitmap word is loaded, so the state doesn't pretend to know the rest of the word
+    pub fn update_position(
+        &mut self,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
+    ) -> Result<(), anyhow::Error> {
+        if tick_lower >= tick_upper {
+            return Err(anyhow::anyhow!("Invalid range: {} - {}", tick_lower, tick_upper));
+        }
+
+        self.update_tick(tick_lower, liquidity_delta, false)?;
+        self.update_tick(tick_upper, liquidity_delta, true)?;
+
+        if tick_lower <= self.tick && self.tick < tick_upper {
+            self.liquidity = self
+                .liquidity
+                .checked_add_signed(liquidity_delta)
+                .ok_or_else(|| anyhow::anyhow!("Liquidity overflow"))?;
+        }
+        Ok(())
+    }
+
+    fn update_tick(&mut self, tick: i32, liquidity_delta: i128, upper: bool) -> Result<(), anyhow::Error> {
+        let (word, bit) = position(tick.div_euclid(self.tick_spacing));
+        let Some(bitmap) = self.tick_bitmap.get_mut(&word) else {
+            return Ok(());
+        };
+
+        let info = self.ticks.entry(tick).or_insert(TickInfo {
+            liquidity_gross: 0,
+            liquidity_net: 0,
+            initialized: false,
+        });
+
+        info.liquidity_gross = info
+            .liquidity_gross
+            .checked_add_signed(liquidity_delta)
+            .ok_or_else(|| anyhow::anyhow!("Liquidity gross overflow at tick {}", tick))?;
+        // the net liquidity is added when crossing the lower tick going up and removed at the upper tick
+        info.liquidity_net = if upper {
+            info.liquidity_net - liquidity_delta
+        } else {
+            info.liquidity_net + liquidity_delta
+        };
+        info.initialized = info.liquidity_gross > 0;
+
+        let mask = U256::from(1) << bit as usize;
+        if info.initialized {
+            *bitmap |= mask;
+        } else {
+            *bitmap &= !mask;
+            self.ticks.remove(&tick);
+        }
+        Ok(())
+    }
+
+    /// Compare this state (before) with `other` (after)
+    ///
+    /// Useful to find where a simulated state diverges from the state fetched on-chain
+    pub fn diff(&self, other: &State) -> StateDiff {
+        let net = |state: &State, tick: &i32| state.ticks.get(tick).map(|info| info.liquidity_net);
+
+        let mut ticks: Vec<TickChange> = self
+            .ticks
+            .keys()
+            .chain(other.ticks.keys().filter(|tick| !self.ticks.contains_key(tick)))
+            .map(|tick| TickChange {
+                tick: *tick,
+                liquidity_net_before: net(self, tick),
+                liquidity_net_after: net(other, tick),
+            })
+            .filter(|change| change.liquidity_net_before != change.liquidity_net_after)
+            .collect();
+        ticks.sort_by_key(|change| change.tick);
+
+        let mut bitmap_words: Vec<i16> = self
+            .tick_bitmap
+            .keys()
+            .chain(other.tick_bitmap.keys())
+            .filter(|word| self.tick_bitmap.get(word) != other.tick_bitmap.get(word))
+            .copied()
+            .collect();
+        bitmap_words.sort();
+        bitmap_words.dedup();
+
+        StateDiff {
+            sqrt_price_before: self.sqrt_price,
+            sqrt_price_after: other.sqrt_price,
+            tick_before: self.tick,
+            tick_after: other.tick,
+            liquidity_before: self.liquidity,
+            liquidity_after: other.liquidity,
+            fee_protocol_before: self.fee_protocol,
+            fee_protocol_after: other.fee_protocol,
+            ticks,
+            bitmap_words,
+        }
+    }
 }
 
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.sqrt_price_before == self.sqrt_price_after
+            && self.tick_before == self.tick_after
+            && self.liquidity_before == self.liquidity_after
+            && self.fee_protocol_before == self.fee_protocol_after
+            && self.ticks.is_empty()
+            && self.bitmap_words.is_empty()
+    }
+
+    /// How many ticks the price moved, positive if the price of token0 went up
+    pub fn tick_delta(&self) -> i32 {
+        self.tick_after - self.tick_before
+    }
+
+    /// The change of the active liquidity
+    pub fn liquidity_delta(&self) -> i128 {
+        self.liquidity_after as i128 - self.liquidity_before as i128
+    }
+
+    /// A human-readable rendering of the changes, only the fields that changed are shown
+    pub fn pretty(&self) -> String {
+        if self.is_empty() {
+            return "No changes".to_string();
+        }
+
+        let mut lines = Vec::new();
+        if self.sqrt_price_before != self.sqrt_price_after {
+            lines.push(format!(
+                "SqrtPriceX96: {} -> {}",
+                self.sqrt_price_before, self.sqrt_price_after
+            ));
+        }
+        if self.tick_before != self.tick_after {
+            lines.push(format!(
+                "Tick: {} -> {} ({:+})",
+                self.tick_before,
+                self.tick_after,
+                self.tick_delta()
+            ));
+        }
+        if self.liquidity_before != self.liquidity_after {
+            lines.push(format!(
+                "Liquidity: {} -> {} ({:+})",
+                self.liquidity_before,
+                self.liquidity_after,
+                self.liquidity_delta()
+            ));
+        }
+        if self.fee_protocol_before != self.fee_protocol_after {
+            lines.push(format!(
+                "Fee Protocol: {} -> {}",
+                self.fee_protocol_before, self.fee_protocol_after
+            ));
+        }
+
+        let or_none = |net: Option<i128>| net.map_or("none".to_string(), |net| net.to_string());
+        for change in &self.ticks {
+            lines.push(format!(
+                "Tick {} liquidityNet: {} -> {}",
+                change.tick,
+                or_none(change.liquidity_net_before),
+                or_none(change.liquidity_net_after)
+            ));
+        }
+        if !self.bitmap_words.is_empty() {
+            lines.push(format!("Bitmap words changed: {:?}", self.bitmap_words));
+        }
+
+        lines.join("\n")
+    }
+}
 
 #[allow(dead_code)]
 struct CurrentState {
@@ -76,6 +430,7 @@ struct CurrentState {
     sqrt_price_x_96: U256,
     tick: i32,
     liquidity: u128,
+    fees: SwapFees,
 }
 
 #[derive(Default)]
@@ -105,6 +460,18 @@ pub struct PoolTick {
     pub block: u64,
 }
 
+/// The tick spacing of a fee tier, the 0.25% tier only exists on PancakeSwap
+pub fn tick_spacing_for_fee(fee: u32) -> Result<i32, anyhow::Error> {
+    match fee {
+        100 => Ok(1),
+        500 => Ok(10),
+        2500 => Ok(50),
+        3000 => Ok(60),
+        10000 => Ok(200),
+        _ => Err(anyhow::anyhow!("Invalid fee tier: {}", fee)),
+    }
+}
+
 impl UniswapV3Pool {
     /// Create a new Uniswap V3 Pool
     ///
@@ -129,10 +496,31 @@ impl UniswapV3Pool {
             fee,
             token0,
             token1,
+            variant: DexVariant::Uniswap,
             state: None,
+            last_log: None,
         }
     }
 
+    /// Set the DEX of the pool, fails if the fee tier doesn't exist on it
+    pub fn with_variant(mut self, variant: DexVariant) -> Result<Self, anyhow::Error> {
+        if !variant.v3_fee_tiers().contains(&self.fee) {
+            return Err(anyhow::anyhow!("{:?} has no {} fee tier", variant, self.fee));
+        }
+        self.variant = variant;
+        Ok(self)
+    }
+
+    /// The swap fee in basis points (the fee tier is in hundredths of a bip)
+    pub fn fee_bps(&self) -> f64 {
+        self.fee as f64 / 100.0
+    }
+
+    /// Do both tokens of the pool track the same asset, see [is_stable_pair](crate::defi::currency::erc20::is_stable_pair)
+    pub fn is_stable_pair(&self) -> bool {
+        crate::defi::currency::erc20::is_stable_pair(&self.token0, &self.token1)
+    }
+
     /// Switch token0 and token1
     pub fn toggle_pair(&mut self) {
         std::mem::swap(&mut self.token0, &mut self.token1);
@@ -146,10 +534,83 @@ impl UniswapV3Pool {
     /// Update the state for this pool
     pub fn update_state(&mut self, state: State) {
         self.state = Some(state);
+        self.last_log = None;
+    }
+
+    /// Apply a `Swap`, `Mint` or `Burn` log of the pool to the state
+    ///
+    /// Keeps the state up to date from a log subscription without calling [Self::fetch_state] every block.
+    /// `Swap` sets the price, tick and active liquidity, `Mint` and `Burn` update the liquidity of their ticks,
+    /// see [State::update_position]
+    ///
+    /// Logs must be applied in order, logs at or before the last applied one are ignored.
+    /// A fetched state already includes the logs of its block (`pool_tick.block`), so they are ignored as well
+    ///
+    /// Returns whether the state changed
+    pub fn apply_log(&mut self, log: &Log) -> Result<bool, anyhow::Error> {
+        if log.address() != self.address {
+            return Ok(false);
+        }
+
+        let block = log
+            .block_number
+            .ok_or_else(|| anyhow::anyhow!("Block number is missing"))?;
+        let log_index = log
+            .log_index
+            .ok_or_else(|| anyhow::anyhow!("Log index is missing"))?;
+
+        let mut state = self
+            .state
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+
+        let last = self.last_log.unwrap_or((state.pool_tick.block, u64::MAX));
+        if (block, log_index) <= last {
+            return Ok(false);
+        }
+
+        match log.topic0() {
+            Some(&IUniswapV3Pool::Swap::SIGNATURE_HASH) => {
+                let swap = log.log_decode::<IUniswapV3Pool::Swap>()?.inner.data;
+                state.sqrt_price = U256::from(swap.sqrtPriceX96);
+                state.tick = swap.tick.to_string().parse()?;
+                state.liquidity = swap.liquidity;
+            }
+            Some(&IUniswapV3Pool::Mint::SIGNATURE_HASH) => {
+                let mint = log.log_decode::<IUniswapV3Pool::Mint>()?.inner.data;
+                let delta = i128::try_from(mint.amount)?;
+                state.update_position(
+                    mint.tickLower.to_string().parse()?,
+                    mint.tickUpper.to_string().parse()?,
+                    delta,
+                )?;
+            }
+            Some(&IUniswapV3Pool::Burn::SIGNATURE_HASH) => {
+                let burn = log.log_decode::<IUniswapV3Pool::Burn>()?.inner.data;
+                // burning 0 liquidity is used to poke the fees
+                if burn.amount == 0 {
+                    return Ok(false);
+                }
+                let delta = -i128::try_from(burn.amount)?;
+                state.update_position(
+                    burn.tickLower.to_string().parse()?,
+                    burn.tickUpper.to_string().parse()?,
+                    delta,
+                )?;
+            }
+            _ => return Ok(false),
+        }
+
+        state.pool_tick.block = block;
+        self.state = Some(state);
+        self.last_log = Some((block, log_index));
+        Ok(true)
     }
 
     /// Fetch the state of the pool at a given block
     /// If block is None, the latest block is used
+    ///
+    /// The state is tagged with the queried block number (`pool_tick.block`) so [Self::apply_log] can order the logs after it
     pub async fn fetch_state<T, P, N>(
         pool: Address,
         client: P,
@@ -160,24 +621,56 @@ impl UniswapV3Pool {
         P: Provider<T, N> + Clone,
         N: Network,
     {
-        let (sqrt_price, tick, _, _, _, _, _) =
-            v3::slot0(pool, client.clone(), block.clone()).await?;
-        let (word_position, _) = position(tick);
-
-        let liquidity = v3::liquidity(pool, client.clone(), block.clone());
-        let tick_spacing = v3::tick_spacing(pool, client.clone());
-        let tick_bitmap = v3::tick_bitmap(pool, word_position, client.clone(), block.clone());
-        let ticks = v3::ticks(pool, tick, client.clone(), block.clone());
-
-        let (liquidity, tick_spacing, tick_bitmap, ticks) =
-            try_join!(liquidity, tick_spacing, tick_bitmap, ticks)?;
+        let number = block_number(client.clone(), block).await?;
+        let block = Some(BlockId::number(number));
+
+        // the bitmap word and the tick depend on slot0, so this takes 2 batched round trips
+        let mut batch = EthCallBatch::new(block);
+        let slot0 = batch.add(pool, v3::encode_slot0());
+        let liquidity = batch.add(pool, v3::encode_liquidity());
+        let tick_spacing = batch.add(pool, v3::encode_tick_spacing());
+        let mut results = batch.send(client.clone()).await?;
+
+        let slot0 = take_result(&mut results, slot0)?;
+        let (sqrt_price, tick) = v3::decode_slot0(&slot0)?;
+        let fee_protocol = IUniswapV3Pool::slot0Call::abi_decode_returns(&slot0, true)?._5;
+        let liquidity = IUniswapV3Pool::liquidityCall::abi_decode_returns(
+            &take_result(&mut results, liquidity)?,
+            true,
+        )?
+        ._0;
+        let tick_spacing: i32 = IUniswapV3Pool::tickSpacingCall::abi_decode_returns(
+            &take_result(&mut results, tick_spacing)?,
+            true,
+        )?
+        ._0
+        .to_string()
+        .parse()?;
+
+        // the bitmap is indexed by the tick divided by the tick spacing
+        let (word_position, _) = position(tick.div_euclid(tick_spacing));
+
+        let mut batch = EthCallBatch::new(block);
+        let tick_bitmap = batch.add(pool, v3::encode_tick_bitmap(word_position));
+        let ticks = batch.add(pool, v3::encode_tick(tick)?);
+        let mut results = batch.send(client).await?;
+
+        let tick_bitmap = IUniswapV3Pool::tickBitmapCall::abi_decode_returns(
+            &take_result(&mut results, tick_bitmap)?,
+            true,
+        )?
+        ._0;
+        let ticks = IUniswapV3Pool::ticksCall::abi_decode_returns(
+            &take_result(&mut results, ticks)?,
+            true,
+        )?;
 
         let mut tick_bitmap_map = HashMap::new();
         tick_bitmap_map.insert(word_position, tick_bitmap);
 
-        let liquidity_gross = ticks.0;
-        let liquidity_net = ticks.1;
-        let initialized = ticks.7;
+        let liquidity_gross = ticks._0;
+        let liquidity_net = ticks._1;
+        let initialized = ticks._7;
 
         let ticks_info = TickInfo {
             liquidity_gross,
@@ -185,15 +678,10 @@ impl UniswapV3Pool {
             initialized,
         };
 
-        let block = if let Some(b) = block {
-            b.as_u64().unwrap_or(0)
-        } else {
-            0
-        };
         let pool_tick = PoolTick {
             tick,
             liquidity_net,
-            block,
+            block: number,
         };
 
         let mut ticks_map = HashMap::new();
@@ -207,287 +695,234 @@ impl UniswapV3Pool {
             tick_bitmap: tick_bitmap_map,
             ticks: ticks_map,
             pool_tick,
+            fee_protocol,
         })
     }
 
-    pub fn simulate_swap(&self, token_in: Address, amount_in: U256) -> Result<U256, anyhow::Error> {
-        let state = self
-            .state
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
-
-        if amount_in.is_zero() {
-            return Ok(U256::ZERO);
+    /// Same as [Self::fetch_state] but loads `num_words` tick bitmap words on each side of the current tick
+    /// and every initialized tick in them
+    ///
+    /// [Self::fetch_state] only loads the word of the current tick, a swap that crosses into another word
+    /// sees no liquidity there. Each word covers 256 * tick spacing ticks, a swap that goes past the loaded words
+    /// is still simulated with an empty bitmap
+    ///
+    /// The ticks are read by a batch contract, see [v3_ticks]
+    ///
+    /// ## Arguments
+    ///
+    /// * `pool` - The pool address
+    /// * `client` - The provider
+    /// * `block` - The block to read at, None for the latest
+    /// * `num_words` - The number of bitmap words to load below and above the word of the current tick
+    pub async fn fetch_state_with_tick_range<T, P, N>(
+        pool: Address,
+        client: P,
+        block: Option<BlockId>,
+        num_words: u16,
+    ) -> Result<State, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let mut state = Self::fetch_state(pool, client.clone(), block).await?;
+        let spacing = state.tick_spacing;
+        // read the ticks at the same block as the state
+        let block = Some(BlockId::number(state.pool_tick.block));
+
+        let (current_word, _) = position(state.tick.div_euclid(spacing));
+        let (min_word, _) = position(MIN_TICK.div_euclid(spacing));
+        let (max_word, _) = position(MAX_TICK.div_euclid(spacing));
+        let first_word = (current_word as i32 - num_words as i32).max(min_word as i32) as i16;
+        let last_word = (current_word as i32 + num_words as i32).min(max_word as i32) as i16;
+
+        let data = v3_ticks(client, pool, first_word, last_word, spacing, block).await?;
+        state.tick_bitmap.extend(data.bitmap);
+        for tick in data.ticks {
+            state.ticks.insert(
+                tick.tick,
+                TickInfo {
+                    liquidity_gross: tick.liquidity_gross,
+                    liquidity_net: tick.liquidity_net,
+                    initialized: true,
+                },
+            );
         }
 
-        let zero_for_one = token_in == self.token0.address;
-
-        // Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
-        let sqrt_price_limit_x_96 = if zero_for_one {
-            MIN_SQRT_RATIO + U256_1
-        } else {
-            MAX_SQRT_RATIO - U256_1
-        };
+        Ok(state)
+    }
 
-        // Initialize a mutable state state struct to hold the dynamic simulated state of the pool
-        let mut current_state = CurrentState {
-            sqrt_price_x_96: state.sqrt_price.clone(), //Active price on the pool
-            amount_calculated: I256::ZERO, //Amount of token_out that has been calculated
-            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
-            tick: state.tick.clone(),                              //Current i24 tick of the pool
-            liquidity: state.liquidity.clone(), //Current available liquidity in the tick range
-        };
+    /// Same as [Self::fetch_state] with every initialized tick of the pool
+    ///
+    /// See [Self::fetch_state_with_tick_range]
+    pub async fn fetch_full_state<T, P, N>(
+        pool: Address,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<State, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        // the word range is clamped to the valid ticks
+        Self::fetch_state_with_tick_range(pool, client, block, u16::MAX).await
+    }
 
-        while current_state.amount_specified_remaining != I256::ZERO
-            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
-        {
-            // Initialize a new step struct to hold the dynamic state of the pool at each step
-            let mut step = StepComputations {
-                // Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
-                sqrt_price_start_x_96: current_state.sqrt_price_x_96,
-                ..Default::default()
-            };
+    /// Same as [Self::fetch_state] but reads the pool storage directly with `eth_getStorageAt`
+    ///
+    /// For providers that block `eth_call` heavy batching, costs 2 JSON-RPC batch round trips
+    ///
+    /// ## Arguments
+    ///
+    /// * `pool` - The pool address
+    /// * `fee` - The fee tier of the pool, the tick spacing is immutable and derived from it
+    /// * `client` - The provider
+    /// * `block` - The block to read at, None for the latest
+    pub async fn fetch_state_from_storage<T, P, N>(
+        pool: Address,
+        fee: u32,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<State, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let tick_spacing = tick_spacing_for_fee(fee)?;
+        let number = block_number(client.clone(), block).await?;
+        let block = Some(BlockId::number(number));
 
-            // Get the next tick from the current tick
-            (step.tick_next, step.initialized) =
-                uniswap_v3_math::tick_bitmap::next_initialized_tick_within_one_word(
-                    &state.tick_bitmap,
-                    current_state.tick,
-                    state.tick_spacing,
-                    zero_for_one,
-                )?;
+        let values = get_storage_batch(
+            client.clone(),
+            &[(pool, v3::SLOT0_SLOT), (pool, v3::LIQUIDITY_SLOT)],
+            block,
+        )
+        .await?;
+        let slot0 = values[0];
+        let liquidity = values[1].to::<u128>();
+
+        let (sqrt_price, tick) = v3::slot0_from_word(slot0);
+        let fee_protocol = extract_bits(slot0, 232, 8).to::<u8>();
+
+        let (word_position, _) = position(tick.div_euclid(tick_spacing));
+        let tick_slot = mapping_slot(signed_key(tick as i64), v3::TICKS_SLOT);
+        let values = get_storage_batch(
+            client,
+            &[
+                (pool, mapping_slot(signed_key(word_position as i64), v3::TICK_BITMAP_SLOT)),
+                (pool, tick_slot),
+                (pool, tick_slot + U256::from(3)),
+            ],
+            block,
+        )
+        .await?;
 
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            // Note: this could be removed as we are clamping in the batch contract
-            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
+        let tick_bitmap = values[0];
 
-            // Get the next sqrt price from the input amount
-            step.sqrt_price_next_x96 =
-                uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
+        // liquidityGross and liquidityNet share the first slot of Tick.Info, initialized is the last byte of the 4th
+        let liquidity_gross = extract_bits(values[1], 0, 128).to::<u128>();
+        let liquidity_net = extract_bits(values[1], 128, 128).to::<u128>() as i128;
+        let initialized = extract_bits(values[2], 248, 8) != U256::ZERO;
 
-            // Target spot price
-            let swap_target_sqrt_ratio = if zero_for_one {
-                if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
-                    sqrt_price_limit_x_96
-                } else {
-                    step.sqrt_price_next_x96
-                }
-            } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
-                sqrt_price_limit_x_96
-            } else {
-                step.sqrt_price_next_x96
-            };
+        let mut tick_bitmap_map = HashMap::new();
+        tick_bitmap_map.insert(word_position, tick_bitmap);
 
-            // Compute swap step and update the current state
-            (
-                current_state.sqrt_price_x_96,
-                step.amount_in,
-                step.amount_out,
-                step.fee_amount,
-            ) = uniswap_v3_math::swap_math::compute_swap_step(
-                current_state.sqrt_price_x_96,
-                swap_target_sqrt_ratio,
-                current_state.liquidity,
-                current_state.amount_specified_remaining,
-                self.fee,
-            )?;
+        let mut ticks_map = HashMap::new();
+        ticks_map.insert(
+            tick,
+            TickInfo {
+                liquidity_gross,
+                liquidity_net,
+                initialized,
+            },
+        );
 
-            // Decrement the amount remaining to be swapped and amount received from the step
-            current_state.amount_specified_remaining = current_state
-                .amount_specified_remaining
-                .overflowing_sub(I256::from_raw(
-                    step.amount_in.overflowing_add(step.fee_amount).0,
-                ))
-                .0;
-
-            current_state.amount_calculated -= I256::from_raw(step.amount_out);
-
-            // If the price moved all the way to the next price, recompute the liquidity change for the next iteration
-            if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
-                if step.initialized {
-                    let mut liquidity_net = if let Some(info) = state.ticks.get(&step.tick_next) {
-                        info.liquidity_net
-                    } else {
-                        0
-                    };
+        Ok(State {
+            liquidity,
+            sqrt_price,
+            tick,
+            tick_spacing,
+            tick_bitmap: tick_bitmap_map,
+            ticks: ticks_map,
+            pool_tick: PoolTick {
+                tick,
+                liquidity_net,
+                block: number,
+            },
+            fee_protocol,
+        })
+    }
 
-                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
-                    if zero_for_one {
-                        liquidity_net = -liquidity_net;
-                    }
+    pub fn simulate_swap(&self, token_in: Address, amount_in: U256) -> Result<U256, anyhow::Error> {
+        let state = self
+            .state
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
 
-                    current_state.liquidity = if liquidity_net < 0 {
-                        if current_state.liquidity < (-liquidity_net as u128) {
-                            return Err(anyhow::anyhow!("Liquidity underflow"));
-                        } else {
-                            current_state.liquidity - (-liquidity_net as u128)
-                        }
-                    } else {
-                        current_state.liquidity + (liquidity_net as u128)
-                    };
-                }
-                // Increment the current tick
-                current_state.tick = if zero_for_one {
-                    step.tick_next.wrapping_sub(1)
-                } else {
-                    step.tick_next
-                }
-                // If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
-                // Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
-            } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
-                current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
-                    current_state.sqrt_price_x_96,
-                )?;
-            }
+        if amount_in.is_zero() {
+            return Ok(U256::ZERO);
         }
 
+        let zero_for_one = token_in == self.token0.address;
+        let current_state = compute_swap(state, self.fee, zero_for_one, amount_in)?;
+
         let amount_out = (-current_state.amount_calculated).into_raw();
 
         Ok(amount_out)
     }
 
-    pub fn simulate_swap_mut(
-        &mut self,
+    /// Same as [Self::simulate_swap] but also returns how the swap fee is split between the LPs and the protocol
+    pub fn simulate_swap_with_fees(
+        &self,
         token_in: Address,
         amount_in: U256,
-    ) -> Result<U256, anyhow::Error> {
-        let mut state = self
+    ) -> Result<(U256, SwapFees), anyhow::Error> {
+        let state = self
             .state
-            .clone()
+            .as_ref()
             .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
 
         if amount_in.is_zero() {
-            return Ok(U256::ZERO);
+            return Ok((U256::ZERO, SwapFees::default()));
         }
 
         let zero_for_one = token_in == self.token0.address;
+        let current_state = compute_swap(state, self.fee, zero_for_one, amount_in)?;
 
-        // Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
-        let sqrt_price_limit_x_96 = if zero_for_one {
-            MIN_SQRT_RATIO + U256_1
-        } else {
-            MAX_SQRT_RATIO - U256_1
-        };
-
-        // Initialize a mutable state state struct to hold the dynamic simulated state of the pool
-        let mut current_state = CurrentState {
-            sqrt_price_x_96: state.sqrt_price, //Active price on the pool
-            amount_calculated: I256::ZERO,     //Amount of token_out that has been calculated
-            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
-            tick: state.tick,                                      //Current i24 tick of the pool
-            liquidity: state.liquidity, //Current available liquidity in the tick range
-        };
-
-        while current_state.amount_specified_remaining != I256::ZERO
-            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
-        {
-            // Initialize a new step struct to hold the dynamic state of the pool at each step
-            let mut step = StepComputations {
-                // Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
-                sqrt_price_start_x_96: current_state.sqrt_price_x_96,
-                ..Default::default()
-            };
-
-            // Get the next tick from the current tick
-            (step.tick_next, step.initialized) =
-                uniswap_v3_math::tick_bitmap::next_initialized_tick_within_one_word(
-                    &state.tick_bitmap,
-                    current_state.tick,
-                    state.tick_spacing,
-                    zero_for_one,
-                )?;
-
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            // Note: this could be removed as we are clamping in the batch contract
-            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
-
-            // Get the next sqrt price from the input amount
-            step.sqrt_price_next_x96 =
-                uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
-
-            // Target spot price
-            let swap_target_sqrt_ratio = if zero_for_one {
-                if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
-                    sqrt_price_limit_x_96
-                } else {
-                    step.sqrt_price_next_x96
-                }
-            } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
-                sqrt_price_limit_x_96
-            } else {
-                step.sqrt_price_next_x96
-            };
-
-            // Compute swap step and update the current state
-            (
-                current_state.sqrt_price_x_96,
-                step.amount_in,
-                step.amount_out,
-                step.fee_amount,
-            ) = uniswap_v3_math::swap_math::compute_swap_step(
-                current_state.sqrt_price_x_96,
-                swap_target_sqrt_ratio,
-                current_state.liquidity,
-                current_state.amount_specified_remaining,
-                self.fee,
-            )?;
+        let amount_out = (-current_state.amount_calculated).into_raw();
 
-            // Decrement the amount remaining to be swapped and amount received from the step
-            current_state.amount_specified_remaining = current_state
-                .amount_specified_remaining
-                .overflowing_sub(I256::from_raw(
-                    step.amount_in.overflowing_add(step.fee_amount).0,
-                ))
-                .0;
-
-            current_state.amount_calculated -= I256::from_raw(step.amount_out);
-
-            // If the price moved all the way to the next price, recompute the liquidity change for the next iteration
-            if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
-                if step.initialized {
-                    let mut liquidity_net = if let Some(info) = state.ticks.get(&step.tick_next) {
-                        info.liquidity_net
-                    } else {
-                        0
-                    };
+        Ok((amount_out, current_state.fees))
+    }
 
-                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
-                    if zero_for_one {
-                        liquidity_net = -liquidity_net;
-                    }
+    /// Same as [Self::simulate_swap] but also moves the price, tick and active liquidity of the pool
+    ///
+    /// The state is updated in place so applying many swaps never clones the tick maps,
+    /// if the swap fails the state is left untouched
+    pub fn simulate_swap_mut(
+        &mut self,
+        token_in: Address,
+        amount_in: U256,
+    ) -> Result<U256, anyhow::Error> {
+        let fee = self.fee;
+        let zero_for_one = token_in == self.token0.address;
+        let state = self
+            .state
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
 
-                    current_state.liquidity = if liquidity_net < 0 {
-                        if current_state.liquidity < (-liquidity_net as u128) {
-                            return Err(anyhow::anyhow!("Liquidity underflow"));
-                        } else {
-                            current_state.liquidity - (-liquidity_net as u128)
-                        }
-                    } else {
-                        current_state.liquidity + (liquidity_net as u128)
-                    };
-                }
-                // Increment the current tick
-                current_state.tick = if zero_for_one {
-                    step.tick_next.wrapping_sub(1)
-                } else {
-                    step.tick_next
-                }
-                // If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
-                // Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
-            } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
-                current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
-                    current_state.sqrt_price_x_96,
-                )?;
-            }
+        if amount_in.is_zero() {
+            return Ok(U256::ZERO);
         }
 
+        let current_state = compute_swap(state, fee, zero_for_one, amount_in)?;
+
         // update the pool state
         state.liquidity = current_state.liquidity;
         state.sqrt_price = current_state.sqrt_price_x_96;
         state.tick = current_state.tick;
 
-        self.state = Some(state);
-
         let amount_out = (-current_state.amount_calculated).into_raw();
 
         Ok(amount_out)
@@ -501,6 +936,11 @@ impl UniswapV3Pool {
             .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
 
         let tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(state.sqrt_price)?;
+        Ok(self.price_at_tick(tick, base_token))
+    }
+
+    /// The price of `base_token` in terms of the other token at a tick, adjusted for the decimals
+    pub fn price_at_tick(&self, tick: i32, base_token: Address) -> f64 {
         let shift = self.token0.decimals as i8 - self.token1.decimals as i8;
 
         let price = match shift.cmp(&0) {
@@ -510,12 +950,86 @@ impl UniswapV3Pool {
         };
 
         if base_token == self.token0.address {
-            Ok(price)
+            price
+        } else {
+            1.0 / price
+        }
+    }
+
+    /// The price of `base_token` in terms of the other token at a sqrtPriceX96, adjusted for the decimals
+    ///
+    /// Unlike [Self::price_at_tick] this is the exact price, not the price at the lower tick
+    pub fn price_at_sqrt_price(&self, sqrt_price_x96: U256, base_token: Address) -> f64 {
+        let price = sqrt_price_to_price(sqrt_price_x96, self.token0.decimals, self.token1.decimals);
+
+        if base_token == self.token0.address {
+            price
         } else {
-            Ok(1.0 / price)
+            1.0 / price
         }
     }
 
+    /// The time weighted average price of the pool over the last `seconds`, from the oracle observations
+    ///
+    /// Fails if the oldest observation of the pool is more recent than `seconds` ago,
+    /// `increaseObservationCardinalityNext` has to be called on the pool to keep a longer history
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - The provider
+    /// * `seconds` - The window of the average
+    /// * `block` - The block the window ends at, the latest if None
+    pub async fn twap<T, P, N>(
+        &self,
+        client: P,
+        seconds: u32,
+        block: Option<BlockId>,
+    ) -> Result<Twap, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        if seconds == 0 {
+            return Err(anyhow::anyhow!("The TWAP window must be at least 1 second"));
+        }
+
+        let (tick_cumulatives, _) = observe(self.address, vec![seconds, 0], client, block).await?;
+        let [start, end] = tick_cumulatives[..] else {
+            return Err(anyhow::anyhow!(
+                "Expected 2 tick cumulatives but got {}",
+                tick_cumulatives.len()
+            ));
+        };
+
+        let tick = average_tick(start.as_i64(), end.as_i64(), seconds)?;
+        Ok(Twap {
+            seconds,
+            tick,
+            token0_price: self.price_at_tick(tick, self.token0.address),
+            token1_price: self.price_at_tick(tick, self.token1.address),
+            token0: self.token0.address,
+        })
+    }
+
+    /// The shortfall of the execution price of a swap from the mid price in percent, fees included
+    ///
+    /// See [price_impact](crate::defi::utils::slippage::price_impact)
+    pub fn price_impact(&self, token_in: Address, amount_in: U256) -> Result<f64, anyhow::Error> {
+        let amount_out = self.simulate_swap(token_in, amount_in)?;
+        let (decimals_in, decimals_out) = if token_in == self.token0.address {
+            (self.token0.decimals, self.token1.decimals)
+        } else {
+            (self.token1.decimals, self.token0.decimals)
+        };
+
+        Ok(slippage::price_impact(
+            self.calculate_price(token_in)?,
+            to_f64(amount_in, decimals_in)?,
+            to_f64(amount_out, decimals_out)?,
+        ))
+    }
+
     /// Get the usd values of token0 and token1 at a given block
     /// If block is None, the latest block is used
     pub async fn tokens_usd<T, P, N>(
@@ -529,8 +1043,15 @@ impl UniswapV3Pool {
         N: Network,
     {
         // find a known token that we can get its usd value
-        let mut token0_usd = get_token_price(client.clone(), block.clone(), self.chain_id, self.token0.address).await?;
-        let mut token1_usd = get_token_price(client, block, self.chain_id, self.token1.address).await?;
+        let prices = get_token_prices(
+            client,
+            block,
+            self.chain_id,
+            &[self.token0.address, self.token1.address],
+        )
+        .await?;
+        let mut token0_usd = prices[0];
+        let mut token1_usd = prices[1];
 
 
         // case 1 token0 is unknown
@@ -551,6 +1072,64 @@ impl UniswapV3Pool {
         Ok((token0_usd, token1_usd))
     }
 
+    /// The USD value of the token balances held by the pool at a given block, priced like [Self::tokens_usd]
+    /// If block is None, the latest block is used
+    ///
+    /// The balances include the fees not collected by the LPs yet
+    pub async fn tvl_usd<T, P, N>(&self, client: P, block: Option<BlockId>) -> Result<f64, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let (balance0, balance1) = pool_balances(
+            client.clone(),
+            self.address,
+            self.token0.address,
+            self.token1.address,
+            block,
+        )
+        .await?;
+
+        // price from the state of the same block
+        let mut pool = self.clone();
+        pool.update_state(Self::fetch_state(self.address, client.clone(), block).await?);
+        let (token0_usd, token1_usd) = pool.tokens_usd(client, block).await?;
+
+        Ok(to_f64(balance0, self.token0.decimals)? * token0_usd + to_f64(balance1, self.token1.decimals)? * token1_usd)
+    }
+
+
+    /// Get the usd values of token0 and token1 using the pool price and a single USD anchor
+    ///
+    /// Same as [Self::tokens_usd] but the relative price always comes from the pool, even when both tokens
+    /// have an external price. The state must be up to date with the given block
+    pub async fn tokens_usd_from_pool<T, P, N>(
+        &self,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<(f64, f64), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let (token0_usd, token1_usd) = self.tokens_usd(client, block).await?;
+        let anchor = if token0_usd != 0.0 {
+            UsdAnchor::Token0(token0_usd)
+        } else if token1_usd != 0.0 {
+            UsdAnchor::Token1(token1_usd)
+        } else {
+            return Err(anyhow::anyhow!(
+                "No USD anchor found for {} / {}",
+                self.token0.symbol,
+                self.token1.symbol
+            ));
+        };
+
+        let price = self.calculate_price(self.token0.address)?;
+        Ok(anchor.token_prices(price))
+    }
 
     /// Does pair support getting values in usd
     /// 
@@ -570,35 +1149,54 @@ impl UniswapV3Pool {
 
     /// Get the volume of the pool
     pub fn get_volume_from_logs(&self, logs: Vec<Log>) -> Result<PoolVolume, anyhow::Error> {
-        let mut buy_volume = U256::ZERO;
-        let mut sell_volume = U256::ZERO;
-        let mut swaps = Vec::new();
+        let swaps = logs
+            .iter()
+            .map(|log| self.decode_swap(log))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(volume_from_swaps(swaps, self.token0.address, self.token1.address))
+    }
 
+    /// Bucket the swaps of the pool into OHLCV candles of `interval_blocks` blocks, see [build_candles]
+    ///
+    /// The price is the price of token0 in token1 after each swap, the volume is in both tokens
+    pub fn ohlc_from_logs(&self, mut logs: Vec<Log>, interval_blocks: u64) -> Result<Vec<Candle>, anyhow::Error> {
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+
+        let mut trades = Vec::with_capacity(logs.len());
         for log in &logs {
-            let swap_data = self.decode_swap(log)?;
-            if swap_data.token_in.address == self.token1.address {
-                buy_volume += swap_data.amount_in;
-            }
+            let swap = self.decode_swap(log)?;
+            let sqrt_price_x96 = swap
+                .sqrt_price_x96
+                .ok_or_else(|| anyhow::anyhow!("Swap without a sqrtPriceX96 in {}", swap.tx_hash))?;
 
-            if swap_data.token_out.address == self.token0.address {
-                sell_volume += swap_data.amount_out;
-            }
-            swaps.push(swap_data);
-        }
+            let (amount0, amount1) = if swap.token_in.address == self.token0.address {
+                (swap.amount_in, swap.amount_out)
+            } else {
+                (swap.amount_out, swap.amount_in)
+            };
 
-        swaps.sort_by(|a, b| a.block.cmp(&b.block));
+            trades.push(Trade {
+                block: swap.block,
+                price: self.price_at_sqrt_price(sqrt_price_x96, self.token0.address),
+                amount0: to_f64(amount0, self.token0.decimals)?,
+                amount1: to_f64(amount1, self.token1.decimals)?,
+            });
+        }
 
-        Ok(PoolVolume {
-            buy_volume,
-            sell_volume,
-            swaps,
-        })
+        build_candles(&trades, interval_blocks)
     }
 
     /// Decode a swap log against this pool
     pub fn decode_swap(&self, log: &Log) -> Result<SwapData, anyhow::Error> {
         let IUniswapV3Pool::Swap {
-            amount0, amount1, ..
+            sender,
+            recipient,
+            amount0,
+            amount1,
+            sqrtPriceX96: sqrt_price_x96,
+            liquidity,
+            tick,
         } = log.log_decode()?.inner.data;
 
         let pair_address = log.address();
@@ -639,13 +1237,428 @@ impl UniswapV3Pool {
             .parse::<U256>()?;
 
         Ok(SwapData {
-            account: None,
+            account: Some(recipient),
+            sender: Some(sender),
+            recipient: Some(recipient),
             token_in,
             token_out,
             amount_in,
             amount_out,
             block: block.unwrap(),
             tx_hash: tx_hash.to_string(),
+            sqrt_price_x96: Some(U256::from(sqrt_price_x96)),
+            tick: Some(tick.to_string().parse::<i32>()?),
+            liquidity: Some(liquidity),
+            gas_used: None,
+            effective_gas_price: None,
         })
     }
-}
\ No newline at end of file
+}
+
+/// The result of [UniswapV3Pool::twap]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Twap {
+    pub seconds: u32,
+
+    /// The average tick over the window
+    pub tick: i32,
+
+    /// The price of token0 in token1
+    pub token0_price: f64,
+
+    /// The price of token1 in token0
+    pub token1_price: f64,
+
+    token0: Address,
+}
+
+impl Twap {
+    /// The price of `base_token` in terms of the other token of the pool
+    pub fn price(&self, base_token: Address) -> f64 {
+        if base_token == self.token0 {
+            self.token0_price
+        } else {
+            self.token1_price
+        }
+    }
+}
+
+/// The average tick between two tick cumulatives `seconds` apart, rounded to negative infinity like the OracleLibrary
+pub fn average_tick(tick_cumulative_start: i64, tick_cumulative_end: i64, seconds: u32) -> Result<i32, anyhow::Error> {
+    if seconds == 0 {
+        return Err(anyhow::anyhow!("The window must be at least 1 second"));
+    }
+
+    let delta = tick_cumulative_end - tick_cumulative_start;
+    let seconds = seconds as i64;
+    let mut tick = delta / seconds;
+    if delta < 0 && delta % seconds != 0 {
+        tick -= 1;
+    }
+
+    Ok(i32::try_from(tick)?)
+}
+
+/// The initialized ticks of a tick bitmap word, in ascending order
+pub fn initialized_ticks(word: i16, bitmap: U256, tick_spacing: i32) -> Vec<i32> {
+    (0..256)
+        .filter(|bit| bitmap.bit(*bit))
+        .map(|bit| (word as i32 * 256 + bit as i32) * tick_spacing)
+        .collect()
+}
+
+/// The square root of the raw price (token1 per token0, not adjusted for the decimals) of a sqrtPriceX96
+pub fn sqrt_price_to_f64(sqrt_price_x96: U256) -> f64 {
+    to_f64(sqrt_price_x96, 0).unwrap_or_default() / 2_f64.powi(96)
+}
+
+/// The price of token0 in terms of token1 at a sqrtPriceX96, adjusted for the decimals
+pub fn sqrt_price_to_price(sqrt_price_x96: U256, decimals0: u8, decimals1: u8) -> f64 {
+    let sqrt_price = sqrt_price_to_f64(sqrt_price_x96);
+    sqrt_price * sqrt_price * 10_f64.powi(decimals0 as i32 - decimals1 as i32)
+}
+
+/// Walk the ticks of the pool for an exact input swap and return the state at the end of it
+fn compute_swap(
+    state: &State,
+    fee: u32,
+    zero_for_one: bool,
+    amount_in: U256,
+) -> Result<CurrentState, anyhow::Error> {
+    // Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
+    let sqrt_price_limit_x_96 = if zero_for_one {
+        MIN_SQRT_RATIO + U256_1
+    } else {
+        MAX_SQRT_RATIO - U256_1
+    };
+
+    // Initialize a mutable state state struct to hold the dynamic simulated state of the pool
+    let mut current_state = CurrentState {
+        sqrt_price_x_96: state.sqrt_price.clone(), //Active price on the pool
+        amount_calculated: I256::ZERO, //Amount of token_out that has been calculated
+        amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
+        tick: state.tick.clone(),                              //Current i24 tick of the pool
+        liquidity: state.liquidity.clone(), //Current available liquidity in the tick range
+        fees: SwapFees::default(),
+    };
+
+    // the protocol takes 1/fee_protocol of the fees of the input token
+    let fee_protocol = if zero_for_one {
+        state.fee_protocol % 16
+    } else {
+        state.fee_protocol >> 4
+    };
+
+    while current_state.amount_specified_remaining != I256::ZERO
+        && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
+    {
+        // Initialize a new step struct to hold the dynamic state of the pool at each step
+        let mut step = StepComputations {
+            // Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
+            sqrt_price_start_x_96: current_state.sqrt_price_x_96,
+            ..Default::default()
+        };
+
+        // Get the next tick from the current tick
+        (step.tick_next, step.initialized) =
+            uniswap_v3_math::tick_bitmap::next_initialized_tick_within_one_word(
+                &state.tick_bitmap,
+                current_state.tick,
+                state.tick_spacing,
+                zero_for_one,
+            )?;
+
+        // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
+        // Note: this could be removed as we are clamping in the batch contract
+        step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
+
+        // Get the next sqrt price from the input amount
+        step.sqrt_price_next_x96 =
+            uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
+
+        // Target spot price
+        let swap_target_sqrt_ratio = if zero_for_one {
+            if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
+                sqrt_price_limit_x_96
+            } else {
+                step.sqrt_price_next_x96
+            }
+        } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
+            sqrt_price_limit_x_96
+        } else {
+            step.sqrt_price_next_x96
+        };
+
+        // Compute swap step and update the current state
+        (
+            current_state.sqrt_price_x_96,
+            step.amount_in,
+            step.amount_out,
+            step.fee_amount,
+        ) = uniswap_v3_math::swap_math::compute_swap_step(
+            current_state.sqrt_price_x_96,
+            swap_target_sqrt_ratio,
+            current_state.liquidity,
+            current_state.amount_specified_remaining,
+            fee,
+        )?;
+
+        // Decrement the amount remaining to be swapped and amount received from the step
+        current_state.amount_specified_remaining = current_state
+            .amount_specified_remaining
+            .overflowing_sub(I256::from_raw(
+                step.amount_in.overflowing_add(step.fee_amount).0,
+            ))
+            .0;
+
+        current_state.amount_calculated -= I256::from_raw(step.amount_out);
+
+        if fee_protocol > 0 {
+            let delta = step.fee_amount / U256::from(fee_protocol);
+            step.fee_amount -= delta;
+            current_state.fees.protocol_fee += delta;
+        }
+        current_state.fees.lp_fee += step.fee_amount;
+
+        // If the price moved all the way to the next price, recompute the liquidity change for the next iteration
+        if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
+            if step.initialized {
+                let mut liquidity_net = if let Some(info) = state.ticks.get(&step.tick_next) {
+                    info.liquidity_net
+                } else {
+                    0
+                };
+
+                // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
+                if zero_for_one {
+                    liquidity_net = -liquidity_net;
+                }
+
+                current_state.liquidity = if liquidity_net < 0 {
+                    if current_state.liquidity < (-liquidity_net as u128) {
+                        return Err(anyhow::anyhow!("Liquidity underflow"));
+                    } else {
+                        current_state.liquidity - (-liquidity_net as u128)
+                    }
+                } else {
+                    current_state.liquidity + (liquidity_net as u128)
+                };
+            }
+            // Increment the current tick
+            current_state.tick = if zero_for_one {
+                step.tick_next.wrapping_sub(1)
+            } else {
+                step.tick_next
+            }
+            // If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
+            // Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
+        } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
+            current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
+                current_state.sqrt_price_x_96,
+            )?;
+        }
+    }
+
+    Ok(current_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::aliases::I24;
+
+    #[test]
+    fn test_top_traders() {
+        let token = |byte| ERC20Token {
+            address: Address::repeat_byte(byte),
+            ..Default::default()
+        };
+        let (token0, token1) = (token(1), token(2));
+        let (alice, bob) = (Address::repeat_byte(0xa), Address::repeat_byte(0xb));
+
+        let swap = |account, buy: bool, amount0: u64, amount1: u64| {
+            let (token_in, token_out, amount_in, amount_out) = if buy {
+                (token1.clone(), token0.clone(), amount1, amount0)
+            } else {
+                (token0.clone(), token1.clone(), amount0, amount1)
+            };
+            SwapData::new(
+                account,
+                token_in,
+                token_out,
+                U256::from(amount_in),
+                U256::from(amount_out),
+                1,
+                String::new(),
+            )
+        };
+
+        let volume = PoolVolume {
+            buy_volume: U256::ZERO,
+            sell_volume: U256::ZERO,
+            swaps: vec![
+                swap(Some(alice), true, 1, 10),
+                swap(Some(bob), false, 3, 30),
+                swap(Some(alice), false, 1, 10),
+                swap(None, true, 100, 1000),
+            ],
+            buys: 2,
+            sells: 2,
+        };
+
+        let traders = volume.by_account(token1.address);
+        assert_eq!(traders.len(), 2);
+        assert_eq!(
+            (traders[0].account, traders[0].sells, traders[0].volume1),
+            (bob, 1, U256::from(30))
+        );
+        assert_eq!(
+            (traders[1].buys, traders[1].sells, traders[1].volume0),
+            (1, 1, U256::from(2))
+        );
+        assert_eq!(volume.top_traders(token1.address, 1).len(), 1);
+
+        // with the pair toggled token1 sorts before token0, the buys are still the swaps paying token1
+        let toggled = volume.by_account(token0.address);
+        assert_eq!(
+            (toggled[0].account, toggled[0].buys, toggled[0].volume0),
+            (bob, 1, U256::from(30))
+        );
+        assert_eq!(
+            (toggled[1].account, toggled[1].buys, toggled[1].sells),
+            (alice, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_price_at_sqrt_price() {
+        let token = |byte, decimals| ERC20Token {
+            address: Address::repeat_byte(byte),
+            decimals,
+            ..Default::default()
+        };
+        let pool = UniswapV3Pool::new(1, Address::ZERO, 3000, token(1, 18), token(2, 6));
+
+        // sqrtPriceX96 = 2 * 2^96 is a raw price of 4
+        let sqrt_price_x96 = U256::from(2) << 96;
+        let price = pool.price_at_sqrt_price(sqrt_price_x96, pool.token0.address);
+        assert!((price - 4e12).abs() < 1e-3);
+        let price = pool.price_at_sqrt_price(sqrt_price_x96, pool.token1.address);
+        assert!((price - 0.25e-12).abs() < 1e-24);
+    }
+
+    #[test]
+    fn test_average_tick() {
+        assert_eq!(average_tick(0, 6000, 60).unwrap(), 100);
+        assert_eq!(average_tick(1000, -5000, 60).unwrap(), -100);
+        // rounds down like the OracleLibrary
+        assert_eq!(average_tick(0, -6001, 60).unwrap(), -101);
+        assert_eq!(average_tick(0, 6001, 60).unwrap(), 100);
+        assert!(average_tick(0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_update_position() {
+        let mut state = State {
+            liquidity: 100,
+            sqrt_price: U256::ZERO,
+            tick: 30,
+            tick_spacing: 60,
+            tick_bitmap: HashMap::from([(0, U256::ZERO), (-1, U256::ZERO)]),
+            ticks: HashMap::new(),
+            pool_tick: PoolTick {
+                tick: 30,
+                liquidity_net: 0,
+                block: 0,
+            },
+            fee_protocol: 0,
+        };
+
+        state.update_position(-60, 120, 50).unwrap();
+        assert_eq!(state.liquidity, 150);
+        assert_eq!(state.ticks[&-60].liquidity_net, 50);
+        assert_eq!(state.ticks[&120].liquidity_net, -50);
+        assert_eq!(initialized_ticks(-1, state.tick_bitmap[&-1], 60), vec![-60]);
+        assert_eq!(initialized_ticks(0, state.tick_bitmap[&0], 60), vec![120]);
+
+        // out of range, the active liquidity doesn't change
+        state.update_position(60, 120, 10).unwrap();
+        assert_eq!(state.liquidity, 150);
+        assert_eq!(state.ticks[&120].liquidity_net, -60);
+
+        state.update_position(-60, 120, -50).unwrap();
+        assert_eq!(state.liquidity, 100);
+        assert!(!state.ticks.contains_key(&-60));
+        assert_eq!(initialized_ticks(0, state.tick_bitmap[&0], 60), vec![60, 120]);
+    }
+
+    #[test]
+    fn test_apply_log_after_fetch() {
+        let mut pool = UniswapV3Pool::new(
+            1,
+            Address::repeat_byte(1),
+            3000,
+            ERC20Token::default(),
+            ERC20Token::default(),
+        );
+        // a state fetched at block 100
+        pool.update_state(State {
+            liquidity: 100,
+            sqrt_price: U256::ZERO,
+            tick: 30,
+            tick_spacing: 60,
+            tick_bitmap: HashMap::from([(0, U256::ZERO), (-1, U256::ZERO)]),
+            ticks: HashMap::new(),
+            pool_tick: PoolTick {
+                tick: 30,
+                liquidity_net: 0,
+                block: 100,
+            },
+            fee_protocol: 0,
+        });
+
+        let address = pool.address;
+        let mint = |block, log_index| Log {
+            inner: alloy_primitives::Log {
+                address,
+                data: IUniswapV3Pool::Mint {
+                    sender: Address::ZERO,
+                    owner: Address::ZERO,
+                    tickLower: I24::try_from(-60).unwrap(),
+                    tickUpper: I24::try_from(120).unwrap(),
+                    amount: 50,
+                    amount0: U256::ZERO,
+                    amount1: U256::ZERO,
+                }
+                .encode_log_data(),
+            },
+            block_number: Some(block),
+            log_index: Some(log_index),
+            ..Default::default()
+        };
+
+        // the fetched state already includes the logs of its block
+        assert!(!pool.apply_log(&mint(100, 3)).unwrap());
+        let state = pool.state.as_ref().unwrap();
+        assert_eq!(state.liquidity, 100);
+        assert!(state.ticks.is_empty());
+
+        assert!(pool.apply_log(&mint(101, 0)).unwrap());
+        let state = pool.state.as_ref().unwrap();
+        assert_eq!(state.liquidity, 150);
+        assert_eq!(state.ticks[&-60].liquidity_net, 50);
+        assert_eq!(state.pool_tick.block, 101);
+
+        // replayed
+        assert!(!pool.apply_log(&mint(101, 0)).unwrap());
+        assert_eq!(pool.state.as_ref().unwrap().liquidity, 150);
+    }
+
+    #[test]
+    fn test_initialized_ticks() {
+        let bitmap = U256::from(1) | (U256::from(1) << 255);
+        assert_eq!(initialized_ticks(0, bitmap, 60), vec![0, 255 * 60]);
+        assert_eq!(initialized_ticks(-1, U256::from(1), 10), vec![-2560]);
+        assert!(initialized_ticks(3, U256::ZERO, 1).is_empty());
+    }
+}
@@ -0,0 +1,313 @@
+//! Value a Uniswap V3 position NFT
+//!
+//! A [Position] holds what the NonfungiblePositionManager stores for a token id and the pool it is in,
+//! which is enough to compute the token amounts of the position, its uncollected fees,
+//! its USD value and its impermanent loss against holding the deposited tokens
+//!
+//! ```ignore
+//! let position = Position::fetch(client.clone(), chain_id, token_id, position_manager, None).await?;
+//! let value = position.value_usd(client.clone(), None).await?;
+//! let il = position.impermanent_loss(deposit0, deposit1, value.token0_usd, value.token1_usd)?;
+//! println!("{} | IL {:.2}%", format_usd(value.total_usd()), il.percent);
+//! ```
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_sol_types::SolCall;
+use alloy_transport::Transport;
+use uniswap_v3_math::{
+    sqrt_price_math::{_get_amount_0_delta, _get_amount_1_delta},
+    tick_math::get_sqrt_ratio_at_tick,
+};
+
+use super::fee_math::get_uncollected_fees;
+use super::UniswapV3Pool;
+use crate::abi::uniswap::factory::v3::get_pool;
+use crate::abi::uniswap::nft_position::{decode_positions, encode_positions, INonfungiblePositionManager};
+use crate::defi::currency::erc20::{ERC20Token, TokenKind};
+use crate::utils::format::to_f64;
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
+
+/// A position NFT and its pool
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub token_id: U256,
+    pub position_manager: Address,
+
+    /// The pool of the position with its state at the block the position was fetched at
+    pub pool: UniswapV3Pool,
+
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub fee_growth_inside0_last_x128: U256,
+    pub fee_growth_inside1_last_x128: U256,
+
+    /// The fees and the withdrawn liquidity credited to the position but not collected yet
+    pub tokens_owed0: u128,
+    pub tokens_owed1: u128,
+}
+
+/// The USD value of a [Position]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionValue {
+    /// The token amounts of the liquidity, formatted
+    pub amount0: f64,
+    pub amount1: f64,
+
+    /// The uncollected fees, formatted
+    pub fees0: f64,
+    pub fees1: f64,
+
+    pub token0_usd: f64,
+    pub token1_usd: f64,
+
+    /// The USD value of the liquidity, without the fees
+    pub liquidity_usd: f64,
+    pub fees_usd: f64,
+}
+
+impl PositionValue {
+    pub fn total_usd(&self) -> f64 {
+        self.liquidity_usd + self.fees_usd
+    }
+}
+
+/// The impermanent loss of a position against holding the deposited tokens, both valued at the same prices
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImpermanentLoss {
+    /// The USD value of the deposited tokens if they were held
+    pub hodl_usd: f64,
+
+    /// The USD value of the liquidity, without the fees
+    pub position_usd: f64,
+
+    /// `position_usd - hodl_usd`, negative for a loss
+    pub loss_usd: f64,
+
+    /// The loss in percent of `hodl_usd`, negative for a loss
+    pub percent: f64,
+}
+
+impl Position {
+    /// Fetch a position and the state of its pool
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - The provider
+    /// * `chain_id` - The chain of the position
+    /// * `token_id` - The id of the position NFT
+    /// * `position_manager` - The NonfungiblePositionManager, see [UniswapDeployment](crate::defi::amm::uniswap::deployments::UniswapDeployment)
+    /// * `block` - The block to read at, None for the latest block
+    pub async fn fetch<T, P, N>(
+        client: P,
+        chain_id: u64,
+        token_id: U256,
+        position_manager: Address,
+        block: Option<BlockId>,
+    ) -> Result<Self, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let mut batch = EthCallBatch::new(block);
+        let positions_index = batch.add(position_manager, encode_positions(token_id));
+        let factory_index = batch.add(
+            position_manager,
+            INonfungiblePositionManager::factoryCall {}.abi_encode().into(),
+        );
+        let mut results = batch.send(client.clone()).await?;
+
+        let position = decode_positions(&take_result(&mut results, positions_index)?)?;
+        let factory = INonfungiblePositionManager::factoryCall::abi_decode_returns(
+            &take_result(&mut results, factory_index)?,
+            true,
+        )?
+        ._0;
+
+        let address = get_pool(client.clone(), factory, position.token0, position.token1, position.fee).await?;
+        if address == Address::ZERO {
+            return Err(anyhow::anyhow!("No pool for position {}", token_id));
+        }
+
+        let token0 = ERC20Token::new(client.clone(), position.token0, chain_id, TokenKind::Other).await?;
+        let token1 = ERC20Token::new(client.clone(), position.token1, chain_id, TokenKind::Other).await?;
+        let mut pool = UniswapV3Pool::new(chain_id, address, position.fee, token0, token1);
+        pool.update_state(UniswapV3Pool::fetch_state(address, client, block).await?);
+
+        Ok(Self {
+            token_id,
+            position_manager,
+            pool,
+            tick_lower: position.tick_lower,
+            tick_upper: position.tick_upper,
+            liquidity: position.liquidity,
+            fee_growth_inside0_last_x128: position.fee_growth_inside0_last_x128,
+            fee_growth_inside1_last_x128: position.fee_growth_inside1_last_x128,
+            tokens_owed0: position.tokens_owed0,
+            tokens_owed1: position.tokens_owed1,
+        })
+    }
+
+    /// Whether the current tick of the pool is in the range of the position
+    pub fn in_range(&self) -> Result<bool, anyhow::Error> {
+        let tick = self.current_tick()?;
+        Ok(tick >= self.tick_lower && tick < self.tick_upper)
+    }
+
+    /// The amounts of token0 and token1 the liquidity is worth at the current price of the pool, rounded down
+    pub fn amounts(&self) -> Result<(U256, U256), anyhow::Error> {
+        let state = self
+            .pool
+            .state()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+        amounts_for_liquidity(state.sqrt_price, self.tick_lower, self.tick_upper, self.liquidity)
+    }
+
+    /// The fees earned and not collected yet, `tokensOwed` included, see [get_uncollected_fees]
+    pub async fn uncollected_fees<T, P, N>(
+        &self,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<(U256, U256), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        get_uncollected_fees(client, self.pool.address, self.position_manager, self.token_id, block).await
+    }
+
+    /// The USD value of the liquidity and of the uncollected fees, priced like [UniswapV3Pool::tokens_usd]
+    pub async fn value_usd<T, P, N>(&self, client: P, block: Option<BlockId>) -> Result<PositionValue, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let (amount0, amount1) = self.amounts()?;
+        let (fees0, fees1) = self.uncollected_fees(client.clone(), block).await?;
+        let (token0_usd, token1_usd) = self.pool.tokens_usd(client, block).await?;
+
+        let (decimals0, decimals1) = (self.pool.token0.decimals, self.pool.token1.decimals);
+        let amount0 = to_f64(amount0, decimals0)?;
+        let amount1 = to_f64(amount1, decimals1)?;
+        let fees0 = to_f64(fees0, decimals0)?;
+        let fees1 = to_f64(fees1, decimals1)?;
+
+        Ok(PositionValue {
+            amount0,
+            amount1,
+            fees0,
+            fees1,
+            token0_usd,
+            token1_usd,
+            liquidity_usd: amount0 * token0_usd + amount1 * token1_usd,
+            fees_usd: fees0 * token0_usd + fees1 * token1_usd,
+        })
+    }
+
+    /// The impermanent loss of the position against holding the deposited amounts, the fees are not included
+    ///
+    /// ## Arguments
+    ///
+    /// * `deposited0` - The amount of token0 deposited
+    /// * `deposited1` - The amount of token1 deposited
+    /// * `token0_usd` - The current USD price of token0
+    /// * `token1_usd` - The current USD price of token1
+    pub fn impermanent_loss(
+        &self,
+        deposited0: U256,
+        deposited1: U256,
+        token0_usd: f64,
+        token1_usd: f64,
+    ) -> Result<ImpermanentLoss, anyhow::Error> {
+        let (decimals0, decimals1) = (self.pool.token0.decimals, self.pool.token1.decimals);
+        let (amount0, amount1) = self.amounts()?;
+
+        let hodl_usd = to_f64(deposited0, decimals0)? * token0_usd + to_f64(deposited1, decimals1)? * token1_usd;
+        let position_usd = to_f64(amount0, decimals0)? * token0_usd + to_f64(amount1, decimals1)? * token1_usd;
+        Ok(impermanent_loss(hodl_usd, position_usd))
+    }
+
+    fn current_tick(&self) -> Result<i32, anyhow::Error> {
+        self.pool
+            .state()
+            .map(|state| state.tick)
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))
+    }
+}
+
+/// The amounts of token0 and token1 of `liquidity` in a range at a price, rounded down
+pub fn amounts_for_liquidity(
+    sqrt_price: U256,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+) -> Result<(U256, U256), anyhow::Error> {
+    let sqrt_lower = get_sqrt_ratio_at_tick(tick_lower)?;
+    let sqrt_upper = get_sqrt_ratio_at_tick(tick_upper)?;
+
+    if sqrt_price <= sqrt_lower {
+        // below the range, all in token0
+        Ok((_get_amount_0_delta(sqrt_lower, sqrt_upper, liquidity, false)?, U256::ZERO))
+    } else if sqrt_price >= sqrt_upper {
+        // above the range, all in token1
+        Ok((U256::ZERO, _get_amount_1_delta(sqrt_lower, sqrt_upper, liquidity, false)?))
+    } else {
+        Ok((
+            _get_amount_0_delta(sqrt_price, sqrt_upper, liquidity, false)?,
+            _get_amount_1_delta(sqrt_lower, sqrt_price, liquidity, false)?,
+        ))
+    }
+}
+
+fn impermanent_loss(hodl_usd: f64, position_usd: f64) -> ImpermanentLoss {
+    let loss_usd = position_usd - hodl_usd;
+    let percent = if hodl_usd == 0.0 {
+        0.0
+    } else {
+        loss_usd / hodl_usd * 100.0
+    };
+
+    ImpermanentLoss {
+        hodl_usd,
+        position_usd,
+        loss_usd,
+        percent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amounts_for_liquidity() {
+        let liquidity = 1_000_000_000_000u128;
+        let below = get_sqrt_ratio_at_tick(-200).unwrap();
+        let inside = get_sqrt_ratio_at_tick(0).unwrap();
+        let above = get_sqrt_ratio_at_tick(200).unwrap();
+
+        let (amount0, amount1) = amounts_for_liquidity(below, -100, 100, liquidity).unwrap();
+        assert!(amount0 > U256::ZERO && amount1.is_zero());
+
+        let (amount0, amount1) = amounts_for_liquidity(above, -100, 100, liquidity).unwrap();
+        assert!(amount0.is_zero() && amount1 > U256::ZERO);
+
+        // symmetric range around tick 0
+        let (amount0, amount1) = amounts_for_liquidity(inside, -100, 100, liquidity).unwrap();
+        let diff = if amount0 > amount1 { amount0 - amount1 } else { amount1 - amount0 };
+        assert!(diff <= U256::from(1));
+    }
+
+    #[test]
+    fn test_impermanent_loss() {
+        let il = impermanent_loss(200.0, 190.0);
+        assert_eq!(il.loss_usd, -10.0);
+        assert_eq!(il.percent, -5.0);
+    }
+}
@@ -0,0 +1,238 @@
+//! Decode the `tokenURI` of Uniswap V3 position NFTs
+//!
+//! The NonfungiblePositionManager returns an on-chain `data:application/json;base64,...` URI
+//! whose `name` holds the pair, the fee and the price range of the position, and whose `image` is an SVG.
+//! Together with `positions` and the `slot0` of the pool this is enough to list the positions of a wallet
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, Bytes, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_sol_types::SolCall;
+use alloy_transport::Transport;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+
+use crate::abi::uniswap::nft_position::{
+    decode_positions, decode_token_uri as decode_token_uri_call, encode_positions, encode_token_uri,
+    INonfungiblePositionManager,
+};
+use crate::abi::uniswap::pool::v3::{decode_slot0, encode_slot0};
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
+
+const JSON_PREFIX: &str = "data:application/json;base64,";
+const SVG_PREFIX: &str = "data:image/svg+xml;base64,";
+
+/// The JSON behind a position `tokenURI`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenUri {
+    pub name: String,
+    pub description: String,
+
+    /// The decoded SVG if the image is a base64 data URI, otherwise the image URI as is
+    pub image: String,
+}
+
+/// Whether a position currently earns fees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionStatus {
+    InRange,
+    OutOfRange,
+
+    /// The position has no liquidity left
+    Closed,
+}
+
+/// A position NFT decoded for display
+#[derive(Debug, Clone)]
+pub struct PositionMetadata {
+    pub token_id: U256,
+
+    /// The symbols of the pair, eg. `USDC/WETH`
+    pub pair: String,
+
+    /// The fee as shown in the NFT, eg. `0.3%`
+    pub fee: String,
+
+    /// The lower price of the range, 0 for the minimum tick
+    pub lower_price: f64,
+
+    /// The upper price of the range, infinity for the maximum tick
+    pub upper_price: f64,
+
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub pool: Address,
+    pub status: PositionStatus,
+    pub image_svg: String,
+}
+
+/// Decode a `data:application/json;base64,` token URI, the base64 SVG image is decoded as well
+pub fn decode_token_uri(uri: &str) -> Result<TokenUri, anyhow::Error> {
+    let json = match uri.strip_prefix(JSON_PREFIX) {
+        Some(data) => String::from_utf8(STANDARD.decode(data)?)?,
+        None => uri
+            .strip_prefix("data:application/json,")
+            .ok_or_else(|| anyhow::anyhow!("Unsupported token URI: {}", uri.chars().take(40).collect::<String>()))?
+            .to_string(),
+    };
+
+    let mut token_uri: TokenUri = serde_json::from_str(&json)?;
+    if let Some(svg) = token_uri.image.strip_prefix(SVG_PREFIX) {
+        token_uri.image = String::from_utf8(STANDARD.decode(svg)?)?;
+    }
+
+    Ok(token_uri)
+}
+
+/// Parse the name of a position NFT, eg. `Uniswap - 0.3% - USDC/WETH - 1234.5<>2345.6`
+///
+/// Returns `(pair, fee, lower_price, upper_price)`
+pub fn parse_name(name: &str) -> Result<(String, String, f64, f64), anyhow::Error> {
+    let parts: Vec<&str> = name.split(" - ").map(str::trim).collect();
+    if parts.len() != 4 {
+        return Err(anyhow::anyhow!("Unexpected position name: {}", name));
+    }
+
+    let (lower, upper) = parts[3]
+        .split_once("<>")
+        .ok_or_else(|| anyhow::anyhow!("Unexpected price range: {}", parts[3]))?;
+
+    Ok((
+        parts[2].to_string(),
+        parts[1].to_string(),
+        parse_price(lower)?,
+        parse_price(upper)?,
+    ))
+}
+
+fn parse_price(price: &str) -> Result<f64, anyhow::Error> {
+    match price {
+        "MIN" => Ok(0.0),
+        "MAX" => Ok(f64::INFINITY),
+        _ => Ok(price.replace(',', "").parse::<f64>()?),
+    }
+}
+
+/// The pool address written in the description of the NFT
+fn pool_from_description(description: &str) -> Option<Address> {
+    description
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Pool Address: "))
+        .and_then(|address| address.trim().parse().ok())
+}
+
+/// Fetch and decode the metadata of a position NFT
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `token_id` - The id of the position
+/// * `position_manager` - The NonfungiblePositionManager, see [NFT_POSITION_CONTRACT](crate::abi::uniswap::nft_position::NFT_POSITION_CONTRACT)
+/// * `block` - The block to read at, None for the latest block
+pub async fn get_position_metadata<T, P, N>(
+    client: P,
+    token_id: U256,
+    position_manager: Address,
+    block: Option<BlockId>,
+) -> Result<PositionMetadata, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut batch = EthCallBatch::new(block);
+    let uri_index = batch.add(position_manager, encode_token_uri(token_id));
+    let positions_index = batch.add(position_manager, encode_positions(token_id));
+    let mut results = batch.send(client.clone()).await?;
+
+    let uri = decode_token_uri_call(&take_result(&mut results, uri_index)?)?;
+    let position = decode_positions(&take_result(&mut results, positions_index)?)?;
+
+    let token_uri = decode_token_uri(&uri)?;
+    let (pair, fee, lower_price, upper_price) = parse_name(&token_uri.name)?;
+    let pool = pool_from_description(&token_uri.description)
+        .ok_or_else(|| anyhow::anyhow!("No pool address in the description of position {}", token_id))?;
+
+    let status = if position.liquidity == 0 {
+        PositionStatus::Closed
+    } else {
+        let mut batch = EthCallBatch::new(block);
+        let slot0_index = batch.add(pool, encode_slot0());
+        let mut results = batch.send(client).await?;
+        let (_, tick) = decode_slot0(&take_result(&mut results, slot0_index)?)?;
+
+        if tick >= position.tick_lower && tick < position.tick_upper {
+            PositionStatus::InRange
+        } else {
+            PositionStatus::OutOfRange
+        }
+    };
+
+    Ok(PositionMetadata {
+        token_id,
+        pair,
+        fee,
+        lower_price,
+        upper_price,
+        tick_lower: position.tick_lower,
+        tick_upper: position.tick_upper,
+        liquidity: position.liquidity,
+        pool,
+        status,
+        image_svg: token_uri.image,
+    })
+}
+
+/// Fetch the metadata of every position NFT held by `owner`
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `owner` - The wallet
+/// * `position_manager` - The NonfungiblePositionManager
+/// * `block` - The block to read at, None for the latest block
+pub async fn get_wallet_positions<T, P, N>(
+    client: P,
+    owner: Address,
+    position_manager: Address,
+    block: Option<BlockId>,
+) -> Result<Vec<PositionMetadata>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut batch = EthCallBatch::new(block);
+    let balance_index = batch.add(
+        position_manager,
+        Bytes::from(INonfungiblePositionManager::balanceOfCall { owner }.abi_encode()),
+    );
+    let mut results = batch.send(client.clone()).await?;
+    let balance = INonfungiblePositionManager::balanceOfCall::abi_decode_returns(
+        &take_result(&mut results, balance_index)?,
+        true,
+    )?
+    ._0;
+    let balance: usize = balance.try_into()?;
+
+    let mut batch = EthCallBatch::new(block);
+    for index in 0..balance {
+        let call = INonfungiblePositionManager::tokenOfOwnerByIndexCall {
+            owner,
+            index: U256::from(index),
+        };
+        batch.add(position_manager, Bytes::from(call.abi_encode()));
+    }
+
+    let mut positions = Vec::with_capacity(balance);
+    for result in batch.send(client.clone()).await? {
+        let token_id =
+            INonfungiblePositionManager::tokenOfOwnerByIndexCall::abi_decode_returns(&result?, true)?._0;
+        positions.push(get_position_metadata(client.clone(), token_id, position_manager, block).await?);
+    }
+
+    Ok(positions)
+}
@@ -0,0 +1,310 @@
+//! Render a [PositionResult] as a self-contained Markdown or HTML report
+//!
+//! The reports have no external assets, the price chart is an inline SVG in HTML
+//! and a unicode sparkline in Markdown, so they can be shared as a single file
+//!
+//! ```ignore
+//! let result = simulate_position(client, BlockTime::Days(7), args).await?;
+//! std::fs::write("position.html", result.to_html())?;
+//! std::fs::write("position.md", result.to_markdown())?;
+//! ```
+
+use super::lp_provider::{PositionResult, PricePoint};
+use crate::utils::format::format_usd;
+
+/// The characters of a unicode sparkline from lowest to highest
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The maximum number of points of a sparkline, longer series are downsampled
+const SPARK_WIDTH: usize = 60;
+
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 200.0;
+
+impl PositionResult {
+    /// A Markdown report with the summary, the per epoch earnings, the big swaps and a sparkline of the price
+    pub fn to_markdown(&self) -> String {
+        let pair = format!("{}/{}", self.token0.symbol, self.token1.symbol);
+        let mut md = format!("# {} position\n\n", pair);
+
+        md.push_str("| | |\n|---|---|\n");
+        for (label, value) in self.summary() {
+            md.push_str(&format!("| {} | {} |\n", label, value));
+        }
+
+        if !self.prices.is_empty() {
+            let prices: Vec<f64> = self.prices.iter().map(|p| p.price).collect();
+            md.push_str(&format!(
+                "\n## Price of {} in {}\n\n`{}`\n\nRange {} - {}, {} of {} swaps in range\n",
+                self.token0.symbol,
+                self.token1.symbol,
+                sparkline(&prices),
+                self.lower_range,
+                self.upper_range,
+                self.in_range,
+                self.in_range + self.out_of_range
+            ));
+        }
+
+        if !self.epochs.is_empty() {
+            md.push_str(&format!(
+                "\n## Earnings per epoch\n\n| Epoch | Blocks | {} | {} | USD |\n|---|---|---|---|---|\n",
+                self.token0.symbol, self.token1.symbol
+            ));
+            for epoch in &self.epochs {
+                md.push_str(&format!(
+                    "| {} | {} - {} | {:.4} | {:.4} | {} |\n",
+                    epoch.epoch,
+                    epoch.start_block,
+                    epoch.end_block,
+                    epoch.earned0,
+                    epoch.earned1,
+                    format_usd(epoch.total_usd())
+                ));
+            }
+        }
+
+        if !self.big_swaps.is_empty() {
+            md.push_str("\n## Big swaps\n\n| Block | Swap | USD | In range | Tx |\n|---|---|---|---|---|\n");
+            for swap in &self.big_swaps {
+                md.push_str(&format!(
+                    "| {} | {:.4} {} -> {:.4} {} | {} | {} | `{}` |\n",
+                    swap.block,
+                    swap.amount_in,
+                    swap.token_in.symbol,
+                    swap.amount_out,
+                    swap.token_out.symbol,
+                    format_usd(swap.amount_usd),
+                    if swap.in_range { "yes" } else { "no" },
+                    swap.tx_hash
+                ));
+            }
+        }
+
+        md
+    }
+
+    /// An HTML page with the summary, an SVG chart of the price against the range, the per epoch earnings and the big swaps
+    pub fn to_html(&self) -> String {
+        let pair = escape(&format!("{}/{}", self.token0.symbol, self.token1.symbol));
+        let mut body = format!("<h1>{} position</h1>\n<table>\n", pair);
+
+        for (label, value) in self.summary() {
+            body.push_str(&format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                escape(&label),
+                escape(&value)
+            ));
+        }
+        body.push_str("</table>\n");
+
+        if !self.prices.is_empty() {
+            body.push_str(&format!(
+                "<h2>Price of {} in {}</h2>\n{}\n<p>Range {} - {}, {} of {} swaps in range</p>\n",
+                escape(&self.token0.symbol),
+                escape(&self.token1.symbol),
+                price_chart(&self.prices, self.lower_range, self.upper_range),
+                self.lower_range,
+                self.upper_range,
+                self.in_range,
+                self.in_range + self.out_of_range
+            ));
+        }
+
+        if !self.epochs.is_empty() {
+            body.push_str(&format!(
+                "<h2>Earnings per epoch</h2>\n<table>\n<tr><th>Epoch</th><th>Blocks</th><th>{}</th><th>{}</th><th>USD</th></tr>\n",
+                escape(&self.token0.symbol),
+                escape(&self.token1.symbol)
+            ));
+            for epoch in &self.epochs {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{} - {}</td><td>{:.4}</td><td>{:.4}</td><td>{}</td></tr>\n",
+                    epoch.epoch,
+                    epoch.start_block,
+                    epoch.end_block,
+                    epoch.earned0,
+                    epoch.earned1,
+                    format_usd(epoch.total_usd())
+                ));
+            }
+            body.push_str("</table>\n");
+        }
+
+        if !self.big_swaps.is_empty() {
+            body.push_str("<h2>Big swaps</h2>\n<table>\n<tr><th>Block</th><th>Swap</th><th>USD</th><th>In range</th><th>Tx</th></tr>\n");
+            for swap in &self.big_swaps {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{:.4} {} &rarr; {:.4} {}</td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>\n",
+                    swap.block,
+                    swap.amount_in,
+                    escape(&swap.token_in.symbol),
+                    swap.amount_out,
+                    escape(&swap.token_out.symbol),
+                    format_usd(swap.amount_usd),
+                    if swap.in_range { "yes" } else { "no" },
+                    escape(&swap.tx_hash)
+                ));
+            }
+            body.push_str("</table>\n");
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{} position</title>\n<style>\n\
+             body {{ font-family: sans-serif; max-width: 800px; margin: 2em auto; }}\n\
+             table {{ border-collapse: collapse; margin-bottom: 1em; }}\n\
+             th, td {{ border: 1px solid #ddd; padding: 4px 8px; text-align: left; }}\n\
+             </style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            pair, body
+        )
+    }
+
+    /// The label and the formatted value of the headline numbers
+    fn summary(&self) -> Vec<(String, String)> {
+        let mut rows = vec![
+            (
+                format!("{} price", self.token0.symbol),
+                format!("{} -> {}", format_usd(self.past_token0_usd), format_usd(self.token0_usd)),
+            ),
+            (
+                format!("{} price", self.token1.symbol),
+                format!("{} -> {}", format_usd(self.past_token1_usd), format_usd(self.token1_usd)),
+            ),
+            (
+                "Earned".to_string(),
+                format!(
+                    "{:.4} {} + {:.4} {} ({})",
+                    self.earned0,
+                    self.token0.symbol,
+                    self.earned1,
+                    self.token1.symbol,
+                    format_usd(self.earned0_usd + self.earned1_usd)
+                ),
+            ),
+            ("APR".to_string(), format!("{:.2}%", self.apr)),
+            ("Gas cost".to_string(), format_usd(self.gas_cost_usd)),
+            ("Net APR".to_string(), format!("{:.2}%", self.net_apr)),
+            ("Principal in".to_string(), format_usd(self.principal_in_usd)),
+            ("Principal out".to_string(), format_usd(self.principal_out_usd)),
+            ("Exit PnL".to_string(), format_usd(self.exit_pnl_usd())),
+            (
+                "Volume".to_string(),
+                format_usd(self.buy_volume_usd + self.sell_volume_usd),
+            ),
+            (
+                "Swaps in range".to_string(),
+                format!("{} of {}", self.in_range, self.in_range + self.out_of_range),
+            ),
+        ];
+
+        if self.incentive_rewards_usd > 0.0 {
+            rows.push(("Incentive APR".to_string(), format!("{:.2}%", self.incentive_apr)));
+        }
+        if let Some(harvested_usd) = self.harvested_usd {
+            rows.push(("Harvested".to_string(), format_usd(harvested_usd)));
+        }
+        if self.failed_swaps > 0 {
+            rows.push(("Failed swaps".to_string(), self.failed_swaps.to_string()));
+        }
+
+        rows
+    }
+}
+
+/// A unicode sparkline of the values, downsampled to [SPARK_WIDTH] characters
+pub fn sparkline(values: &[f64]) -> String {
+    if values.is_empty() {
+        return String::new();
+    }
+
+    let values = downsample(values, SPARK_WIDTH);
+    let (min, max) = min_max(values.iter().copied());
+    let span = max - min;
+
+    values
+        .iter()
+        .map(|value| {
+            if span == 0.0 {
+                return SPARK_CHARS[SPARK_CHARS.len() / 2];
+            }
+            let level = ((value - min) / span * (SPARK_CHARS.len() - 1) as f64).round() as usize;
+            SPARK_CHARS[level.min(SPARK_CHARS.len() - 1)]
+        })
+        .collect()
+}
+
+/// An inline SVG of the price with the range as a shaded band
+fn price_chart(prices: &[PricePoint], lower_range: f64, upper_range: f64) -> String {
+    let values: Vec<f64> = prices.iter().map(|p| p.price).collect();
+    // the bounds of a full range would squash the price line, only finite bounds widen the chart
+    let bounds = [lower_range, upper_range].into_iter().filter(|b| b.is_finite() && *b > 0.0);
+    let (min, max) = min_max(values.iter().copied().chain(bounds));
+
+    // leave some room so the lines don't touch the borders
+    let padding = (max - min).max(max.abs() * 1e-6) * 0.05;
+    let (min, max) = (min - padding, max + padding);
+    let y = |price: f64| (CHART_HEIGHT - (price - min) / (max - min) * CHART_HEIGHT).clamp(0.0, CHART_HEIGHT);
+
+    let step = if values.len() > 1 {
+        CHART_WIDTH / (values.len() - 1) as f64
+    } else {
+        0.0
+    };
+    let points: Vec<String> = values
+        .iter()
+        .enumerate()
+        .map(|(i, price)| format!("{:.1},{:.1}", i as f64 * step, y(*price)))
+        .collect();
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+         <rect x=\"0\" y=\"{top:.1}\" width=\"{w}\" height=\"{band:.1}\" fill=\"#2e7d32\" fill-opacity=\"0.15\"/>\n\
+         <polyline points=\"{points}\" fill=\"none\" stroke=\"#1565c0\" stroke-width=\"1.5\"/>\n\
+         </svg>",
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT,
+        top = y(upper_range),
+        band = y(lower_range) - y(upper_range),
+        points = points.join(" ")
+    )
+}
+
+/// Keep at most `width` values by taking the last value of each bucket
+fn downsample(values: &[f64], width: usize) -> Vec<f64> {
+    if values.len() <= width {
+        return values.to_vec();
+    }
+    (1..=width)
+        .map(|i| values[i * values.len() / width - 1])
+        .collect()
+}
+
+fn min_max(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[1.0, 2.0, 3.0]), "▁▅█");
+        assert_eq!(sparkline(&[5.0, 5.0]), "▅▅");
+
+        let long: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        let line = sparkline(&long);
+        assert_eq!(line.chars().count(), SPARK_WIDTH);
+        assert!(line.ends_with('█'));
+    }
+}
@@ -0,0 +1,394 @@
+//! Range orders, limit orders made of a Uniswap V3 position one tick spacing wide
+//!
+//! A position above the current price holds only token0 and a position below it holds only token1.
+//! When the price moves through the range the position is converted to the other token, earning the swap fees
+//! on the way. The position must be withdrawn once filled, otherwise a move back converts it again
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, Signed, Uint, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
+use anyhow::Context;
+use uniswap_v3_math::{
+    sqrt_price_math::{_get_amount_0_delta, _get_amount_1_delta},
+    tick_math::{get_sqrt_ratio_at_tick, MAX_TICK, MIN_TICK},
+};
+
+use super::fee_math::{get_liquidity_for_amount0, get_liquidity_for_amount1};
+use super::{sqrt_price_to_f64, tick_spacing_for_fee, UniswapV3Pool};
+use crate::abi::uniswap::nft_position::{decode_positions, encode_positions, INonfungiblePositionManager};
+use crate::abi::uniswap::pool::v3::{decode_slot0, encode_slot0};
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
+
+/// A limit order placed as a single tick spacing wide position
+#[derive(Debug, Clone)]
+pub struct RangeOrder {
+    pub pool: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+
+    /// Whether token0 is sold for token1
+    pub zero_for_one: bool,
+
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+
+    /// The amount of the sold token deposited
+    pub amount_in: U256,
+
+    /// The liquidity of the position
+    pub liquidity: u128,
+
+    /// The amount of the bought token received when the order is filled, excluding the swap fees earned
+    pub amount_out: U256,
+}
+
+/// How much of a [RangeOrder] is converted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangeOrderStatus {
+    /// The price has not reached the range
+    Pending,
+
+    /// The price is inside the range, `progress` is the converted share from 0 to 1
+    PartiallyFilled { progress: f64 },
+
+    /// The price went through the range, the position holds only the bought token
+    Filled,
+
+    /// The position has no liquidity left
+    Withdrawn,
+}
+
+impl RangeOrder {
+    /// Build a range order that sells `amount_in` of `token_in` at `target_price` or better
+    ///
+    /// The range is the tick spacing just past the target price, so the whole order is
+    /// converted at a price at least as good as the target
+    ///
+    /// ## Arguments
+    ///
+    /// * `pool` - The pool, must have its state set
+    /// * `token_in` - The token to sell
+    /// * `amount_in` - The amount of `token_in`
+    /// * `target_price` - The price of token0 in terms of token1 to sell at
+    pub fn new(
+        pool: &UniswapV3Pool,
+        token_in: Address,
+        amount_in: U256,
+        target_price: f64,
+    ) -> Result<Self, anyhow::Error> {
+        let state = pool
+            .state()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+
+        if token_in != pool.token0.address && token_in != pool.token1.address {
+            return Err(anyhow::anyhow!("Token {} is not in the pool", token_in));
+        }
+        if !(target_price.is_finite() && target_price > 0.0) {
+            return Err(anyhow::anyhow!("Invalid price: {}", target_price));
+        }
+
+        let zero_for_one = token_in == pool.token0.address;
+        let spacing = tick_spacing_for_fee(pool.fee)?;
+        let target_tick = price_to_tick(target_price, pool.token0.decimals, pool.token1.decimals);
+
+        // selling token0 fills as the price goes up, selling token1 as it goes down
+        let (tick_lower, tick_upper) = if zero_for_one {
+            let lower = target_tick.div_euclid(spacing) * spacing
+                + if target_tick.rem_euclid(spacing) == 0 { 0 } else { spacing };
+            (lower, lower + spacing)
+        } else {
+            let upper = target_tick.div_euclid(spacing) * spacing;
+            (upper - spacing, upper)
+        };
+
+        if tick_lower < MIN_TICK || tick_upper > MAX_TICK {
+            return Err(anyhow::anyhow!("Price {} is out of the tick range", target_price));
+        }
+
+        if zero_for_one && state.tick >= tick_lower {
+            return Err(anyhow::anyhow!(
+                "The target price must be above the current price to sell token0"
+            ));
+        }
+        if !zero_for_one && state.tick < tick_upper {
+            return Err(anyhow::anyhow!(
+                "The target price must be below the current price to sell token1"
+            ));
+        }
+
+        let sqrt_lower = get_sqrt_ratio_at_tick(tick_lower)?;
+        let sqrt_upper = get_sqrt_ratio_at_tick(tick_upper)?;
+
+        let (liquidity, amount_out) = if zero_for_one {
+            let liquidity: u128 = get_liquidity_for_amount0(sqrt_lower, sqrt_upper, amount_in)
+                .try_into()
+                .context("Liquidity overflow")?;
+            let amount_out = _get_amount_1_delta(sqrt_lower, sqrt_upper, liquidity, false)?;
+            (liquidity, amount_out)
+        } else {
+            let liquidity: u128 = get_liquidity_for_amount1(sqrt_lower, sqrt_upper, amount_in)
+                .try_into()
+                .context("Liquidity overflow")?;
+            let amount_out = _get_amount_0_delta(sqrt_lower, sqrt_upper, liquidity, false)?;
+            (liquidity, amount_out)
+        };
+
+        Ok(Self {
+            pool: pool.address,
+            token0: pool.token0.address,
+            token1: pool.token1.address,
+            fee: pool.fee,
+            zero_for_one,
+            tick_lower,
+            tick_upper,
+            amount_in,
+            liquidity,
+            amount_out,
+        })
+    }
+
+    /// The average execution price of the order, token0 in terms of token1
+    pub fn execution_price(&self, decimals0: u8, decimals1: u8) -> f64 {
+        let price_lower = tick_to_price(self.tick_lower, decimals0, decimals1);
+        let price_upper = tick_to_price(self.tick_upper, decimals0, decimals1);
+        (price_lower * price_upper).sqrt()
+    }
+
+    /// The mint params of the position, `amount0Min` and `amount1Min` are left at 0
+    ///
+    /// ## Arguments
+    ///
+    /// * `recipient` - The owner of the position
+    /// * `deadline` - See [deadline_after](crate::utils::deadline::deadline_after)
+    pub fn mint_params(
+        &self,
+        recipient: Address,
+        deadline: U256,
+    ) -> Result<INonfungiblePositionManager::MintParams, anyhow::Error> {
+        let (amount0, amount1) = if self.zero_for_one {
+            (self.amount_in, U256::ZERO)
+        } else {
+            (U256::ZERO, self.amount_in)
+        };
+
+        let tick_lower: Signed<24, 1> = self
+            .tick_lower
+            .to_string()
+            .parse()
+            .context("Failed to parse tick")?;
+        let tick_upper: Signed<24, 1> = self
+            .tick_upper
+            .to_string()
+            .parse()
+            .context("Failed to parse tick")?;
+
+        Ok(INonfungiblePositionManager::MintParams {
+            token0: self.token0,
+            token1: self.token1,
+            fee: Uint::from(self.fee),
+            tickLower: tick_lower,
+            tickUpper: tick_upper,
+            amount0Desired: amount0,
+            amount1Desired: amount1,
+            amount0Min: U256::ZERO,
+            amount1Min: U256::ZERO,
+            recipient,
+            deadline,
+        })
+    }
+
+    /// The status of the order at the given sqrtPriceX96 of the pool
+    pub fn status_at(&self, sqrt_price: U256) -> Result<RangeOrderStatus, anyhow::Error> {
+        let sqrt_lower = get_sqrt_ratio_at_tick(self.tick_lower)?;
+        let sqrt_upper = get_sqrt_ratio_at_tick(self.tick_upper)?;
+
+        if sqrt_price <= sqrt_lower {
+            return Ok(if self.zero_for_one {
+                RangeOrderStatus::Pending
+            } else {
+                RangeOrderStatus::Filled
+            });
+        }
+        if sqrt_price >= sqrt_upper {
+            return Ok(if self.zero_for_one {
+                RangeOrderStatus::Filled
+            } else {
+                RangeOrderStatus::Pending
+            });
+        }
+
+        let (price, lower, upper) = (
+            sqrt_price_to_f64(sqrt_price),
+            sqrt_price_to_f64(sqrt_lower),
+            sqrt_price_to_f64(sqrt_upper),
+        );
+        let position = (price - lower) / (upper - lower);
+        let progress = if self.zero_for_one {
+            position
+        } else {
+            1.0 - position
+        };
+
+        Ok(RangeOrderStatus::PartiallyFilled { progress })
+    }
+
+    /// The status of the order with the current state of the pool
+    pub fn status(&self, pool: &UniswapV3Pool) -> Result<RangeOrderStatus, anyhow::Error> {
+        let state = pool
+            .state()
+            .ok_or_else(|| anyhow::anyhow!("State not initialized"))?;
+        self.status_at(state.sqrt_price)
+    }
+}
+
+/// Fetch the status of a range order that was minted as a position
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `order` - The order
+/// * `token_id` - The id of the position
+/// * `position_manager` - The NonfungiblePositionManager, see [UniswapDeployment](crate::defi::amm::uniswap::deployments::UniswapDeployment)
+/// * `block` - The block to read at, None for the latest block
+pub async fn get_range_order_status<T, P, N>(
+    client: P,
+    order: &RangeOrder,
+    token_id: U256,
+    position_manager: Address,
+    block: Option<BlockId>,
+) -> Result<RangeOrderStatus, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut batch = EthCallBatch::new(block);
+    let positions_index = batch.add(position_manager, encode_positions(token_id));
+    let slot0_index = batch.add(order.pool, encode_slot0());
+    let mut results = batch.send(client).await?;
+
+    let position = decode_positions(&take_result(&mut results, positions_index)?)?;
+    if position.liquidity == 0 {
+        return Ok(RangeOrderStatus::Withdrawn);
+    }
+
+    let (sqrt_price, _) = decode_slot0(&take_result(&mut results, slot0_index)?)?;
+    order.status_at(sqrt_price)
+}
+
+/// The tick of a human readable price, rounded down
+///
+/// ## Arguments
+///
+/// * `price` - Price of token0 in terms of token1
+/// * `decimals0` - Decimals of token0
+/// * `decimals1` - Decimals of token1
+pub fn price_to_tick(price: f64, decimals0: u8, decimals1: u8) -> i32 {
+    let raw_price = price * 10_f64.powi(decimals1 as i32 - decimals0 as i32);
+    (raw_price.ln() / 1.0001_f64.ln()).floor() as i32
+}
+
+/// The human readable price of a tick, token0 in terms of token1
+pub fn tick_to_price(tick: i32, decimals0: u8, decimals1: u8) -> f64 {
+    1.0001_f64.powi(tick) * 10_f64.powi(decimals0 as i32 - decimals1 as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{PoolTick, State};
+    use crate::defi::currency::erc20::ERC20Token;
+    use std::collections::HashMap;
+
+    /// A 0.3% pool of two 18 decimals tokens at tick 0 (price 1)
+    fn pool() -> UniswapV3Pool {
+        let token = |byte| ERC20Token {
+            address: Address::repeat_byte(byte),
+            decimals: 18,
+            ..Default::default()
+        };
+        let mut pool = UniswapV3Pool::new(1, Address::repeat_byte(0xa), 3000, token(1), token(2));
+        pool.update_state(State {
+            liquidity: 0,
+            sqrt_price: U256::from(1) << 96,
+            tick: 0,
+            tick_spacing: 60,
+            tick_bitmap: HashMap::new(),
+            ticks: HashMap::new(),
+            pool_tick: PoolTick {
+                tick: 0,
+                liquidity_net: 0,
+                block: 0,
+            },
+            fee_protocol: 0,
+        });
+        pool
+    }
+
+    #[test]
+    fn test_price_tick_round_trip() {
+        // USDC (6) / WETH (18)
+        let tick = price_to_tick(0.0004, 6, 18);
+        let price = tick_to_price(tick, 6, 18);
+        assert!(price <= 0.0004);
+        assert!(tick_to_price(tick + 1, 6, 18) > 0.0004);
+        assert_eq!(price_to_tick(1.0, 18, 18), 0);
+    }
+
+    #[test]
+    fn test_new_ticks() {
+        let pool = pool();
+        let amount = U256::from(10).pow(U256::from(18));
+
+        // selling token0 at 1.01 (tick 99), the range starts at the next spacing above
+        let order = RangeOrder::new(&pool, pool.token0.address, amount, 1.01).unwrap();
+        assert!(order.zero_for_one);
+        assert_eq!((order.tick_lower, order.tick_upper), (120, 180));
+        assert!(order.liquidity > 0 && order.amount_out > U256::ZERO);
+
+        // selling token1 at 0.99 (tick -101), the range ends at the spacing below
+        let order = RangeOrder::new(&pool, pool.token1.address, amount, 0.99).unwrap();
+        assert!(!order.zero_for_one);
+        assert_eq!((order.tick_lower, order.tick_upper), (-180, -120));
+        assert!(order.liquidity > 0 && order.amount_out > U256::ZERO);
+    }
+
+    #[test]
+    fn test_new_past_the_price() {
+        let pool = pool();
+        let amount = U256::from(1000);
+
+        // token0 can only be sold above the current price and token1 below it
+        assert!(RangeOrder::new(&pool, pool.token0.address, amount, 0.99).is_err());
+        assert!(RangeOrder::new(&pool, pool.token1.address, amount, 1.01).is_err());
+        assert!(RangeOrder::new(&pool, Address::repeat_byte(3), amount, 1.01).is_err());
+    }
+
+    #[test]
+    fn test_status_at() {
+        let pool = pool();
+        let order = RangeOrder::new(&pool, pool.token0.address, U256::from(10).pow(U256::from(18)), 1.01).unwrap();
+        let at_tick = |tick| get_sqrt_ratio_at_tick(tick).unwrap();
+
+        assert_eq!(order.status_at(at_tick(0)).unwrap(), RangeOrderStatus::Pending);
+        assert_eq!(order.status_at(at_tick(120)).unwrap(), RangeOrderStatus::Pending);
+        assert_eq!(order.status_at(at_tick(180)).unwrap(), RangeOrderStatus::Filled);
+
+        let RangeOrderStatus::PartiallyFilled { progress } = order.status_at(at_tick(150)).unwrap() else {
+            panic!("Expected a partial fill");
+        };
+        assert!(progress > 0.45 && progress < 0.55);
+
+        // selling token1 fills as the price goes down
+        let order = RangeOrder::new(&pool, pool.token1.address, U256::from(1000), 0.99).unwrap();
+        assert_eq!(order.status_at(at_tick(0)).unwrap(), RangeOrderStatus::Pending);
+        assert_eq!(order.status_at(at_tick(-180)).unwrap(), RangeOrderStatus::Filled);
+        let RangeOrderStatus::PartiallyFilled { progress } = order.status_at(at_tick(-170)).unwrap() else {
+            panic!("Expected a partial fill");
+        };
+        assert!(progress > 0.8);
+    }
+}
@@ -0,0 +1,96 @@
+//! Reward math of the [UniswapV3Staker](crate::abi::uniswap::staker)
+
+use alloy_primitives::U256;
+
+pub use crate::abi::uniswap::staker::IUniswapV3Staker::IncentiveKey;
+
+/// Compute the reward of a staked position, same as `RewardMath.computeRewardAmount`
+///
+/// Returns `(reward, secondsInsideX128)`
+///
+/// ## Arguments
+///
+/// * `total_reward_unclaimed` - The unclaimed rewards of the incentive
+/// * `total_seconds_claimed_x128` - Seconds claimed by all the stakes so far
+/// * `start_time` - Start of the incentive
+/// * `end_time` - End of the incentive
+/// * `liquidity` - Liquidity of the staked position
+/// * `seconds_per_liquidity_inside_initial_x128` - secondsPerLiquidityInside when the position was staked
+/// * `seconds_per_liquidity_inside_x128` - Current secondsPerLiquidityInside of the position's range
+/// * `current_time` - The current timestamp
+pub fn compute_reward_amount(
+    total_reward_unclaimed: U256,
+    total_seconds_claimed_x128: U256,
+    start_time: u64,
+    end_time: u64,
+    liquidity: u128,
+    seconds_per_liquidity_inside_initial_x128: U256,
+    seconds_per_liquidity_inside_x128: U256,
+    current_time: u64,
+) -> (U256, U256) {
+    // the contract asserts this can't happen
+    if current_time < start_time {
+        return (U256::ZERO, U256::ZERO);
+    }
+
+    let seconds_inside_x128 = seconds_per_liquidity_inside_x128
+        .wrapping_sub(seconds_per_liquidity_inside_initial_x128)
+        .wrapping_mul(U256::from(liquidity));
+
+    let elapsed = end_time.max(current_time) - start_time;
+    let total_seconds_unclaimed_x128 =
+        (U256::from(elapsed) << 128).saturating_sub(total_seconds_claimed_x128);
+
+    if total_seconds_unclaimed_x128.is_zero() {
+        return (U256::ZERO, seconds_inside_x128);
+    }
+
+    let reward = total_reward_unclaimed * seconds_inside_x128 / total_seconds_unclaimed_x128;
+    (reward, seconds_inside_x128)
+}
+
+/// Estimate the incentive reward a new position would earn over a period of time
+///
+/// Assumes the unclaimed rewards are paid out evenly over the unclaimed seconds of the incentive
+/// and that the position earns its share of the active liquidity while it is in range
+///
+/// ## Arguments
+///
+/// * `key` - The incentive
+/// * `total_reward_unclaimed` - The unclaimed rewards of the incentive at `from`
+/// * `total_seconds_claimed_x128` - Seconds claimed by all the stakes at `from`
+/// * `from` - Start timestamp of the period
+/// * `to` - End timestamp of the period
+/// * `liquidity_share` - position liquidity / active liquidity (including the position)
+/// * `in_range_ratio` - The fraction of the period the position was in range
+pub fn estimate_incentive_reward(
+    key: &IncentiveKey,
+    total_reward_unclaimed: U256,
+    total_seconds_claimed_x128: U256,
+    from: u64,
+    to: u64,
+    liquidity_share: f64,
+    in_range_ratio: f64,
+) -> Result<f64, anyhow::Error> {
+    let start_time = key.startTime.to::<u64>();
+    let end_time = key.endTime.to::<u64>();
+
+    // the part of the period that overlaps with the incentive
+    let overlap_start = from.max(start_time);
+    let overlap_end = to.min(end_time);
+    if overlap_end <= overlap_start {
+        return Ok(0.0);
+    }
+
+    let total_seconds_claimed = (total_seconds_claimed_x128 >> 128).to::<u64>();
+    let unclaimed_seconds = (end_time - start_time).saturating_sub(total_seconds_claimed);
+    if unclaimed_seconds == 0 {
+        return Ok(0.0);
+    }
+
+    let unclaimed = total_reward_unclaimed.to_string().parse::<f64>()?;
+    let reward_per_second = unclaimed / unclaimed_seconds as f64;
+    let seconds = (overlap_end - overlap_start) as f64;
+
+    Ok(reward_per_second * seconds * liquidity_share * in_range_ratio)
+}
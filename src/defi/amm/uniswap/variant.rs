@@ -0,0 +1,38 @@
+//! Forks of Uniswap that share its pool contracts but not its fees
+
+use serde::{Deserialize, Serialize};
+
+/// The DEX a V2 or V3 pool was deployed by
+///
+/// The swap math is the one of Uniswap, only the fees and the V3 fee tiers differ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DexVariant {
+    #[default]
+    Uniswap,
+
+    /// 0.25% on V2, a 0.25% tier (tick spacing 50) instead of 0.3% on V3
+    PancakeSwap,
+    SushiSwap,
+
+    /// V2 only, the fee is set per pair and per direction, 0.3% is the default
+    Camelot,
+}
+
+impl DexVariant {
+    /// The swap fee of a V2 pair in basis points
+    pub fn v2_fee_bps(&self) -> u32 {
+        match self {
+            DexVariant::PancakeSwap => 25,
+            DexVariant::Uniswap | DexVariant::SushiSwap | DexVariant::Camelot => 30,
+        }
+    }
+
+    /// The fee tiers of the V3 factory in hundredths of a bip, see [tick_spacing_for_fee](super::v3::tick_spacing_for_fee)
+    pub fn v3_fee_tiers(&self) -> &'static [u32] {
+        match self {
+            DexVariant::Uniswap | DexVariant::SushiSwap => &[100, 500, 3000, 10000],
+            DexVariant::PancakeSwap => &[100, 500, 2500, 10000],
+            DexVariant::Camelot => &[],
+        }
+    }
+}
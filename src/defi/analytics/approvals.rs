@@ -0,0 +1,283 @@
+//! Index the ERC20 approvals granted to a spender
+//!
+//! Useful for the operator of a router or a vault: who approved it, on which tokens and for how much.
+//! The allowance of a user is the amount of its last approval in the window, so a revocation (an approval of 0)
+//! removes it from the total
+//!
+//! ```ignore
+//! let index = approvals_for_spender(client, chain_id, router, None, BlockTime::Days(30), &Config::default()).await?;
+//! println!("{} users, {}", index.unique_users, format_usd(index.total_approved_usd));
+//! ```
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, Filter};
+use alloy_sol_types::SolEvent;
+use alloy_transport::Transport;
+use futures::stream::{self, StreamExt};
+
+use std::collections::{HashMap, HashSet};
+use tracing::trace;
+
+use crate::abi::erc20::ERC20;
+use crate::defi::currency::erc20::{ERC20Token, TokenKind};
+use crate::defi::utils::chain_link::get_token_prices;
+use crate::utils::format::{format_usd, to_f64};
+use crate::utils::logs::{events::ERC20Approval, query::get_filtered_logs_in_range};
+use crate::utils::{config::Config, BlockTime};
+
+/// The approvals of a single token to the spender
+#[derive(Debug, Clone)]
+pub struct TokenApprovals {
+    pub token: ERC20Token,
+
+    /// The number of Approval events
+    pub approvals: usize,
+
+    /// The owners with an allowance left at the end of the window
+    pub users: usize,
+
+    /// The owners whose last approval is unlimited
+    pub unlimited: usize,
+
+    /// The sum of the last limited approval of each owner, formatted
+    pub approved: f64,
+
+    /// The USD value of [TokenApprovals::approved], 0 if the token has no price
+    pub approved_usd: f64,
+}
+
+/// The approvals granted to a spender over a window
+#[derive(Debug, Clone)]
+pub struct ApprovalIndex {
+    pub spender: Address,
+    pub from_block: u64,
+    pub to_block: u64,
+
+    /// Every approval in block order
+    pub approvals: Vec<ERC20Approval>,
+
+    /// The summary per token, sorted by USD value, highest first
+    pub tokens: Vec<TokenApprovals>,
+
+    /// The owners with an allowance left on any token
+    pub unique_users: usize,
+
+    /// The USD value of the limited approvals, the unlimited ones are only counted
+    pub total_approved_usd: f64,
+}
+
+impl ApprovalIndex {
+    /// Return a formatted string to print in the console
+    pub fn pretty(&self) -> String {
+        let mut s = format!(
+            "Approvals to {} | Blocks: {} - {} | Users: {} | Approved: {}",
+            self.spender,
+            self.from_block,
+            self.to_block,
+            self.unique_users,
+            format_usd(self.total_approved_usd)
+        );
+        for token in &self.tokens {
+            s.push_str(&format!(
+                "\n  {}: {} users ({} unlimited) | {:.4} ({})",
+                token.token.symbol,
+                token.users,
+                token.unlimited,
+                token.approved,
+                format_usd(token.approved_usd)
+            ));
+        }
+        s
+    }
+}
+
+/// Find the Approval events granted to `spender` and summarize them per token
+///
+/// The Approval events of ERC721 tokens share the same signature, they fail to decode and are skipped
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `chain_id` - The chain id
+/// * `spender` - The approved contract, eg. a router you operate
+/// * `tokens` - Only look at these tokens, every token if None
+/// * `block_time` - The window to look at
+/// * `config` - See [Config]
+pub async fn approvals_for_spender<T, P, N>(
+    client: P,
+    chain_id: u64,
+    spender: Address,
+    tokens: Option<Vec<Address>>,
+    block_time: BlockTime,
+    config: &Config,
+) -> Result<ApprovalIndex, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone + 'static,
+    N: Network,
+{
+    let current_block = config.timed(client.get_block_number()).await?;
+    let (from_block, to_block) = block_time
+        .block_range_with_client(client.clone(), chain_id, current_block)
+        .await?;
+
+    let filter = Filter::new()
+        .address(tokens.unwrap_or_default())
+        .event_signature(ERC20::Approval::SIGNATURE_HASH)
+        .topic2(spender.into_word());
+    let mut logs = get_filtered_logs_in_range(client.clone(), filter, from_block, to_block, config).await?;
+    logs.sort_by_key(|log| (log.block_number, log.log_index));
+    trace!("{} approvals to {}", logs.len(), spender);
+
+    let decoded: Vec<_> = logs
+        .iter()
+        .filter_map(|log| {
+            let event = log.log_decode::<ERC20::Approval>().ok()?.inner.data;
+            let tx_hash = log.transaction_hash.map(|h| h.to_string()).unwrap_or_default();
+            Some((log.address(), event, log.block_number.unwrap_or_default(), tx_hash))
+        })
+        .collect();
+
+    let mut addresses: Vec<Address> = decoded.iter().map(|(token, ..)| *token).collect();
+    addresses.sort();
+    addresses.dedup();
+
+    let erc20s: HashMap<Address, ERC20Token> = stream::iter(addresses)
+        .map(|address| {
+            let client = client.clone();
+            async move {
+                let token = ERC20Token::new(client, address, chain_id, TokenKind::Other).await;
+                if let Err(e) = &token {
+                    trace!("Failed to resolve token {}: {:?}", address, e);
+                }
+                token.ok().map(|token| (address, token))
+            }
+        })
+        .buffer_unordered(config.concurrency())
+        .filter_map(|token| async move { token })
+        .collect()
+        .await;
+
+    let approvals: Vec<ERC20Approval> = decoded
+        .into_iter()
+        .filter_map(|(token, event, block, tx_hash)| {
+            let token = erc20s.get(&token)?.clone();
+            Some(ERC20Approval::new(token, event.owner, event.spender, event.value, block, tx_hash))
+        })
+        .collect();
+
+    let token_addresses: Vec<Address> = erc20s.keys().copied().collect();
+    let prices = get_token_prices(
+        client,
+        Some(BlockId::number(to_block)),
+        chain_id,
+        &token_addresses,
+    )
+    .await?;
+    let prices: HashMap<Address, f64> = token_addresses.into_iter().zip(prices).collect();
+
+    let (tokens, unique_users) = summarize_approvals(&approvals, &prices)?;
+    let total_approved_usd = tokens.iter().map(|t| t.approved_usd).sum();
+
+    Ok(ApprovalIndex {
+        spender,
+        from_block,
+        to_block,
+        approvals,
+        tokens,
+        unique_users,
+        total_approved_usd,
+    })
+}
+
+/// Keep the last approval of each owner per token and sum them
+///
+/// Returns the summary per token and the number of owners with an allowance left on any token
+fn summarize_approvals(
+    approvals: &[ERC20Approval],
+    prices: &HashMap<Address, f64>,
+) -> Result<(Vec<TokenApprovals>, usize), anyhow::Error> {
+    // token -> (events, owner -> last amount)
+    let mut by_token: HashMap<Address, (&ERC20Token, usize, HashMap<Address, U256>)> = HashMap::new();
+    for approval in approvals {
+        let entry = by_token
+            .entry(approval.token.address)
+            .or_insert_with(|| (&approval.token, 0, HashMap::new()));
+        entry.1 += 1;
+        entry.2.insert(approval.owner, approval.amount);
+    }
+
+    let mut users = HashSet::new();
+    let mut tokens = Vec::with_capacity(by_token.len());
+
+    for (address, (token, events, allowances)) in by_token {
+        let mut summary = TokenApprovals {
+            token: token.clone(),
+            approvals: events,
+            users: 0,
+            unlimited: 0,
+            approved: 0.0,
+            approved_usd: 0.0,
+        };
+
+        for (owner, amount) in allowances {
+            if amount.is_zero() {
+                continue;
+            }
+            users.insert(owner);
+            summary.users += 1;
+            if amount == U256::MAX {
+                summary.unlimited += 1;
+            } else {
+                summary.approved += to_f64(amount, token.decimals)?;
+            }
+        }
+
+        summary.approved_usd = summary.approved * prices.get(&address).copied().unwrap_or_default();
+        tokens.push(summary);
+    }
+
+    tokens.sort_by(|a, b| b.approved_usd.total_cmp(&a.approved_usd));
+    Ok((tokens, users.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::address;
+
+    #[test]
+    fn test_summarize_approvals() {
+        let token = ERC20Token {
+            address: address!("0000000000000000000000000000000000000001"),
+            decimals: 6,
+            ..Default::default()
+        };
+        let alice = address!("00000000000000000000000000000000000000a1");
+        let bob = address!("00000000000000000000000000000000000000b0");
+        let carol = address!("00000000000000000000000000000000000000c0");
+        let approval = |owner, amount| ERC20Approval::new(token.clone(), owner, Address::ZERO, amount, 0, String::new());
+
+        let approvals = vec![
+            approval(alice, U256::from(5_000_000)),
+            // replaces the first one
+            approval(alice, U256::from(2_000_000)),
+            approval(bob, U256::MAX),
+            approval(carol, U256::from(1_000_000)),
+            // revoked
+            approval(carol, U256::ZERO),
+        ];
+        let prices = HashMap::from([(token.address, 2.0)]);
+
+        let (tokens, users) = summarize_approvals(&approvals, &prices).unwrap();
+        assert_eq!(users, 2);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].approvals, 5);
+        assert_eq!(tokens[0].users, 2);
+        assert_eq!(tokens[0].unlimited, 1);
+        assert_eq!(tokens[0].approved, 2.0);
+        assert_eq!(tokens[0].approved_usd, 4.0);
+    }
+}
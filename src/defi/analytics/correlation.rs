@@ -0,0 +1,374 @@
+//! Correlation, beta and co-drawdowns between two price series
+//!
+//! The series can come from two pools or a pool and ETH/USD, see [pool_price_series] and [eth_usd_series]
+
+use alloy_network::Ethereum;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::trace;
+
+use crate::defi::{
+    amm::uniswap::v3::UniswapV3Pool,
+    utils::{chain_link::get_token_price, common_addr::weth},
+};
+use crate::utils::config::Config;
+
+/// A price at a block
+pub type PricePoint = (u64, f64);
+
+/// The risk metrics of a pair of price series
+#[derive(Debug, Clone)]
+pub struct PairRisk {
+    /// Number of returns the metrics were computed on
+    pub samples: usize,
+
+    /// Pearson correlation of the returns
+    pub correlation: f64,
+
+    /// Beta of `a` relative to `b`
+    pub beta: f64,
+
+    /// Max drawdown of `a` as a fraction (0.2 = -20%)
+    pub max_drawdown_a: f64,
+
+    /// Max drawdown of `b` as a fraction
+    pub max_drawdown_b: f64,
+
+    /// Fraction of the samples where both series were in a drawdown deeper than the threshold
+    pub co_drawdown_ratio: f64,
+
+    /// Number of distinct periods where both series were in a drawdown deeper than the threshold
+    pub co_drawdowns: usize,
+}
+
+/// Compute the [PairRisk] of two price series
+///
+/// The series are aligned by block, points that only exist in one of them are ignored
+///
+/// ## Arguments
+///
+/// * `a` - The first price series
+/// * `b` - The second price series (the benchmark for the beta)
+/// * `window` - Only use the last `window` aligned points, None for all of them
+/// * `drawdown_threshold` - The drawdown (as a fraction) from which a series counts as in a drawdown
+pub fn pair_risk(
+    a: &[PricePoint],
+    b: &[PricePoint],
+    window: Option<usize>,
+    drawdown_threshold: f64,
+) -> Result<PairRisk, anyhow::Error> {
+    let (mut a, mut b) = align(a, b);
+
+    if let Some(window) = window {
+        let skip = a.len().saturating_sub(window);
+        a.drain(..skip);
+        b.drain(..skip);
+    }
+
+    if a.len() < 3 {
+        return Err(anyhow::anyhow!("Not enough aligned prices, need at least 3"));
+    }
+
+    let returns_a = log_returns(&a);
+    let returns_b = log_returns(&b);
+
+    let dd_a = drawdowns(&a);
+    let dd_b = drawdowns(&b);
+
+    let mut co_samples = 0;
+    let mut co_drawdowns = 0;
+    let mut in_co_drawdown = false;
+    for (da, db) in dd_a.iter().zip(dd_b.iter()) {
+        let both = *da >= drawdown_threshold && *db >= drawdown_threshold;
+        if both {
+            co_samples += 1;
+            if !in_co_drawdown {
+                co_drawdowns += 1;
+            }
+        }
+        in_co_drawdown = both;
+    }
+
+    Ok(PairRisk {
+        samples: returns_a.len(),
+        correlation: correlation(&returns_a, &returns_b),
+        beta: beta(&returns_a, &returns_b),
+        max_drawdown_a: dd_a.iter().cloned().fold(0.0, f64::max),
+        max_drawdown_b: dd_b.iter().cloned().fold(0.0, f64::max),
+        co_drawdown_ratio: co_samples as f64 / a.len() as f64,
+        co_drawdowns,
+    })
+}
+
+/// The correlation of two price series over a rolling window of returns
+///
+/// Returns `(block, correlation)` for the last block of each window
+pub fn rolling_correlation(a: &[PricePoint], b: &[PricePoint], window: usize) -> Vec<PricePoint> {
+    let (a, b) = align(a, b);
+    let returns_a = log_returns(&a);
+    let returns_b = log_returns(&b);
+
+    if window < 2 || returns_a.len() < window {
+        return Vec::new();
+    }
+
+    (window..=returns_a.len())
+        .map(|end| {
+            let start = end - window;
+            (
+                // returns[i] ends at price[i + 1]
+                a[end].0,
+                correlation(&returns_a[start..end], &returns_b[start..end]),
+            )
+        })
+        .collect()
+}
+
+/// Pearson correlation of two samples of the same length
+pub fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let std_x = variance(x).sqrt();
+    let std_y = variance(y).sqrt();
+    if std_x == 0.0 || std_y == 0.0 {
+        return 0.0;
+    }
+    covariance(x, y) / (std_x * std_y)
+}
+
+/// Beta of `x` relative to `y`, cov(x, y) / var(y)
+pub fn beta(x: &[f64], y: &[f64]) -> f64 {
+    let var_y = variance(y);
+    if var_y == 0.0 {
+        return 0.0;
+    }
+    covariance(x, y) / var_y
+}
+
+fn mean(x: &[f64]) -> f64 {
+    x.iter().sum::<f64>() / x.len() as f64
+}
+
+fn variance(x: &[f64]) -> f64 {
+    covariance(x, x)
+}
+
+fn covariance(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len().min(y.len());
+    if n < 2 {
+        return 0.0;
+    }
+    let mean_x = mean(&x[..n]);
+    let mean_y = mean(&y[..n]);
+    x.iter()
+        .zip(y.iter())
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>()
+        / (n - 1) as f64
+}
+
+fn log_returns(prices: &[PricePoint]) -> Vec<f64> {
+    prices
+        .windows(2)
+        .map(|w| (w[1].1 / w[0].1).ln())
+        .collect()
+}
+
+/// The drawdown from the running peak at each point
+fn drawdowns(prices: &[PricePoint]) -> Vec<f64> {
+    let mut peak = f64::MIN;
+    prices
+        .iter()
+        .map(|(_, price)| {
+            peak = peak.max(*price);
+            1.0 - price / peak
+        })
+        .collect()
+}
+
+/// Keep only the blocks that exist in both series, sorted by block
+fn align(a: &[PricePoint], b: &[PricePoint]) -> (Vec<PricePoint>, Vec<PricePoint>) {
+    let b_prices: HashMap<u64, f64> = b.iter().cloned().collect();
+
+    let mut a: Vec<PricePoint> = a
+        .iter()
+        .filter(|(block, price)| *price > 0.0 && b_prices.get(block).is_some_and(|p| *p > 0.0))
+        .cloned()
+        .collect();
+    a.sort_by_key(|(block, _)| *block);
+    a.dedup_by_key(|(block, _)| *block);
+
+    let b = a.iter().map(|(block, _)| (*block, b_prices[block])).collect();
+    (a, b)
+}
+
+/// Fetch the price of a Uniswap V3 pool at every `step` blocks between `from_block` and `to_block`
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `pool` - The pool
+/// * `base_token` - The token to price in terms of the other token
+/// * `from_block` - The first block
+/// * `to_block` - The last block (exclusive)
+/// * `step` - The number of blocks between each price
+/// * `config` - See [Config]
+pub async fn pool_price_series<T, P>(
+    client: P,
+    pool: UniswapV3Pool,
+    base_token: Address,
+    from_block: u64,
+    to_block: u64,
+    step: usize,
+    config: &Config,
+) -> Result<Vec<PricePoint>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    if step == 0 {
+        return Err(anyhow::anyhow!("The step must be at least 1 block"));
+    }
+
+    let prices = Arc::new(Mutex::new(Vec::new()));
+    let semaphore = Arc::new(Semaphore::new(config.concurrency()));
+    let mut tasks: Vec<JoinHandle<Result<(), anyhow::Error>>> = Vec::new();
+
+    for block in (from_block..to_block).step_by(step) {
+        let client = client.clone();
+        let prices = prices.clone();
+        let mut pool = pool.clone();
+        let semaphore = semaphore.clone();
+        let config = config.clone();
+
+        let task = tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let state = config
+                .timed(UniswapV3Pool::fetch_state(
+                    pool.address,
+                    client,
+                    Some(BlockId::number(block)),
+                ))
+                .await?;
+            pool.update_state(state);
+            let price = pool.calculate_price(base_token)?;
+            prices.lock().await.push((block, price));
+            Ok(())
+        });
+        tasks.push(task);
+    }
+
+    collect(tasks, prices).await
+}
+
+/// Fetch the ETH/USD price at every `step` blocks between `from_block` and `to_block`
+pub async fn eth_usd_series<T, P>(
+    client: P,
+    chain_id: u64,
+    from_block: u64,
+    to_block: u64,
+    step: usize,
+    config: &Config,
+) -> Result<Vec<PricePoint>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    let weth = weth(chain_id)?;
+    if step == 0 {
+        return Err(anyhow::anyhow!("The step must be at least 1 block"));
+    }
+
+    let prices = Arc::new(Mutex::new(Vec::new()));
+    let semaphore = Arc::new(Semaphore::new(config.concurrency()));
+    let mut tasks: Vec<JoinHandle<Result<(), anyhow::Error>>> = Vec::new();
+
+    for block in (from_block..to_block).step_by(step) {
+        let client = client.clone();
+        let prices = prices.clone();
+        let semaphore = semaphore.clone();
+        let config = config.clone();
+
+        let task = tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let price = config
+                .timed(get_token_price(
+                    client,
+                    Some(BlockId::number(block)),
+                    chain_id,
+                    weth,
+                ))
+                .await?;
+            prices.lock().await.push((block, price));
+            Ok(())
+        });
+        tasks.push(task);
+    }
+
+    collect(tasks, prices).await
+}
+
+async fn collect(
+    tasks: Vec<JoinHandle<Result<(), anyhow::Error>>>,
+    prices: Arc<Mutex<Vec<PricePoint>>>,
+) -> Result<Vec<PricePoint>, anyhow::Error> {
+    for task in tasks {
+        match task.await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => trace!("Failed to get price: {:?}", e),
+            Err(e) => trace!("Price task panicked: {:?}", e),
+        }
+    }
+
+    let mut prices = prices.lock().await.clone();
+    prices.sort_by_key(|(block, _)| *block);
+    Ok(prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlation_and_beta() {
+        let x = [1.0, 2.0, 3.0, 4.0];
+        let y = [1.0, 3.0, 2.0, 4.0];
+
+        // cov = 4 / 3, var(x) = var(y) = 5 / 3
+        assert!((correlation(&x, &y) - 0.8).abs() < 1e-12);
+        assert!((beta(&y, &x) - 0.8).abs() < 1e-12);
+
+        let doubled: Vec<f64> = x.iter().map(|v| v * 2.0).collect();
+        let negated: Vec<f64> = x.iter().map(|v| -v).collect();
+        assert!((correlation(&x, &doubled) - 1.0).abs() < 1e-12);
+        assert!((correlation(&x, &negated) + 1.0).abs() < 1e-12);
+        assert!((beta(&doubled, &x) - 2.0).abs() < 1e-12);
+
+        // a flat series has no correlation
+        assert_eq!(correlation(&x, &[1.0; 4]), 0.0);
+        assert_eq!(beta(&x, &[1.0; 4]), 0.0);
+    }
+
+    #[test]
+    fn test_pair_risk() {
+        let a = [(1, 100.0), (2, 80.0), (3, 120.0), (4, 90.0)];
+        // block 5 is not in `a` and is ignored
+        let b = [(4, 9.0), (3, 12.0), (2, 8.0), (1, 10.0), (5, 1.0)];
+
+        let risk = pair_risk(&a, &b, None, 0.1).unwrap();
+        assert_eq!(risk.samples, 3);
+        assert!((risk.max_drawdown_a - 0.25).abs() < 1e-12);
+        assert!((risk.max_drawdown_b - 0.25).abs() < 1e-12);
+
+        // both in a drawdown of at least 10% at blocks 2 and 4, two separate periods
+        assert_eq!(risk.co_drawdowns, 2);
+        assert_eq!(risk.co_drawdown_ratio, 0.5);
+
+        assert!(pair_risk(&a, &b, Some(2), 0.1).is_err());
+    }
+}
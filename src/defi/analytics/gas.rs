@@ -0,0 +1,265 @@
+//! Historical gas usage of the calls to a contract
+//!
+//! The transactions are found through the logs the contract emitted in the window, their receipts give the gas used.
+//! Grouping by selector shows what each function of the contract costs in practice,
+//! which is a good estimate of the execution cost of a strategy before deploying it
+//!
+//! Only transactions sent directly to the contract are counted, calls made through another contract
+//! (eg. a router) pay for more than the call itself. Transactions in which the contract emitted no log are missed
+
+use alloy_network::Ethereum;
+use alloy_primitives::{Address, Selector, TxHash};
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::trace;
+
+use crate::utils::{config::Config, logs::query::get_logs_with_config, BlockTime};
+
+/// The gas used by one transaction to the contract
+#[derive(Debug, Clone)]
+pub struct GasSample {
+    pub tx_hash: TxHash,
+    pub block: u64,
+
+    /// The selector of the call, None for a transfer without calldata
+    pub selector: Option<Selector>,
+    pub gas_used: u64,
+    pub success: bool,
+}
+
+/// The distribution of the gas used by the calls of a selector
+///
+/// The distribution is computed over the successful calls only, reverts stop early and would skew it
+#[derive(Debug, Clone)]
+pub struct GasStats {
+    pub selector: Option<Selector>,
+
+    /// The number of calls, reverted included
+    pub calls: usize,
+    pub reverted: usize,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub median: u64,
+    pub p90: u64,
+}
+
+/// The gas usage of a contract over a window, see [get_gas_report]
+#[derive(Debug, Clone)]
+pub struct GasReport {
+    pub contract: Address,
+    pub samples: Vec<GasSample>,
+
+    /// The stats of each selector, most called first
+    pub stats: Vec<GasStats>,
+}
+
+impl GasReport {
+    /// Group the samples by selector
+    pub fn new(contract: Address, samples: Vec<GasSample>) -> Self {
+        let mut by_selector: BTreeMap<Option<Selector>, Vec<&GasSample>> = BTreeMap::new();
+        for sample in &samples {
+            by_selector.entry(sample.selector).or_default().push(sample);
+        }
+
+        let mut stats: Vec<GasStats> = by_selector
+            .into_iter()
+            .map(|(selector, samples)| {
+                let gas: Vec<u64> = samples
+                    .iter()
+                    .filter(|s| s.success)
+                    .map(|s| s.gas_used)
+                    .collect();
+                GasStats::new(selector, &gas, samples.len())
+            })
+            .collect();
+        stats.sort_by(|a, b| b.calls.cmp(&a.calls));
+
+        Self {
+            contract,
+            samples,
+            stats,
+        }
+    }
+
+    /// The stats of a selector
+    pub fn stats_for(&self, selector: Selector) -> Option<&GasStats> {
+        self.stats.iter().find(|s| s.selector == Some(selector))
+    }
+
+    pub fn pretty(&self) -> String {
+        let mut lines = vec![format!(
+            "Gas used by {} transactions to {}",
+            self.samples.len(),
+            self.contract
+        )];
+
+        for stats in &self.stats {
+            let selector = stats
+                .selector
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "transfer".to_string());
+            lines.push(format!(
+                "  {}: {} calls ({} reverted), min {} median {} p90 {} max {} mean {:.0}",
+                selector,
+                stats.calls,
+                stats.reverted,
+                stats.min,
+                stats.median,
+                stats.p90,
+                stats.max,
+                stats.mean
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl GasStats {
+    /// ## Arguments
+    ///
+    /// * `selector` - The selector of the calls
+    /// * `gas` - The gas used by the successful calls
+    /// * `calls` - The number of calls, reverted included
+    pub fn new(selector: Option<Selector>, gas: &[u64], calls: usize) -> Self {
+        let mut sorted = gas.to_vec();
+        sorted.sort_unstable();
+
+        let mean = if sorted.is_empty() {
+            0.0
+        } else {
+            sorted.iter().sum::<u64>() as f64 / sorted.len() as f64
+        };
+
+        Self {
+            selector,
+            calls,
+            reverted: calls.saturating_sub(sorted.len()),
+            min: sorted.first().copied().unwrap_or(0),
+            max: sorted.last().copied().unwrap_or(0),
+            mean,
+            median: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+        }
+    }
+}
+
+/// The nearest-rank percentile of sorted values, 0 if empty
+pub fn percentile(sorted: &[u64], pct: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Collect the gas used by the transactions to a contract over a window
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `chain_id` - The chain id
+/// * `contract` - The contract
+/// * `block_time` - The window to look at
+/// * `config` - See [Config]
+pub async fn get_gas_report<T, P>(
+    client: P,
+    chain_id: u64,
+    contract: Address,
+    block_time: BlockTime,
+    config: &Config,
+) -> Result<GasReport, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    let no_events: Vec<[u8; 32]> = Vec::new();
+    let logs = get_logs_with_config(
+        client.clone(),
+        chain_id,
+        vec![contract],
+        no_events,
+        block_time,
+        config,
+    )
+    .await?;
+
+    let mut seen = HashSet::new();
+    let tx_hashes: Vec<TxHash> = logs
+        .iter()
+        .filter_map(|log| log.transaction_hash)
+        .filter(|hash| seen.insert(*hash))
+        .collect();
+    trace!("Found {} transactions for {}", tx_hashes.len(), contract);
+
+    let samples = Arc::new(Mutex::new(Vec::new()));
+    let semaphore = Arc::new(Semaphore::new(config.concurrency()));
+    let mut tasks: Vec<JoinHandle<Result<(), anyhow::Error>>> = Vec::new();
+
+    for tx_hash in tx_hashes {
+        let client = client.clone();
+        let samples = samples.clone();
+        let semaphore = semaphore.clone();
+        let config = config.clone();
+
+        let task = tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let tx = config
+                .timed(client.get_transaction_by_hash(tx_hash))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Transaction {} not found", tx_hash))?;
+            if tx.to != Some(contract) {
+                return Ok(());
+            }
+
+            let receipt = config
+                .timed(client.get_transaction_receipt(tx_hash))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Receipt of {} not found", tx_hash))?;
+
+            let selector = tx.input.get(..4).map(Selector::from_slice);
+            samples.lock().await.push(GasSample {
+                tx_hash,
+                block: receipt.block_number.unwrap_or_default(),
+                selector,
+                gas_used: receipt.gas_used as u64,
+                success: receipt.status(),
+            });
+            Ok(())
+        });
+        tasks.push(task);
+    }
+
+    for task in tasks {
+        match task.await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => trace!("Failed to get the receipt: {:?}", e),
+            Err(e) => trace!("Receipt task panicked: {:?}", e),
+        }
+    }
+
+    let mut samples = samples.lock().await.clone();
+    samples.sort_by_key(|s| s.block);
+    Ok(GasReport::new(contract, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let sorted = [10, 20, 30, 40, 50, 60, 70, 80, 90, 100];
+        assert_eq!(percentile(&sorted, 50.0), 50);
+        assert_eq!(percentile(&sorted, 90.0), 90);
+        assert_eq!(percentile(&sorted, 100.0), 100);
+        assert_eq!(percentile(&sorted, 0.0), 10);
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+}
@@ -0,0 +1,434 @@
+//! Detection of just-in-time (JIT) liquidity in the history of a Uniswap V3 pool
+//!
+//! A JIT position is minted right before a large swap and burned right after it, so it earns a share of the swap fee
+//! without bearing any price risk. Every fee taken by a JIT position is a fee passive LPs would have earned,
+//! which matters when interpreting the APR of [simulate_position](crate::defi::amm::uniswap::v3::lp_provider::simulate_position)
+
+use alloy_network::Ethereum;
+use alloy_primitives::{Address, TxHash, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::Log;
+use alloy_sol_types::SolEvent;
+use alloy_transport::Transport;
+
+use crate::abi::uniswap::pool::v3::IUniswapV3Pool;
+use crate::defi::amm::uniswap::v3::UniswapV3Pool;
+use crate::utils::{format::to_f64, logs::query::get_logs_for, BlockTime};
+
+/// A liquidity event of a pool, Mint or Burn
+#[derive(Debug, Clone)]
+struct LiquidityEvent {
+    owner: Address,
+    tick_lower: i32,
+    tick_upper: i32,
+    amount: u128,
+    block: u64,
+    log_index: u64,
+    tx_hash: TxHash,
+}
+
+#[derive(Debug, Clone)]
+struct SwapEvent {
+    zero_for_one: bool,
+    amount_in: U256,
+    liquidity: u128,
+    tick: i32,
+    block: u64,
+    log_index: u64,
+}
+
+#[derive(Debug, Clone)]
+enum PoolEvent {
+    Mint(LiquidityEvent),
+    Burn(LiquidityEvent),
+    Swap(SwapEvent),
+}
+
+impl PoolEvent {
+    fn position(&self) -> (u64, u64) {
+        match self {
+            PoolEvent::Mint(e) | PoolEvent::Burn(e) => (e.block, e.log_index),
+            PoolEvent::Swap(e) => (e.block, e.log_index),
+        }
+    }
+}
+
+/// A detected JIT position
+#[derive(Debug, Clone)]
+pub struct JitPosition {
+    pub owner: Address,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub mint_block: u64,
+    pub burn_block: u64,
+    pub mint_tx: TxHash,
+    pub burn_tx: TxHash,
+
+    /// The number of swaps between the mint and the burn
+    pub swaps: usize,
+
+    /// The fees in token0 earned by the position
+    pub fee0: U256,
+
+    /// The fees in token1 earned by the position
+    pub fee1: U256,
+}
+
+/// The JIT activity of a pool
+#[derive(Debug, Clone)]
+pub struct JitReport {
+    pub positions: Vec<JitPosition>,
+
+    /// The fees in token0 collected by all the LPs of the pool
+    pub total_fee0: U256,
+
+    /// The fees in token1 collected by all the LPs of the pool
+    pub total_fee1: U256,
+
+    /// The fees in token0 taken by JIT positions
+    pub jit_fee0: U256,
+
+    /// The fees in token1 taken by JIT positions
+    pub jit_fee1: U256,
+}
+
+impl JitReport {
+    /// The fraction of the token0 fees taken by JIT positions
+    pub fn jit_share0(&self) -> f64 {
+        share(self.jit_fee0, self.total_fee0)
+    }
+
+    /// The fraction of the token1 fees taken by JIT positions
+    pub fn jit_share1(&self) -> f64 {
+        share(self.jit_fee1, self.total_fee1)
+    }
+}
+
+/// Detect JIT liquidity from the Mint, Swap and Burn logs of a pool
+///
+/// A JIT position is a mint followed by a burn of the same owner, range and liquidity
+/// at most `max_blocks` blocks later with at least one swap in between
+///
+/// ## Arguments
+///
+/// * `pool` - The pool the logs belong to
+/// * `logs` - The Mint, Swap and Burn logs of the pool, other logs are ignored
+/// * `max_blocks` - How many blocks the position can live (0 for the same block)
+pub fn detect_jit(
+    pool: &UniswapV3Pool,
+    logs: &[Log],
+    max_blocks: u64,
+) -> Result<JitReport, anyhow::Error> {
+    let mut events = Vec::new();
+    for log in logs {
+        if log.address() != pool.address {
+            continue;
+        }
+        if let Some(event) = decode_event(log)? {
+            events.push(event);
+        }
+    }
+    events.sort_by_key(|e| e.position());
+
+    let fee = U256::from(pool.fee);
+    let fee_of = |swap: &SwapEvent| swap.amount_in * fee / U256::from(1_000_000);
+
+    let mut total_fee0 = U256::ZERO;
+    let mut total_fee1 = U256::ZERO;
+    for event in &events {
+        if let PoolEvent::Swap(swap) = event {
+            if swap.zero_for_one {
+                total_fee0 += fee_of(swap);
+            } else {
+                total_fee1 += fee_of(swap);
+            }
+        }
+    }
+
+    let mut positions = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        let PoolEvent::Mint(mint) = event else {
+            continue;
+        };
+
+        let mut fee0 = U256::ZERO;
+        let mut fee1 = U256::ZERO;
+        let mut swaps = 0;
+
+        for next in &events[i + 1..] {
+            if next.position().0 > mint.block + max_blocks {
+                break;
+            }
+
+            match next {
+                PoolEvent::Swap(swap) => {
+                    swaps += 1;
+
+                    // the reported liquidity includes the JIT position if the swap ended in its range
+                    let in_range = mint.tick_lower <= swap.tick && swap.tick < mint.tick_upper;
+                    if !in_range || swap.liquidity == 0 {
+                        continue;
+                    }
+
+                    let jit_fee =
+                        fee_of(swap) * U256::from(mint.amount) / U256::from(swap.liquidity);
+                    if swap.zero_for_one {
+                        fee0 += jit_fee;
+                    } else {
+                        fee1 += jit_fee;
+                    }
+                }
+                PoolEvent::Burn(burn)
+                    if burn.owner == mint.owner
+                        && burn.tick_lower == mint.tick_lower
+                        && burn.tick_upper == mint.tick_upper
+                        && burn.amount == mint.amount =>
+                {
+                    if swaps > 0 {
+                        positions.push(JitPosition {
+                            owner: mint.owner,
+                            tick_lower: mint.tick_lower,
+                            tick_upper: mint.tick_upper,
+                            liquidity: mint.amount,
+                            mint_block: mint.block,
+                            burn_block: burn.block,
+                            mint_tx: mint.tx_hash,
+                            burn_tx: burn.tx_hash,
+                            swaps,
+                            fee0,
+                            fee1,
+                        });
+                    }
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let jit_fee0 = positions.iter().map(|p| p.fee0).sum();
+    let jit_fee1 = positions.iter().map(|p| p.fee1).sum();
+
+    Ok(JitReport {
+        positions,
+        total_fee0,
+        total_fee1,
+        jit_fee0,
+        jit_fee1,
+    })
+}
+
+/// Fetch the logs of a pool and detect the JIT liquidity, see [detect_jit]
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `pool` - The pool
+/// * `block_time` - The time range to look at
+/// * `max_blocks` - How many blocks a JIT position can live
+pub async fn get_jit_report<T, P>(
+    client: P,
+    pool: &UniswapV3Pool,
+    block_time: BlockTime,
+    max_blocks: u64,
+) -> Result<JitReport, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    let events = vec![
+        IUniswapV3Pool::Mint::SIGNATURE,
+        IUniswapV3Pool::Swap::SIGNATURE,
+        IUniswapV3Pool::Burn::SIGNATURE,
+    ];
+    let logs = get_logs_for(client, pool.chain_id, vec![pool.address], events, block_time).await?;
+
+    detect_jit(pool, &logs, max_blocks)
+}
+
+fn decode_event(log: &Log) -> Result<Option<PoolEvent>, anyhow::Error> {
+    let block = log
+        .block_number
+        .ok_or_else(|| anyhow::anyhow!("Block number is missing"))?;
+    let log_index = log.log_index.unwrap_or(0);
+    let tx_hash = log.transaction_hash.unwrap_or_default();
+
+    let event = match log.topic0() {
+        Some(&IUniswapV3Pool::Mint::SIGNATURE_HASH) => {
+            let mint = log.log_decode::<IUniswapV3Pool::Mint>()?.inner.data;
+            PoolEvent::Mint(LiquidityEvent {
+                owner: mint.owner,
+                tick_lower: mint.tickLower.to_string().parse()?,
+                tick_upper: mint.tickUpper.to_string().parse()?,
+                amount: mint.amount,
+                block,
+                log_index,
+                tx_hash,
+            })
+        }
+        Some(&IUniswapV3Pool::Burn::SIGNATURE_HASH) => {
+            let burn = log.log_decode::<IUniswapV3Pool::Burn>()?.inner.data;
+            // burning 0 liquidity is used to poke the fees
+            if burn.amount == 0 {
+                return Ok(None);
+            }
+            PoolEvent::Burn(LiquidityEvent {
+                owner: burn.owner,
+                tick_lower: burn.tickLower.to_string().parse()?,
+                tick_upper: burn.tickUpper.to_string().parse()?,
+                amount: burn.amount,
+                block,
+                log_index,
+                tx_hash,
+            })
+        }
+        Some(&IUniswapV3Pool::Swap::SIGNATURE_HASH) => {
+            let swap = log.log_decode::<IUniswapV3Pool::Swap>()?.inner.data;
+            let zero_for_one = swap.amount0.is_positive();
+            let amount_in = if zero_for_one {
+                swap.amount0.unsigned_abs()
+            } else {
+                swap.amount1.unsigned_abs()
+            };
+            PoolEvent::Swap(SwapEvent {
+                zero_for_one,
+                amount_in,
+                liquidity: swap.liquidity,
+                tick: swap.tick.to_string().parse()?,
+                block,
+                log_index,
+            })
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(event))
+}
+
+fn share(part: U256, total: U256) -> f64 {
+    if total.is_zero() {
+        return 0.0;
+    }
+    let part = to_f64(part, 0).unwrap_or(0.0);
+    let total = to_f64(total, 0).unwrap_or(1.0);
+    part / total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{
+        aliases::{I24, U160},
+        I256,
+    };
+
+    fn log_of<E: SolEvent>(pool: Address, event: E, block: u64, log_index: u64) -> Log {
+        Log {
+            inner: alloy_primitives::Log {
+                address: pool,
+                data: event.encode_log_data(),
+            },
+            block_number: Some(block),
+            log_index: Some(log_index),
+            ..Default::default()
+        }
+    }
+
+    fn tick(tick: i32) -> I24 {
+        I24::try_from(tick).unwrap()
+    }
+
+    fn mint(owner: Address, amount: u128) -> IUniswapV3Pool::Mint {
+        IUniswapV3Pool::Mint {
+            sender: owner,
+            owner,
+            tickLower: tick(-60),
+            tickUpper: tick(60),
+            amount,
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        }
+    }
+
+    fn burn(owner: Address, amount: u128) -> IUniswapV3Pool::Burn {
+        IUniswapV3Pool::Burn {
+            owner,
+            tickLower: tick(-60),
+            tickUpper: tick(60),
+            amount,
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        }
+    }
+
+    fn swap(amount0: i64, amount1: i64, liquidity: u128) -> IUniswapV3Pool::Swap {
+        IUniswapV3Pool::Swap {
+            sender: Address::ZERO,
+            recipient: Address::ZERO,
+            amount0: I256::try_from(amount0).unwrap(),
+            amount1: I256::try_from(amount1).unwrap(),
+            sqrtPriceX96: U160::from(1_u128 << 96),
+            liquidity,
+            tick: tick(0),
+        }
+    }
+
+    fn pool() -> UniswapV3Pool {
+        let token = |byte| crate::defi::currency::erc20::ERC20Token {
+            address: Address::repeat_byte(byte),
+            ..Default::default()
+        };
+        UniswapV3Pool::new(1, Address::repeat_byte(0xaa), 3000, token(1), token(2))
+    }
+
+    #[test]
+    fn test_detect_jit() {
+        let pool = pool();
+        let jit = Address::repeat_byte(0xb);
+
+        // the JIT position holds 3/4 of the liquidity of the swap
+        let logs = vec![
+            log_of(pool.address, mint(jit, 3_000), 10, 0),
+            log_of(pool.address, swap(1_000_000, -990_000, 4_000), 10, 1),
+            log_of(pool.address, burn(jit, 3_000), 10, 2),
+            // a later swap without the JIT position
+            log_of(pool.address, swap(-500_000, 1_000_000, 1_000), 11, 0),
+        ];
+
+        let report = detect_jit(&pool, &logs, 0).unwrap();
+        assert_eq!(report.positions.len(), 1);
+
+        let position = &report.positions[0];
+        assert_eq!((position.owner, position.liquidity, position.swaps), (jit, 3_000, 1));
+        assert_eq!((position.fee0, position.fee1), (U256::from(2_250), U256::ZERO));
+        assert_eq!((report.total_fee0, report.total_fee1), (U256::from(3_000), U256::from(3_000)));
+        assert_eq!(report.jit_share0(), 0.75);
+        assert_eq!(report.jit_share1(), 0.0);
+    }
+
+    #[test]
+    fn test_no_jit() {
+        let pool = pool();
+        let (lp, other) = (Address::repeat_byte(0xb), Address::repeat_byte(0xc));
+
+        let logs = vec![
+            // minted and burned without a swap in between
+            log_of(pool.address, mint(lp, 1_000), 10, 0),
+            log_of(pool.address, burn(lp, 1_000), 10, 1),
+            // the burn comes after max_blocks
+            log_of(pool.address, mint(lp, 2_000), 11, 0),
+            log_of(pool.address, swap(1_000_000, -990_000, 4_000), 11, 1),
+            log_of(pool.address, burn(lp, 2_000), 20, 0),
+            // a burn of another owner
+            log_of(pool.address, mint(lp, 3_000), 30, 0),
+            log_of(pool.address, swap(1_000_000, -990_000, 4_000), 30, 1),
+            log_of(pool.address, burn(other, 3_000), 30, 2),
+        ];
+
+        let report = detect_jit(&pool, &logs, 1).unwrap();
+        assert!(report.positions.is_empty());
+        assert_eq!(report.total_fee0, U256::from(6_000));
+        assert_eq!(report.jit_share0(), 0.0);
+    }
+}
@@ -0,0 +1,175 @@
+//! Rank pools by the fee APR earned by their liquidity providers
+//!
+//! For every pool the volume of the window is valued in USD, the fees are `volume * fee` and the APR
+//! is the annualized ratio of the fees to the current TVL. It ignores the price range of V3 positions,
+//! so it is the APR of a full range position
+
+use alloy_contract::private::Network;
+use alloy_network::{BlockResponse, HeaderResponse};
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_sol_types::SolEvent;
+use alloy_transport::Transport;
+
+use std::collections::HashMap;
+use tracing::trace;
+
+use super::mev::{decode_swaps, PoolTokens};
+use super::tvl::{pool_tvl_usd, TvlPool};
+use crate::abi::uniswap::pool::{v2::IUniswapV2Pair, v3::IUniswapV3Pool};
+use crate::utils::{config::Config, logs::query::get_logs_with_config, BlockTime};
+use crate::utils::format::to_f64;
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+/// The fee statistics of a pool over a window
+#[derive(Debug, Clone)]
+pub struct PoolFeeStats {
+    pub pool: Address,
+
+    /// eg. "WETH/USDC"
+    pub pair: String,
+
+    /// The fee in hundredths of a bip
+    pub fee: u32,
+    pub swaps: usize,
+    pub volume_usd: f64,
+    pub tvl_usd: f64,
+    pub fees_usd: f64,
+
+    /// The annualized fees / TVL
+    pub apr: f64,
+}
+
+/// Compute the volume, TVL, fees and APR of the pools and sort them by APR, highest first
+///
+/// The TVL and the token prices are taken at the latest block, pools that fail to be priced are skipped
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `pools` - The pools to rank, all on the same chain
+/// * `block_time` - The window of the volume
+/// * `config` - See [Config]
+pub async fn fee_apr_leaderboard<T, P, N>(
+    client: P,
+    pools: Vec<TvlPool>,
+    block_time: BlockTime,
+    config: &Config,
+) -> Result<Vec<PoolFeeStats>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone + 'static,
+    N: Network,
+{
+    let Some(chain_id) = pools.first().map(|p| p.chain_id()) else {
+        return Ok(Vec::new());
+    };
+
+    let latest = client.get_block_number().await?;
+    let (from_block, to_block) = block_time
+        .block_range_with_client(client.clone(), chain_id, latest)
+        .await?;
+    let window_secs = window_seconds(client.clone(), from_block, to_block).await?;
+
+    let tokens: PoolTokens = pools
+        .iter()
+        .map(|p| {
+            let (token0, token1) = p.tokens();
+            (p.address(), (token0.address, token1.address))
+        })
+        .collect();
+
+    let events = vec![
+        IUniswapV2Pair::Swap::SIGNATURE,
+        IUniswapV3Pool::Swap::SIGNATURE,
+    ];
+    let logs = get_logs_with_config(
+        client.clone(),
+        chain_id,
+        pools.iter().map(|p| p.address()).collect(),
+        events,
+        block_time,
+        config,
+    )
+    .await?;
+    let swaps = decode_swaps(&logs, &tokens)?;
+
+    let mut by_pool: HashMap<Address, Vec<_>> = HashMap::new();
+    for swap in &swaps {
+        by_pool.entry(swap.pool).or_default().push(swap);
+    }
+
+    let mut leaderboard = Vec::with_capacity(pools.len());
+
+    for pool in &pools {
+        let tvl = match config
+            .timed(pool_tvl_usd(client.clone(), pool, Some(BlockId::number(latest))))
+            .await
+        {
+            Ok(tvl) => tvl,
+            Err(e) => {
+                trace!("Failed to get the TVL of {}: {:?}", pool.address(), e);
+                continue;
+            }
+        };
+
+        let (token0, token1) = pool.tokens();
+        let pool_swaps = by_pool.remove(&pool.address()).unwrap_or_default();
+
+        let mut volume_usd = 0.0;
+        for swap in &pool_swaps {
+            let (decimals, price) = if swap.token_in == token0.address {
+                (token0.decimals, tvl.token0_usd)
+            } else {
+                (token1.decimals, tvl.token1_usd)
+            };
+            volume_usd += to_f64(swap.amount_in, decimals)? * price;
+        }
+
+        let fees_usd = volume_usd * pool.fee() as f64 / 1_000_000.0;
+        let apr = if tvl.tvl_usd == 0.0 || window_secs == 0 {
+            0.0
+        } else {
+            tvl.fee_per_tvl(fees_usd) * SECONDS_PER_YEAR / window_secs as f64
+        };
+
+        leaderboard.push(PoolFeeStats {
+            pool: pool.address(),
+            pair: format!("{}/{}", token0.symbol, token1.symbol),
+            fee: pool.fee(),
+            swaps: pool_swaps.len(),
+            volume_usd,
+            tvl_usd: tvl.tvl_usd,
+            fees_usd,
+            apr,
+        });
+    }
+
+    leaderboard.sort_by(|a, b| b.apr.total_cmp(&a.apr));
+    Ok(leaderboard)
+}
+
+/// The seconds between the timestamps of two blocks
+async fn window_seconds<T, P, N>(
+    client: P,
+    from_block: u64,
+    to_block: u64,
+) -> Result<u64, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut timestamps = Vec::with_capacity(2);
+    for block in [from_block, to_block] {
+        let block = client
+            .get_block(BlockId::number(block), false.into())
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Block {} not found", block))?;
+        timestamps.push(block.header().timestamp());
+    }
+
+    Ok(timestamps[1].saturating_sub(timestamps[0]))
+}
@@ -0,0 +1,401 @@
+//! Classification of MEV in historical swaps
+//!
+//! * Sandwiches - a swap followed by a victim swap in the same direction and a back-run in the opposite direction,
+//!   all on the same pool and in the same block
+//! * Atomic arbitrages - swaps in a single transaction that form a cycle ending in the token they started with, with profit
+//!
+//! The swaps are decoded from the Uniswap V2 and V3 Swap logs returned by the log pipeline, see [get_logs_for](crate::utils::logs::query::get_logs_for)
+
+use alloy_primitives::{Address, TxHash, U256};
+use alloy_rpc_types::Log;
+use alloy_sol_types::SolEvent;
+
+use std::collections::HashMap;
+
+use crate::abi::uniswap::pool::{v2::IUniswapV2Pair, v3::IUniswapV3Pool};
+use crate::defi::amm::uniswap::{v2::UniswapV2Pool, v3::UniswapV3Pool};
+
+/// The tokens of the pools the swaps are decoded against, pool -> (token0, token1)
+pub type PoolTokens = HashMap<Address, (Address, Address)>;
+
+/// Build the [PoolTokens] of a set of V2 and V3 pools
+pub fn pool_tokens(v2_pools: &[UniswapV2Pool], v3_pools: &[UniswapV3Pool]) -> PoolTokens {
+    let mut tokens = HashMap::new();
+    for pool in v2_pools {
+        tokens.insert(pool.address, (pool.token0.address, pool.token1.address));
+    }
+    for pool in v3_pools {
+        tokens.insert(pool.address, (pool.token0.address, pool.token1.address));
+    }
+    tokens
+}
+
+/// A swap with its position in the block
+#[derive(Debug, Clone)]
+pub struct OrderedSwap {
+    pub pool: Address,
+    pub sender: Address,
+    pub recipient: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub block: u64,
+    pub tx_index: u64,
+    pub log_index: u64,
+    pub tx_hash: TxHash,
+}
+
+/// A detected sandwich
+#[derive(Debug, Clone)]
+pub struct Sandwich {
+    pub pool: Address,
+    pub block: u64,
+    pub front_run: TxHash,
+    pub victim: TxHash,
+    pub back_run: TxHash,
+
+    /// The token the attacker started and ended with
+    pub profit_token: Address,
+
+    /// back-run amount out - front-run amount in, zero if the sandwich lost money
+    pub profit: U256,
+}
+
+/// A detected atomic arbitrage
+#[derive(Debug, Clone)]
+pub struct AtomicArb {
+    pub tx_hash: TxHash,
+    pub block: u64,
+
+    /// The pools of the cycle in execution order
+    pub pools: Vec<Address>,
+    pub profit_token: Address,
+    pub profit: U256,
+}
+
+/// MEV statistics of a single pool
+#[derive(Debug, Clone, Default)]
+pub struct PoolMevStats {
+    pub swaps: usize,
+
+    /// Swaps that were sandwiched
+    pub sandwiched_swaps: usize,
+
+    /// Front-run and back-run swaps
+    pub sandwich_swaps: usize,
+
+    /// Swaps that are part of an atomic arbitrage
+    pub arb_swaps: usize,
+}
+
+impl PoolMevStats {
+    /// The fraction of the swaps that are MEV (sandwiches and arbs)
+    pub fn mev_ratio(&self) -> f64 {
+        if self.swaps == 0 {
+            return 0.0;
+        }
+        (self.sandwich_swaps + self.arb_swaps) as f64 / self.swaps as f64
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MevReport {
+    pub sandwiches: Vec<Sandwich>,
+    pub arbs: Vec<AtomicArb>,
+    pub pools: HashMap<Address, PoolMevStats>,
+}
+
+/// Decode the V2 and V3 Swap logs of the given pools, logs of unknown pools or events are skipped
+pub fn decode_swaps(logs: &[Log], tokens: &PoolTokens) -> Result<Vec<OrderedSwap>, anyhow::Error> {
+    let mut swaps = Vec::new();
+
+    for log in logs {
+        let Some((token0, token1)) = tokens.get(&log.address()) else {
+            continue;
+        };
+
+        let block = log
+            .block_number
+            .ok_or_else(|| anyhow::anyhow!("Block number is missing"))?;
+        let tx_index = log.transaction_index.unwrap_or(0);
+        let log_index = log.log_index.unwrap_or(0);
+        let tx_hash = log.transaction_hash.unwrap_or_default();
+
+        let (sender, recipient, zero_for_one, amount_in, amount_out) = match log.topic0() {
+            Some(&IUniswapV2Pair::Swap::SIGNATURE_HASH) => {
+                let swap = log.log_decode::<IUniswapV2Pair::Swap>()?.inner.data;
+                let zero_for_one = swap.amount0In > U256::ZERO;
+                let (amount_in, amount_out) = if zero_for_one {
+                    (swap.amount0In, swap.amount1Out)
+                } else {
+                    (swap.amount1In, swap.amount0Out)
+                };
+                (swap.sender, swap.to, zero_for_one, amount_in, amount_out)
+            }
+            Some(&IUniswapV3Pool::Swap::SIGNATURE_HASH) => {
+                let swap = log.log_decode::<IUniswapV3Pool::Swap>()?.inner.data;
+                let zero_for_one = swap.amount0.is_positive();
+                let (amount_in, amount_out) = if zero_for_one {
+                    (swap.amount0.unsigned_abs(), swap.amount1.unsigned_abs())
+                } else {
+                    (swap.amount1.unsigned_abs(), swap.amount0.unsigned_abs())
+                };
+                (swap.sender, swap.recipient, zero_for_one, amount_in, amount_out)
+            }
+            _ => continue,
+        };
+
+        let (token_in, token_out) = if zero_for_one {
+            (*token0, *token1)
+        } else {
+            (*token1, *token0)
+        };
+
+        swaps.push(OrderedSwap {
+            pool: log.address(),
+            sender,
+            recipient,
+            token_in,
+            token_out,
+            amount_in,
+            amount_out,
+            block,
+            tx_index,
+            log_index,
+            tx_hash,
+        });
+    }
+
+    swaps.sort_by_key(|s| (s.block, s.tx_index, s.log_index));
+    Ok(swaps)
+}
+
+/// Classify the MEV in the given swaps
+///
+/// The swaps must be sorted by their position in the chain, as returned by [decode_swaps]
+pub fn classify(swaps: &[OrderedSwap]) -> MevReport {
+    let mut report = MevReport::default();
+    for swap in swaps {
+        report.pools.entry(swap.pool).or_default().swaps += 1;
+    }
+
+    // group the swaps by transaction to find the arbs
+    let mut i = 0;
+    while i < swaps.len() {
+        let mut j = i + 1;
+        while j < swaps.len() && swaps[j].tx_hash == swaps[i].tx_hash {
+            j += 1;
+        }
+
+        if let Some(arb) = find_arb(&swaps[i..j]) {
+            for pool in &arb.pools {
+                report.pools.entry(*pool).or_default().arb_swaps += 1;
+            }
+            report.arbs.push(arb);
+        }
+        i = j;
+    }
+
+    // group the swaps by block and pool to find the sandwiches
+    let mut by_pool: HashMap<(u64, Address), Vec<&OrderedSwap>> = HashMap::new();
+    for swap in swaps {
+        by_pool.entry((swap.block, swap.pool)).or_default().push(swap);
+    }
+
+    for ((block, pool), pool_swaps) in by_pool {
+        if pool_swaps.len() < 3 {
+            continue;
+        }
+
+        let mut used = vec![false; pool_swaps.len()];
+        for f in 0..pool_swaps.len() {
+            if used[f] {
+                continue;
+            }
+            let front = pool_swaps[f];
+
+            let Some(b) = (f + 1..pool_swaps.len()).find(|b| {
+                !used[*b] && is_back_run(front, pool_swaps[*b])
+            }) else {
+                continue;
+            };
+            let back = pool_swaps[b];
+
+            // the victims swap in the same direction between the front and back run
+            let victims: Vec<usize> = (f + 1..b)
+                .filter(|v| {
+                    let victim = pool_swaps[*v];
+                    victim.tx_hash != front.tx_hash
+                        && victim.tx_hash != back.tx_hash
+                        && victim.token_in == front.token_in
+                })
+                .collect();
+
+            if victims.is_empty() {
+                continue;
+            }
+
+            used[f] = true;
+            used[b] = true;
+
+            let stats = report.pools.entry(pool).or_default();
+            stats.sandwich_swaps += 2;
+            stats.sandwiched_swaps += victims.len();
+
+            for v in victims {
+                report.sandwiches.push(Sandwich {
+                    pool,
+                    block,
+                    front_run: front.tx_hash,
+                    victim: pool_swaps[v].tx_hash,
+                    back_run: back.tx_hash,
+                    profit_token: front.token_in,
+                    profit: back.amount_out.saturating_sub(front.amount_in),
+                });
+            }
+        }
+    }
+
+    report
+}
+
+/// Is `back` the back-run of `front`, a different tx in the opposite direction by the same account
+fn is_back_run(front: &OrderedSwap, back: &OrderedSwap) -> bool {
+    if back.tx_hash == front.tx_hash || back.token_in != front.token_out {
+        return false;
+    }
+
+    // the attacker either swaps through the same contract or sells what it bought in the front-run
+    let same_account = front.sender == back.sender || front.recipient == back.sender;
+    let same_amount = back.amount_in <= front.amount_out
+        && back.amount_in >= front.amount_out * U256::from(9) / U256::from(10);
+
+    same_account || same_amount
+}
+
+/// Find a profitable cycle in the swaps of a single transaction
+fn find_arb(swaps: &[OrderedSwap]) -> Option<AtomicArb> {
+    if swaps.len() < 2 {
+        return None;
+    }
+
+    let chained = swaps
+        .windows(2)
+        .all(|w| w[0].token_out == w[1].token_in);
+
+    let first = swaps.first()?;
+    let last = swaps.last()?;
+
+    if !chained || first.token_in != last.token_out || last.amount_out <= first.amount_in {
+        return None;
+    }
+
+    Some(AtomicArb {
+        tx_hash: first.tx_hash,
+        block: first.block,
+        pools: swaps.iter().map(|s| s.pool).collect(),
+        profit_token: first.token_in,
+        profit: last.amount_out - first.amount_in,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WETH: Address = Address::repeat_byte(1);
+    const USDC: Address = Address::repeat_byte(2);
+    const DAI: Address = Address::repeat_byte(3);
+
+    fn swap(
+        pool: u8,
+        account: u8,
+        (token_in, amount_in): (Address, u64),
+        (token_out, amount_out): (Address, u64),
+        (tx_index, log_index): (u64, u64),
+    ) -> OrderedSwap {
+        OrderedSwap {
+            pool: Address::repeat_byte(pool),
+            sender: Address::repeat_byte(account),
+            recipient: Address::repeat_byte(account),
+            token_in,
+            token_out,
+            amount_in: U256::from(amount_in),
+            amount_out: U256::from(amount_out),
+            block: 1,
+            tx_index,
+            log_index,
+            tx_hash: TxHash::with_last_byte(tx_index as u8),
+        }
+    }
+
+    #[test]
+    fn test_sandwich() {
+        let swaps = vec![
+            swap(0xa, 0xb, (WETH, 10), (USDC, 100), (0, 0)),
+            swap(0xa, 0xc, (WETH, 5), (USDC, 45), (1, 0)),
+            swap(0xa, 0xb, (USDC, 100), (WETH, 11), (2, 0)),
+        ];
+
+        let report = classify(&swaps);
+        assert_eq!(report.sandwiches.len(), 1);
+        assert!(report.arbs.is_empty());
+
+        let sandwich = &report.sandwiches[0];
+        assert_eq!(sandwich.victim, swaps[1].tx_hash);
+        assert_eq!((sandwich.profit_token, sandwich.profit), (WETH, U256::from(1)));
+
+        let stats = &report.pools[&Address::repeat_byte(0xa)];
+        assert_eq!((stats.swaps, stats.sandwich_swaps, stats.sandwiched_swaps), (3, 2, 1));
+    }
+
+    #[test]
+    fn test_back_run() {
+        // a back-run without a victim in the same direction is not a sandwich
+        let swaps = vec![
+            swap(0xa, 0xb, (WETH, 10), (USDC, 100), (0, 0)),
+            swap(0xa, 0xc, (USDC, 45), (WETH, 5), (1, 0)),
+            swap(0xa, 0xb, (USDC, 100), (WETH, 11), (2, 0)),
+        ];
+
+        let report = classify(&swaps);
+        assert!(report.sandwiches.is_empty());
+        assert!(report.arbs.is_empty());
+        assert_eq!(report.pools[&Address::repeat_byte(0xa)].mev_ratio(), 0.0);
+    }
+
+    #[test]
+    fn test_arbitrage() {
+        let swaps = vec![
+            swap(0xa, 0xb, (WETH, 10), (USDC, 100), (0, 0)),
+            swap(0xd, 0xb, (USDC, 100), (DAI, 101), (0, 1)),
+            swap(0xe, 0xb, (DAI, 101), (WETH, 12), (0, 2)),
+        ];
+
+        let report = classify(&swaps);
+        assert!(report.sandwiches.is_empty());
+        assert_eq!(report.arbs.len(), 1);
+
+        let arb = &report.arbs[0];
+        assert_eq!(arb.pools.len(), 3);
+        assert_eq!((arb.profit_token, arb.profit), (WETH, U256::from(2)));
+        assert_eq!(report.pools[&Address::repeat_byte(0xd)].mev_ratio(), 1.0);
+    }
+
+    #[test]
+    fn test_no_mev() {
+        let swaps = vec![
+            swap(0xa, 0xb, (WETH, 10), (USDC, 100), (0, 0)),
+            // a cycle at a loss is not an arb
+            swap(0xd, 0xc, (USDC, 100), (DAI, 99), (1, 0)),
+            swap(0xe, 0xc, (DAI, 99), (USDC, 98), (1, 1)),
+            swap(0xa, 0xf, (WETH, 5), (USDC, 45), (2, 0)),
+        ];
+
+        let report = classify(&swaps);
+        assert!(report.sandwiches.is_empty());
+        assert!(report.arbs.is_empty());
+        assert_eq!(report.pools[&Address::repeat_byte(0xa)].swaps, 2);
+    }
+}
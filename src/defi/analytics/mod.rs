@@ -0,0 +1,9 @@
+pub mod approvals;
+pub mod correlation;
+pub mod gas;
+pub mod jit;
+pub mod leaderboard;
+pub mod mev;
+pub mod ohlc;
+pub mod peg;
+pub mod tvl;
@@ -0,0 +1,119 @@
+//! OHLCV candles from swaps
+//!
+//! The swaps are bucketed by block interval, the intervals start at multiples of the interval so the candles of
+//! two pools line up. Intervals without swaps have no candle
+
+use serde::Serialize;
+
+/// A swap reduced to what a candle needs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    pub block: u64,
+
+    /// The price of token0 in token1 after the swap
+    pub price: f64,
+
+    /// The traded amounts, formatted and positive
+    pub amount0: f64,
+    pub amount1: f64,
+}
+
+/// The prices and volume of an interval of blocks
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candle {
+    /// The first block of the interval
+    pub start_block: u64,
+
+    /// The last block of the interval
+    pub end_block: u64,
+
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+
+    /// The volume in token0 and token1, formatted
+    pub volume0: f64,
+    pub volume1: f64,
+
+    /// The number of swaps in the interval
+    pub trades: usize,
+}
+
+impl Candle {
+    fn new(start_block: u64, interval_blocks: u64, trade: &Trade) -> Self {
+        Self {
+            start_block,
+            end_block: start_block + interval_blocks - 1,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume0: trade.amount0,
+            volume1: trade.amount1,
+            trades: 1,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume0 += trade.amount0;
+        self.volume1 += trade.amount1;
+        self.trades += 1;
+    }
+}
+
+/// Bucket trades into candles of `interval_blocks` blocks
+///
+/// The trades must be in execution order, the open and close are the first and last trade of each interval
+pub fn build_candles(trades: &[Trade], interval_blocks: u64) -> Result<Vec<Candle>, anyhow::Error> {
+    if interval_blocks == 0 {
+        return Err(anyhow::anyhow!("The candle interval must be at least 1 block"));
+    }
+
+    let mut candles: Vec<Candle> = Vec::new();
+    for trade in trades {
+        let start_block = trade.block - trade.block % interval_blocks;
+        match candles.last_mut() {
+            Some(candle) if candle.start_block == start_block => candle.add(trade),
+            Some(candle) if candle.start_block > start_block => {
+                return Err(anyhow::anyhow!("The trades are not sorted by block"));
+            }
+            _ => candles.push(Candle::new(start_block, interval_blocks, trade)),
+        }
+    }
+
+    Ok(candles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_candles() {
+        let trade = |block, price| Trade {
+            block,
+            price,
+            amount0: 1.0,
+            amount1: price,
+        };
+        let trades = vec![trade(100, 2.0), trade(105, 3.0), trade(109, 1.5), trade(131, 2.5)];
+
+        let candles = build_candles(&trades, 10).unwrap();
+        assert_eq!(candles.len(), 2);
+
+        assert_eq!((candles[0].start_block, candles[0].end_block), (100, 109));
+        assert_eq!((candles[0].open, candles[0].high, candles[0].low, candles[0].close), (2.0, 3.0, 1.5, 1.5));
+        assert_eq!((candles[0].volume0, candles[0].volume1, candles[0].trades), (3.0, 6.5, 3));
+
+        // no candle for the blocks 110 - 129
+        assert_eq!(candles[1].start_block, 130);
+        assert_eq!(candles[1].open, candles[1].close);
+
+        assert!(build_candles(&[trade(120, 1.0), trade(100, 1.0)], 10).is_err());
+        assert!(build_candles(&trades, 0).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Peg deviation of stable-stable pools
+//!
+//! In a pool like USDC/USDT 0.01% the price should stay at 1, an LP earns the fee on every swap
+//! but the deviations from the peg are what the arbitrageurs take from them. [peg_series] turns
+//! the swap history of a pool into the deviation of its price after each swap and [PegStats] sums it up:
+//! when the price spends a lot of time further from the peg than the fee, the fee doesn't pay for the risk
+//!
+//! ```ignore
+//! let series = peg_series(&swaps, usdc, 1.0);
+//! let stats = PegStats::new(&series, 1.0, to_block)?;
+//! println!("{}", stats.pretty());
+//! ```
+
+use alloy_primitives::Address;
+
+use crate::defi::amm::uniswap::v3::sqrt_price_to_price;
+use crate::utils::format::to_f64;
+use crate::utils::logs::events::SwapData;
+
+/// The price of the pool after a swap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PegPoint {
+    pub block: u64,
+
+    /// The price of the base token in the other token
+    pub price: f64,
+
+    /// The signed deviation from the peg in basis points, positive if the base token is above the peg
+    pub deviation_bps: f64,
+}
+
+/// A summary of a [peg_series]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PegStats {
+    pub samples: usize,
+
+    /// The largest absolute deviation in basis points
+    pub max_deviation_bps: f64,
+
+    /// The mean absolute deviation in basis points, weighted by the blocks each price lasted
+    pub mean_deviation_bps: f64,
+
+    /// The band the deviation is compared against, eg. the fee of the pool
+    pub band_bps: f64,
+
+    /// The number of blocks the price was outside the band
+    pub blocks_outside_band: u64,
+
+    /// The number of blocks from the first swap to the end of the window
+    pub total_blocks: u64,
+}
+
+impl PegStats {
+    /// Summarize a series
+    ///
+    /// Each price lasts until the block of the next one, the last one until `to_block`
+    ///
+    /// ## Arguments
+    ///
+    /// * `series` - The series, sorted by block, see [peg_series]
+    /// * `band_bps` - The band in basis points, 1.0 for a 0.01% pool
+    /// * `to_block` - The end of the window
+    pub fn new(series: &[PegPoint], band_bps: f64, to_block: u64) -> Result<Self, anyhow::Error> {
+        let first = series
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("The series is empty"))?;
+
+        let mut max_deviation_bps: f64 = 0.0;
+        let mut weighted_sum = 0.0;
+        let mut blocks_outside_band = 0;
+
+        for (i, point) in series.iter().enumerate() {
+            let end = series.get(i + 1).map(|next| next.block).unwrap_or(to_block);
+            let blocks = end.saturating_sub(point.block);
+            let deviation = point.deviation_bps.abs();
+
+            max_deviation_bps = max_deviation_bps.max(deviation);
+            weighted_sum += deviation * blocks as f64;
+            if deviation > band_bps {
+                blocks_outside_band += blocks;
+            }
+        }
+
+        let total_blocks = to_block.saturating_sub(first.block);
+        let mean_deviation_bps = if total_blocks == 0 {
+            series.iter().map(|p| p.deviation_bps.abs()).sum::<f64>() / series.len() as f64
+        } else {
+            weighted_sum / total_blocks as f64
+        };
+
+        Ok(Self {
+            samples: series.len(),
+            max_deviation_bps,
+            mean_deviation_bps,
+            band_bps,
+            blocks_outside_band,
+            total_blocks,
+        })
+    }
+
+    /// The fraction of the window the price was outside the band
+    pub fn time_outside_band(&self) -> f64 {
+        if self.total_blocks == 0 {
+            return 0.0;
+        }
+        self.blocks_outside_band as f64 / self.total_blocks as f64
+    }
+
+    pub fn pretty(&self) -> String {
+        format!(
+            "Peg deviation over {} swaps and {} blocks: max {:.2} bps, mean {:.2} bps, outside the {:.2} bps band {:.2}% of the time",
+            self.samples,
+            self.total_blocks,
+            self.max_deviation_bps,
+            self.mean_deviation_bps,
+            self.band_bps,
+            self.time_outside_band() * 100.0
+        )
+    }
+}
+
+/// Build the peg deviation series of a pool from its swaps
+///
+/// The price after each swap is read from its sqrtPriceX96 (Uniswap V3), swaps without it use
+/// their execution price, which includes the fee and the price impact
+///
+/// ## Arguments
+///
+/// * `swaps` - The swaps of the pool, sorted by block
+/// * `base_token` - The token to price, eg. USDC in a USDC/USDT pool
+/// * `peg` - The target price of the base token in the other token, 1.0 for two USD stablecoins
+pub fn peg_series(swaps: &[SwapData], base_token: Address, peg: f64) -> Vec<PegPoint> {
+    swaps
+        .iter()
+        .filter_map(|swap| {
+            let price = swap_price(swap, base_token)?;
+            Some(PegPoint {
+                block: swap.block,
+                price,
+                deviation_bps: (price / peg - 1.0) * 10_000.0,
+            })
+        })
+        .collect()
+}
+
+/// The price of `base_token` after the swap, None if the swap doesn't involve it
+fn swap_price(swap: &SwapData, base_token: Address) -> Option<f64> {
+    let base_is_in = swap.token_in.address == base_token;
+    if !base_is_in && swap.token_out.address != base_token {
+        return None;
+    }
+
+    if let Some(sqrt_price_x96) = swap.sqrt_price_x96 {
+        let (token0, token1) = if swap.token_in.address < swap.token_out.address {
+            (&swap.token_in, &swap.token_out)
+        } else {
+            (&swap.token_out, &swap.token_in)
+        };
+        let price0 = sqrt_price_to_price(sqrt_price_x96, token0.decimals, token1.decimals);
+        if price0 == 0.0 {
+            return None;
+        }
+        return Some(if token0.address == base_token { price0 } else { 1.0 / price0 });
+    }
+
+    let amount_in = to_f64(swap.amount_in, swap.token_in.decimals).ok()?;
+    let amount_out = to_f64(swap.amount_out, swap.token_out.decimals).ok()?;
+    if amount_in == 0.0 || amount_out == 0.0 {
+        return None;
+    }
+    Some(if base_is_in { amount_out / amount_in } else { amount_in / amount_out })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(block: u64, deviation_bps: f64) -> PegPoint {
+        PegPoint {
+            block,
+            price: 1.0 + deviation_bps / 10_000.0,
+            deviation_bps,
+        }
+    }
+
+    #[test]
+    fn test_peg_stats_weights_by_blocks() {
+        let series = [point(100, 0.5), point(110, -3.0), point(120, 0.0)];
+        let stats = PegStats::new(&series, 1.0, 140).unwrap();
+
+        assert_eq!(stats.total_blocks, 40);
+        assert_eq!(stats.blocks_outside_band, 10);
+        assert_eq!(stats.max_deviation_bps, 3.0);
+        assert!((stats.mean_deviation_bps - (0.5 * 10.0 + 3.0 * 10.0) / 40.0).abs() < 1e-12);
+        assert!((stats.time_outside_band() - 0.25).abs() < 1e-12);
+    }
+}
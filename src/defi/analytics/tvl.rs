@@ -0,0 +1,228 @@
+//! Total value locked of Uniswap V2 and V3 pools at historical blocks
+//!
+//! The TVL is the USD value of the token balances held by the pool, so volume and fees can be normalized
+//! into fee-per-TVL metrics
+
+use alloy_contract::private::Network;
+use alloy_network::Ethereum;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_sol_types::SolCall;
+use alloy_transport::Transport;
+
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::trace;
+
+use crate::abi::erc20::ERC20;
+use crate::defi::amm::pool::AnyPool;
+use crate::defi::utils::chain_link::get_token_prices;
+use crate::utils::config::Config;
+use crate::utils::format::to_f64;
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
+
+/// A Uniswap V2 or V3 pool, see [AnyPool]
+pub type TvlPool = AnyPool;
+
+impl TvlPool {
+    /// The USD prices of token0 and token1 derived from the pool price at the given block
+    async fn tokens_usd_at<T, P, N>(
+        &self,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<(f64, f64), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let mut pool = self.clone();
+        pool.sync_state(client.clone(), block).await?;
+        pool.tokens_usd(client, block).await
+    }
+}
+
+/// The TVL of a pool at a block
+#[derive(Debug, Clone)]
+pub struct PoolTvl {
+    /// The balance of token0 held by the pool, formatted
+    pub balance0: f64,
+
+    /// The balance of token1 held by the pool, formatted
+    pub balance1: f64,
+
+    pub token0_usd: f64,
+    pub token1_usd: f64,
+
+    pub tvl_usd: f64,
+}
+
+impl PoolTvl {
+    /// The fees earned per dollar of TVL
+    pub fn fee_per_tvl(&self, fees_usd: f64) -> f64 {
+        if self.tvl_usd == 0.0 {
+            return 0.0;
+        }
+        fees_usd / self.tvl_usd
+    }
+}
+
+/// Get the TVL of a pool at a given block
+///
+/// The balances are valued with the oracle prices of the tokens, when only one of the tokens has a known price
+/// the other one is priced through the pool
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `pool` - The pool, see [TvlPool]
+/// * `block` - The block, None for the latest
+pub async fn pool_tvl_usd<T, P, N>(
+    client: P,
+    pool: &TvlPool,
+    block: Option<BlockId>,
+) -> Result<PoolTvl, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let (token0, token1) = pool.tokens();
+
+    let (balance0, balance1) =
+        pool_balances(client.clone(), pool.address(), token0.address, token1.address, block).await?;
+
+    let balance0 = to_f64(balance0, token0.decimals)?;
+    let balance1 = to_f64(balance1, token1.decimals)?;
+
+    let prices = get_token_prices(
+        client.clone(),
+        block,
+        pool.chain_id(),
+        &[token0.address, token1.address],
+    )
+    .await?;
+
+    let (token0_usd, token1_usd) = if prices[0] != 0.0 && prices[1] != 0.0 {
+        (prices[0], prices[1])
+    } else if prices[0] != 0.0 || prices[1] != 0.0 {
+        pool.tokens_usd_at(client, block).await?
+    } else {
+        return Err(anyhow::anyhow!(
+            "No USD price for {} / {}",
+            token0.symbol,
+            token1.symbol
+        ));
+    };
+
+    Ok(PoolTvl {
+        balance0,
+        balance1,
+        token0_usd,
+        token1_usd,
+        tvl_usd: balance0 * token0_usd + balance1 * token1_usd,
+    })
+}
+
+/// The balances of token0 and token1 held by a pool at a given block, in a single batch
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `pool` - The address of the pool
+/// * `token0` - The address of token0
+/// * `token1` - The address of token1
+/// * `block` - The block, None for the latest
+pub async fn pool_balances<T, P, N>(
+    client: P,
+    pool: Address,
+    token0: Address,
+    token1: Address,
+    block: Option<BlockId>,
+) -> Result<(U256, U256), anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let balance_of = ERC20::balanceOfCall { owner: pool }.abi_encode();
+
+    let mut batch = EthCallBatch::new(block);
+    let balance0 = batch.add(token0, balance_of.clone().into());
+    let balance1 = batch.add(token1, balance_of.into());
+    let mut results = batch.send(client).await?;
+
+    let balance0 =
+        ERC20::balanceOfCall::abi_decode_returns(&take_result(&mut results, balance0)?, true)?
+            .balance;
+    let balance1 =
+        ERC20::balanceOfCall::abi_decode_returns(&take_result(&mut results, balance1)?, true)?
+            .balance;
+
+    Ok((balance0, balance1))
+}
+
+/// Get the TVL of a pool at every `step` blocks between `from_block` and `to_block`
+///
+/// Blocks that fail are skipped, the result is sorted by block
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `pool` - The pool, see [TvlPool]
+/// * `from_block` - The first block
+/// * `to_block` - The last block (exclusive)
+/// * `step` - The number of blocks between each point
+/// * `config` - See [Config]
+pub async fn pool_tvl_series<T, P>(
+    client: P,
+    pool: TvlPool,
+    from_block: u64,
+    to_block: u64,
+    step: usize,
+    config: &Config,
+) -> Result<Vec<(u64, PoolTvl)>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    if step == 0 {
+        return Err(anyhow::anyhow!("The step must be at least 1 block"));
+    }
+
+    let series = Arc::new(Mutex::new(Vec::new()));
+    let semaphore = Arc::new(Semaphore::new(config.concurrency()));
+    let mut tasks: Vec<JoinHandle<Result<(), anyhow::Error>>> = Vec::new();
+
+    for block in (from_block..to_block).step_by(step) {
+        let client = client.clone();
+        let series = series.clone();
+        let pool = pool.clone();
+        let semaphore = semaphore.clone();
+        let config = config.clone();
+
+        let task = tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let tvl = config
+                .timed(pool_tvl_usd(client, &pool, Some(BlockId::number(block))))
+                .await?;
+            series.lock().await.push((block, tvl));
+            Ok(())
+        });
+        tasks.push(task);
+    }
+
+    for task in tasks {
+        match task.await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => trace!("Failed to get TVL: {:?}", e),
+            Err(e) => trace!("TVL task panicked: {:?}", e),
+        }
+    }
+
+    let mut series = series.lock().await.clone();
+    series.sort_by_key(|(block, _)| *block);
+    Ok(series)
+}
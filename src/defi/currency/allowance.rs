@@ -0,0 +1,391 @@
+//! Make sure a spender can pull an amount of a token
+//!
+//! The approval method is chosen per token and spender:
+//!
+//! * Spenders that pull through Permit2 (eg. the Universal Router) get a Permit2 allowance,
+//!   the token is approved to Permit2 first if needed
+//! * Tokens that implement EIP-2612 get a signed permit, submitted by the owner
+//! * Everything else gets a plain `approve`
+//!
+//! Every transaction is simulated with `eth_call` before it is sent
+
+use alloy_contract::private::Network;
+use alloy_network::{ReceiptResponse, TransactionBuilder};
+use alloy_primitives::{
+    aliases::{U160, U48},
+    keccak256, Address, Bytes, TxHash, B256, U256,
+};
+use alloy_provider::Provider;
+use alloy_signer::Signer;
+use alloy_sol_types::{SolCall, SolValue};
+use alloy_transport::Transport;
+
+use crate::abi::erc20::{IERC20Permit, ERC20};
+use crate::abi::permit2::{IPermit2, PERMIT2};
+use crate::defi::amm::uniswap::router::UniversalRouter;
+use crate::utils::deadline::{deadline_from_latest, DEFAULT_DEADLINE_SECS};
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
+
+/// How long a Permit2 allowance stays valid, 30 days
+pub const PERMIT2_EXPIRATION_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// The EIP-2612 Permit typehash
+const PERMIT_TYPEHASH: &str =
+    "Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)";
+
+/// How the allowance was granted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllowanceMethod {
+    /// The existing allowance was enough, nothing was sent
+    Sufficient,
+    Approve,
+    Permit2,
+    Eip2612,
+}
+
+/// The result of [ensure_allowance]
+#[derive(Debug, Clone)]
+pub struct AllowanceResult {
+    pub method: AllowanceMethod,
+
+    /// The transactions sent in order, empty if the allowance was sufficient
+    pub tx_hashes: Vec<TxHash>,
+}
+
+/// Make sure `spender` can pull `amount` of `token` from the signer
+///
+/// The client must be able to sign transactions for the signer (eg. a provider with its wallet),
+/// the signer itself is only used for the EIP-2612 signatures
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `signer` - The owner of the tokens
+/// * `token` - The token to approve
+/// * `spender` - The address that will pull the tokens
+/// * `amount` - The amount the spender needs
+pub async fn ensure_allowance<T, P, N, S>(
+    client: P,
+    signer: &S,
+    token: Address,
+    spender: Address,
+    amount: U256,
+) -> Result<AllowanceResult, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+    S: Signer + Send + Sync,
+{
+    let owner = signer.address();
+
+    if UniversalRouter::is_universal_router(spender) {
+        return ensure_permit2_allowance(client, owner, token, spender, amount).await;
+    }
+
+    let mut result = AllowanceResult {
+        method: AllowanceMethod::Sufficient,
+        tx_hashes: Vec::new(),
+    };
+
+    let current = allowance(client.clone(), token, owner, spender).await?;
+    if current >= amount {
+        return Ok(result);
+    }
+
+    if let Some(tx_hash) = try_permit(client.clone(), signer, token, spender, amount).await? {
+        result.method = AllowanceMethod::Eip2612;
+        result.tx_hashes.push(tx_hash);
+        return Ok(result);
+    }
+
+    result.method = AllowanceMethod::Approve;
+    result.tx_hashes = approve(client, owner, token, spender, amount, current).await?;
+    Ok(result)
+}
+
+/// Make sure `spender` can pull `amount` of `token` through Permit2
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `owner` - The owner of the tokens
+/// * `token` - The token to approve
+/// * `spender` - The address that will pull the tokens through Permit2
+/// * `amount` - The amount the spender needs
+pub async fn ensure_permit2_allowance<T, P, N>(
+    client: P,
+    owner: Address,
+    token: Address,
+    spender: Address,
+    amount: U256,
+) -> Result<AllowanceResult, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut result = AllowanceResult {
+        method: AllowanceMethod::Sufficient,
+        tx_hashes: Vec::new(),
+    };
+
+    // Permit2 itself needs a token allowance
+    let current = allowance(client.clone(), token, owner, PERMIT2).await?;
+    if current < amount {
+        result.method = AllowanceMethod::Permit2;
+        let hashes = approve(client.clone(), owner, token, PERMIT2, U256::MAX, current).await?;
+        result.tx_hashes.extend(hashes);
+    }
+
+    let permit2 = IPermit2::new(PERMIT2, client.clone());
+    let current = permit2.allowance(owner, token, spender).call().await?;
+    let expiration = deadline_from_latest(client.clone(), DEFAULT_DEADLINE_SECS).await?;
+
+    let amount = permit2_amount(amount);
+    if current.amount >= amount && U256::from(current.expiration) > expiration {
+        return Ok(result);
+    }
+
+    let expiration = deadline_from_latest(client.clone(), PERMIT2_EXPIRATION_SECS).await?;
+
+    let call_data = IPermit2::approveCall {
+        token,
+        spender,
+        amount,
+        expiration: U48::saturating_from(expiration),
+    }
+    .abi_encode();
+
+    let tx_hash = simulate_and_send(client, owner, PERMIT2, call_data.into()).await?;
+
+    result.method = AllowanceMethod::Permit2;
+    result.tx_hashes.push(tx_hash);
+    Ok(result)
+}
+
+/// Permit2 allowances are uint160, amounts that don't fit (eg. U256::MAX) become an unlimited allowance
+fn permit2_amount(amount: U256) -> U160 {
+    U160::saturating_from(amount)
+}
+
+/// Does the token implement EIP-2612
+pub async fn supports_permit<T, P, N>(
+    client: P,
+    token: Address,
+    owner: Address,
+) -> Result<bool, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    Ok(permit_domain(client, token, owner).await?.is_some())
+}
+
+/// The domain separator and the permit nonce of the owner, None if the token doesn't implement EIP-2612
+async fn permit_domain<T, P, N>(
+    client: P,
+    token: Address,
+    owner: Address,
+) -> Result<Option<(B256, U256)>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut batch = EthCallBatch::new(None);
+    let domain = batch.add(token, IERC20Permit::DOMAIN_SEPARATORCall {}.abi_encode().into());
+    let nonce = batch.add(token, IERC20Permit::noncesCall { owner }.abi_encode().into());
+    let mut results = batch.send(client).await?;
+
+    let domain = take_result(&mut results, domain)
+        .and_then(|b| Ok(IERC20Permit::DOMAIN_SEPARATORCall::abi_decode_returns(&b, true)?._0));
+    let nonce = take_result(&mut results, nonce)
+        .and_then(|b| Ok(IERC20Permit::noncesCall::abi_decode_returns(&b, true)?._0));
+
+    match (domain, nonce) {
+        (Ok(domain), Ok(nonce)) => Ok(Some((domain, nonce))),
+        _ => Ok(None),
+    }
+}
+
+/// Sign and submit an EIP-2612 permit, None if the token doesn't support it or the permit fails to simulate
+///
+/// Tokens like DAI expose a domain separator and nonces but use a different permit signature,
+/// those are caught by the simulation
+async fn try_permit<T, P, N, S>(
+    client: P,
+    signer: &S,
+    token: Address,
+    spender: Address,
+    amount: U256,
+) -> Result<Option<TxHash>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+    S: Signer + Send + Sync,
+{
+    let owner = signer.address();
+    let Some((domain, nonce)) = permit_domain(client.clone(), token, owner).await? else {
+        return Ok(None);
+    };
+
+    let deadline = deadline_from_latest(client.clone(), DEFAULT_DEADLINE_SECS).await?;
+
+    let struct_hash = keccak256(
+        (
+            keccak256(PERMIT_TYPEHASH),
+            owner,
+            spender,
+            amount,
+            nonce,
+            deadline,
+        )
+            .abi_encode(),
+    );
+
+    let mut digest = Vec::with_capacity(66);
+    digest.extend_from_slice(&[0x19, 0x01]);
+    digest.extend_from_slice(domain.as_slice());
+    digest.extend_from_slice(struct_hash.as_slice());
+
+    let signature = signer.sign_hash(&keccak256(digest)).await?;
+
+    let call_data: Bytes = IERC20Permit::permitCall {
+        owner,
+        spender,
+        value: amount,
+        deadline,
+        v: 27 + signature.v().y_parity_byte(),
+        r: signature.r().into(),
+        s: signature.s().into(),
+    }
+    .abi_encode()
+    .into();
+
+    let tx = N::TransactionRequest::default()
+        .with_from(owner)
+        .with_to(token)
+        .with_input(call_data);
+
+    if client.call(&tx).await.is_err() {
+        return Ok(None);
+    }
+
+    Ok(Some(send(client, tx).await?))
+}
+
+/// Approve with a plain `approve`
+///
+/// Tokens like USDT revert when changing a non-zero allowance, in that case the allowance is reset to zero first
+async fn approve<T, P, N>(
+    client: P,
+    owner: Address,
+    token: Address,
+    spender: Address,
+    amount: U256,
+    current: U256,
+) -> Result<Vec<TxHash>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let call_data: Bytes = ERC20::approveCall { spender, amount }.abi_encode().into();
+    let tx = N::TransactionRequest::default()
+        .with_from(owner)
+        .with_to(token)
+        .with_input(call_data.clone());
+
+    let mut tx_hashes = Vec::new();
+
+    if client.call(&tx).await.is_err() {
+        if current == U256::ZERO {
+            return Err(anyhow::anyhow!("Approve of {} failed to simulate", token));
+        }
+
+        let reset: Bytes = ERC20::approveCall {
+            spender,
+            amount: U256::ZERO,
+        }
+        .abi_encode()
+        .into();
+        tx_hashes.push(simulate_and_send(client.clone(), owner, token, reset).await?);
+    }
+
+    tx_hashes.push(simulate_and_send(client, owner, token, call_data).await?);
+    Ok(tx_hashes)
+}
+
+async fn allowance<T, P, N>(
+    client: P,
+    token: Address,
+    owner: Address,
+    spender: Address,
+) -> Result<U256, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let contract = ERC20::new(token, client);
+    Ok(contract.allowance(owner, spender).call().await?._0)
+}
+
+/// Simulate the call with `eth_call` and send it if it succeeds
+async fn simulate_and_send<T, P, N>(
+    client: P,
+    from: Address,
+    to: Address,
+    call_data: Bytes,
+) -> Result<TxHash, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let tx = N::TransactionRequest::default()
+        .with_from(from)
+        .with_to(to)
+        .with_input(call_data);
+
+    client
+        .call(&tx)
+        .await
+        .map_err(|e| anyhow::anyhow!("Call to {} failed to simulate: {:?}", to, e))?;
+
+    send(client, tx).await
+}
+
+async fn send<T, P, N>(client: P, tx: N::TransactionRequest) -> Result<TxHash, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let receipt = client.send_transaction(tx).await?.get_receipt().await?;
+
+    if !receipt.status() {
+        return Err(anyhow::anyhow!(
+            "Transaction {} reverted",
+            receipt.transaction_hash()
+        ));
+    }
+
+    Ok(receipt.transaction_hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permit2_amount() {
+        assert_eq!(permit2_amount(U256::from(1000)), U160::from(1000));
+        assert_eq!(permit2_amount(U256::from(U160::MAX)), U160::MAX);
+        assert_eq!(permit2_amount(U256::from(U160::MAX) + U256::from(1)), U160::MAX);
+        assert_eq!(permit2_amount(U256::MAX), U160::MAX);
+    }
+}
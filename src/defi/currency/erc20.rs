@@ -6,9 +6,9 @@ use alloy_sol_types::SolCall;
 use alloy_transport::Transport;
 
 use crate::abi::erc20::ERC20;
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
-use tokio::try_join;
 
 /// Enum  to categorize ERC20Tokens
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,12 +45,32 @@ impl ERC20Token {
         P: Provider<T, N> + Clone,
         N: Network,
     {
-        let symbol = Self::symbol(address, client.clone());
-        let name = Self::name(address, client.clone());
-        let decimals = Self::decimals(address, client.clone());
-        let total_supply = Self::total_supply(address, client.clone());
-        let (symbol, name, decimals, total_supply) =
-            try_join!(symbol, name, decimals, total_supply)?;
+        // all the metadata in a single round trip
+        let mut batch = EthCallBatch::new(None);
+        let symbol = batch.add(address, ERC20::symbolCall {}.abi_encode().into());
+        let name = batch.add(address, ERC20::nameCall {}.abi_encode().into());
+        let decimals = batch.add(address, ERC20::decimalsCall {}.abi_encode().into());
+        let total_supply = batch.add(address, ERC20::totalSupplyCall {}.abi_encode().into());
+        let mut results = batch.send(client).await?;
+
+        // ! There are cases like the MKR token where the symbol and name are not available
+        let symbol = take_result(&mut results, symbol)
+            .and_then(|b| Ok(ERC20::symbolCall::abi_decode_returns(&b, true)?._0))
+            .unwrap_or_else(|_| "Unknown".to_string());
+        let name = take_result(&mut results, name)
+            .and_then(|b| Ok(ERC20::nameCall::abi_decode_returns(&b, true)?._0))
+            .unwrap_or_else(|_| "Unknown".to_string());
+        let decimals = ERC20::decimalsCall::abi_decode_returns(
+            &take_result(&mut results, decimals)?,
+            true,
+        )?
+        ._0;
+        let total_supply = ERC20::totalSupplyCall::abi_decode_returns(
+            &take_result(&mut results, total_supply)?,
+            true,
+        )?
+        ._0;
+
         Ok(Self {
             chain_id,
             address,
@@ -135,58 +155,61 @@ impl ERC20Token {
         let allowance = ERC20::allowanceCall::abi_decode_returns(&bytes, true)?;
         Ok(allowance._0)
     }
+}
 
+/// Symbols of the common USD stablecoins, for tokens not marked as [TokenKind::StableCoin]
+const STABLE_SYMBOLS: [&str; 12] = [
+    "USDC", "USDC.e", "USDbC", "USDT", "DAI", "FRAX", "LUSD", "GHO", "crvUSD", "PYUSD", "TUSD", "FDUSD",
+];
 
-    async fn symbol<T, P, N>(address: Address, client: P) -> Result<String, anyhow::Error>
-    where
-        T: Transport + Clone,
-        P: Provider<T, N> + Clone,
-        N: Network,
-    {
-        // ! There are cases like the MKR token where the symbol and name are not available
-        let contract = ERC20::new(address, client.clone());
-        let symbol = match contract.symbol().call().await {
-            Ok(s) => s._0,
-            Err(_) => "Unknown".to_string(),
-        };
-        Ok(symbol)
+/// Symbols of the liquid staking tokens of ETH, for tokens not marked as [TokenKind::LiquidStaking]
+const ETH_LST_SYMBOLS: [&str; 12] = [
+    "stETH", "wstETH", "rETH", "cbETH", "frxETH", "sfrxETH", "weETH", "eETH", "ETHx", "swETH", "mETH", "osETH",
+];
+
+/// Symbols of the liquid staking tokens of BNB
+const BNB_LST_SYMBOLS: [&str; 4] = ["BNBx", "slisBNB", "stkBNB", "ankrBNB"];
+
+impl ERC20Token {
+    /// Is this token a USD stablecoin, by its [TokenKind] or its symbol
+    pub fn is_stablecoin(&self) -> bool {
+        matches!(self.kind, TokenKind::StableCoin) || STABLE_SYMBOLS.contains(&self.symbol.as_str())
     }
 
-    async fn name<T, P, N>(address: Address, client: P) -> Result<String, anyhow::Error>
-    where
-        T: Transport + Clone,
-        P: Provider<T, N> + Clone,
-        N: Network,
-    {
-        // ! There are cases like the MKR token where the symbol and name are not available
-        let contract = ERC20::new(address, client.clone());
-        let name = match contract.name().call().await {
-            Ok(n) => n._0,
-            Err(_) => "Unknown".to_string(),
-        };
-        Ok(name)
+    /// The wrapped native token this token tracks: itself for WETH/WBNB, the staked asset for an LST
+    fn native_underlying(&self) -> Option<&'static str> {
+        match self.kind {
+            TokenKind::WETH => return Some("ETH"),
+            TokenKind::WBNB => return Some("BNB"),
+            _ => {}
+        }
+
+        let symbol = self.symbol.as_str();
+        if ETH_LST_SYMBOLS.contains(&symbol) {
+            Some("ETH")
+        } else if BNB_LST_SYMBOLS.contains(&symbol) {
+            Some("BNB")
+        } else if matches!(self.kind, TokenKind::LiquidStaking) {
+            // most LSTs on the supported chains are ETH derivatives
+            Some("ETH")
+        } else {
+            None
+        }
     }
+}
 
-    async fn decimals<T, P, N>(address: Address, client: P) -> Result<u8, anyhow::Error>
-    where
-        T: Transport + Clone,
-        P: Provider<T, N> + Clone,
-        N: Network,
-    {
-        let contract = ERC20::new(address, client.clone());
-        let d = contract.decimals().call().await?._0;
-        Ok(d)
+/// Do the two tokens track the same asset
+///
+/// True if both are USD stablecoins, or a liquid staking token and its underlying (eg. wstETH/WETH, stETH/rETH).
+/// Stable pairs trade in a narrow band, so they call for tight ranges, the lowest fee tiers and more decimals when displayed
+pub fn is_stable_pair(token0: &ERC20Token, token1: &ERC20Token) -> bool {
+    if token0.is_stablecoin() && token1.is_stablecoin() {
+        return true;
     }
 
-    async fn total_supply<T, P, N>(address: Address, client: P) -> Result<U256, anyhow::Error>
-    where
-        T: Transport + Clone,
-        P: Provider<T, N> + Clone,
-        N: Network,
-    {
-        let contract = ERC20::new(address, client.clone());
-        let t = contract.totalSupply().call().await?._0;
-        Ok(t)
+    match (token0.native_underlying(), token1.native_underlying()) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
     }
 }
 
@@ -0,0 +1,167 @@
+//! Resolve token logos into [ERC20Token::icon]
+//!
+//! Logos are looked up in a token list (by its `logoURI`) and then in the
+//! [Trust Wallet assets](https://github.com/trustwallet/assets) repository.
+//! Fetched logos are cached in memory and optionally on disk
+
+use alloy_primitives::Address;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::trace;
+
+use super::erc20::ERC20Token;
+
+pub const TRUSTWALLET_ASSETS: &str =
+    "https://raw.githubusercontent.com/trustwallet/assets/master/blockchains";
+
+pub const IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+
+/// The Trust Wallet logo URL of a token
+pub fn trustwallet_url(chain_id: u64, token: Address) -> Result<String, anyhow::Error> {
+    let chain = match chain_id {
+        1 => "ethereum",
+        10 => "optimism",
+        56 => "smartchain",
+        8453 => "base",
+        42161 => "arbitrum",
+        _ => return Err(anyhow::anyhow!("Unsupported chain id: {}", chain_id)),
+    };
+
+    Ok(format!(
+        "{}/{}/assets/{}/logo.png",
+        TRUSTWALLET_ASSETS,
+        chain,
+        token.to_checksum(None)
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenListEntry {
+    chain_id: u64,
+    address: Address,
+    #[serde(rename = "logoURI")]
+    logo_uri: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenList {
+    tokens: Vec<TokenListEntry>,
+}
+
+/// Fetches and caches token logos
+#[derive(Debug, Clone, Default)]
+pub struct IconCache {
+    /// (chain id, token) -> logo URL, see [IconCache::load_token_list]
+    pub logo_uris: HashMap<(u64, Address), String>,
+
+    /// Directory where the logos are cached, None to only cache in memory
+    pub dir: Option<PathBuf>,
+
+    icons: HashMap<(u64, Address), Vec<u8>>,
+    client: reqwest::Client,
+}
+
+impl IconCache {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            ..Default::default()
+        }
+    }
+
+    /// Load the logo URLs of a token list (eg. <https://tokens.uniswap.org>)
+    pub async fn load_token_list(&mut self, url: &str) -> Result<(), anyhow::Error> {
+        let list = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenList>()
+            .await?;
+
+        for token in list.tokens {
+            if let Some(uri) = token.logo_uri {
+                self.logo_uris.insert((token.chain_id, token.address), uri);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the logo of a token, None if none of the sources has it
+    pub async fn get(&mut self, chain_id: u64, token: Address) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let key = (chain_id, token);
+        if let Some(icon) = self.icons.get(&key) {
+            return Ok(Some(icon.clone()));
+        }
+
+        let path = self
+            .dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}_{}.png", chain_id, token)));
+
+        if let Some(path) = &path {
+            if let Ok(icon) = tokio::fs::read(path).await {
+                self.icons.insert(key, icon.clone());
+                return Ok(Some(icon));
+            }
+        }
+
+        let mut urls = Vec::new();
+        if let Some(uri) = self.logo_uris.get(&key) {
+            urls.push(resolve_uri(uri));
+        }
+        if let Ok(url) = trustwallet_url(chain_id, token) {
+            urls.push(url);
+        }
+
+        for url in urls {
+            match self.fetch(&url).await {
+                Ok(icon) => {
+                    if let Some(path) = &path {
+                        if let Err(e) = tokio::fs::write(path, &icon).await {
+                            trace!("Failed to cache the icon of {}: {:?}", token, e);
+                        }
+                    }
+                    self.icons.insert(key, icon.clone());
+                    return Ok(Some(icon));
+                }
+                Err(e) => trace!("Failed to fetch {}: {:?}", url, e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Set the icon of the token if one is found
+    pub async fn populate(&mut self, token: &mut ERC20Token) -> Result<(), anyhow::Error> {
+        if token.icon.is_none() {
+            token.icon = self.get(token.chain_id, token.address).await?;
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<u8>, anyhow::Error> {
+        let bytes = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Rewrite `ipfs://` URIs to an HTTP gateway
+fn resolve_uri(uri: &str) -> String {
+    match uri.strip_prefix("ipfs://") {
+        Some(cid) => format!("{}{}", IPFS_GATEWAY, cid),
+        None => uri.to_string(),
+    }
+}
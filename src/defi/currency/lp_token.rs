@@ -0,0 +1,362 @@
+//! USD value of LP tokens
+//!
+//! An LP token is worth its share of the reserves of its pool:
+//! `(sum of reserve * USD price) / total supply`.
+//!
+//! Supported LP tokens:
+//!
+//! - Uniswap V2 pairs and their forks, the pair is the LP token
+//! - Curve pools, the LP token is the pool itself or the token returned by `lp_token()` on older pools
+//! - Balancer V2 pools, the pool is the BPT
+//!
+//! The underlying tokens are priced with [get_token_prices], a token without a known price is valued at 0
+//! and listed in [LpTokenValue::unpriced]
+
+use alloy_contract::private::Network;
+use alloy_primitives::{address, Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
+use serde::{Deserialize, Serialize};
+
+use super::erc20::{ERC20Token, TokenKind};
+use crate::abi::{
+    balancer::{IBalancerPool, IBalancerVault, BALANCER_VAULT},
+    curve::ICurvePool,
+    erc20::ERC20,
+    uniswap::pool::v2,
+};
+use crate::defi::amm::uniswap::v2::UniswapV2Pool;
+use crate::defi::utils::chain_link::get_token_prices;
+use crate::utils::format::to_f64;
+use crate::ChainId;
+
+/// The decimals of the V2, Curve and Balancer LP tokens
+pub const LP_DECIMALS: u8 = 18;
+
+/// The address Curve uses for the native currency of the chain
+const CURVE_NATIVE: Address = address!("EeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE");
+
+/// The most coins a Curve pool can have
+const CURVE_MAX_COINS: usize = 8;
+
+/// The protocol of an LP token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LpKind {
+    UniswapV2,
+    Curve,
+    Balancer,
+}
+
+/// A token held by the pool of an LP token
+#[derive(Debug, Clone, Serialize)]
+pub struct Underlying {
+    pub token: Address,
+
+    /// The reserve of the pool, formatted
+    pub reserve: f64,
+
+    /// The USD price of the token, 0 if unknown
+    pub usd: f64,
+}
+
+/// The value of an LP token
+#[derive(Debug, Clone, Serialize)]
+pub struct LpTokenValue {
+    pub lp_token: Address,
+    pub kind: LpKind,
+
+    /// The total supply of the LP token, formatted
+    pub total_supply: f64,
+
+    pub underlying: Vec<Underlying>,
+
+    /// The USD value of all the reserves of the pool
+    pub tvl_usd: f64,
+
+    /// The USD value of one LP token
+    pub usd_per_token: f64,
+
+    /// The underlying tokens without a USD price, the value is a lower bound if not empty
+    pub unpriced: Vec<Address>,
+}
+
+impl LpTokenValue {
+    pub fn new(lp_token: Address, kind: LpKind, total_supply: f64, underlying: Vec<Underlying>) -> Self {
+        let tvl_usd = underlying.iter().map(|u| u.reserve * u.usd).sum();
+        let usd_per_token = if total_supply == 0.0 {
+            0.0
+        } else {
+            tvl_usd / total_supply
+        };
+        let unpriced = underlying
+            .iter()
+            .filter(|u| u.usd == 0.0)
+            .map(|u| u.token)
+            .collect();
+
+        Self {
+            lp_token,
+            kind,
+            total_supply,
+            underlying,
+            tvl_usd,
+            usd_per_token,
+            unpriced,
+        }
+    }
+
+    /// The USD value of an amount of the LP token
+    pub fn value_usd(&self, amount: U256) -> Result<f64, anyhow::Error> {
+        Ok(to_f64(amount, LP_DECIMALS)? * self.usd_per_token)
+    }
+
+    /// The amounts of the underlying tokens redeemable for one LP token, in the order of [LpTokenValue::underlying]
+    pub fn underlying_per_token(&self) -> Vec<f64> {
+        self.underlying
+            .iter()
+            .map(|u| {
+                if self.total_supply == 0.0 {
+                    0.0
+                } else {
+                    u.reserve / self.total_supply
+                }
+            })
+            .collect()
+    }
+}
+
+/// Value an LP token of any supported [LpKind]
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `chain_id` - The chain of the LP token
+/// * `kind` - The protocol of the LP token
+/// * `lp_token` - The pair, Curve pool or Balancer pool
+/// * `block` - The block to read the reserves and the prices at, the latest if None
+pub async fn lp_token_usd<T, P, N>(
+    client: P,
+    chain_id: u64,
+    kind: LpKind,
+    lp_token: Address,
+    block: Option<BlockId>,
+) -> Result<LpTokenValue, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    match kind {
+        LpKind::UniswapV2 => {
+            let token0 = v2::token0(lp_token, client.clone()).await?;
+            let token1 = v2::token1(lp_token, client.clone()).await?;
+            let token0 = ERC20Token::new(client.clone(), token0, chain_id, TokenKind::Other).await?;
+            let token1 = ERC20Token::new(client.clone(), token1, chain_id, TokenKind::Other).await?;
+            let pool = UniswapV2Pool::new(chain_id, lp_token, token0, token1);
+            v2_lp_token_usd(client, &pool, block).await
+        }
+        LpKind::Curve => curve_lp_token_usd(client, chain_id, lp_token, block).await,
+        LpKind::Balancer => balancer_lp_token_usd(client, chain_id, lp_token, block).await,
+    }
+}
+
+/// Value the LP token of a Uniswap V2 pair
+pub async fn v2_lp_token_usd<T, P, N>(
+    client: P,
+    pool: &UniswapV2Pool,
+    block: Option<BlockId>,
+) -> Result<LpTokenValue, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let state = UniswapV2Pool::fetch_state(client.clone(), pool.address, block).await?;
+    let total_supply = total_supply(client.clone(), pool.address, block).await?;
+    let (token0_usd, token1_usd) = pool.tokens_usd(client, block).await?;
+
+    let underlying = vec![
+        Underlying {
+            token: pool.token0.address,
+            reserve: to_f64(state.reserve0, pool.token0.decimals)?,
+            usd: token0_usd,
+        },
+        Underlying {
+            token: pool.token1.address,
+            reserve: to_f64(state.reserve1, pool.token1.decimals)?,
+            usd: token1_usd,
+        },
+    ];
+
+    Ok(LpTokenValue::new(
+        pool.address,
+        LpKind::UniswapV2,
+        to_f64(total_supply, LP_DECIMALS)?,
+        underlying,
+    ))
+}
+
+/// Value the LP token of a Curve pool
+///
+/// The native currency of the pool is priced as the wrapped native token
+pub async fn curve_lp_token_usd<T, P, N>(
+    client: P,
+    chain_id: u64,
+    pool: Address,
+    block: Option<BlockId>,
+) -> Result<LpTokenValue, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block_id = block.unwrap_or(BlockId::latest());
+    let contract = ICurvePool::new(pool, client.clone());
+
+    // the old pools have a separate LP token, the newer ones are the LP token
+    let lp_token = match contract.lp_token().block(block_id).call().await {
+        Ok(lp_token) => lp_token._0,
+        Err(_) => pool,
+    };
+
+    let mut coins = Vec::new();
+    let mut balances = Vec::new();
+    for i in 0..CURVE_MAX_COINS {
+        let Ok(coin) = contract.coins(U256::from(i)).block(block_id).call().await else {
+            break;
+        };
+        let balance = contract.balances(U256::from(i)).block(block_id).call().await?._0;
+        coins.push(coin._0);
+        balances.push(balance);
+    }
+
+    if coins.is_empty() {
+        return Err(anyhow::anyhow!("{} is not a Curve pool", pool));
+    }
+
+    let wrapped_native = ChainId::try_new(chain_id)?.wrapped_native();
+    let coins: Vec<Address> = coins
+        .into_iter()
+        .map(|coin| if coin == CURVE_NATIVE { wrapped_native } else { coin })
+        .collect();
+
+    let underlying = price_underlying(client.clone(), chain_id, &coins, &balances, block).await?;
+    let total_supply = total_supply(client, lp_token, block).await?;
+
+    Ok(LpTokenValue::new(
+        lp_token,
+        LpKind::Curve,
+        to_f64(total_supply, LP_DECIMALS)?,
+        underlying,
+    ))
+}
+
+/// Value the BPT of a Balancer V2 pool
+///
+/// The BPT pre-minted by composable stable pools is excluded from both the reserves and the supply
+pub async fn balancer_lp_token_usd<T, P, N>(
+    client: P,
+    chain_id: u64,
+    pool: Address,
+    block: Option<BlockId>,
+) -> Result<LpTokenValue, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block_id = block.unwrap_or(BlockId::latest());
+    let contract = IBalancerPool::new(pool, client.clone());
+    let pool_id = contract.getPoolId().block(block_id).call().await?._0;
+
+    let vault = IBalancerVault::new(BALANCER_VAULT, client.clone());
+    let pool_tokens = vault.getPoolTokens(pool_id).block(block_id).call().await?;
+
+    let (tokens, balances): (Vec<Address>, Vec<U256>) = pool_tokens
+        .tokens
+        .into_iter()
+        .zip(pool_tokens.balances)
+        .filter(|(token, _)| *token != pool)
+        .unzip();
+
+    // only the newer pools have getActualSupply
+    let total_supply = match contract.getActualSupply().block(block_id).call().await {
+        Ok(supply) => supply._0,
+        Err(_) => contract.totalSupply().block(block_id).call().await?._0,
+    };
+
+    let underlying = price_underlying(client, chain_id, &tokens, &balances, block).await?;
+
+    Ok(LpTokenValue::new(
+        pool,
+        LpKind::Balancer,
+        to_f64(total_supply, LP_DECIMALS)?,
+        underlying,
+    ))
+}
+
+async fn total_supply<T, P, N>(client: P, token: Address, block: Option<BlockId>) -> Result<U256, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let contract = ERC20::new(token, client);
+    let supply = contract
+        .totalSupply()
+        .block(block.unwrap_or(BlockId::latest()))
+        .call()
+        .await?;
+    Ok(supply._0)
+}
+
+/// Format the balances with the decimals of each token and attach their USD prices
+async fn price_underlying<T, P, N>(
+    client: P,
+    chain_id: u64,
+    tokens: &[Address],
+    balances: &[U256],
+    block: Option<BlockId>,
+) -> Result<Vec<Underlying>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let prices = get_token_prices(client.clone(), block, chain_id, tokens).await?;
+
+    let mut underlying = Vec::with_capacity(tokens.len());
+    for ((token, balance), usd) in tokens.iter().zip(balances).zip(prices) {
+        let erc20 = ERC20Token::new(client.clone(), *token, chain_id, TokenKind::Other).await?;
+        underlying.push(Underlying {
+            token: *token,
+            reserve: to_f64(*balance, erc20.decimals)?,
+            usd,
+        });
+    }
+
+    Ok(underlying)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lp_token_value() {
+        let a = address!("0000000000000000000000000000000000000001");
+        let b = address!("0000000000000000000000000000000000000002");
+        let underlying = vec![
+            Underlying { token: a, reserve: 100.0, usd: 2.0 },
+            Underlying { token: b, reserve: 50.0, usd: 0.0 },
+        ];
+
+        let value = LpTokenValue::new(Address::ZERO, LpKind::Curve, 40.0, underlying);
+        assert_eq!(value.tvl_usd, 200.0);
+        assert_eq!(value.usd_per_token, 5.0);
+        assert_eq!(value.unpriced, vec![b]);
+        assert_eq!(value.underlying_per_token(), vec![2.5, 1.25]);
+        assert_eq!(value.value_usd(U256::from(2) * U256::from(10).pow(U256::from(18))).unwrap(), 10.0);
+    }
+}
@@ -1,7 +1,14 @@
+pub mod allowance;
 pub mod erc20;
+pub mod lp_token;
 pub mod native;
 
+#[cfg(feature = "icons")]
+pub mod icon;
+
 use self::{erc20::ERC20Token, native::NativeCurrency};
+use crate::defi::utils::common_addr::{wbnb, weth};
+use alloy_primitives::Address;
 use serde::{Deserialize, Serialize};
 
 
@@ -35,6 +42,24 @@ impl Currency {
         }
     }
 
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            Self::Native(native) => native.chain_id,
+            Self::ERC20(erc20) => erc20.chain_id,
+        }
+    }
+
+    /// The address of the token that represents this currency in the pools
+    ///
+    /// The wrapped token (eg. WETH, WBNB) for a native currency and the token itself for an ERC20
+    pub fn wrapped_address(&self) -> Result<Address, anyhow::Error> {
+        match self {
+            Self::Native(native) if native.chain_id == 56 => wbnb(native.chain_id),
+            Self::Native(native) => weth(native.chain_id),
+            Self::ERC20(erc20) => Ok(erc20.address),
+        }
+    }
+
     pub fn symbol(&self) -> &String {
         match self {
             Self::Native(native) => &native.symbol,
@@ -1,5 +1,25 @@
+use alloy_contract::private::Network;
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
 use serde::{Deserialize, Serialize};
 
+use crate::defi::utils::chain_link::{get_bnb_price, get_eth_price};
+
+/// (chain id, symbol, name) of the native currencies, all of them have 18 decimals
+pub const NATIVE_CURRENCIES: [(u64, &str, &str); 10] = [
+    (1, "ETH", "Ether"),
+    (10, "ETH", "Ether"),
+    (56, "BNB", "BNB"),
+    (100, "XDAI", "xDAI"),
+    (137, "POL", "Polygon Ecosystem Token"),
+    (324, "ETH", "Ether"),
+    (8453, "ETH", "Ether"),
+    (42161, "ETH", "Ether"),
+    (43114, "AVAX", "Avalanche"),
+    (59144, "ETH", "Ether"),
+];
+
 /// Represents a Native Currency to its chain
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NativeCurrency {
@@ -28,30 +48,51 @@ impl NativeCurrency {
     }
 
     /// Create a new Native Currency from the chain id
+    ///
+    /// Unknown chains get ETH, which is the gas token of most EVM rollups
     pub fn from_chain_id(id: u64) -> Self {
-        match id {
-            1 => Self {
-                ..Default::default()
-            },
-            10 => Self {
-                ..Default::default()
-            },
-            56 => Self {
-                chain_id: 56,
-                symbol: "BNB".to_string(),
-                name: "Binance Smart Chain".to_string(),
-                decimals: 18,
-                icon: None,
-            },
-            8453 => Self {
-                ..Default::default()
-            },
-            42161 => Self {
-                ..Default::default()
-            },
-            _ => Self {
-                ..Default::default()
-            },
+        Self::try_from_chain_id(id).unwrap_or_else(|_| Self {
+            chain_id: id,
+            ..Default::default()
+        })
+    }
+
+    /// Same as [Self::from_chain_id] but fails for chains that are not in [NATIVE_CURRENCIES]
+    pub fn try_from_chain_id(id: u64) -> Result<Self, anyhow::Error> {
+        let (chain_id, symbol, name) = NATIVE_CURRENCIES
+            .iter()
+            .find(|(chain_id, _, _)| *chain_id == id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown native currency for chain id: {}", id))?;
+
+        Ok(Self {
+            chain_id: *chain_id,
+            symbol: symbol.to_string(),
+            name: name.to_string(),
+            decimals: 18,
+            icon: None,
+        })
+    }
+
+    /// Get the USD price of the currency at a given block
+    /// If block is None, the latest block is used
+    pub async fn usd_price<T, P, N>(
+        &self,
+        client: P,
+        block: Option<BlockId>,
+    ) -> Result<f64, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        match self.symbol.as_str() {
+            "ETH" => get_eth_price(client, block, self.chain_id).await,
+            "BNB" => get_bnb_price(client, block, self.chain_id).await,
+            _ => Err(anyhow::anyhow!(
+                "No USD price for {} on chain id {}",
+                self.symbol,
+                self.chain_id
+            )),
         }
     }
 }
@@ -61,7 +102,7 @@ impl Default for NativeCurrency {
         Self {
             chain_id: 1,
             symbol: "ETH".to_string(),
-            name: "Ethereum".to_string(),
+            name: "Ether".to_string(),
             decimals: 18,
             icon: None,
         }
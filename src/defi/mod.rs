@@ -1,3 +1,5 @@
 pub mod currency;
 pub mod amm;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod analytics;
+pub mod staking;
\ No newline at end of file
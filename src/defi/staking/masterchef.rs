@@ -0,0 +1,239 @@
+//! Find the LP tokens a user has staked in MasterChef-style contracts
+//!
+//! LP tokens deposited in a farm leave the wallet, so a balance snapshot misses them.
+//! [find_staked_lp] asks every known MasterChef of the chain for the `userInfo` of each of its pools
+//! in a single batch, and resolves the LP token of the pools with a non-zero stake.
+//!
+//! ```ignore
+//! let positions = find_staked_lp(client.clone(), chain_id, user, None).await?;
+//! for position in positions {
+//!     let value = position.value(client.clone(), None).await?;
+//!     println!("{} {} in {}: ${:.2}", position.amount, position.lp_token, position.chef.name, value.value_usd(position.amount)?);
+//! }
+//! ```
+
+use alloy_contract::private::Network;
+use alloy_primitives::{address, Address, Bytes, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_sol_types::SolCall;
+use alloy_transport::Transport;
+use serde::{Deserialize, Serialize};
+
+use crate::abi::masterchef::IMasterChef;
+use crate::defi::currency::lp_token::{lp_token_usd, LpKind, LpTokenValue};
+use crate::utils::rpc_batch::{take_result, EthCallBatch};
+
+/// How the LP token of a pool is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChefVersion {
+    /// `poolInfo(pid)` starts with the LP token
+    V1,
+
+    /// `lpToken(pid)`, MasterChefV2, MiniChef and PancakeSwap's MasterChef v2
+    V2,
+}
+
+/// A MasterChef contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MasterChef {
+    pub chain_id: u64,
+    pub address: Address,
+    pub name: String,
+    pub version: ChefVersion,
+
+    /// The protocol of the staked LP tokens
+    pub lp_kind: LpKind,
+}
+
+impl MasterChef {
+    pub fn new(chain_id: u64, address: Address, name: &str, version: ChefVersion, lp_kind: LpKind) -> Self {
+        Self {
+            chain_id,
+            address,
+            name: name.to_string(),
+            version,
+            lp_kind,
+        }
+    }
+
+    /// The known MasterChefs of a chain
+    pub fn known(chain_id: u64) -> Vec<Self> {
+        let chef = |address, name, version| MasterChef::new(chain_id, address, name, version, LpKind::UniswapV2);
+
+        match chain_id {
+            1 => vec![
+                chef(
+                    address!("c2EdaD668740f1aA35E4D8f227fB8E17dcA888Cd"),
+                    "SushiSwap MasterChef",
+                    ChefVersion::V1,
+                ),
+                chef(
+                    address!("EF0881eC094552b2e128Cf945EF17a6752B4Ec5d"),
+                    "SushiSwap MasterChefV2",
+                    ChefVersion::V2,
+                ),
+            ],
+            56 => vec![chef(
+                address!("a5f8C5Dbd5F286960b9d90548680aE5ff69FF652"),
+                "PancakeSwap MasterChef v2",
+                ChefVersion::V2,
+            )],
+            42161 => vec![chef(
+                address!("F4d73326C13a4Fc5FD7A064217e12780e9Bd62c3"),
+                "SushiSwap MiniChef",
+                ChefVersion::V2,
+            )],
+            _ => Vec::new(),
+        }
+    }
+
+    fn encode_lp_token(&self, pid: U256) -> Bytes {
+        match self.version {
+            ChefVersion::V1 => IMasterChef::poolInfoCall { pid }.abi_encode().into(),
+            ChefVersion::V2 => IMasterChef::lpTokenCall { pid }.abi_encode().into(),
+        }
+    }
+}
+
+/// LP tokens staked by a user in a [MasterChef] pool
+#[derive(Debug, Clone, Serialize)]
+pub struct StakedPosition {
+    pub chef: MasterChef,
+    pub pid: u64,
+    pub lp_token: Address,
+    pub amount: U256,
+}
+
+impl StakedPosition {
+    /// The USD value of the LP token, see [lp_token_usd]
+    pub async fn value<T, P, N>(&self, client: P, block: Option<BlockId>) -> Result<LpTokenValue, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        lp_token_usd(client, self.chef.chain_id, self.chef.lp_kind, self.lp_token, block).await
+    }
+}
+
+/// Find the LP tokens staked by `user` in the known MasterChefs of the chain, see [MasterChef::known]
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `chain_id` - The chain to look at
+/// * `user` - The staker
+/// * `block` - The block to read the stakes at, the latest if None
+pub async fn find_staked_lp<T, P, N>(
+    client: P,
+    chain_id: u64,
+    user: Address,
+    block: Option<BlockId>,
+) -> Result<Vec<StakedPosition>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    find_staked_lp_in(client, &MasterChef::known(chain_id), user, block).await
+}
+
+/// Same as [find_staked_lp] with the given MasterChefs
+///
+/// A MasterChef that fails to answer `poolLength` is skipped
+pub async fn find_staked_lp_in<T, P, N>(
+    client: P,
+    chefs: &[MasterChef],
+    user: Address,
+    block: Option<BlockId>,
+) -> Result<Vec<StakedPosition>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    // the number of pools of each chef
+    let mut batch = EthCallBatch::new(block);
+    for chef in chefs {
+        batch.add(chef.address, IMasterChef::poolLengthCall {}.abi_encode().into());
+    }
+    let lengths: Vec<Option<u64>> = batch
+        .send(client.clone())
+        .await?
+        .into_iter()
+        .map(|result| result.ok().and_then(|b| first_word(&b)).map(|w| w.saturating_to()))
+        .collect();
+
+    // the stake of the user in every pool
+    let mut batch = EthCallBatch::new(block);
+    let mut pools = Vec::new();
+    for (chef, length) in chefs.iter().zip(&lengths) {
+        let Some(length) = length else {
+            continue;
+        };
+        for pid in 0..*length {
+            let call = IMasterChef::userInfoCall {
+                pid: U256::from(pid),
+                user,
+            };
+            batch.add(chef.address, call.abi_encode().into());
+            pools.push((chef, pid));
+        }
+    }
+    let stakes = batch.send(client.clone()).await?;
+
+    let staked: Vec<(&MasterChef, u64, U256)> = pools
+        .into_iter()
+        .zip(stakes)
+        .filter_map(|((chef, pid), result)| {
+            let amount = first_word(&result.ok()?)?;
+            (!amount.is_zero()).then_some((chef, pid, amount))
+        })
+        .collect();
+
+    // the LP token of the pools with a stake
+    let mut batch = EthCallBatch::new(block);
+    for (chef, pid, _) in &staked {
+        batch.add(chef.address, chef.encode_lp_token(U256::from(*pid)));
+    }
+    let mut lp_tokens = batch.send(client).await?;
+
+    let mut positions = Vec::with_capacity(staked.len());
+    for (i, (chef, pid, amount)) in staked.into_iter().enumerate() {
+        let lp_token = take_result(&mut lp_tokens, i)?;
+        let lp_token = first_word(&lp_token)
+            .map(|word| Address::from_word(word.into()))
+            .ok_or_else(|| anyhow::anyhow!("No LP token for pool {} of {}", pid, chef.name))?;
+
+        positions.push(StakedPosition {
+            chef: chef.clone(),
+            pid,
+            lp_token,
+            amount,
+        });
+    }
+
+    Ok(positions)
+}
+
+/// The first 32 bytes of the return data, the forks append fields to `userInfo` and `poolInfo`
+fn first_word(data: &Bytes) -> Option<U256> {
+    data.get(..32).map(U256::from_be_slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_chefs() {
+        let chefs = MasterChef::known(56);
+        assert_eq!(chefs.len(), 1);
+        assert_eq!(chefs[0].address, address!("a5f8C5Dbd5F286960b9d90548680aE5ff69FF652"));
+        assert_eq!(chefs[0].version, ChefVersion::V2);
+
+        assert_eq!(MasterChef::known(1).len(), 2);
+        assert!(MasterChef::known(10).is_empty());
+    }
+}
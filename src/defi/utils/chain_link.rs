@@ -1,11 +1,13 @@
-use alloy_primitives::{address, utils::format_units, Address, U256};
+use alloy_primitives::{address, Address, U256};
 use alloy_rpc_types::BlockId;
-use alloy_sol_types::sol;
+use alloy_sol_types::{sol, SolCall};
 
 use alloy_contract::private::Network;
 use alloy_provider::Provider;
 use alloy_transport::Transport;
-use super::common_addr::*;
+use crate::ChainId;
+use crate::utils::format::to_f64;
+use crate::utils::rpc_batch::EthCallBatch;
 
 
 // Ethereum mainnet
@@ -40,12 +42,11 @@ where
     P: Provider<T, N> + Clone,
     N: Network,
 {
-    let feed = match chain_id {
-        1 => ETH_USD_FEED,
-        8453 => BASE_ETH_USD_FEED,
-        42161 => ARB_ETH_USD_FEED,
-        _ => return Err(anyhow::anyhow!("Unsupported chain id {}", chain_id)),
-    };
+    let chain = ChainId::try_new(chain_id)?;
+    if chain.weth().is_none() {
+        return Err(anyhow::anyhow!("Unsupported chain id {}", chain_id));
+    }
+    let feed = native_usd_feed(&chain)?;
 
     let block_id = block_id.unwrap_or(BlockId::latest());
 
@@ -53,7 +54,7 @@ where
     let eth_usd = oracle.latestAnswer().block(block_id).call().await?._0;
 
     let eth_usd = eth_usd.to_string().parse::<U256>()?;
-    let formatted = format_units(eth_usd, 8)?.parse::<f64>()?;
+    let formatted = to_f64(eth_usd, 8)?;
     Ok(formatted)
 }
 
@@ -78,11 +79,13 @@ where
     let bnb_usd = oracle.latestAnswer().block(block_id).call().await?._0;
 
     let bnb_usd = bnb_usd.to_string().parse::<U256>()?;
-    let formatted = format_units(bnb_usd, 8)?.parse::<f64>()?;
+    let formatted = to_f64(bnb_usd, 8)?;
     Ok(formatted)
 }
 
 /// Get the USD value of commonly paired tokens
+///
+/// Returns 0.0 for any other token, see [usd_price_via_pools](super::pool_pricing::usd_price_via_pools) to route them through known pools
 pub async fn get_token_price<T, P, N>(
     client: P,
     block_id: Option<BlockId>,
@@ -94,39 +97,101 @@ where
     P: Provider<T, N> + Clone,
     N: Network,
 {
+    let Ok(chain) = ChainId::try_new(chain_id) else {
+        return Ok(0.0);
+    };
     let mut price = 0.0;
 
-    if chain_id == 1 || chain_id == 10 || chain_id == 42161 {
-        if token == usdc(chain_id)? {
-            price = 1.0;
-        } else if token == usdt(chain_id)? {
-            price = 1.0;
-        } else if token == dai(chain_id)? {
-            price = 1.0;
-        } else if token == weth(chain_id)? {
-            price = get_eth_price(client, block_id, chain_id).await?;
-        }
-    } else if chain_id == 8453 {
-        // USDT not available on Base
-        if token == usdc(chain_id)? {
-            price = 1.0;
-        } else if token == dai(chain_id)? {
-            price = 1.0;
-        } else if token == weth(chain_id)? {
-            price = get_eth_price(client, block_id, chain_id).await?;
-        }
-    } else if chain_id == 56 {
-        if token == usdc(chain_id)? {
-            price = 1.0;
-        } else if token == usdt(chain_id)? {
-            price = 1.0;
-        } else if token == dai(chain_id)? {
-            price = 1.0;
-        } else if token == wbnb(chain_id)? {
-            price = get_bnb_price(client, block_id, chain_id).await?;
-        }
+    if chain.stablecoins().contains(&token) {
+        price = 1.0;
+    } else if token == chain.wrapped_native() {
+        price = match chain {
+            ChainId::BinanceSmartChain(_) => get_bnb_price(client, block_id, chain_id).await?,
+            _ => get_eth_price(client, block_id, chain_id).await?,
+        };
     }
 
-
     Ok(price)
-}
\ No newline at end of file
+}
+
+/// The source of the USD price of a token
+enum PriceSource {
+    Fixed(f64),
+    Feed(Address),
+}
+
+fn price_source(chain_id: u64, token: Address) -> Result<PriceSource, anyhow::Error> {
+    let Ok(chain) = ChainId::try_new(chain_id) else {
+        return Ok(PriceSource::Fixed(0.0));
+    };
+
+    if chain.stablecoins().contains(&token) {
+        return Ok(PriceSource::Fixed(1.0));
+    }
+
+    if token != chain.wrapped_native() {
+        return Ok(PriceSource::Fixed(0.0));
+    }
+
+    Ok(PriceSource::Feed(native_usd_feed(&chain)?))
+}
+
+/// The Chainlink feed of the native currency in USD
+fn native_usd_feed(chain: &ChainId) -> Result<Address, anyhow::Error> {
+    match chain {
+        ChainId::Ethereum(_) => Ok(ETH_USD_FEED),
+        ChainId::Base(_) => Ok(BASE_ETH_USD_FEED),
+        ChainId::Arbitrum(_) => Ok(ARB_ETH_USD_FEED),
+        ChainId::BinanceSmartChain(_) => Ok(BNB_USD_FEED),
+        ChainId::Optimism(id) => Err(anyhow::anyhow!("Unsupported chain id {}", id)),
+    }
+}
+
+/// Same as [get_token_price] for many tokens, all the oracle calls are sent in a single batch
+///
+/// Returns the prices in the same order as `tokens`
+pub async fn get_token_prices<T, P, N>(
+    client: P,
+    block_id: Option<BlockId>,
+    chain_id: u64,
+    tokens: &[Address],
+) -> Result<Vec<f64>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut batch = EthCallBatch::new(block_id);
+    // (fixed price, index of the oracle call)
+    let mut sources = Vec::with_capacity(tokens.len());
+
+    for token in tokens {
+        let source = match price_source(chain_id, *token)? {
+            PriceSource::Fixed(price) => (price, None),
+            PriceSource::Feed(feed) => {
+                let call = ChainLinkOracle::latestAnswerCall {}.abi_encode();
+                (0.0, Some(batch.add(feed, call.into())))
+            }
+        };
+        sources.push(source);
+    }
+
+    let results = batch.send(client).await?;
+
+    let mut prices = Vec::with_capacity(tokens.len());
+    for (price, call) in sources {
+        let Some(index) = call else {
+            prices.push(price);
+            continue;
+        };
+
+        let data = results[index]
+            .as_ref()
+            .map_err(|e| anyhow::anyhow!("Failed to get price: {:?}", e))?;
+        let answer = ChainLinkOracle::latestAnswerCall::abi_decode_returns(data, true)?._0;
+        let answer = answer.to_string().parse::<U256>()?;
+        prices.push(to_f64(answer, 8)?);
+    }
+
+    Ok(prices)
+}
@@ -0,0 +1,211 @@
+//! Names of well-known addresses
+//!
+//! [Labels::new] starts with the routers, aggregators, bridges, exchange hot wallets and MEV bots listed here
+//! and the Uniswap deployments of every supported chain. Add your own with [Labels::with_label].
+//!
+//! The labels are used to print names instead of addresses (see [SwapData::pretty_with_labels]) and to split
+//! activity by who is behind it, eg. the volume of known bots vs organic volume with [volume_by_kind]
+
+use alloy_primitives::{address, Address, U256};
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::defi::amm::uniswap::deployments::DEPLOYMENTS;
+use crate::utils::logs::events::SwapData;
+
+/// What is behind an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LabelKind {
+    /// A DEX router
+    Router,
+
+    /// A DEX aggregator or settlement contract
+    Aggregator,
+    Bridge,
+
+    /// A hot wallet of a centralized exchange
+    Exchange,
+    MevBot,
+    Other,
+}
+
+/// A named address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub chain_id: u64,
+    pub address: Address,
+    pub name: String,
+    pub kind: LabelKind,
+}
+
+/// A built-in label
+#[derive(Debug, Clone, Copy)]
+pub struct KnownLabel {
+    pub chain_id: u64,
+    pub address: Address,
+    pub name: &'static str,
+    pub kind: LabelKind,
+}
+
+const fn known(chain_id: u64, address: Address, name: &'static str, kind: LabelKind) -> KnownLabel {
+    KnownLabel {
+        chain_id,
+        address,
+        name,
+        kind,
+    }
+}
+
+/// The built-in labels, the Uniswap deployments are added from [DEPLOYMENTS]
+pub const KNOWN_LABELS: &[KnownLabel] = &[
+    // Ethereum
+    known(1, address!("7a250d5630B4cF539739dF2C5dAcb4c659F2488D"), "Uniswap V2 Router", LabelKind::Router),
+    known(1, address!("E592427A0AEce92De3Edee1F18E0157C05861564"), "Uniswap V3 SwapRouter", LabelKind::Router),
+    known(1, address!("1111111254EEB25477B68fb85Ed929f73A960582"), "1inch Router V5", LabelKind::Aggregator),
+    known(1, address!("111111125421cA6dc452d289314280a0f8842A65"), "1inch Router V6", LabelKind::Aggregator),
+    known(1, address!("Def1C0ded9bec7F1a1670819833240f027b25EfF"), "0x Exchange Proxy", LabelKind::Aggregator),
+    known(1, address!("9008D19f58AAbD9eD0D60971565AA8510560ab41"), "CoW Protocol Settlement", LabelKind::Aggregator),
+    known(1, address!("DEF171Fe48CF0115B1d80b88dc8eAB59176FEe57"), "ParaSwap Augustus V5", LabelKind::Aggregator),
+    known(1, address!("72Ce9c846789fdB6fC1f34aC4AD25Dd9ef7031ef"), "Arbitrum Gateway Router", LabelKind::Bridge),
+    known(1, address!("99C9fc46f92E8a1c0deC1b1747d010903E884bE1"), "Optimism L1 Standard Bridge", LabelKind::Bridge),
+    known(1, address!("3154Cf16ccdb4C6d922629664174b904d80F2C35"), "Base L1 Standard Bridge", LabelKind::Bridge),
+    known(1, address!("5c7BCd6E7De5423a257D81B442095A1a6ced35C5"), "Across Spoke Pool", LabelKind::Bridge),
+    known(1, address!("28C6c06298d514Db089934071355E5743bf21d60"), "Binance 14", LabelKind::Exchange),
+    known(1, address!("F977814e90dA44bFA03b6295A0616a897441aceC"), "Binance 8", LabelKind::Exchange),
+    known(1, address!("71660c4005BA85c37ccec55d0C4493E66Fe775d3"), "Coinbase 1", LabelKind::Exchange),
+    known(1, address!("6cC5F688a315f3dC28A7781717a9A798a59fDA7b"), "OKX", LabelKind::Exchange),
+    known(1, address!("ae2Fc483527B8EF99EB5D9B44875F005ba1FaE13"), "jaredfromsubway.eth", LabelKind::MevBot),
+    // Optimism
+    known(10, address!("1111111254EEB25477B68fb85Ed929f73A960582"), "1inch Router V5", LabelKind::Aggregator),
+    known(10, address!("111111125421cA6dc452d289314280a0f8842A65"), "1inch Router V6", LabelKind::Aggregator),
+    known(10, address!("a062aE8A9c5e11aaA026fc2670B0D65cCc8B2858"), "Velodrome Router", LabelKind::Router),
+    known(10, address!("4200000000000000000000000000000000000010"), "L2 Standard Bridge", LabelKind::Bridge),
+    // Binance Smart Chain
+    known(56, address!("10ED43C718714eb63d5aA57B78B54704E256024E"), "PancakeSwap V2 Router", LabelKind::Router),
+    known(56, address!("13f4EA83D0bd40E75C8222255bc855a974568Dd4"), "PancakeSwap Smart Router", LabelKind::Router),
+    known(56, address!("1111111254EEB25477B68fb85Ed929f73A960582"), "1inch Router V5", LabelKind::Aggregator),
+    known(56, address!("111111125421cA6dc452d289314280a0f8842A65"), "1inch Router V6", LabelKind::Aggregator),
+    known(56, address!("8894E0a0c962CB723c1976a4421c95949bE2D4E3"), "Binance Hot Wallet", LabelKind::Exchange),
+    // Base
+    known(8453, address!("cF77a3Ba9A5CA399B7c97c74d54e5b1Beb874E43"), "Aerodrome Router", LabelKind::Router),
+    known(8453, address!("1111111254EEB25477B68fb85Ed929f73A960582"), "1inch Router V5", LabelKind::Aggregator),
+    known(8453, address!("111111125421cA6dc452d289314280a0f8842A65"), "1inch Router V6", LabelKind::Aggregator),
+    known(8453, address!("4200000000000000000000000000000000000010"), "L2 Standard Bridge", LabelKind::Bridge),
+    // Arbitrum
+    known(42161, address!("c873fEcbd354f5A56E00E710B90EF4201db2448d"), "Camelot Router", LabelKind::Router),
+    known(42161, address!("1111111254EEB25477B68fb85Ed929f73A960582"), "1inch Router V5", LabelKind::Aggregator),
+    known(42161, address!("111111125421cA6dc452d289314280a0f8842A65"), "1inch Router V6", LabelKind::Aggregator),
+];
+
+/// A set of labels, see the [module](self) docs
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    labels: HashMap<(u64, Address), Label>,
+}
+
+impl Labels {
+    /// The built-in labels
+    pub fn new() -> Self {
+        let mut labels = Self::empty();
+
+        for d in DEPLOYMENTS.iter() {
+            labels.insert(d.chain_id, d.swap_router, "Uniswap SwapRouter02", LabelKind::Router);
+            labels.insert(d.chain_id, d.universal_router, "Uniswap Universal Router", LabelKind::Router);
+            labels.insert(d.chain_id, d.position_manager, "Uniswap V3 Position Manager", LabelKind::Other);
+        }
+        for k in KNOWN_LABELS {
+            labels.insert(k.chain_id, k.address, k.name, k.kind);
+        }
+
+        labels
+    }
+
+    /// No labels at all
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Add a label, replacing the existing one of the address
+    pub fn with_label(mut self, chain_id: u64, address: Address, name: &str, kind: LabelKind) -> Self {
+        self.insert(chain_id, address, name, kind);
+        self
+    }
+
+    /// Add a label, replacing the existing one of the address
+    pub fn insert(&mut self, chain_id: u64, address: Address, name: &str, kind: LabelKind) {
+        self.labels.insert(
+            (chain_id, address),
+            Label {
+                chain_id,
+                address,
+                name: name.to_string(),
+                kind,
+            },
+        );
+    }
+
+    pub fn get(&self, chain_id: u64, address: Address) -> Option<&Label> {
+        self.labels.get(&(chain_id, address))
+    }
+
+    pub fn kind(&self, chain_id: u64, address: Address) -> Option<LabelKind> {
+        self.get(chain_id, address).map(|label| label.kind)
+    }
+
+    /// The name of the address if labeled, the address otherwise
+    pub fn name_or_address(&self, chain_id: u64, address: Address) -> String {
+        match self.get(chain_id, address) {
+            Some(label) => label.name.clone(),
+            None => address.to_string(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+/// Sum the input amounts of the swaps by the kind of their account
+///
+/// Swaps without an account or from an unlabeled account are grouped under None (organic).
+/// Only swaps with `token_in` are summed so the amounts share the same decimals
+///
+/// ## Arguments
+///
+/// * `swaps` - The swaps, see [enrich_swaps](crate::utils::logs::enrich::enrich_swaps) to fill their accounts
+/// * `token_in` - The input token to sum
+/// * `labels` - The labels
+pub fn volume_by_kind(
+    swaps: &[SwapData],
+    token_in: Address,
+    labels: &Labels,
+) -> BTreeMap<Option<LabelKind>, U256> {
+    let mut volume = BTreeMap::new();
+    for swap in swaps.iter().filter(|s| s.token_in.address == token_in) {
+        let kind = swap
+            .account
+            .and_then(|account| labels.kind(swap.token_in.chain_id, account));
+        *volume.entry(kind).or_insert(U256::ZERO) += swap.amount_in;
+    }
+    volume
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_labels_override_built_in() {
+        let router = address!("7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+        let labels = Labels::new();
+        assert_eq!(labels.kind(1, router), Some(LabelKind::Router));
+        assert_eq!(labels.kind(10, router), None);
+
+        let labels = labels.with_label(1, router, "My Router", LabelKind::Other);
+        assert_eq!(labels.name_or_address(1, router), "My Router");
+    }
+}
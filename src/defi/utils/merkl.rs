@@ -0,0 +1,96 @@
+//! Lookup of external incentive campaigns from the [Merkl](https://merkl.xyz) API
+//!
+//! Merkl distributes rewards to LPs off-chain, so they don't show up in the swap fees of a pool.
+//! The reward APR reported by Merkl can be folded into a [PositionResult] with [apply_to_position]
+
+use alloy_primitives::Address;
+use serde::Deserialize;
+
+use crate::defi::amm::uniswap::v3::lp_provider::PositionResult;
+
+pub const MERKL_API: &str = "https://api.merkl.xyz/v4";
+
+/// A Merkl opportunity (a pool with one or more reward campaigns)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MerklOpportunity {
+    pub chain_id: u64,
+
+    /// The pool address
+    pub identifier: String,
+    pub name: String,
+
+    /// LIVE, PAST or SOON
+    pub status: String,
+
+    /// The reward APR in percent
+    #[serde(default)]
+    pub apr: f64,
+
+    /// The TVL in USD that earns the rewards
+    #[serde(default)]
+    pub tvl: f64,
+
+    /// The rewards distributed per day in USD
+    #[serde(default)]
+    pub daily_rewards: f64,
+}
+
+impl MerklOpportunity {
+    pub fn is_live(&self) -> bool {
+        self.status == "LIVE"
+    }
+}
+
+/// Fetch the live Merkl opportunities of a pool
+///
+/// ## Arguments
+///
+/// * `chain_id` - The chain the pool is on
+/// * `pool` - The pool address
+pub async fn get_pool_opportunities(
+    chain_id: u64,
+    pool: Address,
+) -> Result<Vec<MerklOpportunity>, anyhow::Error> {
+    let url = format!(
+        "{}/opportunities?chainId={}&identifier={}&status=LIVE",
+        MERKL_API, chain_id, pool
+    );
+
+    let opportunities = reqwest::get(url)
+        .await?
+        .error_for_status()?
+        .json::<Vec<MerklOpportunity>>()
+        .await?;
+
+    // the identifier filter is a search, keep only the exact pool
+    let pool = pool.to_string().to_lowercase();
+    let opportunities = opportunities
+        .into_iter()
+        .filter(|o| o.identifier.to_lowercase() == pool && o.is_live())
+        .collect();
+
+    Ok(opportunities)
+}
+
+/// The total live reward APR of a pool in percent
+pub async fn get_pool_reward_apr(chain_id: u64, pool: Address) -> Result<f64, anyhow::Error> {
+    let opportunities = get_pool_opportunities(chain_id, pool).await?;
+    Ok(opportunities.iter().map(|o| o.apr).sum())
+}
+
+/// Fold the Merkl reward APR into a simulated position
+///
+/// The APR reported by Merkl is the average over all LPs, the position only earns it while in range
+pub fn apply_to_position(result: &mut PositionResult, opportunities: &[MerklOpportunity]) {
+    let apr: f64 = opportunities.iter().map(|o| o.apr).sum();
+
+    let total = result.in_range + result.out_of_range;
+    let in_range_ratio = if total == 0 {
+        0.0
+    } else {
+        result.in_range as f64 / total as f64
+    };
+
+    result.external_incentive_apr = apr * in_range_ratio;
+}
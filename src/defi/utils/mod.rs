@@ -1,2 +1,9 @@
 pub mod chain_link;
-pub mod common_addr;
\ No newline at end of file
+pub mod common_addr;
+pub mod labels;
+pub mod op_stack;
+pub mod pool_pricing;
+pub mod slippage;
+
+#[cfg(feature = "merkl")]
+pub mod merkl;
\ No newline at end of file
@@ -0,0 +1,153 @@
+//! Helpers for OP-stack chains (Optimism, Base)
+//!
+//! Transactions on these chains pay an L1 data fee on top of the L2 execution fee,
+//! the L1 fee depends on the (compressed) size of the RLP encoded signed transaction and the L1 gas price
+
+use alloy_consensus::{SignableTransaction, TxEip1559, TxEnvelope};
+use alloy_contract::private::Network;
+use alloy_eips::eip2718::Encodable2718;
+use alloy_primitives::{keccak256, Address, Bytes, Parity, Signature, TxKind, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
+
+use crate::abi::gas_price_oracle::get_l1_fee;
+use crate::utils::format::to_f64;
+
+/// Is the chain an OP-stack chain that charges an L1 data fee
+pub fn is_op_stack(chain_id: u64) -> bool {
+    matches!(chain_id, 10 | 8453)
+}
+
+/// The L1 data fee in wei for a transaction
+///
+/// Returns zero on chains that are not OP-stack chains
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `chain_id` - The chain id
+/// * `tx` - The RLP encoded signed transaction, see [encode_tx_for_l1_fee] for a transaction not signed yet
+/// * `block_id` - The block to read the L1 gas price at, None for the latest
+pub async fn l1_data_fee<T, P, N>(
+    client: P,
+    chain_id: u64,
+    tx: Bytes,
+    block_id: Option<BlockId>,
+) -> Result<U256, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    if !is_op_stack(chain_id) {
+        return Ok(U256::ZERO);
+    }
+
+    get_l1_fee(client, tx, block_id).await
+}
+
+/// RLP encode an EIP-1559 transaction with a dummy signature, the input of `GasPriceOracle.getL1Fee`
+///
+/// The signature bytes don't compress, so the fee of the signed transaction is not underestimated
+///
+/// ## Arguments
+///
+/// * `chain_id` - The chain id
+/// * `to` - The called contract
+/// * `calldata` - The calldata of the transaction
+/// * `gas_limit` - The gas limit of the transaction
+/// * `gas_price` - The max fee per gas of the transaction
+pub fn encode_tx_for_l1_fee(chain_id: u64, to: Address, calldata: Bytes, gas_limit: u64, gas_price: u128) -> Bytes {
+    let tx = TxEip1559 {
+        chain_id,
+        nonce: 0,
+        gas_limit: gas_limit as u128,
+        max_fee_per_gas: gas_price,
+        max_priority_fee_per_gas: gas_price,
+        to: TxKind::Call(to),
+        value: U256::ZERO,
+        access_list: Default::default(),
+        input: calldata,
+    };
+
+    let signature = Signature::new(
+        U256::from_be_bytes(keccak256("r").0),
+        U256::from_be_bytes(keccak256("s").0),
+        Parity::Parity(false),
+    );
+
+    TxEnvelope::from(tx.into_signed(signature)).encoded_2718().into()
+}
+
+/// The fee a transaction pays split into its L2 execution and L1 data parts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TxFee {
+    /// gas used * gas price
+    pub l2_fee: U256,
+
+    /// The L1 data fee, zero on chains that are not OP-stack chains
+    pub l1_fee: U256,
+}
+
+impl TxFee {
+    pub fn total(&self) -> U256 {
+        self.l2_fee + self.l1_fee
+    }
+
+    /// The total fee in USD
+    ///
+    /// ## Arguments
+    ///
+    /// * `native_usd` - The USD price of the native currency
+    pub fn total_usd(&self, native_usd: f64) -> Result<f64, anyhow::Error> {
+        let total = to_f64(self.total(), 18)?;
+        Ok(total * native_usd)
+    }
+}
+
+/// Suggest the fee of a transaction at the current gas price, including the L1 data fee on OP-stack chains
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `chain_id` - The chain id
+/// * `to` - The called contract
+/// * `calldata` - The calldata of the transaction
+/// * `gas_used` - The L2 gas the transaction uses (eg. from a simulation)
+pub async fn suggest_tx_fee<T, P, N>(
+    client: P,
+    chain_id: u64,
+    to: Address,
+    calldata: Bytes,
+    gas_used: u64,
+) -> Result<TxFee, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let gas_price = client.get_gas_price().await?;
+    let l2_fee = U256::from(gas_used) * U256::from(gas_price);
+
+    let tx = encode_tx_for_l1_fee(chain_id, to, calldata, gas_used, gas_price);
+    let l1_fee = l1_data_fee(client, chain_id, tx, None).await?;
+
+    Ok(TxFee { l2_fee, l1_fee })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_tx_for_l1_fee() {
+        let calldata = Bytes::from(vec![0xab; 100]);
+        let tx = encode_tx_for_l1_fee(10, Address::repeat_byte(1), calldata.clone(), 200_000, 1_000_000);
+
+        // EIP-1559 type, the calldata and a 65 bytes signature on top of it
+        assert_eq!(tx[0], 0x02);
+        assert!(tx.windows(calldata.len()).any(|w| w == calldata.as_ref()));
+        assert!(tx.len() > calldata.len() + 65);
+    }
+}
@@ -0,0 +1,121 @@
+//! USD prices of arbitrary tokens routed through known pools
+//!
+//! [get_token_price](super::chain_link::get_token_price) only knows stablecoins and the wrapped native token,
+//! [usd_price_via_pools] extends it to any token that is at most two hops away from one of them
+
+use alloy_contract::private::Network;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+
+use std::collections::HashMap;
+
+use super::chain_link::get_token_prices;
+use crate::defi::amm::registry::{PoolRegistry, PriceEdge};
+
+/// A USD price and the pools it was routed through
+#[derive(Debug, Clone)]
+pub struct PathPrice {
+    pub usd: f64,
+
+    /// The pools from the token to the USD anchor, empty if the token is an anchor itself
+    pub pools: Vec<Address>,
+
+    /// The USD value of the shallowest reserve along the path
+    pub depth_usd: f64,
+}
+
+/// Find the USD price of a token through up to two hops of the pools in the registry
+///
+/// The anchors are the tokens with an oracle price (stablecoins, WETH, WBNB), among all the paths
+/// to an anchor the one with the deepest liquidity is used
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `token` - The token to price
+/// * `registry` - The pools to route through, see [PoolRegistry::sync_states]
+pub async fn usd_price_via_pools<T, P, N>(
+    client: P,
+    token: Address,
+    registry: &PoolRegistry,
+) -> Result<PathPrice, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut tokens = registry.tokens();
+    if !tokens.contains(&token) {
+        tokens.push(token);
+    }
+
+    let prices = get_token_prices(client, None, registry.chain_id, &tokens).await?;
+    let anchors: HashMap<Address, f64> = tokens
+        .into_iter()
+        .zip(prices)
+        .filter(|(_, price)| *price != 0.0)
+        .collect();
+
+    if let Some(usd) = anchors.get(&token) {
+        return Ok(PathPrice {
+            usd: *usd,
+            pools: Vec::new(),
+            depth_usd: f64::INFINITY,
+        });
+    }
+
+    best_path(token, &registry.edges(), &anchors).ok_or_else(|| {
+        anyhow::anyhow!("No path from {} to a USD anchor in the registry", token)
+    })
+}
+
+/// The deepest path of at most two hops from `token` to one of the `anchors`
+fn best_path(
+    token: Address,
+    edges: &[PriceEdge],
+    anchors: &HashMap<Address, f64>,
+) -> Option<PathPrice> {
+    let mut best: Option<PathPrice> = None;
+    let mut consider = |candidate: PathPrice| {
+        if candidate.usd.is_finite()
+            && candidate.usd > 0.0
+            && best.as_ref().map_or(true, |b| candidate.depth_usd > b.depth_usd)
+        {
+            best = Some(candidate);
+        }
+    };
+
+    for first in edges.iter().filter(|e| e.base == token) {
+        // token -> anchor
+        if let Some(anchor_usd) = anchors.get(&first.quote) {
+            consider(PathPrice {
+                usd: first.price * anchor_usd,
+                pools: vec![first.pool],
+                depth_usd: first.quote_reserve * anchor_usd,
+            });
+            continue;
+        }
+
+        // token -> mid -> anchor
+        for second in edges
+            .iter()
+            .filter(|e| e.base == first.quote && e.pool != first.pool && e.quote != token)
+        {
+            let Some(anchor_usd) = anchors.get(&second.quote) else {
+                continue;
+            };
+
+            let mid_usd = second.price * anchor_usd;
+            let depth_usd = (first.quote_reserve * mid_usd).min(second.quote_reserve * anchor_usd);
+
+            consider(PathPrice {
+                usd: first.price * mid_usd,
+                pools: vec![first.pool, second.pool],
+                depth_usd,
+            });
+        }
+    }
+
+    best
+}
@@ -0,0 +1,148 @@
+//! Slippage adjusted amounts for router calls and the price impact of swaps
+
+use alloy_primitives::U256;
+
+/// 100% in basis points
+pub const MAX_BPS: u32 = 10_000;
+
+/// The minimum output of a swap that is expected to return `amount_out` with `bps` slippage tolerance
+pub fn min_amount_out(amount_out: U256, bps: u32) -> Result<U256, anyhow::Error> {
+    if bps > MAX_BPS {
+        return Err(anyhow::anyhow!("Slippage of {} bps is over 100%", bps));
+    }
+    Ok(amount_out * U256::from(MAX_BPS - bps) / U256::from(MAX_BPS))
+}
+
+/// The maximum input of a swap that is expected to cost `amount_in` with `bps` slippage tolerance
+pub fn max_amount_in(amount_in: U256, bps: u32) -> Result<U256, anyhow::Error> {
+    if bps > MAX_BPS {
+        return Err(anyhow::anyhow!("Slippage of {} bps is over 100%", bps));
+    }
+    Ok(amount_in * U256::from(MAX_BPS + bps) / U256::from(MAX_BPS))
+}
+
+/// The minimum output of a swap quoted to return `amount_out` with a tolerance in percent, eg. 0.5 for 0.5%
+///
+/// Unlike [min_amount_out] the tolerance can be finer than a basis point, down to 0.0001%
+pub fn minimum_received(amount_out: U256, slippage_percent: f64) -> Result<U256, anyhow::Error> {
+    if !(0.0..=100.0).contains(&slippage_percent) {
+        return Err(anyhow::anyhow!("Slippage of {}% is not between 0 and 100", slippage_percent));
+    }
+
+    // in millionths so fractional bps are kept
+    let kept = ((100.0 - slippage_percent) * 10_000.0).round() as u64;
+    Ok(amount_out * U256::from(kept) / U256::from(1_000_000))
+}
+
+/// The shortfall of the execution price of a swap from the mid price in percent (1.0 = 1%), fees included
+///
+/// Returns 0 if the mid price or the input is 0
+///
+/// ## Arguments
+///
+/// * `mid_price` - The price of the input token in the output token before the swap
+/// * `amount_in` - The formatted input amount
+/// * `amount_out` - The formatted output amount
+pub fn price_impact(mid_price: f64, amount_in: f64, amount_out: f64) -> f64 {
+    if mid_price == 0.0 || amount_in == 0.0 {
+        return 0.0;
+    }
+    (1.0 - amount_out / amount_in / mid_price) * 100.0
+}
+
+/// How much worse than quoted a trade is allowed to execute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slippage {
+    /// A tolerance in basis points of the quoted amount
+    Bps(u32),
+
+    /// A fixed tolerance in units of the token
+    Absolute(U256),
+
+    /// A tolerance derived from the volatility of the price
+    ///
+    /// The tolerance is `volatility * multiplier`, eg. a volatility of 0.01 (1% per period) with a multiplier of 2 is 200 bps
+    Volatility { volatility: f64, multiplier: f64 },
+}
+
+impl Default for Slippage {
+    /// 0.5%
+    fn default() -> Self {
+        Slippage::Bps(50)
+    }
+}
+
+impl Slippage {
+    /// A [Slippage::Volatility] from the standard deviation of the log returns of a price series
+    pub fn from_prices(prices: &[f64], multiplier: f64) -> Self {
+        let returns: Vec<f64> = prices
+            .windows(2)
+            .filter(|w| w[0] > 0.0 && w[1] > 0.0)
+            .map(|w| (w[1] / w[0]).ln())
+            .collect();
+
+        let volatility = if returns.len() < 2 {
+            0.0
+        } else {
+            let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+            let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+                / (returns.len() - 1) as f64;
+            variance.sqrt()
+        };
+
+        Slippage::Volatility {
+            volatility,
+            multiplier,
+        }
+    }
+
+    /// The tolerance in basis points, None for [Slippage::Absolute]
+    pub fn bps(&self) -> Option<u32> {
+        match self {
+            Slippage::Bps(bps) => Some(*bps),
+            Slippage::Absolute(_) => None,
+            Slippage::Volatility {
+                volatility,
+                multiplier,
+            } => {
+                let bps = (volatility * multiplier * MAX_BPS as f64).ceil();
+                Some(bps.clamp(1.0, MAX_BPS as f64) as u32)
+            }
+        }
+    }
+
+    /// The minimum output of a trade quoted to return `amount_out`
+    pub fn min_amount_out(&self, amount_out: U256) -> Result<U256, anyhow::Error> {
+        match (self, self.bps()) {
+            (Slippage::Absolute(amount), _) => Ok(amount_out.saturating_sub(*amount)),
+            (_, Some(bps)) => min_amount_out(amount_out, bps),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The maximum input of a trade quoted to cost `amount_in`
+    pub fn max_amount_in(&self, amount_in: U256) -> Result<U256, anyhow::Error> {
+        match (self, self.bps()) {
+            (Slippage::Absolute(amount), _) => Ok(amount_in.saturating_add(*amount)),
+            (_, Some(bps)) => max_amount_in(amount_in, bps),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minimum_received() {
+        let amount = U256::from(1_000_000);
+        assert_eq!(minimum_received(amount, 0.5).unwrap(), U256::from(995_000));
+        assert_eq!(minimum_received(amount, 0.05).unwrap(), U256::from(999_500));
+        assert_eq!(minimum_received(amount, 0.0).unwrap(), amount);
+        assert!(minimum_received(amount, 101.0).is_err());
+
+        assert!((price_impact(2.0, 1.0, 1.9) - 5.0).abs() < 1e-9);
+        assert_eq!(price_impact(0.0, 1.0, 1.0), 0.0);
+    }
+}
@@ -3,6 +3,7 @@ pub mod revm_utils;
 pub mod abi;
 pub mod utils;
 pub mod prelude;
+pub mod runtime;
 
 
 // RE-EXPORTS
@@ -23,6 +24,10 @@ pub use alloy_contract;
 // Revm
 pub use revm;
 
+use alloy_primitives::{address, Address};
+
+use defi::amm::uniswap::deployments::UniswapDeployment;
+
 pub const SUPPORTED_CHAINS: [u64; 5] = [1, 10, 56, 8453, 42161];
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -36,14 +41,22 @@ pub enum ChainId {
 
 impl ChainId {
 
+    /// Panics on an unsupported chain id, see [ChainId::try_new]
     pub fn new(id: u64) -> Self {
+        match Self::try_new(id) {
+            Ok(chain) => chain,
+            Err(_) => panic!("Unsupported chain id: {}", id),
+        }
+    }
+
+    pub fn try_new(id: u64) -> Result<Self, anyhow::Error> {
         match id {
-            1 => ChainId::Ethereum(id),
-            10 => ChainId::Optimism(id),
-            56 => ChainId::BinanceSmartChain(id),
-            8453 => ChainId::Base(id),
-            42161 => ChainId::Arbitrum(id),
-            _ => panic!("Unsupported chain id: {}", id),
+            1 => Ok(ChainId::Ethereum(id)),
+            10 => Ok(ChainId::Optimism(id)),
+            56 => Ok(ChainId::BinanceSmartChain(id)),
+            8453 => Ok(ChainId::Base(id)),
+            42161 => Ok(ChainId::Arbitrum(id)),
+            _ => Err(anyhow::anyhow!("Unsupported chain id: {}", id)),
         }
     }
 
@@ -57,6 +70,11 @@ impl ChainId {
         }
     }
 
+    /// The native currency of the chain
+    pub fn native_currency(&self) -> defi::currency::native::NativeCurrency {
+        defi::currency::native::NativeCurrency::from_chain_id(self.id())
+    }
+
     pub fn name(&self) -> &str {
         match self {
             ChainId::Ethereum(_) => "Ethereum",
@@ -66,4 +84,96 @@ impl ChainId {
             ChainId::Arbitrum(_) => "Arbitrum",
         }
     }
+
+    /// The average block time in seconds
+    pub fn average_block_secs(&self) -> f64 {
+        match self {
+            ChainId::Ethereum(_) => 12.0,
+            ChainId::BinanceSmartChain(_) => 3.0,
+            ChainId::Optimism(_) | ChainId::Base(_) => 2.0,
+            ChainId::Arbitrum(_) => 0.25,
+        }
+    }
+
+    /// The average number of blocks in an hour
+    pub fn blocks_per_hour(&self) -> u64 {
+        (3600.0 / self.average_block_secs()) as u64
+    }
+
+    /// The average number of blocks in a day
+    pub fn blocks_per_day(&self) -> u64 {
+        self.blocks_per_hour() * 24
+    }
+
+    /// WETH, None on chains where ETH is not the native currency
+    pub fn weth(&self) -> Option<Address> {
+        match self {
+            ChainId::Ethereum(_) => Some(address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")),
+            ChainId::Optimism(_) | ChainId::Base(_) => {
+                Some(address!("4200000000000000000000000000000000000006"))
+            }
+            ChainId::Arbitrum(_) => Some(address!("82aF49447D8a07e3bd95BD0d56f35241523fBab1")),
+            ChainId::BinanceSmartChain(_) => None,
+        }
+    }
+
+    /// The wrapped native currency (WETH or WBNB)
+    pub fn wrapped_native(&self) -> Address {
+        match self {
+            ChainId::BinanceSmartChain(_) => address!("bb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c"),
+            // every other chain has WETH
+            _ => self.weth().unwrap_or_default(),
+        }
+    }
+
+    pub fn usdc(&self) -> Address {
+        match self {
+            ChainId::Ethereum(_) => address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+            // USDC.e (Bridged from Ethereum)
+            ChainId::Optimism(_) => address!("7F5c764cBc14f9669B88837ca1490cCa17c31607"),
+            ChainId::BinanceSmartChain(_) => address!("8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d"),
+            ChainId::Base(_) => address!("833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"),
+            // Not Bridged
+            ChainId::Arbitrum(_) => address!("af88d065e77c8cC2239327C5EDb3A432268e5831"),
+        }
+    }
+
+    /// USDT, None on Base
+    pub fn usdt(&self) -> Option<Address> {
+        match self {
+            ChainId::Ethereum(_) => Some(address!("dAC17F958D2ee523a2206206994597C13D831ec7")),
+            ChainId::Optimism(_) => Some(address!("94b008aA00579c1307B0EF2c499aD98a8ce58e58")),
+            ChainId::BinanceSmartChain(_) => Some(address!("55d398326f99059fF775485246999027B3197955")),
+            ChainId::Base(_) => None,
+            ChainId::Arbitrum(_) => Some(address!("Fd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9")),
+        }
+    }
+
+    pub fn dai(&self) -> Address {
+        match self {
+            ChainId::Ethereum(_) => address!("6B175474E89094C44Da98b954EedeAC495271d0F"),
+            ChainId::Optimism(_) | ChainId::Arbitrum(_) => {
+                address!("DA10009cBd5D07dd0CeCc66161FC93D7c9000da1")
+            }
+            ChainId::BinanceSmartChain(_) => address!("1AF3F329e8BE154074D8769D1FFa4eE058B1DBc3"),
+            ChainId::Base(_) => address!("50c5725949A6F0c72E6C4a641F24049A917DB0Cb"),
+        }
+    }
+
+    /// The USD stablecoins available on the chain
+    pub fn stablecoins(&self) -> Vec<Address> {
+        let mut stables = vec![self.usdc()];
+        stables.extend(self.usdt());
+        stables.push(self.dai());
+        stables
+    }
+
+    /// The Uniswap contracts on the chain
+    pub fn uniswap(&self) -> UniswapDeployment {
+        UniswapDeployment::for_chain(self.id()).expect("Every supported chain has a Uniswap deployment")
+    }
+
+    pub fn universal_router(&self) -> Address {
+        self.uniswap().universal_router
+    }
 }
\ No newline at end of file
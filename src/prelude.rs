@@ -2,5 +2,5 @@ pub use crate::defi::amm::uniswap::{v2::*, v3::UniswapV3Pool};
 pub use crate::defi::currency::erc20::{ERC20Token, TokenKind};
 
 pub use crate::revm_utils::{dummy_account::*, fork_db::fork_factory::ForkFactory, utils::*};
-pub use crate::utils::{BlockTime, logs::query::get_logs_for};
+pub use crate::utils::{BlockTime, config::Config, logs::query::{fold_logs_for, get_logs_for, get_logs_with_config}};
 pub use crate::defi::utils::common_addr::*;
\ No newline at end of file
@@ -0,0 +1,219 @@
+//! An alternative to [ForkFactory](super::fork_db::fork_factory::ForkFactory) that executes against an Anvil node
+//!
+//! Instead of executing locally with revm, state is modified through the `anvil_*` RPC methods
+//! and transactions are validated by the node itself.
+
+use std::marker::PhantomData;
+
+use alloy_contract::private::Ethereum;
+use alloy_node_bindings::{Anvil, AnvilInstance};
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+use revm::primitives::AccountInfo;
+
+/// Spawn a new Anvil instance that forks from `fork_url`
+///
+/// The instance is killed when dropped
+///
+/// ## Arguments
+///
+/// * `fork_url` - The RPC endpoint to fork from
+/// * `fork_block` - The block to fork from, if None the latest block is used
+pub fn spawn_anvil(fork_url: &str, fork_block: Option<u64>) -> Result<AnvilInstance, anyhow::Error> {
+    let mut anvil = Anvil::new().fork(fork_url);
+
+    if let Some(block) = fork_block {
+        anvil = anvil.fork_block_number(block);
+    }
+
+    anvil
+        .try_spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to spawn anvil: {}", e))
+}
+
+/// A fork backed by an Anvil node
+///
+/// Can own the [AnvilInstance] it talks to (see [spawn_anvil]) or attach to an already running node
+pub struct AnvilFork<T, P> {
+    client: P,
+    instance: Option<AnvilInstance>,
+    transport: PhantomData<T>,
+}
+
+impl<T, P> AnvilFork<T, P>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone,
+{
+    /// Attach to an already running Anvil node
+    pub fn attach(client: P) -> Self {
+        Self {
+            client,
+            instance: None,
+            transport: PhantomData,
+        }
+    }
+
+    /// Use a spawned [AnvilInstance], it is killed when this fork is dropped
+    ///
+    /// The `client` must be connected to the `instance` endpoint
+    pub fn with_instance(client: P, instance: AnvilInstance) -> Self {
+        Self {
+            client,
+            instance: Some(instance),
+            transport: PhantomData,
+        }
+    }
+
+    /// The client connected to the node
+    pub fn client(&self) -> P {
+        self.client.clone()
+    }
+
+    /// The HTTP endpoint of the owned instance
+    pub fn endpoint(&self) -> Option<String> {
+        self.instance.as_ref().map(|i| i.endpoint())
+    }
+
+    /// Insert storage into the node
+    pub async fn insert_account_storage(
+        &self,
+        address: Address,
+        slot: U256,
+        value: U256,
+    ) -> Result<(), anyhow::Error> {
+        self.client
+            .raw_request::<_, bool>("anvil_setStorageAt".into(), set_storage_params(address, slot, value))
+            .await?;
+        Ok(())
+    }
+
+    /// Insert account basic info (balance, nonce and code) into the node
+    pub async fn insert_account_info(
+        &self,
+        address: Address,
+        info: AccountInfo,
+    ) -> Result<(), anyhow::Error> {
+        self.set_balance(address, info.balance).await?;
+        self.set_nonce(address, info.nonce).await?;
+
+        if let Some(code) = info.code {
+            self.set_code(address, code.original_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Fund an account with an ERC20 token
+    ///
+    /// ## Arguments
+    ///
+    /// * `token` - The token address
+    /// * `owner` - The account to fund
+    /// * `slot` - The balance mapping slot of the token (see [DummyAccount::find_balance_slot](super::dummy_account::DummyAccount::find_balance_slot))
+    /// * `amount` - The new balance
+    pub async fn insert_erc20_balance(
+        &self,
+        token: Address,
+        owner: Address,
+        slot: U256,
+        amount: U256,
+    ) -> Result<(), anyhow::Error> {
+        let mut data = [0u8; 64];
+        data[12..32].copy_from_slice(owner.as_slice());
+        data[32..].copy_from_slice(&slot.to_be_bytes::<32>());
+        let slot = U256::from_be_bytes(keccak256(data).0);
+
+        self.insert_account_storage(token, slot, amount).await
+    }
+
+    pub async fn set_balance(&self, address: Address, balance: U256) -> Result<(), anyhow::Error> {
+        self.client
+            .raw_request::<_, ()>("anvil_setBalance".into(), (address, balance))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_nonce(&self, address: Address, nonce: u64) -> Result<(), anyhow::Error> {
+        self.client
+            .raw_request::<_, ()>("anvil_setNonce".into(), (address, U256::from(nonce)))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_code(&self, address: Address, code: Bytes) -> Result<(), anyhow::Error> {
+        self.client
+            .raw_request::<_, ()>("anvil_setCode".into(), (address, code))
+            .await?;
+        Ok(())
+    }
+
+    /// Send transactions from `address` without its private key
+    pub async fn impersonate(&self, address: Address) -> Result<(), anyhow::Error> {
+        self.client
+            .raw_request::<_, ()>("anvil_impersonateAccount".into(), (address,))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn stop_impersonating(&self, address: Address) -> Result<(), anyhow::Error> {
+        self.client
+            .raw_request::<_, ()>("anvil_stopImpersonatingAccount".into(), (address,))
+            .await?;
+        Ok(())
+    }
+
+    /// Snapshot the state of the node, returns the snapshot id
+    pub async fn snapshot(&self) -> Result<U256, anyhow::Error> {
+        let id = self
+            .client
+            .raw_request::<_, U256>("evm_snapshot".into(), ())
+            .await?;
+        Ok(id)
+    }
+
+    /// Revert the state of the node to a snapshot
+    ///
+    /// A snapshot can only be reverted once
+    pub async fn revert(&self, id: U256) -> Result<bool, anyhow::Error> {
+        let reverted = self
+            .client
+            .raw_request::<_, bool>("evm_revert".into(), (id,))
+            .await?;
+        Ok(reverted)
+    }
+
+    /// Mine `blocks` blocks
+    pub async fn mine(&self, blocks: u64) -> Result<(), anyhow::Error> {
+        self.client
+            .raw_request::<_, ()>("anvil_mine".into(), (U256::from(blocks),))
+            .await?;
+        Ok(())
+    }
+}
+
+/// The params of `anvil_setStorageAt`, the value must be a 32 bytes hex string
+fn set_storage_params(address: Address, slot: U256, value: U256) -> (Address, U256, B256) {
+    (address, slot, B256::from(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_storage_params() {
+        let params = set_storage_params(Address::ZERO, U256::from(1), U256::from(2));
+        let json = serde_json::to_value(params).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!([
+                "0x0000000000000000000000000000000000000000",
+                "0x1",
+                "0x0000000000000000000000000000000000000000000000000000000000000002"
+            ])
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Storage layouts of ERC20 balances and allowances
+//!
+//! [DummyAccount::insert](super::dummy_account::DummyAccount::insert) finds the balance mapping by
+//! writing to the first slots of the token and checking `balanceOf`. That fails for tokens that
+//! don't keep a Solidity mapping in a low slot, so the layouts of some known tokens are listed here,
+//! and the common non-standard layouts are tried when the search finds nothing.
+//!
+//! The allowances are searched the same way, see [AllowanceLayout::candidates]
+
+use alloy_primitives::{address, keccak256, uint, Address, U256};
+
+/// Where the balance of an owner is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceLayout {
+    /// A Solidity `mapping(address => uint256)` at the given slot, `keccak256(owner . slot)`
+    Solidity(U256),
+
+    /// A Vyper `HashMap[address, uint256]` at the given slot, `keccak256(slot . owner)`
+    Vyper(U256),
+
+    /// The ERC20 of Solady, `keccak256(owner . 0x0000000000000000 . 0x87a211a2)`
+    Solady,
+}
+
+/// Where the allowance of an owner to a spender is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllowanceLayout {
+    /// A Solidity `mapping(address => mapping(address => uint256))` at the given slot
+    Solidity(U256),
+
+    /// A Vyper `HashMap[address, HashMap[address, uint256]]` at the given slot
+    Vyper(U256),
+
+    /// The ERC20 of Solady, `keccak256(owner . 0x0000000000000000 . 0x7f5e9f20 . spender)`
+    Solady,
+}
+
+/// The balance layout of a token on a chain
+#[derive(Debug, Clone, Copy)]
+pub struct KnownBalanceSlot {
+    pub chain_id: u64,
+    pub token: Address,
+    pub layout: BalanceLayout,
+}
+
+/// The `ERC20Storage` namespace of the upgradeable OpenZeppelin v5 ERC20 (ERC-7201),
+/// the balances are the first field
+pub const OZ_ERC20_STORAGE: U256 =
+    uint!(0x52c63247e1f47db19d5ce0460030c497f067ca4cebf71ba98eeadabe20bace00_U256);
+
+/// `_BALANCE_SLOT_SEED` of the Solady ERC20
+const SOLADY_BALANCE_SLOT_SEED: [u8; 4] = [0x87, 0xa2, 0x11, 0xa2];
+
+/// `_ALLOWANCE_SLOT_SEED` of the Solady ERC20
+const SOLADY_ALLOWANCE_SLOT_SEED: [u8; 4] = [0x7f, 0x5e, 0x9f, 0x20];
+
+/// The balance layouts of known tokens
+///
+/// The FiatToken (USDC) packs the blacklist flag in the highest bit of the balance,
+/// so any amount below 2^255 is a valid balance
+pub const KNOWN_BALANCE_SLOTS: &[KnownBalanceSlot] = &[
+    // Ethereum
+    known(1, address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"), BalanceLayout::Solidity(uint!(9_U256))), // USDC
+    known(1, address!("dAC17F958D2ee523a2206206994597C13D831ec7"), BalanceLayout::Solidity(uint!(2_U256))), // USDT
+    known(1, address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"), BalanceLayout::Solidity(uint!(3_U256))), // WETH
+    known(1, address!("6B175474E89094C44Da98b954EedeAC495271d0F"), BalanceLayout::Solidity(uint!(2_U256))), // DAI
+    known(1, address!("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599"), BalanceLayout::Solidity(uint!(0_U256))), // WBTC
+    // Optimism
+    known(10, address!("0b2C639c533813f4Aa9D7837CAf62653d097Ff85"), BalanceLayout::Solidity(uint!(9_U256))), // USDC
+    // Base
+    known(8453, address!("833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"), BalanceLayout::Solidity(uint!(9_U256))), // USDC
+    // Arbitrum
+    known(42161, address!("af88d065e77c8cC2239327C5EDb3A432268e5831"), BalanceLayout::Solidity(uint!(9_U256))), // USDC
+];
+
+const fn known(chain_id: u64, token: Address, layout: BalanceLayout) -> KnownBalanceSlot {
+    KnownBalanceSlot {
+        chain_id,
+        token,
+        layout,
+    }
+}
+
+/// The layout of a known token
+pub fn known_layout(chain_id: u64, token: Address) -> Option<BalanceLayout> {
+    KNOWN_BALANCE_SLOTS
+        .iter()
+        .find(|k| k.chain_id == chain_id && k.token == token)
+        .map(|k| k.layout)
+}
+
+/// The non-standard layouts tried when the search of the Solidity mapping finds nothing
+pub fn fallback_layouts() -> Vec<BalanceLayout> {
+    let mut layouts = vec![BalanceLayout::Solady, BalanceLayout::Solidity(OZ_ERC20_STORAGE)];
+    layouts.extend((0..20).map(|slot| BalanceLayout::Vyper(U256::from(slot))));
+    layouts
+}
+
+impl BalanceLayout {
+    /// The storage slot of the balance of `owner`
+    pub fn storage_slot(&self, owner: Address) -> U256 {
+        let mut data = Vec::with_capacity(64);
+        match self {
+            BalanceLayout::Solidity(slot) => {
+                data.extend_from_slice(owner.into_word().as_slice());
+                data.extend_from_slice(&slot.to_be_bytes::<32>());
+            }
+            BalanceLayout::Vyper(slot) => {
+                data.extend_from_slice(&slot.to_be_bytes::<32>());
+                data.extend_from_slice(owner.into_word().as_slice());
+            }
+            BalanceLayout::Solady => {
+                data.extend_from_slice(owner.as_slice());
+                data.extend_from_slice(&[0u8; 8]);
+                data.extend_from_slice(&SOLADY_BALANCE_SLOT_SEED);
+            }
+        }
+        U256::from_be_bytes(keccak256(&data).0)
+    }
+}
+
+impl AllowanceLayout {
+    /// The storage slot of the allowance of `owner` to `spender`
+    pub fn storage_slot(&self, owner: Address, spender: Address) -> U256 {
+        match self {
+            AllowanceLayout::Solidity(slot) => {
+                let inner = BalanceLayout::Solidity(*slot).storage_slot(owner);
+                BalanceLayout::Solidity(inner).storage_slot(spender)
+            }
+            AllowanceLayout::Vyper(slot) => {
+                let inner = BalanceLayout::Vyper(*slot).storage_slot(owner);
+                BalanceLayout::Vyper(inner).storage_slot(spender)
+            }
+            AllowanceLayout::Solady => {
+                let mut data = Vec::with_capacity(52);
+                data.extend_from_slice(owner.as_slice());
+                data.extend_from_slice(&[0u8; 8]);
+                data.extend_from_slice(&SOLADY_ALLOWANCE_SLOT_SEED);
+                data.extend_from_slice(spender.as_slice());
+                U256::from_be_bytes(keccak256(&data).0)
+            }
+        }
+    }
+
+    /// The layouts searched for the allowance mapping, the Solidity slots first
+    pub fn candidates() -> Vec<AllowanceLayout> {
+        let mut layouts: Vec<AllowanceLayout> =
+            (0..200).map(|slot| AllowanceLayout::Solidity(U256::from(slot))).collect();
+        layouts.push(AllowanceLayout::Solady);
+        // the second field of the OpenZeppelin v5 ERC20Storage
+        layouts.push(AllowanceLayout::Solidity(OZ_ERC20_STORAGE + U256::from(1)));
+        layouts.extend((0..20).map(|slot| AllowanceLayout::Vyper(U256::from(slot))));
+        layouts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solidity_slot_matches_mapping_hash() {
+        let owner = address!("000000000000000000000000000000000000dEaD");
+        let mut data = [0u8; 64];
+        data[12..32].copy_from_slice(owner.as_slice());
+        data[63] = 9;
+        let expected = U256::from_be_bytes(keccak256(data).0);
+
+        assert_eq!(BalanceLayout::Solidity(U256::from(9)).storage_slot(owner), expected);
+        assert_ne!(BalanceLayout::Vyper(U256::from(9)).storage_slot(owner), expected);
+    }
+}
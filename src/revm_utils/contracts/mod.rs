@@ -0,0 +1,3 @@
+//! Helper contracts that are not deployed on chain and are inserted into a fork
+
+pub mod swap_router;
@@ -1,5 +1,9 @@
+use crate::abi::permit2::{allowance_slot, pack_allowance, PERMIT2};
 use crate::defi::currency::erc20::ERC20Token;
-use alloy_primitives::{keccak256, Address, U256};
+use alloy_primitives::{
+    aliases::{U160, U48},
+    Address, U256,
+};
 use alloy_signer_local::PrivateKeySigner;
 use revm::primitives::{AccountInfo, Bytecode, B256};
 
@@ -8,9 +12,10 @@ use alloy_provider::Provider;
 use alloy_transport::Transport;
 
 use super::{
+    balance_slots::{fallback_layouts, known_layout, AllowanceLayout, BalanceLayout},
     fork_db::fork_factory::ForkFactory,
     utils::new_evm,
-    simulate::erc20_balance
+    simulate::{erc20_allowance, erc20_balance},
 };
 
 #[derive(Clone, Debug)]
@@ -39,6 +44,15 @@ impl DummyAccount {
         }
     }
 
+    /// An account at the address of `signer`, so it can sign permits
+    pub fn from_signer(account_type: AccountType, balance: U256, signer: &PrivateKeySigner) -> Self {
+        Self {
+            account_type,
+            balance,
+            address: signer.address(),
+        }
+    }
+
     /// This function will try to find the storage slot of a token
     pub fn find_balance_slot<T, P>(
         &self,
@@ -47,8 +61,8 @@ impl DummyAccount {
         amount: U256,
     ) -> Result<Option<U256>, anyhow::Error>
     where
-        T: Transport + Clone + Unpin,
-        P: Provider<T, Ethereum> + Clone + 'static + Unpin,
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
     {
         if amount == U256::ZERO {
             return Ok(Some(U256::ZERO))
@@ -81,9 +95,43 @@ impl DummyAccount {
         Ok(balance_slot)
     }
 
+    /// Find the balance layout of a token among the non-standard ones, see [fallback_layouts]
+    pub fn find_balance_layout<T, P>(
+        &self,
+        fork_factory: &mut ForkFactory<T, P>,
+        token: ERC20Token,
+        amount: U256,
+    ) -> Result<Option<BalanceLayout>, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
+    {
+        if amount == U256::ZERO {
+            return Ok(None);
+        }
+
+        for layout in fallback_layouts() {
+            let mut cloned_fork_factory = fork_factory.clone();
+            self.insert_with_layout(&mut cloned_fork_factory, layout, token.address, amount)?;
+
+            let db = cloned_fork_factory.new_sandbox_fork();
+            let mut evm = new_evm(db, None);
+            let balance = erc20_balance(&mut evm, token.clone(), self.address)?;
+
+            if balance > U256::ZERO {
+                return Ok(Some(layout));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Insert this dummy account into the fork enviroment
     ///
     /// If you don't know the storage slot of the token you want to fund the account with, use this function
+    ///
+    /// The layout of known tokens is taken from [KNOWN_BALANCE_SLOTS](super::balance_slots::KNOWN_BALANCE_SLOTS),
+    /// otherwise the Solidity mapping is searched and then the other common layouts
     pub fn insert<T, P>(
         &self,
         fork_factory: &mut ForkFactory<T, P>,
@@ -91,18 +139,46 @@ impl DummyAccount {
         amount: U256,
     ) -> Result<(), anyhow::Error>
     where
-        T: Transport + Clone + Unpin,
-        P: Provider<T, Ethereum> + Clone + 'static + Unpin,
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
     {
-        let slot = self.find_balance_slot(fork_factory, token.clone(), amount)?;
-        if let Some(slot) = slot {
-            self.insert_with_slot(fork_factory, slot, token.address, amount)
-        } else {
-            Err(anyhow::anyhow!(
-                "Balance Storage Slot not found for: {}",
-                token.symbol
-            ))
+        if let Some(layout) = known_layout(token.chain_id, token.address) {
+            return self.insert_with_layout(fork_factory, layout, token.address, amount);
+        }
+
+        if let Some(slot) = self.find_balance_slot(fork_factory, token.clone(), amount)? {
+            return self.insert_with_slot(fork_factory, slot, token.address, amount);
+        }
+
+        if let Some(layout) = self.find_balance_layout(fork_factory, token.clone(), amount)? {
+            return self.insert_with_layout(fork_factory, layout, token.address, amount);
         }
+
+        Err(anyhow::anyhow!(
+            "Balance Storage Slot not found for: {}",
+            token.symbol
+        ))
+    }
+
+    /// Insert this dummy account into the fork enviroment without funding it with any token
+    pub fn insert_account<T, P>(&self, fork_factory: &mut ForkFactory<T, P>)
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
+    {
+        let code = match &self.account_type {
+            AccountType::EOA => Bytecode::default(),
+            AccountType::Contract(code) => code.clone(),
+        };
+
+        let account_info = AccountInfo {
+            balance: self.balance,
+            nonce: 0,
+            code_hash: B256::default(),
+            code: Some(code),
+        };
+
+        fork_factory.insert_account_info(self.address, account_info);
     }
 
     /// Insert this dummy account into the fork enviroment
@@ -116,47 +192,144 @@ impl DummyAccount {
         amount: U256,
     ) -> Result<(), anyhow::Error>
     where
-        T: Transport + Clone + Unpin,
-        P: Provider<T, Ethereum> + Clone + 'static + Unpin,
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
     {
-        let code = match &self.account_type {
-            AccountType::EOA => Bytecode::default(),
-            AccountType::Contract(code) => code.clone(),
-        };
+        self.insert_with_layout(fork_factory, BalanceLayout::Solidity(slot), token, amount)
+    }
 
-        let eth_balance = self.balance.clone();
-        let address = self.address.clone();
+    /// Insert this dummy account into the fork enviroment with the balance stored at the given [BalanceLayout]
+    pub fn insert_with_layout<T, P>(
+        &self,
+        fork_factory: &mut ForkFactory<T, P>,
+        layout: BalanceLayout,
+        token: Address,
+        amount: U256,
+    ) -> Result<(), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
+    {
+        self.insert_account(fork_factory);
 
-        let account_info = AccountInfo {
-            balance: eth_balance,
-            nonce: 0,
-            code_hash: B256::default(),
-            code: Some(code),
-        };
+        let slot = layout.storage_slot(self.address);
+        if let Err(e) = fork_factory.insert_account_storage(token, slot, amount) {
+            return Err(anyhow::anyhow!("Failed to insert account storage: {}", e));
+        }
 
-        fork_factory.insert_account_info(address.clone(), account_info);
+        Ok(())
+    }
 
-        let addr_padded = pad_left(address.to_vec(), 32);
-        let slot = slot.to_be_bytes_vec();
+    /// Find the storage layout of the allowance mapping of a token, see [AllowanceLayout::candidates]
+    pub fn find_allowance_layout<T, P>(
+        &self,
+        fork_factory: &mut ForkFactory<T, P>,
+        token: ERC20Token,
+        spender: Address,
+    ) -> Result<Option<AllowanceLayout>, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
+    {
+        // keep the orignal fork factory intact
+        let mut cloned_fork_factory = fork_factory.clone();
 
-        let data = [&addr_padded, &slot]
-            .iter()
-            .flat_map(|x| x.iter().copied())
-            .collect::<Vec<u8>>();
-        let slot_hash = keccak256(&data);
-        let slot: U256 =
-            U256::from_be_bytes(slot_hash.try_into().expect("Slot Hash must be 32 bytes"));
+        for layout in AllowanceLayout::candidates() {
+            let slot = layout.storage_slot(self.address, spender);
+            if let Err(e) =
+                cloned_fork_factory.insert_account_storage(token.address, slot, U256::from(1))
+            {
+                return Err(anyhow::anyhow!("Failed to insert account storage: {}", e));
+            }
 
+            let db = cloned_fork_factory.new_sandbox_fork();
+            let mut evm = new_evm(db, None);
+            let allowance = erc20_allowance(&mut evm, token.clone(), self.address, spender)?;
+
+            if allowance > U256::ZERO {
+                return Ok(Some(layout));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Approve `spender` to spend `amount` of `token` by writing the allowance directly to the storage of the token
+    ///
+    /// Skips the `approve` transaction, the account must be inserted separately (eg. with [Self::insert])
+    pub fn insert_allowance<T, P>(
+        &self,
+        fork_factory: &mut ForkFactory<T, P>,
+        token: ERC20Token,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
+    {
+        let layout = self
+            .find_allowance_layout(fork_factory, token.clone(), spender)?
+            .ok_or_else(|| anyhow::anyhow!("Allowance Storage Slot not found for: {}", token.symbol))?;
+
+        self.insert_allowance_with_layout(fork_factory, layout, token.address, spender, amount)
+    }
+
+    /// Same as [Self::insert_allowance] with a known [AllowanceLayout]
+    ///
+    /// Use it when inserting many accounts, the layout is the same for every owner of a token
+    pub fn insert_allowance_with_layout<T, P>(
+        &self,
+        fork_factory: &mut ForkFactory<T, P>,
+        layout: AllowanceLayout,
+        token: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
+    {
+        let slot = layout.storage_slot(self.address, spender);
         if let Err(e) = fork_factory.insert_account_storage(token, slot, amount) {
             return Err(anyhow::anyhow!("Failed to insert account storage: {}", e));
         }
 
         Ok(())
     }
-}
 
-fn pad_left(vec: Vec<u8>, full_len: usize) -> Vec<u8> {
-    let mut padded = vec![0u8; full_len - vec.len()];
-    padded.extend(vec);
-    padded
-}
\ No newline at end of file
+    /// Approve `spender` to pull `amount` of `token` through [Permit2](crate::abi::permit2) by writing the storage
+    ///
+    /// Writes an unlimited token allowance to Permit2 and the Permit2 allowance, with nonce 0
+    ///
+    /// ## Arguments
+    ///
+    /// * `token` - The token to approve
+    /// * `spender` - The address that pulls the tokens through Permit2 (eg. the Universal Router)
+    /// * `amount` - The Permit2 allowance, must fit in a uint160
+    /// * `expiration` - The timestamp the allowance expires at
+    pub fn insert_permit2_allowance<T, P>(
+        &self,
+        fork_factory: &mut ForkFactory<T, P>,
+        token: ERC20Token,
+        spender: Address,
+        amount: U256,
+        expiration: u64,
+    ) -> Result<(), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
+    {
+        self.insert_allowance(fork_factory, token.clone(), PERMIT2, U256::MAX)?;
+
+        let amount = U160::uint_try_from(amount)
+            .map_err(|_| anyhow::anyhow!("Permit2 amount {} does not fit in uint160", amount))?;
+        let slot = allowance_slot(self.address, token.address, spender);
+        let value = pack_allowance(amount, U48::saturating_from(expiration), U48::ZERO);
+        if let Err(e) = fork_factory.insert_account_storage(PERMIT2, slot, value) {
+            return Err(anyhow::anyhow!("Failed to insert account storage: {}", e));
+        }
+
+        Ok(())
+    }
+}
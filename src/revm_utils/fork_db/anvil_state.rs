@@ -0,0 +1,146 @@
+//! Export and import the local state of a fork in the Anvil state format
+//!
+//! A state prepared with [ForkFactory](super::fork_factory::ForkFactory) and
+//! [DummyAccount](crate::revm_utils::dummy_account::DummyAccount) can be dumped to a JSON file and:
+//!
+//! - loaded in Anvil with `anvil --load-state state.json`
+//! - loaded in a Foundry test with `vm.loadAllocs("allocs.json")`, see [AnvilState::to_allocs_json]
+//!
+//! The other way around, a file written by `anvil --dump-state` can be loaded into a fork with [AnvilState::apply_to].
+//!
+//! Only the accounts and the storage loaded or written locally are exported, not the whole chain state.
+//! The blocks and transactions of an Anvil dump are ignored
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use revm::{
+    db::{AccountState, CacheDB, EmptyDB},
+    primitives::{AccountInfo, Bytecode},
+};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+/// An account of an [AnvilState]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnvilAccount {
+    pub nonce: u64,
+    pub balance: U256,
+    pub code: Bytes,
+    pub storage: BTreeMap<U256, U256>,
+}
+
+/// The accounts of an `anvil --dump-state` file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnvilState {
+    pub accounts: BTreeMap<Address, AnvilAccount>,
+}
+
+impl AnvilState {
+    /// Collect the accounts of a database, the accounts known not to exist are skipped
+    pub fn from_db(db: &CacheDB<EmptyDB>) -> Self {
+        let mut accounts = BTreeMap::new();
+
+        for (address, account) in &db.accounts {
+            if matches!(account.account_state, AccountState::NotExisting) {
+                continue;
+            }
+
+            let code = account
+                .info
+                .code
+                .clone()
+                .or_else(|| db.contracts.get(&account.info.code_hash).cloned())
+                .map(|code| code.original_bytes())
+                .unwrap_or_default();
+
+            accounts.insert(
+                *address,
+                AnvilAccount {
+                    nonce: account.info.nonce,
+                    balance: account.info.balance,
+                    code,
+                    storage: account.storage.iter().map(|(k, v)| (*k, *v)).collect(),
+                },
+            );
+        }
+
+        Self { accounts }
+    }
+
+    /// Insert the accounts into a database
+    ///
+    /// The balance, nonce and code replace the existing ones, the slots are written on top of the existing storage
+    pub fn apply_to(&self, db: &mut CacheDB<EmptyDB>) {
+        for (address, account) in &self.accounts {
+            let code = Bytecode::new_raw(account.code.clone());
+            let info = AccountInfo::new(account.balance, account.nonce, code.hash_slow(), code);
+            db.insert_account_info(*address, info);
+
+            for (slot, value) in &account.storage {
+                // the account is in the db, so this never falls back to EmptyDB
+                db.insert_account_storage(*address, *slot, *value).unwrap();
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parse an Anvil state, the fields other than `accounts` are ignored
+    pub fn from_json(json: &str) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The accounts in the genesis alloc format read by Foundry's `vm.loadAllocs`
+    pub fn to_allocs_json(&self) -> Result<String, anyhow::Error> {
+        let allocs: BTreeMap<Address, serde_json::Value> = self
+            .accounts
+            .iter()
+            .map(|(address, account)| {
+                let storage: BTreeMap<B256, B256> = account
+                    .storage
+                    .iter()
+                    .map(|(slot, value)| (B256::from(*slot), B256::from(*value)))
+                    .collect();
+                let alloc = serde_json::json!({
+                    "nonce": format!("{:#x}", account.nonce),
+                    "balance": account.balance,
+                    "code": account.code,
+                    "storage": storage,
+                });
+                (*address, alloc)
+            })
+            .collect();
+
+        Ok(serde_json::to_string_pretty(&allocs)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, bytes};
+
+    #[test]
+    fn test_db_round_trip() {
+        let contract = address!("000000000000000000000000000000000000c0de");
+        let mut state = AnvilState::default();
+        state.accounts.insert(
+            contract,
+            AnvilAccount {
+                nonce: 1,
+                balance: U256::from(100),
+                code: bytes!("6000"),
+                storage: BTreeMap::from([(U256::from(3), U256::from(42))]),
+            },
+        );
+
+        let parsed = AnvilState::from_json(&state.to_json().unwrap()).unwrap();
+        assert_eq!(parsed, state);
+
+        let mut db = CacheDB::new(EmptyDB::default());
+        parsed.apply_to(&mut db);
+        assert_eq!(AnvilState::from_db(&db), state);
+    }
+}
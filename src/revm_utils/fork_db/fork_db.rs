@@ -11,6 +11,7 @@ use revm::{
 };
 
 use super::{
+    anvil_state::AnvilState,
     database_error::{DatabaseError, DatabaseResult},
     global_backend::BackendFetchRequest,
 };
@@ -27,6 +28,38 @@ impl ForkDB {
         Self { backend, db }
     }
 
+    /// Insert storage into the local db
+    ///
+    /// If the account is not yet loaded, its basic info is fetched first
+    pub fn insert_account_storage(
+        &mut self,
+        address: Address,
+        slot: U256,
+        value: U256,
+    ) -> DatabaseResult<()> {
+        if !self.db.accounts.contains_key(&address) {
+            if let Some(info) = self.do_get_basic(address)? {
+                self.db.insert_account_info(address, info);
+            }
+        }
+
+        self.db
+            .insert_account_storage(address, slot, value)
+            .unwrap();
+
+        Ok(())
+    }
+
+    /// The accounts loaded or written so far, see [AnvilState]
+    pub fn dump_state(&self) -> AnvilState {
+        AnvilState::from_db(&self.db)
+    }
+
+    /// Insert the accounts of an [AnvilState] into the local db
+    pub fn load_state(&mut self, state: &AnvilState) {
+        state.apply_to(&mut self.db);
+    }
+
     fn do_get_basic(&self, address: Address) -> DatabaseResult<Option<AccountInfo>> {
         tokio::task::block_in_place(|| {
             let (sender, rx) = oneshot_channel();
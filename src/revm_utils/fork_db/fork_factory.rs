@@ -1,18 +1,24 @@
 use std::marker::PhantomData;
 use std::sync::mpsc::channel as oneshot_channel;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 
 use alloy_contract::private::Ethereum;
 use alloy_provider::Provider;
 use alloy_transport::Transport;
 
 use super::{
+    anvil_state::AnvilState,
     database_error::DatabaseResult,
     fork_db::ForkDB,
-    global_backend::{BackendFetchRequest, GlobalBackend},
+    global_backend::{BackendFetchRequest, BackendStats, GlobalBackend},
 };
 
 use alloy_rpc_types::eth::BlockId;
-use futures::channel::mpsc::{channel, Sender};
+use futures::channel::{
+    mpsc::{channel, Sender},
+    oneshot,
+};
 use revm::{
     db::{CacheDB, EmptyDB},
     primitives::{AccountInfo, Address as rAddress, U256 as rU256},
@@ -25,14 +31,25 @@ use revm::{
 pub struct ForkFactory<T, P> {
     backend: Sender<BackendFetchRequest>,
     initial_db: CacheDB<EmptyDB>,
+    /// The thread running the backend, shared between the clones of the factory
+    backend_thread: Option<Arc<Mutex<BackendThread>>>,
+    /// The counters of the backend, see [BackendStats]
+    stats: Arc<BackendStats>,
     transport: PhantomData<T>,
     provider: PhantomData<P>,
 }
 
+/// The thread spawned by [ForkFactory::new_sandbox_factory]
+#[derive(Debug)]
+struct BackendThread {
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
 impl<T, P> ForkFactory<T, P>
 where
-    T: Transport + Clone + Unpin,
-    P: Provider<T, Ethereum> + Clone + 'static + Unpin,
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
 {
     // Create a new `ForkFactory` instance
     //
@@ -57,6 +74,8 @@ where
             Self {
                 backend,
                 initial_db,
+                backend_thread: None,
+                stats: handler.stats(),
                 transport: PhantomData,
                 provider: PhantomData,
             },
@@ -76,6 +95,8 @@ where
     }
 
     // Create a new sandbox environment with backend running on own thread
+    //
+    // The thread runs until every clone of the factory and every fork is dropped, or until `close` is called
     pub fn new_sandbox_factory(
         provider: P,
         initial_db: CacheDB<EmptyDB>,
@@ -83,11 +104,13 @@ where
     ) -> Self
 
     {
-        let (shared, handler) = Self::new(provider, initial_db, fork_block);
+        let (mut shared, handler) = Self::new(provider, initial_db, fork_block);
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let handler = handler.with_shutdown(shutdown_rx);
 
         // spawn a light-weight thread with a thread-local async runtime just for
         // sending and receiving data from the remote client
-        let _ = std::thread::Builder::new()
+        let handle = std::thread::Builder::new()
             .name("fork-backend-thread".to_string())
             .spawn(move || {
                 let rt = tokio::runtime::Builder::new_current_thread()
@@ -99,9 +122,56 @@ where
             })
             .expect("failed to spawn backendhandler thread");
 
+        shared.backend_thread = Some(Arc::new(Mutex::new(BackendThread {
+            shutdown: Some(shutdown),
+            handle: Some(handle),
+        })));
+
         shared
     }
 
+    /// Stop the backend thread and wait for it to exit
+    ///
+    /// Affects all the clones of this factory, the forks created from it fail on any data
+    /// that is not already in their cache. Calling it again is a no-op
+    pub fn close(&self) -> Result<(), anyhow::Error> {
+        let Some(thread) = &self.backend_thread else {
+            return Ok(());
+        };
+
+        let mut thread = thread
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Backend thread lock poisoned"))?;
+
+        if let Some(shutdown) = thread.shutdown.take() {
+            // the backend may have already exited on its own
+            let _ = shutdown.send(());
+        }
+
+        if let Some(handle) = thread.handle.take() {
+            handle
+                .join()
+                .map_err(|_| anyhow::anyhow!("Backend thread panicked"))?;
+        }
+
+        Ok(())
+    }
+
+    /// The requests the backend has fetched from the provider so far, shared by all the clones of this factory
+    pub fn stats(&self) -> Arc<BackendStats> {
+        self.stats.clone()
+    }
+
+    /// Is the backend thread still running
+    pub fn is_running(&self) -> bool {
+        self.backend_thread.as_ref().map_or(false, |thread| {
+            thread
+                .lock()
+                .map(|t| t.handle.as_ref().is_some_and(|h| !h.is_finished()))
+                .unwrap_or(false)
+        })
+    }
+
     // Creates new ForkDB that fallsback on this `ForkFactory` instance
     pub fn new_sandbox_fork(&self) -> ForkDB {
         ForkDB::new(self.backend.clone(), self.initial_db.clone())
@@ -139,4 +209,54 @@ where
     pub fn insert_account_info(&mut self, address: rAddress, info: AccountInfo) {
         self.initial_db.insert_account_info(address, info);
     }
+
+    /// The accounts of the initial db, see [AnvilState]
+    pub fn dump_state(&self) -> AnvilState {
+        AnvilState::from_db(&self.initial_db)
+    }
+
+    /// Insert the accounts of an [AnvilState] into the initial db, the forks created afterwards see them
+    pub fn load_state(&mut self, state: &AnvilState) {
+        state.apply_to(&mut self.initial_db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Address;
+    use alloy_provider::ProviderBuilder;
+    use revm::db::{CacheDB, EmptyDB};
+
+    use super::ForkFactory;
+    use crate::defi::amm::uniswap::v3::{lp_provider::*, UniswapV3Pool};
+    use crate::defi::currency::erc20::ERC20Token;
+    use crate::utils::BlockTime;
+
+    fn assert_send<F: Send>(_: &F) {}
+
+    // none of these make a request, they only check that an HTTP provider fits the bounds
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_http_provider() {
+        let client = ProviderBuilder::new().on_http("http://localhost:8545".parse().unwrap());
+
+        let fork_factory =
+            ForkFactory::new_sandbox_factory(client.clone(), CacheDB::new(EmptyDB::default()), None);
+        let _fork_db = fork_factory.new_sandbox_fork();
+        assert!(fork_factory.is_running());
+        assert_eq!(fork_factory.stats().accounts(), 0);
+
+        fork_factory.close().unwrap();
+        assert!(!fork_factory.is_running());
+
+        let pool = UniswapV3Pool::new(
+            1,
+            Address::ZERO,
+            3000,
+            ERC20Token::default(),
+            ERC20Token::default(),
+        );
+        let args = PositionArgs::new(0.0, 0.0, 0.0, 0.0, pool);
+        let fut = simulate_position(client, BlockTime::Block(0), args);
+        assert_send(&fut);
+    }
 }
@@ -11,7 +11,7 @@ use alloy_transport::Transport;
 
 use eyre::Result;
 use futures::{
-    channel::mpsc::Receiver,
+    channel::{mpsc::Receiver, oneshot},
     task::{Context, Poll},
     Future, FutureExt, Stream,
 };
@@ -24,10 +24,18 @@ use std::marker::PhantomData;
 use std::{
     collections::{VecDeque, hash_map::{Entry, HashMap}},
     pin::Pin,
-    sync::{mpsc::Sender as OneshotSender, Arc},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Sender as OneshotSender,
+        Arc,
+    },
 };
 
 use super::database_error::{DatabaseError, DatabaseResult};
+use tracing::trace;
+
+#[cfg(feature = "telemetry")]
+use tracing::Instrument;
 
 // **incoming req and outcoming req handled using revm types
 // all logic internal to this module handled using ethers types (because of provider)
@@ -58,13 +66,53 @@ pub enum BackendFetchRequest {
     BlockHash(u64, BlockHashSender),
 }
 
+/// Counters of the requests the backend has served from the provider
+///
+/// Shared with the [ForkFactory](super::fork_factory::ForkFactory) so they can be read while the backend runs
+/// on its own thread, only the provider fetches are counted, not the cache hits
+#[derive(Debug, Default)]
+pub struct BackendStats {
+    accounts: AtomicU64,
+    storage: AtomicU64,
+    block_hashes: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl BackendStats {
+    /// Number of accounts fetched
+    pub fn accounts(&self) -> u64 {
+        self.accounts.load(Ordering::Relaxed)
+    }
+
+    /// Number of storage slots fetched
+    pub fn storage(&self) -> u64 {
+        self.storage.load(Ordering::Relaxed)
+    }
+
+    /// Number of block hashes fetched
+    pub fn block_hashes(&self) -> u64 {
+        self.block_hashes.load(Ordering::Relaxed)
+    }
+
+    /// Number of failed requests
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 /// Holds db and provdier_db to fallback on so that
 /// we can make rpc calls for missing data
+///
+/// The provider is behind an [Arc] so the backend is [Unpin] whatever the transport (WS, IPC, HTTP)
 pub struct GlobalBackend<T, P> {
     db: CacheDB<EmptyDB>,
     // used to make calls for missing data
-    provider: P,
-    transport: PhantomData<T>,
+    provider: Arc<P>,
+    transport: PhantomData<fn() -> T>,
     block_num: Option<BlockId>,
     /// Requests currently in progress
     pending_requests: Vec<FetchRequestFuture<RpcError<TransportErrorKind>>>,
@@ -78,12 +126,16 @@ pub struct GlobalBackend<T, P> {
     incoming: Receiver<BackendFetchRequest>,
     /// unprocessed queued requests
     queued_requests: VecDeque<BackendFetchRequest>,
+    /// Counters of the fetched data
+    stats: Arc<BackendStats>,
+    /// Stops the backend when a value is sent
+    shutdown: Option<oneshot::Receiver<()>>,
 }
 
 impl<T, P> GlobalBackend<T, P>
 where
     T: Transport + Clone,
-    P: Provider<T, Ethereum> + Clone + Unpin + 'static,
+    P: Provider<T, Ethereum> + Clone + 'static,
 {
     // not so elegeant but create sim env from state diffs
     pub fn new(
@@ -94,7 +146,7 @@ where
     ) -> Self {
         Self {
             db: initial_db,
-            provider,
+            provider: Arc::new(provider),
             transport: PhantomData,
             block_num,
             pending_requests: Default::default(),
@@ -103,9 +155,24 @@ where
             block_requests: Default::default(),
             incoming: rx,
             queued_requests: Default::default(),
+            stats: Default::default(),
+            shutdown: None,
         }
     }
 
+    /// Stop the backend when a value is sent on `shutdown`, even if there are still clients connected
+    ///
+    /// Dropping the sender without sending doesn't stop the backend
+    pub fn with_shutdown(mut self, shutdown: oneshot::Receiver<()>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// The counters of the requests served, they keep updating after the backend is moved to its thread
+    pub fn stats(&self) -> Arc<BackendStats> {
+        self.stats.clone()
+    }
+
     /// handle the request in queue in the future.
     ///
     /// We always check:
@@ -156,7 +223,7 @@ where
                 entry.insert(vec![listener]);
                 let provider = self.provider.clone();
                 let block_num = self.block_num.unwrap_or(BlockId::latest());
-                let fut = Box::pin(async move {
+                let fut = async move {
                     let balance = provider
                         .get_balance(address)
                         .block_id(block_num)
@@ -174,8 +241,13 @@ where
                     let resp = tokio::try_join!(balance, nonce, code);
 
                     (resp, address)
-                });
-                self.pending_requests.push(FetchRequestFuture::Basic(fut));
+                };
+
+                #[cfg(feature = "telemetry")]
+                let fut = fut.instrument(tracing::debug_span!("fork_fetch_account", %address));
+
+                self.pending_requests
+                    .push(FetchRequestFuture::Basic(Box::pin(fut)));
             }
         }
     }
@@ -190,15 +262,24 @@ where
                 entry.insert(vec![listener]);
                 let provider = self.provider.clone();
                 let block_num = self.block_num.unwrap_or(BlockId::latest());
-                let fut = Box::pin(async move {
+                let fut = async move {
                     let storage = provider
                         .get_storage_at(address, idx)
                         .block_id(block_num)
                         .await;
 
                     (storage, address, idx)
-                });
-                self.pending_requests.push(FetchRequestFuture::Storage(fut));
+                };
+
+                #[cfg(feature = "telemetry")]
+                let fut = fut.instrument(tracing::debug_span!(
+                    "fork_fetch_storage",
+                    %address,
+                    %idx
+                ));
+
+                self.pending_requests
+                    .push(FetchRequestFuture::Storage(Box::pin(fut)));
             }
         }
     }
@@ -214,7 +295,7 @@ where
                 let provider = self.provider.clone();
                 let block_id = self.block_num.unwrap_or(BlockId::latest());
 
-                let fut = Box::pin(async move {
+                let fut = async move {
                     let block = provider.get_block(block_id, true.into()).await;
 
                     let block_hash = match block {
@@ -230,9 +311,13 @@ where
                     };
 
                     (block_hash, number)
-                });
+                };
+
+                #[cfg(feature = "telemetry")]
+                let fut = fut.instrument(tracing::debug_span!("fork_fetch_block_hash", %number));
+
                 self.pending_requests
-                    .push(FetchRequestFuture::BlockHash(fut));
+                    .push(FetchRequestFuture::BlockHash(Box::pin(fut)));
             }
         }
     }
@@ -240,13 +325,26 @@ where
 
 impl<T, P> Future for GlobalBackend<T, P>
 where
-    T: Transport + Clone + Unpin,
-    P: Provider<T, Ethereum> + Clone + Unpin + 'static,
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
 {
     type Output = ();
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let pin = self.get_mut();
+
+        if let Some(shutdown) = pin.shutdown.as_mut() {
+            match shutdown.poll_unpin(cx) {
+                Poll::Ready(Ok(())) => {
+                    trace!("Backend shut down, stats: {:?}", pin.stats);
+                    return Poll::Ready(());
+                }
+                // the handle was dropped without closing
+                Poll::Ready(Err(_)) => pin.shutdown = None,
+                Poll::Pending => (),
+            }
+        }
+
         loop {
             // Drain queued requests first.
             while let Some(req) = pin.queued_requests.pop_front() {
@@ -260,6 +358,7 @@ where
                         pin.queued_requests.push_back(req);
                     }
                     Poll::Ready(None) => {
+                        trace!("Backend shutting down, stats: {:?}", pin.stats);
                         return Poll::Ready(());
                     }
                     Poll::Pending => {
@@ -278,6 +377,8 @@ where
                             let (balance, nonce, code) = match resp {
                                 Ok(res) => res,
                                 Err(err) => {
+                                    BackendStats::increment(&pin.stats.errors);
+                                    trace!("Failed to fetch account {}: {:?}", addr, err);
                                     let err = Arc::new(eyre::Error::new(err));
                                     if let Some(listeners) = pin.account_requests.remove(&addr) {
                                         listeners.into_iter().for_each(|l| {
@@ -306,6 +407,7 @@ where
                                 code_hash,
                             };
                             pin.db.insert_account_info(addr, acc.clone());
+                            BackendStats::increment(&pin.stats.accounts);
 
                             // notify all listeners
                             if let Some(listeners) = pin.account_requests.remove(&addr) {
@@ -321,6 +423,8 @@ where
                             let value = match resp {
                                 Ok(value) => value,
                                 Err(err) => {
+                                    BackendStats::increment(&pin.stats.errors);
+                                    trace!("Failed to fetch storage {} {}: {:?}", addr, idx, err);
                                     // notify all listeners
                                     let err = Arc::new(eyre::Error::new(err));
                                     if let Some(listeners) =
@@ -340,6 +444,7 @@ where
 
                             // update the cache
                             pin.db.insert_account_storage(addr, idx, value).unwrap();
+                            BackendStats::increment(&pin.stats.storage);
 
                             // notify all listeners
                             if let Some(listeners) = pin.storage_requests.remove(&(addr, idx)) {
@@ -355,6 +460,8 @@ where
                             let value = match block_hash {
                                 Ok(value) => value,
                                 Err(err) => {
+                                    BackendStats::increment(&pin.stats.errors);
+                                    trace!("Failed to fetch block hash {}: {:?}", number, err);
                                     let err = Arc::new(eyre::Error::new(err));
                                     // notify all listeners
                                     if let Some(listeners) = pin.block_requests.remove(&number) {
@@ -371,6 +478,7 @@ where
 
                             // update the cache
                             pin.db.block_hashes.insert(number, value);
+                            BackendStats::increment(&pin.stats.block_hashes);
 
                             // notify all listeners
                             if let Some(listeners) = pin.block_requests.remove(&number) {
@@ -1,3 +1,4 @@
+pub mod anvil_state;
 pub mod fork_db;
 pub mod fork_factory;
 pub mod database_error;
@@ -0,0 +1,249 @@
+use alloy_primitives::{Address, Bytes, U256};
+use revm::{
+    interpreter::{
+        CallInputs, CallOutcome, CallScheme, CreateInputs, CreateOutcome, InterpreterResult,
+    },
+    primitives::CreateScheme,
+    Database, EvmContext, Inspector,
+};
+use serde::{Deserialize, Serialize};
+
+/// The type of a call frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CallKind {
+    Call,
+    StaticCall,
+    DelegateCall,
+    CallCode,
+    Create,
+    Create2,
+    SelfDestruct,
+}
+
+impl From<CallScheme> for CallKind {
+    fn from(scheme: CallScheme) -> Self {
+        match scheme {
+            CallScheme::Call | CallScheme::ExtCall => CallKind::Call,
+            CallScheme::StaticCall | CallScheme::ExtStaticCall => CallKind::StaticCall,
+            CallScheme::DelegateCall | CallScheme::ExtDelegateCall => CallKind::DelegateCall,
+            CallScheme::CallCode => CallKind::CallCode,
+        }
+    }
+}
+
+/// A call frame and all the calls it made
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallTrace {
+    pub kind: CallKind,
+    pub from: Address,
+
+    /// The callee, or the created contract for [CallKind::Create] and [CallKind::Create2]
+    ///
+    /// The code address for [CallKind::DelegateCall] and [CallKind::CallCode]
+    pub to: Address,
+    pub value: U256,
+    pub input: Bytes,
+    pub output: Bytes,
+    pub gas_used: u64,
+    pub success: bool,
+
+    /// Why the call failed if not successful
+    pub error: Option<String>,
+
+    /// The calls made by this call in execution order
+    pub calls: Vec<CallTrace>,
+}
+
+impl CallTrace {
+    pub fn new(kind: CallKind, from: Address, to: Address, value: U256, input: Bytes) -> Self {
+        Self {
+            kind,
+            from,
+            to,
+            value,
+            input,
+            output: Bytes::new(),
+            gas_used: 0,
+            success: true,
+            error: None,
+            calls: Vec::new(),
+        }
+    }
+
+    /// All the calls of this trace (including itself) in execution order
+    pub fn flatten(&self) -> Vec<&CallTrace> {
+        let mut calls = vec![self];
+        for call in &self.calls {
+            calls.extend(call.flatten());
+        }
+        calls
+    }
+
+    /// Depth of the deepest call, a trace without subcalls has depth 0
+    pub fn depth(&self) -> usize {
+        self.calls
+            .iter()
+            .map(|c| c.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn finish(&mut self, result: &InterpreterResult) {
+        self.output = result.output.clone();
+        self.gas_used = result.gas.spent();
+        self.success = result.is_ok();
+        if !self.success {
+            self.error = Some(format!("{:?}", result.result));
+        }
+    }
+}
+
+/// An [Inspector] that records the call tree of a transaction as a [CallTrace]
+#[derive(Debug, Default)]
+pub struct CallTraceInspector {
+    /// Calls that have started but not yet ended
+    stack: Vec<CallTrace>,
+
+    /// The top level call once it has ended
+    trace: Option<CallTrace>,
+}
+
+impl CallTraceInspector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The recorded trace, None if nothing was executed
+    pub fn into_trace(self) -> Option<CallTrace> {
+        self.trace
+    }
+
+    pub fn trace(&self) -> Option<&CallTrace> {
+        self.trace.as_ref()
+    }
+
+    fn end(&mut self, result: &InterpreterResult, created: Option<Address>) {
+        let Some(mut call) = self.stack.pop() else {
+            return;
+        };
+
+        call.finish(result);
+        if let Some(address) = created {
+            call.to = address;
+        }
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.calls.push(call),
+            None => self.trace = Some(call),
+        }
+    }
+}
+
+impl<DB> Inspector<DB> for CallTraceInspector
+where
+    DB: Database,
+{
+    fn call(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        inputs: &mut CallInputs,
+    ) -> Option<CallOutcome> {
+        // like the callTracer of geth, a DELEGATECALL or CALLCODE is to the code it runs,
+        // not to the account whose storage it uses
+        self.stack.push(CallTrace::new(
+            inputs.scheme.into(),
+            inputs.caller,
+            inputs.bytecode_address,
+            inputs.call_value(),
+            inputs.input.clone(),
+        ));
+        None
+    }
+
+    fn call_end(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        _inputs: &CallInputs,
+        outcome: CallOutcome,
+    ) -> CallOutcome {
+        self.end(&outcome.result, None);
+        outcome
+    }
+
+    fn create(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        inputs: &mut CreateInputs,
+    ) -> Option<CreateOutcome> {
+        let kind = match inputs.scheme {
+            CreateScheme::Create => CallKind::Create,
+            CreateScheme::Create2 { .. } => CallKind::Create2,
+        };
+
+        self.stack.push(CallTrace::new(
+            kind,
+            inputs.caller,
+            Address::ZERO,
+            inputs.value,
+            inputs.init_code.clone(),
+        ));
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        _inputs: &CreateInputs,
+        outcome: CreateOutcome,
+    ) -> CreateOutcome {
+        self.end(&outcome.result, outcome.address);
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use revm::{
+        db::{CacheDB, EmptyDB},
+        inspector_handle_register,
+        primitives::{AccountInfo, Bytecode, TransactTo},
+        Evm,
+    };
+
+    #[test]
+    fn test_delegatecall_to() {
+        let proxy = Address::repeat_byte(1);
+        let implementation = Address::repeat_byte(2);
+
+        // DELEGATECALL(gas, implementation, 0, 0, 0, 0) then STOP
+        let mut code = vec![0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73];
+        code.extend_from_slice(implementation.as_slice());
+        code.extend_from_slice(&[0x5a, 0xf4, 0x00]);
+
+        let mut db = CacheDB::new(EmptyDB::default());
+        db.insert_account_info(proxy, AccountInfo::from_bytecode(Bytecode::new_raw(code.into())));
+        db.insert_account_info(
+            implementation,
+            AccountInfo::from_bytecode(Bytecode::new_raw(vec![0x00].into())),
+        );
+
+        let mut evm = Evm::builder()
+            .with_db(db)
+            .with_external_context(CallTraceInspector::new())
+            .modify_tx_env(|tx| {
+                tx.caller = Address::repeat_byte(3);
+                tx.transact_to = TransactTo::Call(proxy);
+                tx.gas_limit = 1_000_000;
+            })
+            .append_handler_register(inspector_handle_register)
+            .build();
+        evm.transact().unwrap();
+
+        let trace = evm.context.external.trace().unwrap();
+        assert_eq!((trace.kind, trace.to), (CallKind::Call, proxy));
+        assert_eq!(trace.calls.len(), 1);
+        assert_eq!(trace.calls[0].kind, CallKind::DelegateCall);
+        assert_eq!((trace.calls[0].from, trace.calls[0].to), (proxy, implementation));
+    }
+}
@@ -1 +1,2 @@
-pub mod access_list;
\ No newline at end of file
+pub mod access_list;
+pub mod call_trace;
\ No newline at end of file
@@ -2,4 +2,8 @@ pub mod fork_db;
 pub mod inspectors;
 pub mod utils;
 pub mod simulate;
-pub mod dummy_account;
\ No newline at end of file
+pub mod dummy_account;
+pub mod balance_slots;
+pub mod anvil_fork;
+pub mod contracts;
+pub mod scenario;
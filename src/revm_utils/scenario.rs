@@ -0,0 +1,301 @@
+//! Declarative setup of a fork with funded accounts, deployed contracts and approvals
+//!
+//! Every simulation starts the same way: create some [DummyAccount]s, fund them with tokens,
+//! insert a few contracts, build the [Evm] and approve the contracts to spend the tokens.
+//! A [Scenario] describes all of that and [Scenario::build] does it in the right order
+//!
+//! ```ignore
+//! let scenario = Scenario::new()
+//!     .with_chain(ChainId::new(chain_id))
+//!     .with_account(ScenarioAccount::contract("router", swap_router_bytecode()?))
+//!     .with_account(
+//!         ScenarioAccount::eoa("alice")
+//!             .with_token(weth.clone(), weth.total_supply)
+//!             .with_approval(weth.clone(), Spender::Account("router".to_string()), U256::MAX),
+//!     );
+//!
+//! let (mut evm, handles) = scenario.build(&mut fork_factory)?;
+//! let alice = handles.address("alice")?;
+//! ```
+//!
+//! Universal Router swaps pull the tokens through Permit2, use [ScenarioAccount::with_permit2_approval]
+//! to give the router a Permit2 allowance either by writing the storage or by executing a signed permit
+
+use alloy_contract::private::Ethereum;
+use alloy_primitives::{Address, U256};
+use alloy_signer_local::PrivateKeySigner;
+use alloy_provider::Provider;
+use alloy_rpc_types::Block;
+use alloy_transport::Transport;
+use revm::{primitives::Bytecode, Evm};
+
+use std::collections::HashMap;
+
+use super::{
+    dummy_account::{AccountType, DummyAccount},
+    fork_db::{fork_db::ForkDB, fork_factory::ForkFactory},
+    simulate::{approve_token, permit2_permit},
+    utils::{new_evm, new_evm_for_chain},
+};
+use crate::defi::currency::erc20::ERC20Token;
+use crate::ChainId;
+
+/// Who is approved to spend the tokens of a [ScenarioAccount]
+#[derive(Debug, Clone)]
+pub enum Spender {
+    /// Another account of the scenario, by name
+    Account(String),
+
+    /// A contract that already exists in the fork (eg. the position manager)
+    Address(Address),
+}
+
+/// How a Permit2 allowance of a [ScenarioAccount] is set up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permit2Setup {
+    /// Write the token allowance to Permit2 and the Permit2 allowance to the storage of the fork
+    Storage,
+
+    /// Approve Permit2 and execute a `PermitSingle` signed by the account, like the Universal Router flow
+    SignedPermit,
+}
+
+/// The expiration of the Permit2 allowances of a [Scenario], the max uint48 so they never expire
+const PERMIT2_NO_EXPIRATION: u64 = (1 << 48) - 1;
+
+/// An account of a [Scenario]
+#[derive(Debug, Clone)]
+pub struct ScenarioAccount {
+    /// The name used to get the address from the [ScenarioHandles]
+    pub name: String,
+
+    pub account_type: AccountType,
+
+    /// The native balance
+    pub eth_balance: U256,
+
+    /// The token balances, the balance slot of each token is searched
+    pub tokens: Vec<(ERC20Token, U256)>,
+
+    /// The approvals (token, spender, amount) sent after the fork is built
+    pub approvals: Vec<(ERC20Token, Spender, U256)>,
+
+    /// The Permit2 allowances (token, spender, amount, setup)
+    pub permit2_approvals: Vec<(ERC20Token, Spender, U256, Permit2Setup)>,
+
+    /// The key of the account, a random one is created if the account signs permits
+    pub signer: Option<PrivateKeySigner>,
+}
+
+impl ScenarioAccount {
+    /// An externally owned account
+    pub fn eoa(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            account_type: AccountType::EOA,
+            eth_balance: U256::ZERO,
+            tokens: Vec::new(),
+            approvals: Vec::new(),
+            permit2_approvals: Vec::new(),
+            signer: None,
+        }
+    }
+
+    /// A contract inserted at a random address
+    pub fn contract(name: &str, code: Bytecode) -> Self {
+        Self {
+            account_type: AccountType::Contract(code),
+            ..Self::eoa(name)
+        }
+    }
+
+    pub fn with_eth(mut self, amount: U256) -> Self {
+        self.eth_balance = amount;
+        self
+    }
+
+    pub fn with_token(mut self, token: ERC20Token, amount: U256) -> Self {
+        self.tokens.push((token, amount));
+        self
+    }
+
+    pub fn with_approval(mut self, token: ERC20Token, spender: Spender, amount: U256) -> Self {
+        self.approvals.push((token, spender, amount));
+        self
+    }
+
+    /// Let `spender` pull `amount` of `token` through Permit2, the allowance never expires
+    pub fn with_permit2_approval(
+        mut self,
+        token: ERC20Token,
+        spender: Spender,
+        amount: U256,
+        setup: Permit2Setup,
+    ) -> Self {
+        self.permit2_approvals.push((token, spender, amount, setup));
+        self
+    }
+
+    /// Use the address of `signer` for the account
+    pub fn with_signer(mut self, signer: PrivateKeySigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    fn signs_permits(&self) -> bool {
+        self.permit2_approvals
+            .iter()
+            .any(|(.., setup)| *setup == Permit2Setup::SignedPermit)
+    }
+}
+
+/// A description of a fork, see the [module](self) docs
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    /// The chain of the fork, selects the [EvmPreset](super::utils::EvmPreset)
+    ///
+    /// If None, the [Evm] is created with [new_evm]
+    pub chain: Option<ChainId>,
+
+    /// The block the env of the [Evm] is set to
+    pub block: Option<Block>,
+
+    pub accounts: Vec<ScenarioAccount>,
+}
+
+/// The addresses of the accounts of a built [Scenario]
+#[derive(Debug, Clone, Default)]
+pub struct ScenarioHandles {
+    pub accounts: HashMap<String, DummyAccount>,
+
+    /// The keys of the accounts that have one
+    pub signers: HashMap<String, PrivateKeySigner>,
+}
+
+impl ScenarioHandles {
+    /// The address of an account by name
+    pub fn address(&self, name: &str) -> Result<Address, anyhow::Error> {
+        self.accounts
+            .get(name)
+            .map(|account| account.address)
+            .ok_or_else(|| anyhow::anyhow!("No account named {} in the scenario", name))
+    }
+
+    /// The key of an account by name
+    pub fn signer(&self, name: &str) -> Result<&PrivateKeySigner, anyhow::Error> {
+        self.signers
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("No signer for the account {} in the scenario", name))
+    }
+
+    fn spender(&self, spender: &Spender) -> Result<Address, anyhow::Error> {
+        match spender {
+            Spender::Account(name) => self.address(name),
+            Spender::Address(address) => Ok(*address),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_chain(mut self, chain: ChainId) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    pub fn with_block(mut self, block: Block) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn with_account(mut self, account: ScenarioAccount) -> Self {
+        self.accounts.push(account);
+        self
+    }
+
+    /// Insert the accounts into the fork, create the [Evm] and send the approvals
+    ///
+    /// The approvals and the signed permits are committed, the accounts, balances and [Permit2Setup::Storage]
+    /// allowances are written to `fork_factory` so later forks of the same factory have them as well
+    pub fn build<T, P>(
+        &self,
+        fork_factory: &mut ForkFactory<T, P>,
+    ) -> Result<(Evm<'static, (), ForkDB>, ScenarioHandles), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, Ethereum> + Clone + 'static,
+    {
+        let mut handles = ScenarioHandles::default();
+
+        for account in &self.accounts {
+            if handles.accounts.contains_key(&account.name) {
+                return Err(anyhow::anyhow!("Duplicate account name {} in the scenario", account.name));
+            }
+
+            let signer = match &account.signer {
+                Some(signer) => Some(signer.clone()),
+                None if account.signs_permits() => Some(PrivateKeySigner::random()),
+                None => None,
+            };
+            let dummy = match &signer {
+                Some(signer) => DummyAccount::from_signer(account.account_type.clone(), account.eth_balance, signer),
+                None => DummyAccount::new(account.account_type.clone(), account.eth_balance),
+            };
+            dummy.insert_account(fork_factory);
+            for (token, amount) in &account.tokens {
+                dummy.insert(fork_factory, token.clone(), *amount)?;
+            }
+
+            handles.accounts.insert(account.name.clone(), dummy);
+            if let Some(signer) = signer {
+                handles.signers.insert(account.name.clone(), signer);
+            }
+        }
+
+        // the spenders can be accounts inserted after the owner
+        for account in &self.accounts {
+            let dummy = &handles.accounts[&account.name];
+            for (token, spender, amount, setup) in &account.permit2_approvals {
+                if *setup == Permit2Setup::Storage {
+                    let spender = handles.spender(spender)?;
+                    dummy.insert_permit2_allowance(fork_factory, token.clone(), spender, *amount, PERMIT2_NO_EXPIRATION)?;
+                }
+            }
+        }
+
+        let fork_db = fork_factory.new_sandbox_fork();
+        let mut evm = match &self.chain {
+            Some(chain) => new_evm_for_chain(fork_db, self.block.clone(), chain),
+            None => new_evm(fork_db, self.block.clone()),
+        };
+
+        for account in &self.accounts {
+            let owner = handles.address(&account.name)?;
+            for (token, spender, amount) in &account.approvals {
+                let spender = handles.spender(spender)?;
+                approve_token(&mut evm, token.clone(), owner, spender, *amount)?;
+            }
+
+            for (token, spender, amount, setup) in &account.permit2_approvals {
+                if *setup == Permit2Setup::SignedPermit {
+                    let spender = handles.spender(spender)?;
+                    let signer = handles.signer(&account.name)?;
+                    permit2_permit(
+                        &mut evm,
+                        signer,
+                        token.clone(),
+                        spender,
+                        *amount,
+                        PERMIT2_NO_EXPIRATION,
+                        U256::MAX,
+                    )?;
+                }
+            }
+        }
+
+        Ok((evm, handles))
+    }
+}
@@ -0,0 +1,542 @@
+//! Rehearse a token launch in a fork
+//!
+//! Deploys a [mock ERC20](crate::abi::mock_erc20), creates a Uniswap V2 or V3 pool against a quote token,
+//! seeds the liquidity and runs a sequence of buys and sells, reporting the price after every trade
+//! and the fees earned by the liquidity provider.
+//!
+//! The Uniswap contracts used are the ones deployed on the chain of the quote token, see [UniswapDeployment].
+//! V3 trades go through the [SwapRouter](crate::revm_utils::contracts::swap_router) which only supports
+//! the V3 factory of Ethereum, Optimism and Arbitrum
+
+use alloy_contract::private::Ethereum;
+use alloy_primitives::{address, Address, Bytes, Signed, Uint, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::Block;
+use alloy_transport::Transport;
+use anyhow::Context;
+use revm::{
+    db::{Database, DatabaseCommit},
+    primitives::{AccountInfo, TransactTo, B256},
+    Evm,
+};
+
+use super::*;
+use crate::abi::{
+    mock_erc20::{initial_storage, mock_erc20_bytecode},
+    uniswap::{factory::v2 as factory_abi, pool::v2 as pair_abi, pool::v3 as pool_abi},
+};
+use crate::defi::amm::uniswap::deployments::UniswapDeployment;
+use crate::defi::amm::uniswap::v3::{create_pool::CreatePoolParams, sqrt_price_to_price, tick_spacing_for_fee};
+use crate::defi::currency::erc20::TokenKind;
+use crate::defi::utils::slippage::Slippage;
+use crate::utils::deadline::{deadline_after, DEFAULT_DEADLINE_SECS};
+use crate::utils::format::to_f64;
+use crate::revm_utils::{
+    dummy_account::{AccountType, DummyAccount},
+    fork_db::fork_factory::ForkFactory,
+    utils::new_evm,
+};
+
+/// Uniswap V2 Factory on Ethereum mainnet, see [UniswapDeployment] for the other chains
+pub const UNISWAP_V2_FACTORY: Address = address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f");
+
+const MIN_TICK: i32 = -887272;
+const MAX_TICK: i32 = 887272;
+
+/// Where the token is launched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchVenue {
+    UniswapV2,
+
+    /// The liquidity is seeded as a full range position
+    UniswapV3 { fee: u32 },
+}
+
+/// A trade against the launched token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchTrade {
+    /// Buy the token with this amount of the quote token
+    Buy(U256),
+
+    /// Sell this amount of the token for the quote token
+    Sell(U256),
+}
+
+#[derive(Debug, Clone)]
+pub struct LaunchParams {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub total_supply: U256,
+
+    /// The token the launched token is paired with (eg. WETH)
+    pub quote_token: ERC20Token,
+
+    pub venue: LaunchVenue,
+
+    /// Amount of the launched token to seed the pool with
+    pub token_liquidity: U256,
+
+    /// Amount of the quote token to seed the pool with, must not be zero
+    pub quote_liquidity: U256,
+
+    /// The swap fee of the V2 pair in basis points, 30 for Uniswap
+    ///
+    /// Used to account for the fees that stay in the reserves, ignored for V3
+    pub v2_fee_bps: u32,
+
+    /// The trades to run in order after the pool is seeded
+    pub trades: Vec<LaunchTrade>,
+
+    /// The slippage tolerance of the trades
+    pub slippage: Slippage,
+}
+
+impl LaunchParams {
+    /// Create new params that seed the pool with the whole supply and `quote_liquidity` of the quote token
+    pub fn new(
+        name: String,
+        symbol: String,
+        decimals: u8,
+        total_supply: U256,
+        quote_token: ERC20Token,
+        venue: LaunchVenue,
+        quote_liquidity: U256,
+    ) -> Self {
+        Self {
+            name,
+            symbol,
+            decimals,
+            total_supply,
+            quote_token,
+            venue,
+            token_liquidity: total_supply,
+            quote_liquidity,
+            v2_fee_bps: 30,
+            trades: Vec::new(),
+            slippage: Slippage::default(),
+        }
+    }
+
+    pub fn with_v2_fee_bps(mut self, fee_bps: u32) -> Self {
+        self.v2_fee_bps = fee_bps;
+        self
+    }
+
+    /// Check that both sides of the pool are seeded, a V2 mint reverts and a V3 pool has no price otherwise
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.token_liquidity.is_zero() || self.quote_liquidity.is_zero() {
+            return Err(anyhow::anyhow!(
+                "The pool must be seeded with both tokens, got {} {} and {} {}",
+                self.token_liquidity,
+                self.symbol,
+                self.quote_liquidity,
+                self.quote_token.symbol
+            ));
+        }
+        if self.token_liquidity > self.total_supply {
+            return Err(anyhow::anyhow!("The token liquidity is more than the total supply"));
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of a [LaunchTrade]
+#[derive(Debug, Clone)]
+pub struct LaunchStep {
+    pub trade: LaunchTrade,
+
+    /// Amount received, zero if the trade failed
+    pub amount_out: U256,
+
+    /// Price of the token in terms of the quote token after the trade
+    pub price: f64,
+
+    /// The revert reason if the trade failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LaunchReport {
+    /// The launched token
+    pub token: ERC20Token,
+
+    /// The pool the token was launched on
+    pub pool: Address,
+
+    /// Price of the token in terms of the quote token after the liquidity was seeded
+    pub initial_price: f64,
+
+    pub steps: Vec<LaunchStep>,
+
+    /// Fees earned by the liquidity provider in the launched token
+    pub fees_token: U256,
+
+    /// Fees earned by the liquidity provider in the quote token
+    pub fees_quote: U256,
+}
+
+impl LaunchReport {
+    /// Price of the token after the last trade
+    pub fn final_price(&self) -> f64 {
+        self.steps
+            .last()
+            .map(|s| s.price)
+            .unwrap_or(self.initial_price)
+    }
+}
+
+/// Rehearse a token launch in the fork
+///
+/// ## Arguments
+///
+/// * `fork_factory` - The fork to run the launch in, it is not modified
+/// * `block` - The block to use for the [Evm] environment
+/// * `params` - See [LaunchParams]
+pub fn rehearse_launch<T, P>(
+    fork_factory: &ForkFactory<T, P>,
+    block: Option<Block>,
+    params: LaunchParams,
+) -> Result<LaunchReport, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    params.validate()?;
+
+    // keep the original fork factory intact
+    let mut fork_factory = fork_factory.clone();
+
+    let deployer = DummyAccount::new(AccountType::EOA, U256::ZERO);
+    let trader = DummyAccount::new(AccountType::EOA, U256::ZERO);
+    let token_contract = DummyAccount::new(AccountType::Contract(mock_erc20_bytecode()?), U256::ZERO);
+
+    let token = ERC20Token {
+        chain_id: params.quote_token.chain_id,
+        address: token_contract.address,
+        symbol: params.symbol.clone(),
+        name: params.name.clone(),
+        decimals: params.decimals,
+        total_supply: params.total_supply,
+        kind: TokenKind::Other,
+        icon: None,
+    };
+    let quote = params.quote_token.clone();
+
+    let swap_router = deploy_swap_router(&mut fork_factory)?;
+    deploy(&mut fork_factory, &token_contract);
+
+    let storage = initial_storage(
+        &params.name,
+        &params.symbol,
+        params.decimals,
+        params.total_supply,
+        deployer.address,
+    )?;
+    for (slot, value) in storage {
+        fork_factory.insert_account_storage(token.address, slot, value)?;
+    }
+
+    let total_buys = params
+        .trades
+        .iter()
+        .fold(U256::ZERO, |acc, trade| match trade {
+            LaunchTrade::Buy(amount) => acc + *amount,
+            LaunchTrade::Sell(_) => acc,
+        });
+
+    deployer.insert(&mut fork_factory, quote.clone(), params.quote_liquidity)?;
+    trader.insert(&mut fork_factory, quote.clone(), total_buys)?;
+
+    let deployment = UniswapDeployment::for_chain(quote.chain_id)?;
+
+    let fork_db = fork_factory.new_sandbox_fork();
+    let mut evm = new_evm(fork_db, block);
+
+    for token in [&token, &quote] {
+        approve_token(&mut evm, token.clone(), trader.address, swap_router, U256::MAX)?;
+        approve_token(&mut evm, token.clone(), deployer.address, deployment.position_manager, U256::MAX)?;
+    }
+
+    let (pool, token_id) = match params.venue {
+        LaunchVenue::UniswapV2 => {
+            let pair = seed_v2(&mut evm, &params, &deployment, &token, &quote, deployer.address)?;
+            (pair, None)
+        }
+        LaunchVenue::UniswapV3 { fee } => {
+            let (pool, token_id) = seed_v3(&mut evm, &params, &deployment, &token, &quote, fee, deployer.address)?;
+            (pool, Some(token_id))
+        }
+    };
+
+    let initial_price = pool_price(&mut evm, params.venue, pool, &token, &quote)?;
+
+    let (pool_variant, fee) = match params.venue {
+        LaunchVenue::UniswapV2 => (PoolVariant::UniswapV2, 3000),
+        LaunchVenue::UniswapV3 { fee } => (PoolVariant::UniswapV3, fee),
+    };
+
+    let mut steps = Vec::with_capacity(params.trades.len());
+    let mut v2_fees_token = U256::ZERO;
+    let mut v2_fees_quote = U256::ZERO;
+
+    for trade in params.trades.iter().copied() {
+        let (input, output, amount_in) = match trade {
+            LaunchTrade::Buy(amount) => (&quote, &token, amount),
+            LaunchTrade::Sell(amount) => (&token, &quote, amount),
+        };
+
+        let swap_params = Params::new(input.address, output.address, amount_in, pool, pool_variant, fee);
+
+        let swap_res = swap_with_slippage(
+            &mut evm,
+            swap_params,
+            &params.slippage,
+            trader.address,
+            swap_router,
+            true,
+        );
+
+        let (amount_out, error) = match swap_res {
+            Ok(amount_out) => {
+                // V2 fees stay in the reserves, so we track them as the trades go
+                let lp_fee = amount_in * U256::from(params.v2_fee_bps) / U256::from(10_000);
+                match trade {
+                    LaunchTrade::Buy(_) => v2_fees_quote += lp_fee,
+                    LaunchTrade::Sell(_) => v2_fees_token += lp_fee,
+                }
+                (amount_out, None)
+            }
+            Err(e) => (U256::ZERO, Some(e.to_string())),
+        };
+
+        let price = pool_price(&mut evm, params.venue, pool, &token, &quote)?;
+
+        steps.push(LaunchStep {
+            trade,
+            amount_out,
+            price,
+            error,
+        });
+    }
+
+    let (fees_token, fees_quote) = match token_id {
+        None => (v2_fees_token, v2_fees_quote),
+        Some(token_id) => {
+            let collect_params = INonfungiblePositionManager::CollectParams {
+                tokenId: token_id,
+                recipient: deployer.address,
+                amount0Max: u128::MAX,
+                amount1Max: u128::MAX,
+            };
+
+            let (amount0, amount1) = collect_fees(
+                &mut evm,
+                collect_params,
+                deployer.address,
+                deployment.position_manager,
+                true,
+            )?;
+
+            if token.address < quote.address {
+                (amount0, amount1)
+            } else {
+                (amount1, amount0)
+            }
+        }
+    };
+
+    Ok(LaunchReport {
+        token,
+        pool,
+        initial_price,
+        steps,
+        fees_token,
+        fees_quote,
+    })
+}
+
+/// Create the V2 pair and mint the initial liquidity
+fn seed_v2<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    params: &LaunchParams,
+    deployment: &UniswapDeployment,
+    token: &ERC20Token,
+    quote: &ERC20Token,
+    deployer: Address,
+) -> Result<Address, anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    let call_data = factory_abi::encode_create_pair(token.address, quote.address);
+    let output = transact(evm, deployer, deployment.v2_factory, call_data)
+        .context("Failed to create pair")?;
+    let pair = factory_abi::decode_create_pair(&output)?;
+
+    transact(
+        evm,
+        deployer,
+        token.address,
+        token.encode_transfer(pair, params.token_liquidity),
+    )?;
+    transact(
+        evm,
+        deployer,
+        quote.address,
+        quote.encode_transfer(pair, params.quote_liquidity),
+    )?;
+    transact(evm, deployer, pair, pair_abi::encode_mint(deployer))
+        .context("Failed to mint liquidity")?;
+
+    Ok(pair)
+}
+
+/// Create the V3 pool and mint a full range position
+fn seed_v3<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    params: &LaunchParams,
+    deployment: &UniswapDeployment,
+    token: &ERC20Token,
+    quote: &ERC20Token,
+    fee: u32,
+    deployer: Address,
+) -> Result<(Address, U256), anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    let token_amount = to_f64(params.token_liquidity, token.decimals)?;
+    let quote_amount = to_f64(params.quote_liquidity, quote.decimals)?;
+    let price = quote_amount / token_amount;
+
+    let create_params = CreatePoolParams::new(token.clone(), quote.clone(), fee, price);
+    let pool = create_pool_from_params(evm, &create_params, deployer, deployment.position_manager, true)?;
+
+    let (amount0, amount1) = if token.address == create_params.token0.address {
+        (params.token_liquidity, params.quote_liquidity)
+    } else {
+        (params.quote_liquidity, params.token_liquidity)
+    };
+
+    let spacing = tick_spacing_for_fee(fee)?;
+    let lower_tick: Signed<24, 1> = ((MIN_TICK / spacing) * spacing)
+        .to_string()
+        .parse()
+        .context("Failed to parse tick")?;
+    let upper_tick: Signed<24, 1> = ((MAX_TICK / spacing) * spacing)
+        .to_string()
+        .parse()
+        .context("Failed to parse tick")?;
+
+    let mint_params = INonfungiblePositionManager::MintParams {
+        token0: create_params.token0.address,
+        token1: create_params.token1.address,
+        fee: Uint::from(fee),
+        tickLower: lower_tick,
+        tickUpper: upper_tick,
+        amount0Desired: amount0,
+        amount1Desired: amount1,
+        amount0Min: U256::ZERO,
+        amount1Min: U256::ZERO,
+        recipient: deployer,
+        deadline: deadline_after(evm.block().timestamp.to::<u64>(), DEFAULT_DEADLINE_SECS),
+    };
+
+    let (token_id, _, _, _) = mint_position(evm, mint_params, deployer, deployment.position_manager, true)?;
+
+    Ok((pool, token_id))
+}
+
+/// Price of `token` in terms of `quote` as reported by the pool
+fn pool_price<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    venue: LaunchVenue,
+    pool: Address,
+    token: &ERC20Token,
+    quote: &ERC20Token,
+) -> Result<f64, anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    let token_is_0 = token.address < quote.address;
+
+    match venue {
+        LaunchVenue::UniswapV2 => {
+            let output = transact(evm, Address::ZERO, pool, pair_abi::encode_get_reserves())?;
+            let (reserve0, reserve1) = pair_abi::decode_get_reserves(&output)?;
+            let (token_reserve, quote_reserve) = if token_is_0 {
+                (reserve0, reserve1)
+            } else {
+                (reserve1, reserve0)
+            };
+
+            let token_reserve = to_f64(token_reserve, token.decimals)?;
+            let quote_reserve = to_f64(quote_reserve, quote.decimals)?;
+            Ok(quote_reserve / token_reserve)
+        }
+        LaunchVenue::UniswapV3 { .. } => {
+            let output = transact(evm, Address::ZERO, pool, pool_abi::encode_slot0())?;
+            let (sqrt_price_x96, _) = pool_abi::decode_slot0(&output)?;
+
+            let (decimals0, decimals1) = if token_is_0 {
+                (token.decimals, quote.decimals)
+            } else {
+                (quote.decimals, token.decimals)
+            };
+
+            let price0 = sqrt_price_to_price(sqrt_price_x96, decimals0, decimals1);
+
+            if token_is_0 {
+                Ok(price0)
+            } else {
+                Ok(1.0 / price0)
+            }
+        }
+    }
+}
+
+/// Insert the code of a contract [DummyAccount] into the fork
+fn deploy<T, P>(fork_factory: &mut ForkFactory<T, P>, account: &DummyAccount)
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    let code = match &account.account_type {
+        AccountType::EOA => None,
+        AccountType::Contract(code) => Some(code.clone()),
+    };
+
+    let account_info = AccountInfo {
+        balance: account.balance,
+        nonce: 0,
+        code_hash: B256::default(),
+        code,
+    };
+
+    fork_factory.insert_account_info(account.address, account_info);
+}
+
+/// Execute and commit a call, returning its output
+fn transact<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    caller: Address,
+    contract: Address,
+    call_data: Bytes,
+) -> Result<Bytes, anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = call_data;
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(contract);
+
+    let res = evm.transact_commit().ok().unwrap();
+    let output = res.output().unwrap().clone();
+
+    if !res.is_success() {
+        let err = revert_msg(&output);
+        return Err(anyhow::anyhow!("Call to {} failed: {}", contract, err));
+    }
+
+    Ok(output)
+}
@@ -1,8 +1,24 @@
 // ! Shortcuts for simulating commonly used interactions with contracts
 
-use crate::abi::{swap_router::*, uniswap::nft_position::{*, INonfungiblePositionManager}};
+pub mod launch;
+
+use crate::abi::uniswap::nft_position::{*, INonfungiblePositionManager};
+use crate::revm_utils::contracts::swap_router::*;
+use crate::abi::erc20::ERC20;
+use crate::abi::permit2::{permit2_domain, IPermit2, PERMIT2};
+use crate::defi::amm::uniswap::router::{Input, UniversalRouter};
+use crate::abi::flash_receiver::{encode_flash_call, FlashRepay};
+use crate::abi::uniswap::pool::{v2 as pair_abi, v3 as pool_abi};
 use crate::defi::currency::erc20::ERC20Token;
-use alloy_primitives::{Address, U256};
+use crate::defi::amm::uniswap::v3::create_pool::CreatePoolParams;
+use crate::defi::utils::slippage::Slippage;
+use alloy_primitives::{
+    aliases::{U160, U48},
+    Address, Bytes, U256,
+};
+use alloy_signer::SignerSync;
+use alloy_signer_local::PrivateKeySigner;
+use alloy_sol_types::{SolCall, SolStruct};
 use revm::{Evm, primitives::TransactTo, db::{Database, DatabaseCommit}};
 use super::utils::revert_msg;
 
@@ -41,6 +57,106 @@ where
     Ok(amount)
 }
 
+/// Simulate a swap using [SwapRouter] with a slippage adjusted `minimum_received`
+///
+/// The swap is first quoted without committing, the minimum is then derived from the quote
+///
+/// ## Arguments
+///
+/// * `params` - The swap params, `minimum_received` is overwritten
+/// * `slippage` - See [Slippage]
+pub fn swap_with_slippage<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    mut params: SwapRouter::Params,
+    slippage: &Slippage,
+    caller: Address,
+    contract: Address,
+    commit: bool,
+) -> Result<U256, anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    params.minimum_received = U256::ZERO;
+    let quote = swap(evm, params.clone(), caller, contract, false)?;
+
+    params.minimum_received = slippage.min_amount_out(quote)?;
+    swap(evm, params, caller, contract, commit)
+}
+
+/// Simulate a swap using [SwapRouter] paying with native ETH
+///
+/// The ETH is first wrapped by depositing `amount_in` into `params.input_token` (which must be the WETH of the chain)
+/// and approved to the router, then swapped like [swap]
+///
+/// The wrap and the approval are always committed, `commit` only applies to the swap
+///
+/// ## Arguments
+///
+/// * `params` - The swap params, `input_token` must be WETH
+/// * `caller` - The account paying the ETH, it must have at least `amount_in` balance
+/// * `contract` - The address of the deployed [SwapRouter]
+pub fn swap_eth_in<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    params: SwapRouter::Params,
+    caller: Address,
+    contract: Address,
+    commit: bool,
+) -> Result<U256, anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    wrap_eth(evm, params.input_token, params.amount_in, caller)?;
+
+    let call_data = ERC20::approveCall {
+        spender: contract,
+        amount: params.amount_in,
+    }
+    .abi_encode();
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = call_data.into();
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(params.input_token);
+
+    let res = evm.transact_commit().ok().unwrap();
+
+    if !res.is_success() {
+        let err = revert_msg(res.output().unwrap());
+        return Err(anyhow::anyhow!("Failed to approve WETH: {}", err));
+    }
+
+    swap(evm, params, caller, contract, commit)
+}
+
+/// Wrap `amount` of native ETH by depositing it into the `weth` contract
+///
+/// Always committed
+pub fn wrap_eth<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    weth: Address,
+    amount: U256,
+    caller: Address,
+) -> Result<(), anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = ERC20::depositCall {}.abi_encode().into();
+    evm.tx_mut().value = amount;
+    evm.tx_mut().transact_to = TransactTo::Call(weth);
+
+    let res = evm.transact_commit();
+    evm.tx_mut().value = U256::ZERO;
+
+    let res = res.map_err(|e| anyhow::anyhow!("Failed to wrap ETH: {:?}", e))?;
+
+    if !res.is_success() {
+        let err = revert_msg(res.output().unwrap());
+        return Err(anyhow::anyhow!("Failed to wrap ETH: {}", err));
+    }
+
+    Ok(())
+}
+
 /// Simulate the collect function in the [INonfungiblePositionManager] contract
 pub fn collect_fees<DB>(
     evm: &mut Evm<'static, (), DB>,
@@ -75,6 +191,42 @@ where
     Ok((amount0, amount1))
 }
 
+/// Simulate the decreaseLiquidity function in the [INonfungiblePositionManager] contract
+///
+/// The returned amounts are owed to the position, they still have to be collected with [collect_fees]
+pub fn decrease_liquidity<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    params: INonfungiblePositionManager::DecreaseLiquidityParams,
+    caller: Address,
+    contract: Address,
+    commit: bool
+) -> Result<(U256, U256), anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    let call_data = encode_decrease_liquidity(params);
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = call_data.into();
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(contract);
+
+    let res = if commit {
+        evm.transact_commit().map_err(|e| anyhow::anyhow!("Failed to decrease liquidity: {:?}", e))?
+    } else {
+        evm.transact()
+            .map_err(|e| anyhow::anyhow!("Failed to decrease liquidity: {:?}", e))?
+            .result
+    };
+
+    if !res.is_success() {
+        let err = revert_msg(res.output().unwrap_or(&Bytes::new()));
+        return Err(anyhow::anyhow!("Failed to decrease liquidity: {}", err));
+    }
+
+    let (amount0, amount1) = decode_decrease_liquidity(res.output().unwrap())?;
+    Ok((amount0, amount1))
+}
+
 /// Simulate the mint function in the [INonfungiblePositionManager] contract
 pub fn mint_position<DB>(
     evm: &mut Evm<'static, (), DB>,
@@ -109,6 +261,76 @@ where
     Ok((token_id, liquidity, amount0, amount1))
 }
 
+/// Simulate the createAndInitializePoolIfNecessary function in the [INonfungiblePositionManager] contract
+///
+/// Returns the address of the pool
+///
+/// ## Arguments
+///
+/// * `token0` - The token with the lower address
+/// * `token1` - The token with the higher address
+/// * `fee` - Fee tier of the pool
+/// * `sqrt_price_x96` - The initial price of the pool, see [sqrt_price_x96_from_price](crate::defi::amm::uniswap::v3::create_pool::sqrt_price_x96_from_price)
+pub fn create_pool<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    token0: Address,
+    token1: Address,
+    fee: u32,
+    sqrt_price_x96: U256,
+    caller: Address,
+    contract: Address,
+    commit: bool
+) -> Result<Address, anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    let call_data = encode_create_pool(token0, token1, fee, sqrt_price_x96)?;
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = call_data;
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(contract);
+
+    let res = if commit {
+        evm.transact_commit().ok().unwrap()
+    } else {
+        evm.transact().ok().unwrap().result
+    };
+
+    let output = res.output().unwrap();
+
+    if !res.is_success() {
+        let err = revert_msg(&output);
+        return Err(anyhow::anyhow!("Failed to create pool: {}", err));
+    }
+
+    let pool = decode_create_pool(output)?;
+    Ok(pool)
+}
+
+/// Create and initialize a Uniswap V3 pool in the fork from a human readable price
+///
+/// See [CreatePoolParams]
+pub fn create_pool_from_params<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    params: &CreatePoolParams,
+    caller: Address,
+    contract: Address,
+    commit: bool
+) -> Result<Address, anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    create_pool(
+        evm,
+        params.token0.address,
+        params.token1.address,
+        params.fee,
+        params.sqrt_price_x96()?,
+        caller,
+        contract,
+        commit,
+    )
+}
 
 pub fn erc20_balance<DB>(
     evm: &mut Evm<'static, (), DB>,
@@ -131,6 +353,28 @@ where
     Ok(balance)
 }
 
+pub fn erc20_allowance<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    token: ERC20Token,
+    owner: Address,
+    spender: Address,
+) -> Result<U256, anyhow::Error>
+where
+    DB: Database,
+{
+    let call_data = token.encode_allowance(owner, spender);
+    evm.tx_mut().data = call_data.into();
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(token.address);
+
+    let res = evm.transact().ok().unwrap();
+    let output = res.result.output().unwrap();
+
+    let allowance = token.decode_allowance(output)?;
+
+    Ok(allowance)
+}
+
 /// Simulate the approve function in the [ERC20Token] contract
 pub fn approve_token<DB>(
     evm: &mut Evm<'static, (), DB>,
@@ -159,6 +403,188 @@ where
     Ok(())
 }
 
+/// The Permit2 allowance of `owner` to `spender` for a token: (amount, expiration, nonce)
+pub fn permit2_allowance<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    owner: Address,
+    token: Address,
+    spender: Address,
+) -> Result<(U160, U48, U48), anyhow::Error>
+where
+    DB: Database,
+{
+    let call_data = IPermit2::allowanceCall { owner, token, spender }.abi_encode();
+    evm.tx_mut().data = call_data.into();
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(PERMIT2);
+
+    let res = evm.transact().ok().unwrap().result;
+    let output = res.output().unwrap();
+
+    if !res.is_success() {
+        let err = revert_msg(output);
+        return Err(anyhow::anyhow!("Failed to get the Permit2 allowance: {}", err));
+    }
+
+    let allowance = IPermit2::allowanceCall::abi_decode_returns(output, true)?;
+    Ok((allowance.amount, allowance.expiration, allowance.nonce))
+}
+
+/// Simulate a Permit2 allowance set with `Permit2.approve`, like a user would do for the Universal Router
+///
+/// The token is first approved to Permit2 with an unlimited amount, both transactions are committed
+///
+/// ## Arguments
+///
+/// * `token` - The token to approve
+/// * `owner` - The owner of the tokens
+/// * `spender` - The address that pulls the tokens through Permit2 (eg. the Universal Router)
+/// * `amount` - The Permit2 allowance, must fit in a uint160
+/// * `expiration` - The timestamp the allowance expires at
+pub fn permit2_approve<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    token: ERC20Token,
+    owner: Address,
+    spender: Address,
+    amount: U256,
+    expiration: u64,
+) -> Result<(), anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    approve_token(evm, token.clone(), owner, PERMIT2, U256::MAX)?;
+
+    let amount = U160::uint_try_from(amount)
+        .map_err(|_| anyhow::anyhow!("Permit2 amount {} does not fit in uint160", amount))?;
+    let call_data = IPermit2::approveCall {
+        token: token.address,
+        spender,
+        amount,
+        expiration: U48::saturating_from(expiration),
+    }
+    .abi_encode();
+
+    evm.tx_mut().caller = owner;
+    evm.tx_mut().data = call_data.into();
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(PERMIT2);
+
+    let res = evm.transact_commit().ok().unwrap();
+
+    if !res.is_success() {
+        let err = revert_msg(res.output().unwrap());
+        return Err(anyhow::anyhow!("Failed to approve through Permit2: {}", err));
+    }
+
+    Ok(())
+}
+
+/// Simulate a Permit2 allowance set with a signed `PermitSingle`, executed with `Permit2.permit`
+///
+/// The token is first approved to Permit2 with an unlimited amount, the permit uses the current Permit2 nonce
+/// and is signed for the chain id of the [Evm]. Both transactions are committed
+///
+/// ## Arguments
+///
+/// * `signer` - The owner of the tokens
+/// * `token` - The token to approve
+/// * `spender` - The address that pulls the tokens through Permit2 (eg. the Universal Router)
+/// * `amount` - The Permit2 allowance, must fit in a uint160
+/// * `expiration` - The timestamp the allowance expires at
+/// * `sig_deadline` - The timestamp the signature is valid until
+pub fn permit2_permit<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    signer: &PrivateKeySigner,
+    token: ERC20Token,
+    spender: Address,
+    amount: U256,
+    expiration: u64,
+    sig_deadline: U256,
+) -> Result<(), anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    let owner = signer.address();
+    approve_token(evm, token.clone(), owner, PERMIT2, U256::MAX)?;
+
+    let (_, _, nonce) = permit2_allowance(evm, owner, token.address, spender)?;
+    let permit = IPermit2::PermitSingle {
+        details: IPermit2::PermitDetails {
+            token: token.address,
+            amount: U160::uint_try_from(amount)
+                .map_err(|_| anyhow::anyhow!("Permit2 amount {} does not fit in uint160", amount))?,
+            expiration: U48::saturating_from(expiration),
+            nonce,
+        },
+        spender,
+        sigDeadline: sig_deadline,
+    };
+
+    let hash = permit.eip712_signing_hash(&permit2_domain(evm.cfg().chain_id));
+    let signature = signer.sign_hash_sync(&hash)?;
+
+    let call_data = IPermit2::permitCall {
+        owner,
+        permitSingle: permit,
+        signature: signature.as_bytes().to_vec().into(),
+    }
+    .abi_encode();
+
+    evm.tx_mut().caller = owner;
+    evm.tx_mut().data = call_data.into();
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(PERMIT2);
+
+    let res = evm.transact_commit().ok().unwrap();
+
+    if !res.is_success() {
+        let err = revert_msg(res.output().unwrap());
+        return Err(anyhow::anyhow!("Failed to execute the Permit2 permit: {}", err));
+    }
+
+    Ok(())
+}
+
+/// Simulate `execute` on the [UniversalRouter], the router pulls the input tokens of the caller through Permit2
+///
+/// See [permit2_approve] and [permit2_permit] to set up the Permit2 allowance first
+///
+/// ## Arguments
+///
+/// * `router` - The Universal Router of the chain
+/// * `inputs` - The commands to execute
+/// * `caller` - The account that swaps
+/// * `value` - The native ETH sent with the call
+pub fn universal_router_execute<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    router: &UniversalRouter,
+    inputs: Vec<Input>,
+    caller: Address,
+    value: U256,
+    commit: bool,
+) -> Result<(), anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = router.encode_execute(inputs);
+    evm.tx_mut().value = value;
+    evm.tx_mut().transact_to = TransactTo::Call(router.address);
+
+    let res = if commit {
+        evm.transact_commit().ok().unwrap()
+    } else {
+        evm.transact().ok().unwrap().result
+    };
+
+    if !res.is_success() {
+        let err = revert_msg(res.output().unwrap());
+        return Err(anyhow::anyhow!("Failed to execute on the Universal Router: {}", err));
+    }
+
+    Ok(())
+}
+
 
 pub fn can_tranfer_erc20<DB>(
     evm: &mut Evm<'static, (), DB>,
@@ -185,4 +611,126 @@ where
     }
 
     Ok((true, "".to_string()))
-}
\ No newline at end of file
+}
+
+
+/// Simulate a Uniswap V2 flash swap through a [FlashReceiver](crate::abi::flash_receiver) contract
+///
+/// The pair sends `amount0_out` and `amount1_out` to the `receiver` and calls it back,
+/// the `receiver` then pays back the `repay` amounts to the pair
+///
+/// ## Arguments
+///
+/// * `pair` - The address of the V2 pair
+/// * `amount0_out` - The amount of token0 to borrow
+/// * `amount1_out` - The amount of token1 to borrow
+/// * `repay` - The tokens and amounts the receiver pays back to the pair (at most 2)
+/// * `caller` - An EOA that initiates the flash swap
+/// * `receiver` - The address of the deployed [FlashReceiver](crate::abi::flash_receiver)
+pub fn v2_flash_swap<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    pair: Address,
+    amount0_out: U256,
+    amount1_out: U256,
+    repay: &[FlashRepay],
+    caller: Address,
+    receiver: Address,
+    commit: bool,
+) -> Result<(), anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    // the data must not be empty for the pair to call us back
+    let swap_data = pair_abi::encode_swap(amount0_out, amount1_out, receiver, Bytes::from(vec![1u8]));
+    let call_data = encode_flash_call(pair, repay, swap_data)?;
+
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = call_data;
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(receiver);
+
+    let res = if commit {
+        evm.transact_commit().ok().unwrap()
+    } else {
+        evm.transact().ok().unwrap().result
+    };
+
+    let output = res.output().unwrap();
+
+    if !res.is_success() {
+        let err = revert_msg(output);
+        return Err(anyhow::anyhow!("Failed to flash swap: {}", err));
+    }
+
+    Ok(())
+}
+
+/// Simulate a Uniswap V3 flash loan through a [FlashReceiver](crate::abi::flash_receiver) contract
+///
+/// The pool sends `amount0` and `amount1` to the `receiver` and calls `uniswapV3FlashCallback`,
+/// the `receiver` then pays back the `repay` amounts to the pool
+///
+/// See [v3_flash_fee] for the minimum amount the pool expects back
+///
+/// ## Arguments
+///
+/// * `pool` - The address of the V3 pool
+/// * `amount0` - The amount of token0 to borrow
+/// * `amount1` - The amount of token1 to borrow
+/// * `repay` - The tokens and amounts the receiver pays back to the pool (at most 2)
+/// * `caller` - An EOA that initiates the flash loan
+/// * `receiver` - The address of the deployed [FlashReceiver](crate::abi::flash_receiver)
+pub fn v3_flash<DB>(
+    evm: &mut Evm<'static, (), DB>,
+    pool: Address,
+    amount0: U256,
+    amount1: U256,
+    repay: &[FlashRepay],
+    caller: Address,
+    receiver: Address,
+    commit: bool,
+) -> Result<(), anyhow::Error>
+where
+    DB: Database + DatabaseCommit,
+{
+    let flash_data = pool_abi::encode_flash(receiver, amount0, amount1, Bytes::new());
+    let call_data = encode_flash_call(pool, repay, flash_data)?;
+
+    evm.tx_mut().caller = caller;
+    evm.tx_mut().data = call_data;
+    evm.tx_mut().value = U256::ZERO;
+    evm.tx_mut().transact_to = TransactTo::Call(receiver);
+
+    let res = if commit {
+        evm.transact_commit().ok().unwrap()
+    } else {
+        evm.transact().ok().unwrap().result
+    };
+
+    let output = res.output().unwrap();
+
+    if !res.is_success() {
+        let err = revert_msg(output);
+        return Err(anyhow::anyhow!("Failed to flash: {}", err));
+    }
+
+    Ok(())
+}
+
+/// The minimum amount a Uniswap V2 pair expects back when flash borrowing `amount_out` of a token
+/// and repaying in the same token
+pub fn v2_flash_repay_amount(amount_out: U256) -> U256 {
+    amount_out * U256::from(1000) / U256::from(997) + U256::from(1)
+}
+
+/// The fee a Uniswap V3 pool charges for flash borrowing `amount`
+///
+/// ## Arguments
+///
+/// * `amount` - The borrowed amount
+/// * `fee` - The fee of the pool in hundredths of a bip (eg. 3000 for 0.3%)
+pub fn v3_flash_fee(amount: U256, fee: u32) -> U256 {
+    let fee = U256::from(fee);
+    let denominator = U256::from(1_000_000);
+    (amount * fee + denominator - U256::from(1)) / denominator
+}
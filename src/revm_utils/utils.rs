@@ -1,3 +1,5 @@
+use crate::utils::arbitrum::{is_arbitrum, l1_block_number};
+use crate::ChainId;
 use alloy_rpc_types::Block;
 use revm::{
     inspector_handle_register,
@@ -51,28 +53,155 @@ where
     Ok(InspectRes::new(res, env, db))
 }
 
+/// Create a new [Evm] with the Ethereum mainnet defaults, see [new_evm_for_chain] for the other chains
 pub fn new_evm<DB>(db: DB, block: Option<Block>) -> Evm<'static, (), DB>
+where
+    DB: Database,
+{
+    new_evm_with_preset(db, block, &EvmPreset::default())
+}
+
+/// Chain specific settings of the [Evm]
+#[derive(Debug, Clone)]
+pub struct EvmPreset {
+    pub spec_id: SpecId,
+
+    /// The value returned by the `CHAINID` opcode
+    pub chain_id: u64,
+
+    /// Allow transactions from accounts that have code (eg. impersonated contracts)
+    pub disable_eip3607: bool,
+
+    pub disable_balance_check: bool,
+    pub disable_block_gas_limit: bool,
+    pub disable_base_fee: bool,
+}
+
+/// Ethereum mainnet with the checks disabled for easier testing, what [new_evm] uses
+impl Default for EvmPreset {
+    fn default() -> Self {
+        Self {
+            spec_id: SpecId::CANCUN,
+            chain_id: 1,
+            disable_eip3607: false,
+            disable_balance_check: true,
+            disable_block_gas_limit: true,
+            disable_base_fee: true,
+        }
+    }
+}
+
+impl EvmPreset {
+    /// The preset for the latest block of a supported chain
+    pub fn new(chain: &ChainId) -> Self {
+        Self::at(chain, u64::MAX)
+    }
+
+    /// The preset for a block of a supported chain, the spec is the one active at `timestamp`
+    pub fn at(chain: &ChainId, timestamp: u64) -> Self {
+        Self {
+            spec_id: spec_at(chain, timestamp),
+            chain_id: chain.id(),
+            disable_eip3607: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_spec_id(mut self, spec_id: SpecId) -> Self {
+        self.spec_id = spec_id;
+        self
+    }
+}
+
+/// Create a new [Evm] configured with the given [EvmPreset]
+pub fn new_evm_with_preset<DB>(db: DB, block: Option<Block>, preset: &EvmPreset) -> Evm<'static, (), DB>
 where
     DB: Database,
 {
     let mut evm = Evm::builder()
         .with_db(db)
-        .with_spec_id(SpecId::CANCUN)
+        .with_spec_id(preset.spec_id)
         .build();
 
     if let Some(block) = block {
-        evm.block_mut().number = U256::from(block.header.number);
+        // on Arbitrum the NUMBER opcode returns the L1 block number
+        let number = match is_arbitrum(preset.chain_id) {
+            true => l1_block_number(&block).unwrap_or(block.header.number),
+            false => block.header.number,
+        };
+
+        evm.block_mut().number = U256::from(number);
         evm.block_mut().timestamp = U256::from(block.header.timestamp);
         evm.block_mut().coinbase = block.header.miner;
     }
 
-    // Disable some checks for easier testing
-    evm.cfg_mut().disable_balance_check = true;
-    evm.cfg_mut().disable_block_gas_limit = true;
-    evm.cfg_mut().disable_base_fee = true;
+    evm.cfg_mut().chain_id = preset.chain_id;
+    evm.cfg_mut().disable_eip3607 = preset.disable_eip3607;
+    evm.cfg_mut().disable_balance_check = preset.disable_balance_check;
+    evm.cfg_mut().disable_block_gas_limit = preset.disable_block_gas_limit;
+    evm.cfg_mut().disable_base_fee = preset.disable_base_fee;
     evm
 }
 
+/// Move the block env forward to `number` and `timestamp`
+///
+/// The env never goes backwards so time dependent contracts (eg. the oracle of a V3 pool) see a monotonic clock,
+/// pass None as `number` to only move the timestamp (eg. on Arbitrum where NUMBER is the L1 block)
+pub fn advance_block<DB>(evm: &mut Evm<'static, (), DB>, number: Option<u64>, timestamp: u64)
+where
+    DB: Database,
+{
+    let block = evm.block_mut();
+    if let Some(number) = number {
+        block.number = block.number.max(U256::from(number));
+    }
+    block.timestamp = block.timestamp.max(U256::from(timestamp));
+}
+
+/// The spec of a chain at a block timestamp
+///
+/// The OP-stack and BSC upgrades are mapped to the Ethereum spec with the same opcodes,
+/// Arbitrum blocks before ArbOS 11 (no PUSH0) need the spec set manually
+fn spec_at(chain: &ChainId, timestamp: u64) -> SpecId {
+    match chain {
+        ChainId::Ethereum(_) => match timestamp {
+            t if t >= 1710338135 => SpecId::CANCUN,
+            t if t >= 1681338455 => SpecId::SHANGHAI,
+            t if t >= 1663224162 => SpecId::MERGE,
+            _ => SpecId::LONDON,
+        },
+        // Ecotone and Canyon
+        ChainId::Optimism(_) | ChainId::Base(_) => match timestamp {
+            t if t >= 1710374401 => SpecId::CANCUN,
+            t if t >= 1704992401 => SpecId::SHANGHAI,
+            _ => SpecId::MERGE,
+        },
+        // Haber and Kepler, BSC never had the merge so PREVRANDAO is still DIFFICULTY before Kepler
+        ChainId::BinanceSmartChain(_) => match timestamp {
+            t if t >= 1718863500 => SpecId::CANCUN,
+            t if t >= 1705996800 => SpecId::SHANGHAI,
+            _ => SpecId::LONDON,
+        },
+        // ArbOS 20
+        ChainId::Arbitrum(_) => match timestamp {
+            t if t >= 1710424089 => SpecId::CANCUN,
+            _ => SpecId::SHANGHAI,
+        },
+    }
+}
+
+/// Create a new [Evm] with the [EvmPreset] of the given chain at the block
+pub fn new_evm_for_chain<DB>(db: DB, block: Option<Block>, chain: &ChainId) -> Evm<'static, (), DB>
+where
+    DB: Database,
+{
+    let preset = match &block {
+        Some(block) => EvmPreset::at(chain, block.header.timestamp),
+        None => EvmPreset::new(chain),
+    };
+    new_evm_with_preset(db, block, &preset)
+}
+
 pub fn revert_msg(bytes: &Bytes) -> String {
     if bytes.len() < 4 {
         return "EVM Returned 0x (Empty Bytes)".to_string();
@@ -83,4 +212,21 @@ pub fn revert_msg(bytes: &Bytes) -> String {
         Ok(s) => s.trim_matches(char::from(0)).to_string(),
         Err(_) => "EVM Returned 0x (Empty Bytes)".to_string(),
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_at() {
+        let bsc = ChainId::BinanceSmartChain(56);
+        assert_eq!(EvmPreset::new(&bsc).spec_id, SpecId::CANCUN);
+        assert_eq!(EvmPreset::at(&bsc, 1_710_000_000).spec_id, SpecId::SHANGHAI);
+        assert_eq!(EvmPreset::at(&bsc, 1_700_000_000).spec_id, SpecId::LONDON);
+        assert_eq!(EvmPreset::at(&bsc, 1_700_000_000).chain_id, 56);
+
+        let ethereum = ChainId::Ethereum(1);
+        assert_eq!(EvmPreset::at(&ethereum, 1_690_000_000).spec_id, SpecId::SHANGHAI);
+        assert_eq!(EvmPreset::default().spec_id, EvmPreset::new(&ethereum).spec_id);
+    }
+}
@@ -0,0 +1,246 @@
+//! A small event loop for bots
+//!
+//! The [Runtime] merges new blocks, log subscriptions and timers into a single stream of [RuntimeEvent]s
+//! and feeds them to the registered handlers, one event at a time, in the order they arrive.
+//! Every handler gets a [Context] with the provider and the shared [PoolManager]
+//!
+//! The subscriptions need a pubsub (WS/IPC) provider
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let runtime = Runtime::new(client, pools)
+//!     .with_pool_sync(true)
+//!     .on_logs(Filter::new().address(pool).event(IUniswapV3Pool::Swap::SIGNATURE))
+//!     .every("report", Duration::from_secs(60))
+//!     .handler(|ctx, event| Box::pin(async move {
+//!         if let RuntimeEvent::Block { number, .. } = event {
+//!             let pools = ctx.pools.read().await;
+//!             println!("Block {} {} pools", number, pools.v3_pools.len());
+//!         }
+//!         Ok(())
+//!     }));
+//!
+//! let shutdown = runtime.shutdown_handle();
+//! tokio::spawn(async move {
+//!     tokio::signal::ctrl_c().await.ok();
+//!     shutdown.shutdown();
+//! });
+//!
+//! runtime.run().await?;
+//! ```
+
+use alloy_network::Ethereum;
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, Filter, Log};
+use alloy_transport::Transport;
+
+use futures::future::BoxFuture;
+use futures_util::StreamExt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch, RwLock};
+use tokio::task::JoinHandle;
+use tracing::trace;
+
+use crate::defi::amm::registry::PoolRegistry;
+
+/// The pools shared between the handlers
+pub type PoolManager = Arc<RwLock<PoolRegistry>>;
+
+/// An event fed to the handlers
+#[derive(Debug, Clone)]
+pub enum RuntimeEvent {
+    /// A new block
+    Block { number: u64, timestamp: u64 },
+
+    /// A log matching one of the filters registered with [Runtime::on_logs]
+    Log(Log),
+
+    /// A timer registered with [Runtime::every] fired
+    Timer(String),
+}
+
+/// What the handlers have access to
+#[derive(Debug, Clone)]
+pub struct Context<P> {
+    pub client: P,
+    pub pools: PoolManager,
+}
+
+/// A handler of [RuntimeEvent]s
+///
+/// An error is logged and doesn't stop the runtime
+pub type Handler<P> =
+    Box<dyn Fn(Context<P>, RuntimeEvent) -> BoxFuture<'static, Result<(), anyhow::Error>> + Send + Sync>;
+
+/// Stops a running [Runtime], see [Runtime::shutdown_handle]
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    /// Stop the runtime, the event being handled is finished first
+    pub fn shutdown(&self) {
+        let _ = self.0.send(true);
+    }
+}
+
+/// The event loop, see the [module docs](self)
+pub struct Runtime<T, P> {
+    pub client: P,
+    pub pools: PoolManager,
+    filters: Vec<Filter>,
+    timers: Vec<(String, Duration)>,
+    handlers: Vec<Handler<P>>,
+    sync_pools: bool,
+    shutdown: Arc<watch::Sender<bool>>,
+    _transport: PhantomData<T>,
+}
+
+impl<T, P> Runtime<T, P>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    pub fn new(client: P, pools: PoolRegistry) -> Self {
+        Self {
+            client,
+            pools: Arc::new(RwLock::new(pools)),
+            filters: Vec::new(),
+            timers: Vec::new(),
+            handlers: Vec::new(),
+            sync_pools: false,
+            shutdown: Arc::new(watch::channel(false).0),
+            _transport: PhantomData,
+        }
+    }
+
+    /// Fetch the state of every pool on each new block, before the handlers see the block
+    pub fn with_pool_sync(mut self, sync_pools: bool) -> Self {
+        self.sync_pools = sync_pools;
+        self
+    }
+
+    /// Subscribe to the logs matching `filter`
+    pub fn on_logs(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Fire a [RuntimeEvent::Timer] with `name` every `interval`
+    pub fn every(mut self, name: impl Into<String>, interval: Duration) -> Self {
+        self.timers.push((name.into(), interval));
+        self
+    }
+
+    /// Register a handler, every handler sees every event
+    pub fn handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Context<P>, RuntimeEvent) -> BoxFuture<'static, Result<(), anyhow::Error>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    /// A handle to stop the runtime from another task
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.shutdown.clone())
+    }
+
+    /// Run until all the subscriptions end or the runtime is shut down
+    ///
+    /// On shutdown the subscriptions and timers are stopped before returning
+    pub async fn run(self) -> Result<(), anyhow::Error> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut shutdown = self.shutdown.subscribe();
+        let mut tasks: Vec<JoinHandle<()>> = Vec::new();
+
+        let blocks = self.client.subscribe_blocks().await?;
+        let block_tx = tx.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut stream = blocks.into_stream();
+            while let Some(block) = stream.next().await {
+                let event = RuntimeEvent::Block {
+                    number: block.header.number,
+                    timestamp: block.header.timestamp,
+                };
+                if block_tx.send(event).is_err() {
+                    break;
+                }
+            }
+        }));
+
+        for filter in &self.filters {
+            let logs = self.client.subscribe_logs(filter).await?;
+            let log_tx = tx.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut stream = logs.into_stream();
+                while let Some(log) = stream.next().await {
+                    if log_tx.send(RuntimeEvent::Log(log)).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+
+        for (name, interval) in self.timers.clone() {
+            let timer_tx = tx.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(interval);
+                // the first tick completes immediately
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if timer_tx.send(RuntimeEvent::Timer(name.clone())).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+
+        // only the spawned tasks hold a sender now, the loop ends when they all finish
+        drop(tx);
+
+        let ctx = Context {
+            client: self.client.clone(),
+            pools: self.pools.clone(),
+        };
+
+        loop {
+            let event = tokio::select! {
+                event = rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = shutdown.wait_for(|stop| *stop) => break,
+            };
+
+            if let (true, RuntimeEvent::Block { number, .. }) = (self.sync_pools, &event) {
+                let mut pools = self.pools.write().await;
+                if let Err(e) = pools
+                    .sync_states(self.client.clone(), Some(BlockId::number(*number)))
+                    .await
+                {
+                    trace!("Failed to sync the pools at block {}: {:?}", number, e);
+                }
+            }
+
+            for handler in &self.handlers {
+                if let Err(e) = handler(ctx.clone(), event.clone()).await {
+                    trace!("Handler failed on {:?}: {:?}", event, e);
+                }
+            }
+        }
+
+        // dropping the subscriptions unsubscribes from the node
+        for task in tasks {
+            task.abort();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+//! Helpers for Arbitrum
+//!
+//! On Arbitrum the `NUMBER` opcode returns (an approximation of) the L1 block number
+//! and L2 block times are not constant, so windows of time can't be expressed as a fixed number of blocks
+
+use alloy_contract::private::Network;
+use alloy_network::{BlockResponse, HeaderResponse};
+use alloy_primitives::{address, Address, U64};
+use alloy_provider::Provider;
+use alloy_rpc_types::{Block, BlockId};
+use alloy_sol_types::sol;
+use alloy_transport::Transport;
+
+/// The ArbSys precompile
+pub const ARB_SYS: Address = address!("0000000000000000000000000000000000000064");
+
+sol! {
+    #[sol(rpc)]
+    interface IArbSys {
+        function arbBlockNumber() external view returns (uint256);
+        function arbBlockHash(uint256 arbBlockNum) external view returns (bytes32);
+        function arbChainID() external view returns (uint256);
+    }
+}
+
+pub fn is_arbitrum(chain_id: u64) -> bool {
+    chain_id == 42161
+}
+
+/// The L2 block number as returned by ArbSys
+pub async fn arb_block_number<T, P, N>(
+    client: P,
+    block_id: Option<BlockId>,
+) -> Result<u64, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = block_id.unwrap_or(BlockId::latest());
+    let arb_sys = IArbSys::new(ARB_SYS, client);
+    let number = arb_sys.arbBlockNumber().block(block).call().await?;
+    Ok(number._0.to::<u64>())
+}
+
+/// The L1 block number of an Arbitrum block, this is what the `NUMBER` opcode returns
+///
+/// Returns None if the block was not fetched from an Arbitrum node
+pub fn l1_block_number(block: &Block) -> Option<u64> {
+    block
+        .other
+        .get_deserialized::<U64>("l1BlockNumber")
+        .and_then(|n| n.ok())
+        .map(|n| n.to::<u64>())
+}
+
+/// The timestamp of a block
+pub async fn block_timestamp<T, P, N>(client: P, block: u64) -> Result<u64, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = client
+        .get_block(BlockId::number(block), false.into())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Block {} not found", block))?;
+
+    Ok(block.header().timestamp())
+}
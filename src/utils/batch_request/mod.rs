@@ -1,6 +1,8 @@
-use alloy_sol_types::sol;
+use alloy_sol_types::{sol, SolValue};
 use alloy_dyn_abi::DynSolType;
-use alloy_primitives::{Address, U256};
+use alloy_primitives::{Address, Signed, U256};
+use alloy_rpc_types::BlockId;
+use anyhow::Context;
 
 use alloy_contract::private::Network;
 use alloy_provider::Provider;
@@ -13,6 +15,21 @@ sol! {
     "src/utils/batch_request/abi/GetErc20Balance.json",
 }
 
+sol! {
+    #[sol(rpc)]
+    IGetV3Ticks,
+    "src/utils/batch_request/abi/GetV3Ticks.json",
+}
+
+sol! {
+    #[sol(rpc)]
+    IGetV2Pairs,
+    "src/utils/batch_request/abi/GetV2Pairs.json",
+}
+
+/// The number of bitmap words asked in one call of [v3_ticks]
+const V3_WORDS_PER_CALL: i32 = 16;
+
 pub struct TokenBalance {
     pub token: Address,
     pub balance: U256,
@@ -55,29 +72,220 @@ where
 }
 
 
+/// The most pairs [v2_pairs] can return, the response is capped by the max code size (24KB)
+pub const V2_PAIRS_PER_CALL: u64 = 150;
+
+/// A pair of a Uniswap V2 factory with its reserves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct V2PairData {
+    /// The index of the pair in `allPairs`
+    pub index: u64,
+    pub pair: Address,
+    pub token0: Address,
+    pub token1: Address,
+
+    /// 0 if `getReserves` failed
+    pub reserve0: U256,
+    pub reserve1: U256,
+}
+
+/// Get `count` pairs of a V2 factory starting at `allPairs(start)`, with their tokens and reserves, in a single `eth_call`
+///
+/// The call reverts if the range goes past `allPairsLength`
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `factory` - The V2 factory
+/// * `start` - The index of the first pair
+/// * `count` - The number of pairs, at most [V2_PAIRS_PER_CALL]
+/// * `block` - The block to read at, None for the latest
+pub async fn v2_pairs<T, P, N>(
+    client: P,
+    factory: Address,
+    start: u64,
+    count: u64,
+    block: Option<BlockId>,
+) -> Result<Vec<V2PairData>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    if count > V2_PAIRS_PER_CALL {
+        return Err(anyhow::anyhow!("At most {} pairs per call, got {}", V2_PAIRS_PER_CALL, count));
+    }
+
+    let deployer = IGetV2Pairs::deploy_builder(client, factory, U256::from(start), U256::from(count))
+        .block(block.unwrap_or(BlockId::latest()));
+    let res = deployer.call_raw().await?;
+
+    // pair, token0, token1, reserve0, reserve1 for each pair
+    let values = Vec::<U256>::abi_decode(&res, true)?;
+    if values.len() != count as usize * 5 {
+        return Err(anyhow::anyhow!("Expected {} values but got {}", count * 5, values.len()));
+    }
+
+    let address = |value: &U256| Address::from_word(value.to_be_bytes::<32>().into());
+    Ok(values
+        .chunks_exact(5)
+        .zip(start..)
+        .map(|(values, index)| V2PairData {
+            index,
+            pair: address(&values[0]),
+            token0: address(&values[1]),
+            token1: address(&values[2]),
+            reserve0: values[3],
+            reserve1: values[4],
+        })
+        .collect())
+}
+
+/// An initialized tick of a Uniswap V3 pool
+#[derive(Debug, Clone, Copy)]
+pub struct V3Tick {
+    pub tick: i32,
+    pub liquidity_gross: u128,
+    pub liquidity_net: i128,
+}
+
+/// The tick bitmap words of a Uniswap V3 pool and the initialized ticks in them
+#[derive(Debug, Clone, Default)]
+pub struct V3TickData {
+    pub bitmap: Vec<(i16, U256)>,
+    pub ticks: Vec<V3Tick>,
+}
+
+/// Get the tick bitmap words from `from_word` to `to_word` and every initialized tick in them in a single `eth_call`
+///
+/// The contract returns the data from its constructor, so the response is capped by the max code size (24KB),
+/// about 700 initialized ticks. See [v3_ticks] for larger ranges
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `pool` - The pool address
+/// * `from_word` - The first bitmap word
+/// * `to_word` - The last bitmap word, inclusive
+/// * `tick_spacing` - The tick spacing of the pool
+/// * `block` - The block to read at, None for the latest
+pub async fn v3_ticks_in_words<T, P, N>(
+    client: P,
+    pool: Address,
+    from_word: i16,
+    to_word: i16,
+    tick_spacing: i32,
+    block: Option<BlockId>,
+) -> Result<V3TickData, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let spacing: Signed<24, 1> = tick_spacing
+        .to_string()
+        .parse()
+        .context("Failed to parse tick spacing")?;
+    let deployer = IGetV3Ticks::deploy_builder(client, pool, from_word, to_word, spacing)
+        .block(block.unwrap_or(BlockId::latest()));
+    let res = deployer.call_raw().await?;
+
+    // the bitmap words, then liquidityGross << 128 | liquidityNet of each initialized tick
+    let values = Vec::<U256>::abi_decode(&res, true)?;
+    let words = (to_word as i32 - from_word as i32 + 1).max(0) as usize;
+    if values.len() < words {
+        return Err(anyhow::anyhow!("Expected {} bitmap words but got {}", words, values.len()));
+    }
+
+    let mut data = V3TickData::default();
+    let mut packed = values[words..].iter();
+    for (word, bitmap) in (from_word..=to_word).zip(values[..words].iter()) {
+        data.bitmap.push((word, *bitmap));
+        for tick in crate::defi::amm::uniswap::v3::initialized_ticks(word, *bitmap, tick_spacing) {
+            let value = packed
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing the data of tick {}", tick))?;
+            data.ticks.push(V3Tick {
+                tick,
+                liquidity_gross: (*value >> 128).to::<u128>(),
+                liquidity_net: (*value & U256::from(u128::MAX)).to::<u128>() as i128,
+            });
+        }
+    }
+
+    Ok(data)
+}
+
+/// Same as [v3_ticks_in_words] for any number of words
+///
+/// The words are asked in chunks, a chunk that fails (eg. too many ticks for one response) is split in half
+pub async fn v3_ticks<T, P, N>(
+    client: P,
+    pool: Address,
+    from_word: i16,
+    to_word: i16,
+    tick_spacing: i32,
+    block: Option<BlockId>,
+) -> Result<V3TickData, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let mut ranges = Vec::new();
+    let mut start = from_word as i32;
+    while start <= to_word as i32 {
+        let end = (start + V3_WORDS_PER_CALL - 1).min(to_word as i32);
+        ranges.push((start as i16, end as i16));
+        start = end + 1;
+    }
+    // pop from the front
+    ranges.reverse();
+
+    let mut data = V3TickData::default();
+    while let Some((from, to)) = ranges.pop() {
+        match v3_ticks_in_words(client.clone(), pool, from, to, tick_spacing, block).await {
+            Ok(chunk) => {
+                data.bitmap.extend(chunk.bitmap);
+                data.ticks.extend(chunk.ticks);
+            }
+            Err(e) if from < to => {
+                tracing::debug!("Splitting words {}..={} of pool {}: {}", from, to, pool, e);
+                let mid = ((from as i32 + to as i32).div_euclid(2)) as i16;
+                ranges.push((mid + 1, to));
+                ranges.push((from, mid));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(data)
+}
+
+
 #[cfg(test)]
 
 mod tests {
 
     #[tokio::test]
     async fn test_erc20_balance() {
-        use alloy_provider::{ProviderBuilder, WsConnect};
-        use alloy_signer_local::PrivateKeySigner;
+        use alloy_primitives::Address;
         use crate::prelude::{usdc, weth};
+        use crate::utils::cassette::cassette_provider;
         use super::erc20_balance;
 
         let url = "wss://eth.merkle.io";
-        let ws_connect = WsConnect::new(url);
-        let client = ProviderBuilder::new().on_ws(ws_connect).await.unwrap();
+        let client = cassette_provider("erc20_balance", url).await.unwrap();
 
         let weth = weth(1).unwrap();
         let usdc = usdc(1).unwrap();
 
-        let owner = PrivateKeySigner::random();
+        // a fixed owner so the recorded calls match
+        let owner = Address::repeat_byte(0x11);
 
         let tokens = vec![weth, usdc];
 
-        let balances = erc20_balance(client, owner.address(), tokens).await.unwrap();
+        let balances = erc20_balance(client, owner, tokens).await.unwrap();
 
         assert_eq!(balances.len(), 2);
 
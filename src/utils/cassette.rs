@@ -0,0 +1,328 @@
+//! Record and replay the RPC calls of a provider
+//!
+//! A [CassetteTransport] wraps a transport: in [CassetteMode::Record] it forwards the requests and writes every
+//! request/response pair to a JSON file, in [CassetteMode::Replay] it answers from that file without any network.
+//! Requests are matched by method and params, the id is ignored, so the calls of a test must be deterministic
+//! (eg. no random addresses, no `latest` dependent logic between runs).
+//!
+//! The cassette is written when the last clone of the transport is dropped or with [CassetteTransport::save]
+//!
+//! ```ignore
+//! // records with RECORD_CASSETTES=1, replays offline otherwise
+//! let client = cassette_provider("erc20_balance", "wss://eth.merkle.io").await?;
+//! let balances = erc20_balance(client, owner, tokens).await?;
+//! ```
+
+use alloy_json_rpc::{RequestPacket, Response, ResponsePacket, SerializedRequest};
+use alloy_provider::{Provider, ProviderBuilder, RootProvider};
+use alloy_rpc_client::RpcClient;
+use alloy_transport::{BoxTransport, Transport, TransportError, TransportErrorKind, TransportFut};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower::Service;
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tracing::trace;
+
+/// Set to record the cassettes again even if they exist
+pub const RECORD_ENV: &str = "RECORD_CASSETTES";
+
+/// Where [cassette_provider] keeps the cassettes, relative to the crate root
+pub const CASSETTE_DIR: &str = "tests/cassettes";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Forward the requests and record them
+    Record,
+
+    /// Answer from the cassette, a request that was not recorded fails
+    Replay,
+}
+
+/// A request and its response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub params: Value,
+
+    /// The JSON-RPC response without its id
+    pub response: Value,
+}
+
+/// The recorded interactions of a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read the cassette {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// The state shared by the clones of a [CassetteTransport]
+#[derive(Debug)]
+struct CassetteState {
+    path: PathBuf,
+    mode: CassetteMode,
+    cassette: Cassette,
+
+    /// The responses not replayed yet by (method, params), a request made twice gets the responses in order
+    pending: HashMap<(String, String), VecDeque<Value>>,
+}
+
+impl CassetteState {
+    fn save(&self) -> Result<(), anyhow::Error> {
+        self.cassette.save(&self.path)
+    }
+
+    fn replay(&mut self, request: &SerializedRequest) -> Result<Response, TransportError> {
+        let (method, params) = request_key(request)?;
+        let key = (method, params.to_string());
+
+        let mut response = self
+            .pending
+            .get_mut(&key)
+            .and_then(|responses| responses.pop_front())
+            .ok_or_else(|| {
+                TransportErrorKind::custom_str(&format!(
+                    "No recorded response for {} {} in {}",
+                    key.0,
+                    key.1,
+                    self.path.display()
+                ))
+            })?;
+
+        if let Value::Object(map) = &mut response {
+            map.insert("id".to_string(), serde_json::to_value(request.id()).map_err(TransportError::ser_err)?);
+        }
+        serde_json::from_str(&response.to_string()).map_err(|e| TransportError::deser_err(e, response.to_string()))
+    }
+}
+
+impl Drop for CassetteState {
+    fn drop(&mut self) {
+        if self.mode == CassetteMode::Record {
+            if let Err(e) = self.save() {
+                trace!("Failed to save the cassette {}: {:?}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// A transport that records or replays the calls of another one, see the [module](self) docs
+#[derive(Debug, Clone)]
+pub struct CassetteTransport<T> {
+    /// None when replaying
+    inner: Option<T>,
+    state: Arc<Mutex<CassetteState>>,
+}
+
+impl<T> CassetteTransport<T> {
+    /// Forward the requests to `inner` and record them to `path`, an existing cassette is overwritten
+    pub fn record(inner: T, path: impl Into<PathBuf>) -> Self {
+        Self::with_state(Some(inner), path.into(), CassetteMode::Record, Cassette::default())
+    }
+
+    /// Answer the requests from the cassette at `path`
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        let cassette = Cassette::load(&path)?;
+        Ok(Self::with_state(None, path, CassetteMode::Replay, cassette))
+    }
+
+    fn with_state(inner: Option<T>, path: PathBuf, mode: CassetteMode, cassette: Cassette) -> Self {
+        let mut pending: HashMap<(String, String), VecDeque<Value>> = HashMap::new();
+        for interaction in &cassette.interactions {
+            pending
+                .entry((interaction.method.clone(), interaction.params.to_string()))
+                .or_default()
+                .push_back(interaction.response.clone());
+        }
+
+        Self {
+            inner,
+            state: Arc::new(Mutex::new(CassetteState {
+                path,
+                mode,
+                cassette,
+                pending,
+            })),
+        }
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.state.lock().unwrap().mode
+    }
+
+    /// Write the recorded interactions now instead of when the transport is dropped
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        self.state.lock().unwrap().save()
+    }
+}
+
+impl<T> Service<RequestPacket> for CassetteTransport<T>
+where
+    T: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.inner {
+            Some(inner) => inner.poll_ready(cx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let state = Arc::clone(&self.state);
+
+        let Some(inner) = &mut self.inner else {
+            return Box::pin(async move {
+                let mut state = state.lock().unwrap();
+                match request {
+                    RequestPacket::Single(request) => Ok(ResponsePacket::Single(state.replay(&request)?)),
+                    RequestPacket::Batch(requests) => {
+                        let responses = requests
+                            .iter()
+                            .map(|request| state.replay(request))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(ResponsePacket::Batch(responses))
+                    }
+                }
+            });
+        };
+
+        let future = inner.call(request.clone());
+        Box::pin(async move {
+            let response = future.await?;
+
+            let requests: Vec<&SerializedRequest> = match &request {
+                RequestPacket::Single(request) => vec![request],
+                RequestPacket::Batch(requests) => requests.iter().collect(),
+            };
+            let responses: Vec<&Response> = match &response {
+                ResponsePacket::Single(response) => vec![response],
+                ResponsePacket::Batch(responses) => responses.iter().collect(),
+            };
+
+            let mut state = state.lock().unwrap();
+            for request in requests {
+                // the responses of a batch can come in any order
+                let Some(response) = responses.iter().find(|r| &r.id == request.id()) else {
+                    continue;
+                };
+                let (method, params) = request_key(request)?;
+
+                let mut response = serde_json::to_value(response).map_err(TransportError::ser_err)?;
+                if let Value::Object(map) = &mut response {
+                    map.remove("id");
+                }
+                trace!("Recorded {}", method);
+                state.cassette.interactions.push(Interaction {
+                    method,
+                    params,
+                    response,
+                });
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+/// The method and the params of a request
+fn request_key(request: &SerializedRequest) -> Result<(String, Value), TransportError> {
+    let params = match request.params() {
+        Some(params) => serde_json::from_str(params.get()).map_err(|e| TransportError::deser_err(e, params.get()))?,
+        None => Value::Null,
+    };
+    Ok((request.method().to_string(), params))
+}
+
+/// A provider for a test that replays `tests/cassettes/{name}.json`
+///
+/// The cassette is recorded from `url` only if [RECORD_ENV] is set, a missing cassette is an error otherwise
+/// so a test never reaches the network by accident
+///
+/// ## Arguments
+///
+/// * `name` - The name of the cassette, usually the name of the test
+/// * `url` - The RPC to record from, http, ws or ipc
+pub async fn cassette_provider(
+    name: &str,
+    url: &str,
+) -> Result<RootProvider<CassetteTransport<BoxTransport>>, anyhow::Error> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(CASSETTE_DIR)
+        .join(format!("{}.json", name));
+
+    let transport = if std::env::var_os(RECORD_ENV).is_some() {
+        trace!("Recording the cassette {}", path.display());
+        let provider = ProviderBuilder::new().on_builtin(url).await?;
+        CassetteTransport::record(provider.client().transport().clone(), path)
+    } else if path.exists() {
+        CassetteTransport::replay(path)?
+    } else {
+        return Err(anyhow::anyhow!(
+            "The cassette {} is missing, set {}=1 to record it",
+            path.display(),
+            RECORD_ENV
+        ));
+    };
+
+    Ok(ProviderBuilder::new().on_client(RpcClient::new(transport, false)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replay() {
+        let path = std::env::temp_dir().join("hello-eth-cassette-test.json");
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                method: "eth_blockNumber".to_string(),
+                params: Value::Null,
+                response: serde_json::json!({ "jsonrpc": "2.0", "result": "0x10" }),
+            }],
+        };
+        cassette.save(&path).unwrap();
+
+        let transport = CassetteTransport::<BoxTransport>::replay(&path).unwrap();
+        assert_eq!(transport.mode(), CassetteMode::Replay);
+        let client = ProviderBuilder::new().on_client(RpcClient::new(transport, false));
+
+        assert_eq!(client.get_block_number().await.unwrap(), 16);
+        // every response is replayed once
+        assert!(client.get_block_number().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_missing_cassette() {
+        if std::env::var_os(RECORD_ENV).is_some() {
+            return;
+        }
+        let err = cassette_provider("missing_cassette", "http://localhost:8545").await.err().unwrap();
+        assert!(err.to_string().contains("is missing"));
+    }
+}
@@ -0,0 +1,462 @@
+//! Tuning of the requests ([Config]) and analyses described in a file ([AnalysisConfig])
+
+use alloy_contract::private::Network;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+use super::BlockTime;
+use crate::abi::uniswap::pool::{v2, v3};
+use crate::defi::amm::pool::{AnyPool, DexKind};
+use crate::defi::amm::solidly::SolidlyPool;
+use crate::defi::amm::uniswap::{
+    v2::UniswapV2Pool,
+    v3::{
+        lp_provider::{DepositPricing, PositionArgs, ReplayMode},
+        UniswapV3Pool,
+    },
+    variant::DexVariant,
+};
+use crate::defi::currency::erc20::{ERC20Token, TokenKind};
+
+/// Tuning parameters for operations that make many requests to the provider
+///
+/// ## Example
+///
+/// ```ignore
+/// let config = Config::default()
+///     .with_max_concurrency(10)
+///     .with_log_chunk_size(10_000)
+///     .with_request_timeout(Duration::from_secs(30));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Maximum number of requests in flight at the same time, 0 is treated as 1, see [Config::concurrency]
+    pub max_concurrency: usize,
+
+    /// Maximum number of blocks queried by a single `eth_getLogs` request
+    pub log_chunk_size: u64,
+
+    /// Timeout for a single request, None means no timeout
+    pub request_timeout: Option<Duration>,
+}
+
+impl Config {
+    pub fn new(max_concurrency: usize, log_chunk_size: u64, request_timeout: Option<Duration>) -> Self {
+        Self {
+            max_concurrency: max_concurrency.max(1),
+            log_chunk_size: log_chunk_size.max(1),
+            request_timeout,
+        }
+    }
+
+    /// The number of requests allowed in flight, at least 1 even if `max_concurrency` was set to 0
+    pub fn concurrency(&self) -> usize {
+        self.max_concurrency.max(1)
+    }
+
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    pub fn with_log_chunk_size(mut self, log_chunk_size: u64) -> Self {
+        self.log_chunk_size = log_chunk_size.max(1);
+        self
+    }
+
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Await the future respecting the configured request timeout
+    pub async fn timed<F, R, E>(&self, fut: F) -> Result<R, anyhow::Error>
+    where
+        F: Future<Output = Result<R, E>>,
+        anyhow::Error: From<E>,
+    {
+        match self.request_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, fut).await {
+                Ok(res) => Ok(res?),
+                Err(_) => Err(anyhow::anyhow!("Request timed out after {:?}", timeout)),
+            },
+            None => Ok(fut.await?),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 5,
+            log_chunk_size: 100_000,
+            request_timeout: None,
+        }
+    }
+}
+
+/// An analysis described in a TOML or JSON file
+///
+/// Keeps the chain, the endpoints, the pools, the window and the strategy parameters of a research
+/// pipeline out of the source code
+///
+/// ```toml
+/// chain_id = 1
+///
+/// [rpc]
+/// url = "wss://eth.merkle.io"
+/// max_concurrency = 10
+///
+/// [[tokens]]
+/// symbol = "wstETH"
+/// address = "0x7f39C581F595B53c5cb19bD0b3f8dA6c935E2Ca0"
+/// kind = "LiquidStaking"
+///
+/// [[pools]]
+/// name = "wstETH/WETH 0.01%"
+/// address = "0x109830a1aaad605bbf02a9dfa7b0b92ec2fb7daa"
+/// dex = "UniswapV3"
+///
+/// [window]
+/// from = "2024-06-01"
+/// to = "2024-07-01"
+///
+/// [position]
+/// pool = "wstETH/WETH 0.01%"
+/// lower_range = 1.106
+/// upper_range = 1.197
+/// price_assumption = 1.167
+/// deposit_amount = 500000.0
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisConfig {
+    pub chain_id: u64,
+
+    #[serde(default)]
+    pub rpc: RpcConfig,
+
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+
+    #[serde(default)]
+    pub pools: Vec<PoolConfig>,
+
+    pub window: Option<WindowConfig>,
+
+    /// The parameters of [simulate_position](crate::defi::amm::uniswap::v3::lp_provider::simulate_position)
+    pub position: Option<PositionConfig>,
+
+    /// Free-form parameters of custom strategies
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The endpoints and the request tuning, see [Config]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RpcConfig {
+    /// The main endpoint, HTTP or WebSocket
+    pub url: Option<String>,
+
+    /// Fallback endpoints
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+
+    pub max_concurrency: Option<usize>,
+    pub log_chunk_size: Option<u64>,
+    pub request_timeout_secs: Option<u64>,
+}
+
+/// A token referenced by its symbol in the rest of the file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenConfig {
+    pub symbol: String,
+    pub address: Address,
+    pub kind: Option<TokenKind>,
+}
+
+/// A pool, its tokens are read from the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolConfig {
+    /// A name to reference the pool, the address can be used too
+    pub name: Option<String>,
+    pub address: Address,
+    pub dex: DexKind,
+
+    /// The fee tier of a V3 pool, read from the chain if not set
+    pub fee: Option<u32>,
+
+    #[serde(default)]
+    pub variant: DexVariant,
+}
+
+/// The blocks to look at, the first set field of `from`/`to`, `days`, `hours` and `from_block` is used
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WindowConfig {
+    /// The first day (UTC) as `YYYY-MM-DD`
+    pub from: Option<String>,
+
+    /// The day (UTC) after the last one as `YYYY-MM-DD`, today if not set
+    pub to: Option<String>,
+    pub days: Option<u64>,
+    pub hours: Option<u64>,
+    pub from_block: Option<u64>,
+}
+
+/// The liquidity position to simulate, see [PositionArgs]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionConfig {
+    /// The name or the address of a V3 pool of [AnalysisConfig::pools]
+    pub pool: String,
+    pub lower_range: f64,
+    pub upper_range: f64,
+    pub price_assumption: f64,
+    pub deposit_amount: f64,
+    pub epoch_blocks: Option<u64>,
+
+    #[serde(default)]
+    pub replay_mode: ReplayMode,
+
+    #[serde(default)]
+    pub deposit_pricing: DepositPricing,
+}
+
+impl AnalysisConfig {
+    /// An empty config of a chain
+    pub fn new(chain_id: u64) -> Self {
+        Self {
+            chain_id,
+            rpc: RpcConfig::default(),
+            tokens: Vec::new(),
+            pools: Vec::new(),
+            window: None,
+            position: None,
+            params: serde_json::Map::new(),
+        }
+    }
+
+    /// Read a `.json` or `.toml` file, TOML needs the `toml` feature
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&content),
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&content),
+            _ => Err(anyhow::anyhow!("Unsupported config file: {}", path.display())),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<Self, anyhow::Error> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    /// The request tuning, the unset fields keep their default
+    pub fn request_config(&self) -> Config {
+        let mut config = Config::default();
+        if let Some(max_concurrency) = self.rpc.max_concurrency {
+            config = config.with_max_concurrency(max_concurrency);
+        }
+        if let Some(log_chunk_size) = self.rpc.log_chunk_size {
+            config = config.with_log_chunk_size(log_chunk_size);
+        }
+        if let Some(secs) = self.rpc.request_timeout_secs {
+            config = config.with_request_timeout(Duration::from_secs(secs));
+        }
+        config
+    }
+
+    /// Resolve a token symbol of [AnalysisConfig::tokens] or an address
+    pub fn token_address(&self, token: &str) -> Result<Address, anyhow::Error> {
+        if let Some(t) = self.tokens.iter().find(|t| t.symbol.eq_ignore_ascii_case(token)) {
+            return Ok(t.address);
+        }
+        token
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Unknown token {}", token))
+    }
+
+    /// Find a pool by its name or address
+    pub fn pool(&self, pool: &str) -> Result<&PoolConfig, anyhow::Error> {
+        let address: Option<Address> = pool.parse().ok();
+        self.pools
+            .iter()
+            .find(|p| p.name.as_deref() == Some(pool) || Some(p.address) == address)
+            .ok_or_else(|| anyhow::anyhow!("Unknown pool {}", pool))
+    }
+
+    /// The window as a [BlockTime], a day if not set
+    pub fn block_time(&self) -> Result<BlockTime, anyhow::Error> {
+        let Some(window) = &self.window else {
+            return Ok(BlockTime::Days(1));
+        };
+
+        if let Some(from) = &window.from {
+            let start = parse_day(from)?;
+            let end = match &window.to {
+                Some(to) => parse_day(to)?,
+                None => Utc::now(),
+            };
+            if start >= end {
+                return Err(anyhow::anyhow!("The window starts after it ends"));
+            }
+            return Ok(BlockTime::Period(start, end));
+        }
+
+        if let Some(days) = window.days {
+            Ok(BlockTime::Days(days))
+        } else if let Some(hours) = window.hours {
+            Ok(BlockTime::Hours(hours))
+        } else if let Some(block) = window.from_block {
+            Ok(BlockTime::Block(block))
+        } else {
+            Err(anyhow::anyhow!("The window has no field set"))
+        }
+    }
+
+    /// The kind of a token, from [AnalysisConfig::tokens] or [TokenKind::Other]
+    fn token_kind(&self, address: Address) -> TokenKind {
+        self.tokens
+            .iter()
+            .find(|t| t.address == address)
+            .and_then(|t| t.kind.clone())
+            .unwrap_or(TokenKind::Other)
+    }
+
+    /// Load a pool and its tokens from the chain, the state is not fetched
+    pub async fn load_pool<T, P, N>(&self, client: P, pool: &PoolConfig) -> Result<AnyPool, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        if pool.dex == DexKind::Solidly {
+            return Ok(SolidlyPool::from_address(client, self.chain_id, pool.address).await?.into());
+        }
+
+        let (token0, token1) = match pool.dex {
+            DexKind::UniswapV2 => (
+                v2::token0(pool.address, client.clone()).await?,
+                v2::token1(pool.address, client.clone()).await?,
+            ),
+            _ => (
+                v3::token0(pool.address, client.clone()).await?,
+                v3::token1(pool.address, client.clone()).await?,
+            ),
+        };
+        let token0 = ERC20Token::new(client.clone(), token0, self.chain_id, self.token_kind(token0)).await?;
+        let token1 = ERC20Token::new(client.clone(), token1, self.chain_id, self.token_kind(token1)).await?;
+
+        let pool = match pool.dex {
+            DexKind::UniswapV2 => UniswapV2Pool::new(self.chain_id, pool.address, token0, token1)
+                .with_variant(pool.variant)
+                .into(),
+            _ => {
+                let fee = match pool.fee {
+                    Some(fee) => fee,
+                    None => v3::fee(pool.address, client).await?,
+                };
+                UniswapV3Pool::new(self.chain_id, pool.address, fee, token0, token1)
+                    .with_variant(pool.variant)?
+                    .into()
+            }
+        };
+        Ok(pool)
+    }
+
+    /// Load all the pools, see [Self::load_pool]
+    pub async fn load_pools<T, P, N>(&self, client: P) -> Result<Vec<AnyPool>, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let mut pools = Vec::with_capacity(self.pools.len());
+        for pool in &self.pools {
+            pools.push(self.load_pool(client.clone(), pool).await?);
+        }
+        Ok(pools)
+    }
+
+    /// The [PositionArgs] of the `position` section, its pool is loaded from the chain
+    pub async fn position_args<T, P, N>(&self, client: P) -> Result<PositionArgs, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let position = self
+            .position
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("The config has no position"))?;
+
+        let AnyPool::V3(pool) = self.load_pool(client, self.pool(&position.pool)?).await? else {
+            return Err(anyhow::anyhow!("The pool of the position is not a V3 pool"));
+        };
+
+        let mut args = PositionArgs::new(
+            position.lower_range,
+            position.upper_range,
+            position.price_assumption,
+            position.deposit_amount,
+            pool,
+        );
+        args.epoch_blocks = position.epoch_blocks;
+        args.replay_mode = position.replay_mode;
+        args.deposit_pricing = position.deposit_pricing;
+        Ok(args)
+    }
+}
+
+/// The start of a `YYYY-MM-DD` day in UTC
+fn parse_day(day: &str) -> Result<chrono::DateTime<Utc>, anyhow::Error> {
+    let date = NaiveDate::parse_from_str(day, "%Y-%m-%d")?;
+    let midnight = date
+        .and_hms_opt(0, 0, 0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid day {}", day))?;
+    Ok(Utc.from_utc_datetime(&midnight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analysis_config_from_json() {
+        let json = r#"{
+            "chain_id": 1,
+            "rpc": { "url": "https://eth.merkle.io", "max_concurrency": 12 },
+            "tokens": [{ "symbol": "WETH", "address": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "kind": "WETH" }],
+            "pools": [{ "name": "main", "address": "0x109830a1aaad605bbf02a9dfa7b0b92ec2fb7daa", "dex": "UniswapV3" }],
+            "window": { "from": "2024-06-01", "to": "2024-07-01" }
+        }"#;
+        let config = AnalysisConfig::from_json(json).unwrap();
+
+        assert_eq!(config.request_config().max_concurrency, 12);
+        assert_eq!(config.token_address("weth").unwrap(), config.tokens[0].address);
+        assert_eq!(config.pool("main").unwrap().variant, DexVariant::Uniswap);
+        assert!(matches!(config.block_time().unwrap(), BlockTime::Period(..)));
+        assert!(config.position.is_none());
+    }
+
+    #[test]
+    fn test_zero_concurrency() {
+        assert_eq!(Config::new(0, 0, None).concurrency(), 1);
+
+        let mut config = Config::default();
+        config.max_concurrency = 0;
+        assert_eq!(config.concurrency(), 1);
+    }
+}
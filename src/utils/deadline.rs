@@ -0,0 +1,39 @@
+//! Deadlines for router and position manager calls
+//!
+//! Deadlines are relative to the timestamp of the chain (the latest block) and not the local clock,
+//! so they also work on forks and simulations at past blocks
+
+use alloy_contract::private::Network;
+use alloy_network::{BlockResponse, HeaderResponse};
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
+
+/// The default time a transaction has to be included, 20 minutes
+pub const DEFAULT_DEADLINE_SECS: u64 = 20 * 60;
+
+/// A deadline `seconds` after `timestamp`
+pub fn deadline_after(timestamp: u64, seconds: u64) -> U256 {
+    U256::from(timestamp.saturating_add(seconds))
+}
+
+/// A deadline `seconds` after the timestamp of the latest block
+pub async fn deadline_from_latest<T, P, N>(client: P, seconds: u64) -> Result<U256, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = client
+        .get_block(BlockId::latest(), false.into())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Latest block not found"))?;
+
+    Ok(deadline_after(block.header().timestamp(), seconds))
+}
+
+/// Has the deadline passed at `timestamp`
+pub fn is_expired(deadline: U256, timestamp: u64) -> bool {
+    U256::from(timestamp) > deadline
+}
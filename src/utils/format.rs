@@ -0,0 +1,141 @@
+//! Formatting of token amounts and USD values for display
+//!
+//! Amounts are rounded on the integer value, so no precision is lost to floats before the last step
+
+use alloy_primitives::{
+    utils::{format_units, ParseUnits},
+    U256,
+};
+
+/// Convert an amount of a token to a float, eg. `1500000` with 6 decimals is `1.5`
+pub fn to_f64<T: Into<ParseUnits>>(amount: T, decimals: u8) -> Result<f64, anyhow::Error> {
+    Ok(format_units(amount, decimals)?.parse::<f64>()?)
+}
+
+/// Format an amount of a token rounded to `significant_digits`
+///
+/// The integer part is never rounded and trailing zeros are removed,
+/// eg. `1234567` with 3 decimals and 3 significant digits is `1235`, `1234` with 6 decimals is `0.00123`
+pub fn format_amount(
+    amount: U256,
+    decimals: u8,
+    significant_digits: usize,
+) -> Result<String, anyhow::Error> {
+    if amount.is_zero() {
+        return Ok("0".to_string());
+    }
+
+    let digits = amount.to_string().len();
+    let decimals_usize = decimals as usize;
+
+    // how many decimals to keep
+    let keep = if digits > decimals_usize {
+        let integer_digits = digits - decimals_usize;
+        significant_digits.saturating_sub(integer_digits)
+    } else {
+        // the leading zeros after the point don't count as significant
+        (decimals_usize - digits) + significant_digits
+    }
+    .min(decimals_usize);
+
+    let divisor = U256::from(10).pow(U256::from(decimals_usize - keep));
+    let rounded = (amount + divisor / U256::from(2)) / divisor;
+
+    let formatted = format_units(rounded, keep as u8)?;
+    Ok(trim_zeros(&formatted))
+}
+
+/// Format a USD value with thousands separators, eg. `$1,234.56`
+///
+/// Values below $1 keep 2 significant digits, eg. `$0.0012`
+pub fn format_usd(value: f64) -> String {
+    if !value.is_finite() {
+        return format!("${}", value);
+    }
+
+    let sign = if value < 0.0 { "-" } else { "" };
+    let value = value.abs();
+
+    if value != 0.0 && value < 1.0 {
+        let leading_zeros = (-value.log10()).floor() as usize;
+        return format!("{}${:.*}", sign, leading_zeros + 2, value);
+    }
+
+    let formatted = format!("{:.2}", value);
+    let (integer, fraction) = formatted.split_once('.').unwrap_or((&formatted, "00"));
+    format!("{}${}.{}", sign, group_thousands(integer), fraction)
+}
+
+/// Format a value in a compact notation, eg. `1.2K`, `3.45M`, `6.7B`
+///
+/// Values below 1000 are formatted with 2 decimals
+pub fn format_compact(value: f64) -> String {
+    const UNITS: [(f64, &str); 4] = [(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "K")];
+
+    let abs = value.abs();
+    for (size, suffix) in UNITS {
+        if abs >= size {
+            let formatted = format!("{:.2}", value / size);
+            return format!("{}{}", trim_zeros(&formatted), suffix);
+        }
+    }
+
+    trim_zeros(&format!("{:.2}", value))
+}
+
+/// [format_compact] with a dollar sign, eg. `$1.2M`
+pub fn format_usd_compact(value: f64) -> String {
+    if value < 0.0 {
+        format!("-${}", format_compact(-value))
+    } else {
+        format!("${}", format_compact(value))
+    }
+}
+
+fn trim_zeros(value: &str) -> String {
+    if !value.contains('.') {
+        return value.to_string();
+    }
+    value.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn group_thousands(integer: &str) -> String {
+    let mut grouped = String::with_capacity(integer.len() + integer.len() / 3);
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(U256::from(1234567), 3, 3).unwrap(), "1235");
+        assert_eq!(format_amount(U256::from(1234), 6, 3).unwrap(), "0.00123");
+        assert_eq!(format_amount(U256::from(1500000), 6, 4).unwrap(), "1.5");
+        assert_eq!(format_amount(U256::from(999999), 6, 2).unwrap(), "1");
+        assert_eq!(format_amount(U256::ZERO, 18, 4).unwrap(), "0");
+    }
+
+    #[test]
+    fn test_format_usd() {
+        assert_eq!(format_usd(1234567.891), "$1,234,567.89");
+        assert_eq!(format_usd(12.5), "$12.50");
+        assert_eq!(format_usd(0.001234), "$0.0012");
+        assert_eq!(format_usd(-999.0), "-$999.00");
+    }
+
+    #[test]
+    fn test_format_compact() {
+        assert_eq!(format_compact(1_200_000.0), "1.2M");
+        assert_eq!(format_compact(3_456.0), "3.46K");
+        assert_eq!(format_compact(12.0), "12");
+        assert_eq!(format_usd_compact(-2_000_000_000.0), "-$2B");
+    }
+}
@@ -0,0 +1,95 @@
+//! Attach transaction data to decoded logs
+//!
+//! A log only knows its transaction hash, the receipt adds who sent the transaction and what it paid
+
+use alloy_network::Ethereum;
+use alloy_primitives::TxHash;
+use alloy_provider::Provider;
+use alloy_rpc_types::TransactionReceipt;
+use alloy_transport::Transport;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::trace;
+
+use super::events::SwapData;
+use crate::utils::config::Config;
+
+/// Fetch the receipt of the transaction of each swap and fill `account` (the sender of the transaction),
+/// `gas_used` and `effective_gas_price`
+///
+/// Every transaction is fetched once, swaps whose receipt can't be fetched are left as they are
+///
+/// Returns the number of enriched swaps
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `swaps` - The swaps, eg. from [get_volume_from_logs](crate::defi::amm::uniswap::v3::UniswapV3Pool::get_volume_from_logs)
+/// * `config` - See [Config]
+pub async fn enrich_swaps<T, P>(
+    client: P,
+    swaps: &mut [SwapData],
+    config: &Config,
+) -> Result<usize, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum> + Clone + 'static,
+{
+    let mut seen = HashSet::new();
+    let mut tx_hashes: Vec<TxHash> = Vec::new();
+    for swap in swaps.iter() {
+        let hash = swap.tx_hash.parse::<TxHash>()?;
+        if seen.insert(hash) {
+            tx_hashes.push(hash);
+        }
+    }
+
+    let receipts = Arc::new(Mutex::new(HashMap::new()));
+    let semaphore = Arc::new(Semaphore::new(config.concurrency()));
+    let mut tasks: Vec<JoinHandle<Result<(), anyhow::Error>>> = Vec::new();
+
+    for tx_hash in tx_hashes {
+        let client = client.clone();
+        let receipts = receipts.clone();
+        let semaphore = semaphore.clone();
+        let config = config.clone();
+
+        let task = tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let receipt: TransactionReceipt = config
+                .timed(client.get_transaction_receipt(tx_hash))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Receipt of {} not found", tx_hash))?;
+            receipts.lock().await.insert(tx_hash, receipt);
+            Ok(())
+        });
+        tasks.push(task);
+    }
+
+    for task in tasks {
+        match task.await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => trace!("Failed to get the receipt: {:?}", e),
+            Err(e) => trace!("Receipt task panicked: {:?}", e),
+        }
+    }
+
+    let receipts = receipts.lock().await;
+    let mut enriched = 0;
+    for swap in swaps.iter_mut() {
+        let hash = swap.tx_hash.parse::<TxHash>()?;
+        let Some(receipt) = receipts.get(&hash) else {
+            continue;
+        };
+
+        swap.account = Some(receipt.from);
+        swap.gas_used = Some(receipt.gas_used as u64);
+        swap.effective_gas_price = Some(receipt.effective_gas_price);
+        enriched += 1;
+    }
+
+    Ok(enriched)
+}
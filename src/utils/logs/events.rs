@@ -1,4 +1,5 @@
 use crate::defi::currency::erc20::ERC20Token;
+use crate::defi::utils::labels::Labels;
 use alloy_primitives::{utils::format_units, Address, U256};
 use serde::{Deserialize, Serialize};
 
@@ -7,6 +8,7 @@ use serde::{Deserialize, Serialize};
 pub enum Event {
     Swap(SwapData),
     TokenTransfer(ERC20Transfer),
+    Approval(ERC20Approval),
 }
 
 impl Event {
@@ -18,6 +20,10 @@ impl Event {
         matches!(self, Event::TokenTransfer(_))
     }
 
+    pub fn is_approval(&self) -> bool {
+        matches!(self, Event::Approval(_))
+    }
+
     pub fn get_swap(&self) -> Option<&SwapData> {
         match self {
             Event::Swap(data) => Some(data),
@@ -31,18 +37,54 @@ impl Event {
             _ => None,
         }
     }
+
+    pub fn get_approval(&self) -> Option<&ERC20Approval> {
+        match self {
+            Event::Approval(data) => Some(data),
+            _ => None,
+        }
+    }
 }
 
 /// A swap that took place on a DEX (Uniswap)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SwapData {
+    /// The trader, the recipient of the Swap event or the sender of the transaction once enriched
     pub account: Option<Address>,
+
+    /// The `sender` of the Swap event, usually a router
+    #[serde(default)]
+    pub sender: Option<Address>,
+
+    /// The `recipient` of the Swap event, `to` for a Uniswap V2 pair
+    #[serde(default)]
+    pub recipient: Option<Address>,
     pub token_in: ERC20Token,
     pub token_out: ERC20Token,
     pub amount_in: U256,
     pub amount_out: U256,
     pub block: u64,
     pub tx_hash: String,
+
+    /// The sqrtPriceX96 of the pool after the swap (Uniswap V3 only)
+    #[serde(default)]
+    pub sqrt_price_x96: Option<U256>,
+
+    /// The tick of the pool after the swap (Uniswap V3 only)
+    #[serde(default)]
+    pub tick: Option<i32>,
+
+    /// The active liquidity of the pool after the swap (Uniswap V3 only)
+    #[serde(default)]
+    pub liquidity: Option<u128>,
+
+    /// The gas used by the whole transaction, see [enrich_swaps](super::enrich::enrich_swaps)
+    #[serde(default)]
+    pub gas_used: Option<u64>,
+
+    /// The effective gas price of the transaction in wei, see [enrich_swaps](super::enrich::enrich_swaps)
+    #[serde(default)]
+    pub effective_gas_price: Option<u128>,
 }
 
 impl SwapData {
@@ -57,12 +99,19 @@ impl SwapData {
     ) -> Self {
         Self {
             account,
+            sender: None,
+            recipient: None,
             token_in,
             token_out,
             amount_in,
             amount_out,
             block,
             tx_hash,
+            sqrt_price_x96: None,
+            tick: None,
+            liquidity: None,
+            gas_used: None,
+            effective_gas_price: None,
         }
     }
 
@@ -88,6 +137,21 @@ impl SwapData {
     }
 }
 
+impl SwapData {
+    /// Same as [SwapData::pretty] with the name of the account if it is labeled
+    pub fn pretty_with_labels(&self, labels: &Labels) -> Result<String, anyhow::Error> {
+        let pretty = self.pretty()?;
+        let Some(account) = self.account else {
+            return Ok(pretty);
+        };
+
+        match labels.get(self.token_in.chain_id, account) {
+            Some(label) => Ok(pretty.replacen(&account.to_string(), &label.name, 1)),
+            None => Ok(pretty),
+        }
+    }
+}
+
 /// An ERC20 Transfer that took place
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ERC20Transfer {
@@ -132,3 +196,61 @@ impl ERC20Transfer {
         Ok(s)
     }
 }
+
+/// An ERC20 Approval that took place
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ERC20Approval {
+    pub token: ERC20Token,
+    pub owner: Address,
+    pub spender: Address,
+
+    /// The new allowance, 0 for a revocation
+    pub amount: U256,
+    pub block: u64,
+    pub tx_hash: String,
+}
+
+impl ERC20Approval {
+    pub fn new(
+        token: ERC20Token,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+        block: u64,
+        tx_hash: String,
+    ) -> Self {
+        Self {
+            token,
+            owner,
+            spender,
+            amount,
+            block,
+            tx_hash,
+        }
+    }
+
+    /// An approval of `U256::MAX`, most tokens never decrease it
+    pub fn is_unlimited(&self) -> bool {
+        self.amount == U256::MAX
+    }
+
+    /// Return a formatted string to print in the console
+    pub fn pretty(&self) -> Result<String, anyhow::Error> {
+        let amount = if self.is_unlimited() {
+            "Unlimited".to_string()
+        } else {
+            format_units(self.amount, self.token.decimals)?
+        };
+
+        let s = format!(
+            "Approval: {} | Owner: {} -> Spender: {} | Amount: {} | Block: {} | Tx: {}",
+            self.token.symbol,
+            self.owner,
+            self.spender,
+            amount,
+            self.block,
+            self.tx_hash,
+        );
+        Ok(s)
+    }
+}
@@ -1,2 +1,3 @@
 pub mod query;
-pub mod events;
\ No newline at end of file
+pub mod events;
+pub mod enrich;
\ No newline at end of file
@@ -4,14 +4,18 @@ use alloy_rpc_types::{BlockNumberOrTag, Filter, Log};
 use alloy_contract::private::Network;
 use alloy_provider::Provider;
 use alloy_transport::Transport;
+use futures::stream::{self, StreamExt};
 
 use std::sync::Arc;
 use tokio::sync::{Mutex, Semaphore};
 use tokio::task::JoinHandle;
 
-use super::super::BlockTime;
+use super::super::{config::Config, BlockTime};
 use tracing::trace;
 
+#[cfg(feature = "telemetry")]
+use tracing::Instrument;
+
 /// Get logs based on a filter
 ///
 /// ## Arguments
@@ -33,62 +37,299 @@ where
     P: Provider<T, N> + Clone + 'static,
     N: Network,
 {
-    let latest_block = client.get_block_number().await?;
-    let from_block = block_time.go_back(chain_id, latest_block)?;
+    get_logs_with_config(
+        client,
+        chain_id,
+        target_address,
+        events,
+        block_time,
+        &Config::default(),
+    )
+    .await
+}
+
+/// Get logs based on a filter using the given [Config]
+///
+/// The block range is split into chunks of `config.log_chunk_size` blocks
+/// and at most `config.max_concurrency` chunks are queried at the same time
+///
+/// ## Arguments
+///
+/// * `client` - The provider client
+/// * `chain_id` - The chain id
+/// * `target_address` - The addresses you want to get logs for
+/// * `events` - The events you want to get logs for
+/// * `block_time` - The time range you want to get logs for
+/// * `config` - See [Config]
+#[cfg_attr(
+    feature = "telemetry",
+    tracing::instrument(
+        name = "get_logs",
+        skip_all,
+        fields(
+            chain_id = chain_id,
+            from_block = tracing::field::Empty,
+            to_block = tracing::field::Empty,
+            chunks = tracing::field::Empty,
+            logs = tracing::field::Empty
+        )
+    )
+)]
+pub async fn get_logs_with_config<T, P, N>(
+    client: P,
+    chain_id: u64,
+    target_address: Vec<Address>,
+    events: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    block_time: BlockTime,
+    config: &Config,
+) -> Result<Vec<Log>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone + 'static,
+    N: Network,
+{
+    let current_block = config.timed(client.get_block_number()).await?;
+    let (from_block, latest_block) = block_time
+        .block_range_with_client(client.clone(), chain_id, current_block)
+        .await?;
 
     trace!("Fetching logs from block {} to {}", from_block, latest_block);
 
-    let filter = Filter::new()
-        .address(target_address)
-        .events(events)
+    #[cfg(feature = "telemetry")]
+    {
+        let span = tracing::Span::current();
+        span.record("from_block", from_block);
+        span.record("to_block", latest_block);
+    }
+
+    get_logs_in_range(client, target_address, events, from_block, latest_block, config).await
+}
+
+/// Get the logs of a fixed block range, both ends included
+///
+/// The range is split into chunks like in [get_logs_with_config]
+///
+/// ## Arguments
+///
+/// * `client` - The provider client
+/// * `target_address` - The addresses you want to get logs for
+/// * `events` - The events you want to get logs for
+/// * `from_block` - The first block of the range
+/// * `latest_block` - The last block of the range
+/// * `config` - See [Config]
+pub async fn get_logs_in_range<T, P, N>(
+    client: P,
+    target_address: Vec<Address>,
+    events: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    from_block: u64,
+    latest_block: u64,
+    config: &Config,
+) -> Result<Vec<Log>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone + 'static,
+    N: Network,
+{
+    let filter = Filter::new().address(target_address).events(events);
+    get_filtered_logs_in_range(client, filter, from_block, latest_block, config).await
+}
+
+/// Same as [get_logs_in_range] with a custom [Filter], eg. to match an indexed topic
+///
+/// The block range of the filter is replaced by `from_block` - `latest_block`
+///
+/// ## Arguments
+///
+/// * `client` - The provider client
+/// * `filter` - The addresses, events and topics to match
+/// * `from_block` - The first block of the range
+/// * `latest_block` - The last block of the range
+/// * `config` - See [Config]
+pub async fn get_filtered_logs_in_range<T, P, N>(
+    client: P,
+    filter: Filter,
+    from_block: u64,
+    latest_block: u64,
+    config: &Config,
+) -> Result<Vec<Log>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone + 'static,
+    N: Network,
+{
+    if latest_block < from_block {
+        return Err(anyhow::anyhow!(
+            "The range ends at block {} before it starts at {}",
+            latest_block,
+            from_block
+        ));
+    }
+
+    let filter = filter
         .from_block(BlockNumberOrTag::Number(from_block))
         .to_block(BlockNumberOrTag::Number(latest_block));
 
     let logs = Arc::new(Mutex::new(Vec::new()));
-    let semaphore = Arc::new(Semaphore::new(5));
+    let semaphore = Arc::new(Semaphore::new(config.concurrency()));
+    let chunk_size = config.log_chunk_size;
 
     let mut tasks: Vec<JoinHandle<Result<(), anyhow::Error>>> = Vec::new();
 
-    if latest_block - from_block > 100_000 {
+    if latest_block - from_block > chunk_size {
         let mut start_block = from_block;
 
         while start_block <= latest_block {
-            let end_block = std::cmp::min(start_block + 100_000, latest_block);
+            let end_block = std::cmp::min(start_block + chunk_size, latest_block);
             let client_clone = client.clone();
             let logs_clone = Arc::clone(&logs);
             let filter_clone = filter.clone();
+            let config = config.clone();
             let permit = Arc::clone(&semaphore).acquire_owned().await?;
 
             trace!("Quering Logs for block range: {} - {}", start_block, end_block);
 
-            let task = tokio::spawn(async move {
+            let task = async move {
                 let local_filter = filter_clone
                     .from_block(BlockNumberOrTag::Number(start_block))
                     .to_block(BlockNumberOrTag::Number(end_block));
 
-                let log_chunk = client_clone.get_logs(&local_filter).await?;
+                let log_chunk = config.timed(client_clone.get_logs(&local_filter)).await?;
+                trace!("Received {} logs", log_chunk.len());
                 let mut logs_lock = logs_clone.lock().await;
                 logs_lock.extend(log_chunk);
                 drop(permit);
-                Ok(())
-            });
+                Ok::<(), anyhow::Error>(())
+            };
+
+            #[cfg(feature = "telemetry")]
+            let task = task.instrument(tracing::debug_span!("log_chunk", start_block, end_block));
 
-            tasks.push(task);
+            tasks.push(tokio::spawn(task));
             start_block = end_block + 1;
         }
 
+        #[cfg(feature = "telemetry")]
+        let tasks_len = tasks.len();
+
+        // a failed chunk would leave a hole in the logs, wait for all the chunks and fail the whole range
+        let mut error = None;
         for task in tasks {
-            match task.await {
-                Ok(_) => {}
-                Err(e) => {
-                    trace!("Error fetching logs: {:?}", e);
-                }
+            let res = match task.await {
+                Ok(res) => res,
+                Err(e) => Err(anyhow::anyhow!("Log task panicked: {:?}", e)),
+            };
+            if let Err(e) = res {
+                trace!("Error fetching logs: {:?}", e);
+                error.get_or_insert(e);
             }
         }
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        let logs = Arc::try_unwrap(logs).unwrap().into_inner();
+
+        #[cfg(feature = "telemetry")]
+        {
+            let span = tracing::Span::current();
+            span.record("chunks", tasks_len);
+            span.record("logs", logs.len());
+        }
 
-        return Ok(Arc::try_unwrap(logs).unwrap().into_inner());
+        return Ok(logs);
+    }
+
+    let log_chunk = config.timed(client.get_logs(&filter)).await?;
+
+    #[cfg(feature = "telemetry")]
+    {
+        let span = tracing::Span::current();
+        span.record("chunks", 1);
+        span.record("logs", log_chunk.len());
     }
 
-    let log_chunk = client.get_logs(&filter).await?;
     Ok(log_chunk)
-}
\ No newline at end of file
+}
+
+/// Fold the logs matching `filter` over a window without keeping them all in memory
+///
+/// The window is split into chunks of `log_chunk_size` blocks, see [fold_logs_with_config]
+///
+/// ## Arguments
+///
+/// * `client` - The provider client
+/// * `filter` - The addresses, events and topics to match, its block range is replaced by the window
+/// * `block_time` - The time range you want to fold the logs of
+/// * `init` - The initial value of the accumulator
+/// * `f` - Called with the accumulator and each log, in block order
+pub async fn fold_logs_for<T, P, N, A, F>(
+    client: P,
+    filter: Filter,
+    block_time: BlockTime,
+    init: A,
+    f: F,
+) -> Result<A, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+    F: FnMut(A, Log) -> A,
+{
+    fold_logs_with_config(client, filter, block_time, init, f, &Config::default()).await
+}
+
+/// Same as [fold_logs_for] with the given [Config]
+///
+/// At most `config.max_concurrency` chunks are fetched ahead, so the memory used is bounded by
+/// `max_concurrency * log_chunk_size` blocks of logs whatever the length of the window.
+/// A chunk that fails stops the fold with its error
+pub async fn fold_logs_with_config<T, P, N, A, F>(
+    client: P,
+    filter: Filter,
+    block_time: BlockTime,
+    init: A,
+    mut f: F,
+    config: &Config,
+) -> Result<A, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+    F: FnMut(A, Log) -> A,
+{
+    let chain_id = config.timed(client.get_chain_id()).await?;
+    let current_block = config.timed(client.get_block_number()).await?;
+    let (from_block, latest_block) = block_time
+        .block_range_with_client(client.clone(), chain_id, current_block)
+        .await?;
+
+    let chunk_size = config.log_chunk_size.max(1);
+    let chunks: Vec<(u64, u64)> = (from_block..=latest_block)
+        .step_by(chunk_size as usize)
+        .map(|start| (start, (start + chunk_size - 1).min(latest_block)))
+        .collect();
+    trace!("Folding logs from block {} to {} in {} chunks", from_block, latest_block, chunks.len());
+
+    // buffered keeps the chunks in order while fetching the next ones
+    let mut chunks = stream::iter(chunks)
+        .map(|(start, end)| {
+            let client = client.clone();
+            let filter = filter
+                .clone()
+                .from_block(BlockNumberOrTag::Number(start))
+                .to_block(BlockNumberOrTag::Number(end));
+            async move { config.timed(client.get_logs(&filter)).await }
+        })
+        .buffered(config.concurrency());
+
+    let mut acc = init;
+    while let Some(logs) = chunks.next().await {
+        let mut logs = logs?;
+        logs.sort_by_key(|log| (log.block_number, log.log_index));
+        for log in logs {
+            acc = f(acc, log);
+        }
+    }
+
+    Ok(acc)
+}
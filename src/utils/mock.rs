@@ -0,0 +1,313 @@
+//! An in-memory provider to unit test code built on this crate without a node
+//!
+//! [MockProvider] implements [Provider] on top of a transport that answers from programmed responses:
+//! `eth_call`s by target and calldata (or selector), `eth_getLogs` from a list of logs filtered like a node would,
+//! and a few static methods like `eth_blockNumber`. A call without a response fails with a JSON-RPC error,
+//! so a missing mock shows up as a failed call and not as a hang.
+//!
+//! ```ignore
+//! let client = MockProvider::new()
+//!     .with_block_number(20_000_000)
+//!     .with_erc20(&weth)
+//!     .with_reserves(pair, reserve0, reserve1)
+//!     .with_balance(weth.address, owner, U256::from(10).pow(U256::from(18)));
+//!
+//! let state = UniswapV2Pool::fetch_state(client.clone(), pair, None).await?;
+//! ```
+
+use alloy_json_rpc::{RequestPacket, Response, ResponsePacket, SerializedRequest};
+use alloy_network::Ethereum;
+use alloy_primitives::{Address, Bytes, I256, U256};
+use alloy_provider::{Provider, ProviderBuilder, RootProvider};
+use alloy_rpc_client::RpcClient;
+use alloy_rpc_types::{Filter, Log, TransactionRequest};
+use alloy_sol_types::{SolCall, SolValue};
+use alloy_transport::{TransportError, TransportFut};
+use serde_json::{json, Value};
+use tower::Service;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::abi::erc20::ERC20;
+use crate::abi::uniswap::pool::{v2::IUniswapV2Pair, v3::IUniswapV3Pool};
+use crate::defi::currency::erc20::ERC20Token;
+
+/// The code of the JSON-RPC error of a request without a programmed response
+pub const NO_RESPONSE_CODE: i64 = -32601;
+
+/// The programmed responses
+#[derive(Debug, Default)]
+struct MockState {
+    /// (to, calldata) -> return data
+    calls: HashMap<(Address, Bytes), Bytes>,
+
+    /// (to, selector) -> return data, used when no exact calldata matches
+    selectors: HashMap<(Address, [u8; 4]), Bytes>,
+
+    logs: Vec<Log>,
+
+    /// method -> result, for the methods without params that matter (eg. `eth_blockNumber`)
+    methods: HashMap<String, Value>,
+
+    /// The methods received, in order
+    received: Vec<String>,
+}
+
+impl MockState {
+    fn respond(&mut self, request: &SerializedRequest) -> Result<Value, String> {
+        let method = request.method().to_string();
+        self.received.push(method.clone());
+
+        let params: Value = match request.params() {
+            Some(params) => serde_json::from_str(params.get()).map_err(|e| e.to_string())?,
+            None => Value::Null,
+        };
+
+        match method.as_str() {
+            "eth_call" => {
+                let tx: TransactionRequest =
+                    serde_json::from_value(params[0].clone()).map_err(|e| e.to_string())?;
+                let to = tx.to.and_then(|to| to.to().copied()).unwrap_or_default();
+                let data = tx.input.input().cloned().unwrap_or_default();
+
+                let result = self.calls.get(&(to, data.clone())).or_else(|| {
+                    let selector: [u8; 4] = data.get(..4)?.try_into().ok()?;
+                    self.selectors.get(&(to, selector))
+                });
+                match result {
+                    Some(result) => Ok(json!(result)),
+                    None => Err(format!("No mock response for eth_call to {} with {}", to, data)),
+                }
+            }
+            "eth_getLogs" => {
+                let filter: Filter = serde_json::from_value(params[0].clone()).map_err(|e| e.to_string())?;
+                let logs: Vec<&Log> = self.logs.iter().filter(|log| log_matches(&filter, log)).collect();
+                Ok(json!(logs))
+            }
+            _ => self
+                .methods
+                .get(&method)
+                .cloned()
+                .ok_or_else(|| format!("No mock response for {}", method)),
+        }
+    }
+
+    fn response(&mut self, request: &SerializedRequest) -> Result<Response, TransportError> {
+        let response = match self.respond(request) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id(), "result": result }),
+            Err(message) => json!({
+                "jsonrpc": "2.0",
+                "id": request.id(),
+                "error": { "code": NO_RESPONSE_CODE, "message": message },
+            }),
+        };
+        let response = response.to_string();
+        serde_json::from_str(&response).map_err(|e| TransportError::deser_err(e, response))
+    }
+}
+
+/// The transport of a [MockProvider]
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl Service<RequestPacket> for MockTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let state = Arc::clone(&self.state);
+        Box::pin(async move {
+            let mut state = state.lock().unwrap();
+            match request {
+                RequestPacket::Single(request) => Ok(ResponsePacket::Single(state.response(&request)?)),
+                RequestPacket::Batch(requests) => {
+                    let responses = requests
+                        .iter()
+                        .map(|request| state.response(request))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(ResponsePacket::Batch(responses))
+                }
+            }
+        })
+    }
+}
+
+/// A [Provider] with programmed responses, see the [module](self) docs
+#[derive(Debug, Clone)]
+pub struct MockProvider {
+    transport: MockTransport,
+    inner: RootProvider<MockTransport, Ethereum>,
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Provider<MockTransport, Ethereum> for MockProvider {
+    fn root(&self) -> &RootProvider<MockTransport, Ethereum> {
+        &self.inner
+    }
+}
+
+impl MockProvider {
+    /// A provider for chain 1 at block 1
+    pub fn new() -> Self {
+        let transport = MockTransport::default();
+        let inner = ProviderBuilder::new().on_client(RpcClient::new(transport.clone(), true));
+        Self { transport, inner }
+            .with_chain_id(1)
+            .with_block_number(1)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.transport.state.lock().unwrap()
+    }
+
+    /// Answer any JSON-RPC method without a dedicated handler
+    pub fn with_method(self, method: &str, result: Value) -> Self {
+        self.state().methods.insert(method.to_string(), result);
+        self
+    }
+
+    pub fn with_chain_id(self, chain_id: u64) -> Self {
+        self.with_method("eth_chainId", json!(format!("0x{:x}", chain_id)))
+    }
+
+    pub fn with_block_number(self, block: u64) -> Self {
+        self.with_method("eth_blockNumber", json!(format!("0x{:x}", block)))
+    }
+
+    /// Return `result` for an `eth_call` to `to` with exactly `data`
+    pub fn with_call(self, to: Address, data: impl Into<Bytes>, result: impl Into<Bytes>) -> Self {
+        self.state().calls.insert((to, data.into()), result.into());
+        self
+    }
+
+    /// Return `result` for any `eth_call` to `to` with this selector, whatever the arguments
+    pub fn with_selector(self, to: Address, selector: [u8; 4], result: impl Into<Bytes>) -> Self {
+        self.state().selectors.insert((to, selector), result.into());
+        self
+    }
+
+    /// The `slot0` of a V3 pool, the other fields are 0 and unlocked is true
+    pub fn with_slot0(self, pool: Address, sqrt_price_x96: U256, tick: i32) -> Self {
+        let result = (sqrt_price_x96, I256::try_from(tick).unwrap(), 0u16, 0u16, 0u16, 0u8, true).abi_encode();
+        self.with_selector(pool, IUniswapV3Pool::slot0Call::SELECTOR, result)
+    }
+
+    /// The `getReserves` of a V2 pair, with a last update timestamp of 0
+    pub fn with_reserves(self, pair: Address, reserve0: U256, reserve1: U256) -> Self {
+        let result = (reserve0, reserve1, 0u32).abi_encode();
+        self.with_selector(pair, IUniswapV2Pair::getReservesCall::SELECTOR, result)
+    }
+
+    /// The `balanceOf` of `owner` for a token
+    pub fn with_balance(self, token: Address, owner: Address, balance: U256) -> Self {
+        let call = ERC20::balanceOfCall { owner };
+        self.with_call(token, call.abi_encode(), balance.abi_encode())
+    }
+
+    /// The metadata read by [ERC20Token::new]: name, symbol, decimals and total supply
+    pub fn with_erc20(self, token: &ERC20Token) -> Self {
+        let address = token.address;
+        self.with_selector(
+            address,
+            ERC20::nameCall::SELECTOR,
+            ERC20::nameCall::abi_encode_returns(&(token.name.clone(),)),
+        )
+        .with_selector(
+            address,
+            ERC20::symbolCall::SELECTOR,
+            ERC20::symbolCall::abi_encode_returns(&(token.symbol.clone(),)),
+        )
+        .with_selector(address, ERC20::decimalsCall::SELECTOR, token.decimals.abi_encode())
+        .with_selector(address, ERC20::totalSupplyCall::SELECTOR, token.total_supply.abi_encode())
+    }
+
+    /// Logs returned by `eth_getLogs` when they match the filter (addresses, topics and block range)
+    pub fn with_logs(self, logs: impl IntoIterator<Item = Log>) -> Self {
+        self.state().logs.extend(logs);
+        self
+    }
+
+    /// The methods received so far, in order
+    pub fn received(&self) -> Vec<String> {
+        self.state().received.clone()
+    }
+}
+
+fn log_matches(filter: &Filter, log: &Log) -> bool {
+    if !filter.address.matches(&log.address()) {
+        return false;
+    }
+
+    let topics = log.topics();
+    for (i, topic) in filter.topics.iter().enumerate() {
+        if topic.is_empty() {
+            continue;
+        }
+        match topics.get(i) {
+            Some(value) if topic.matches(value) => {}
+            _ => return false,
+        }
+    }
+
+    let block = log.block_number.unwrap_or_default();
+    let after_start = filter.get_from_block().map_or(true, |from| block >= from);
+    let before_end = filter.get_to_block().map_or(true, |to| block <= to);
+    after_start && before_end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defi::amm::uniswap::v2::UniswapV2Pool;
+    use alloy_primitives::{address, LogData, B256};
+
+    #[tokio::test]
+    async fn test_mock_provider() {
+        let pair = address!("0000000000000000000000000000000000000001");
+        let owner = address!("0000000000000000000000000000000000000002");
+        let topic = B256::repeat_byte(1);
+
+        let log = |block| Log {
+            inner: alloy_primitives::Log {
+                address: pair,
+                data: LogData::new_unchecked(vec![topic], Bytes::new()),
+            },
+            block_number: Some(block),
+            ..Default::default()
+        };
+
+        let client = MockProvider::new()
+            .with_block_number(100)
+            .with_reserves(pair, U256::from(10), U256::from(20))
+            .with_balance(pair, owner, U256::from(5))
+            .with_logs([log(10), log(50)]);
+
+        assert_eq!(client.get_block_number().await.unwrap(), 100);
+
+        let state = UniswapV2Pool::fetch_state(client.clone(), pair, None).await.unwrap();
+        assert_eq!((state.reserve0, state.reserve1), (U256::from(10), U256::from(20)));
+        // tagged with the block it was read at
+        assert_eq!(state.block, 100);
+
+        let balance = ERC20::new(pair, client.clone()).balanceOf(owner).call().await.unwrap();
+        assert_eq!(balance.balance, U256::from(5));
+        assert!(ERC20::new(pair, client.clone()).balanceOf(pair).call().await.is_err());
+
+        let filter = Filter::new().address(pair).event_signature(topic).from_block(20).to_block(60);
+        assert_eq!(client.get_logs(&filter).await.unwrap().len(), 1);
+    }
+}
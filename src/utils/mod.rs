@@ -1,19 +1,36 @@
 pub mod logs;
 pub mod batch_request;
-
+pub mod config;
+pub mod trace;
+pub mod rpc_simulation;
+pub mod arbitrum;
+pub mod storage;
+pub mod rpc_batch;
+pub mod deadline;
+pub mod format;
+pub mod timestamps;
+pub mod report;
+pub mod cassette;
+pub mod mock;
+pub mod submit;
+
+use alloy_contract::private::Network;
+use alloy_provider::Provider;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
 use anyhow::anyhow;
+use chrono::{DateTime, Utc};
 
-/*
-Legend:
-1 Hour in Eth = 300 blocks
-1 Day in Eth = 7200 blocks
+use timestamps::BlockTimestamps;
+use crate::ChainId;
 
-1 Hour in Bsc = 1200 blocks
-1 Day in Bsc = 28800 blocks
+// The number of blocks in an hour or a day comes from the block time of the chain,
+// see [ChainId::blocks_per_hour]
 
-1 Hour in OP Chains = 1800 blocks
-1 Day in OP Chains = 43200 blocks
-*/
+/// The average block time of a chain in seconds, None if unknown
+pub fn average_block_secs(chain_id: u64) -> Option<f64> {
+    ChainId::try_new(chain_id).ok().map(|chain| chain.average_block_secs())
+}
 
 /// Enum to express time in blocks (hours, days, block number)
 #[derive(Debug, Clone)]
@@ -27,28 +44,23 @@ pub enum BlockTime {
     /// Go back at X block
     Block(u64),
 
-    // TODO
-    // Choose a start and end time period
-   // Period(Date, Date),
+    /// From the first block at or after the start to the last block before the end
+    ///
+    /// The blocks are found by their timestamps so this needs a client,
+    /// see [BlockTime::go_back_with_client] and [BlockTime::block_range_with_client]
+    Period(DateTime<Utc>, DateTime<Utc>),
 }
 
 impl BlockTime {
     /// Go back X blocks from the current block
     pub fn go_back(&self, chain_id: u64, current_block: u64) -> Result<u64, anyhow::Error> {
         let blocks_to_subtract = match self {
-            BlockTime::Hours(hours) => match chain_id {
-                1 => hours * 300,
-                56 => hours * 1200,
-                8453 => hours * 1800,
-                _ => return Err(anyhow!("Unsupported chain_id: {}", chain_id)),
-            },
-            BlockTime::Days(days) => match chain_id {
-                1 => days * 7200,
-                56 => days * 28800,
-                8453 => days * 43200,
-                _ => return Err(anyhow!("Unsupported chain_id: {}", chain_id)),
-            },
+            BlockTime::Hours(hours) => hours * ChainId::try_new(chain_id)?.blocks_per_hour(),
+            BlockTime::Days(days) => days * ChainId::try_new(chain_id)?.blocks_per_day(),
             BlockTime::Block(block) => return Ok(*block),
+            BlockTime::Period(..) => {
+                return Err(anyhow!("A period needs a client, use go_back_with_client"))
+            }
         };
 
         if blocks_to_subtract > current_block {
@@ -61,24 +73,114 @@ impl BlockTime {
     /// Go forward X blocks from the start block
     pub fn go_forward(&self, chain_id: u64, start_block: u64) -> Result<u64, anyhow::Error> {
         let blocks_to_add = match self {
-            BlockTime::Hours(hours) => match chain_id {
-                1 => hours * 300,
-                56 => hours * 1200,
-                8453 => hours * 1800,
-                _ => return Err(anyhow!("Unsupported chain_id: {}", chain_id)),
-            },
-            BlockTime::Days(days) => match chain_id {
-                1 => days * 7200,
-                56 => days * 28800,
-                8453 => days * 43200,
-                _ => return Err(anyhow!("Unsupported chain_id: {}", chain_id)),
-            },
+            BlockTime::Hours(hours) => hours * ChainId::try_new(chain_id)?.blocks_per_hour(),
+            BlockTime::Days(days) => days * ChainId::try_new(chain_id)?.blocks_per_day(),
             BlockTime::Block(block) => *block,
+            BlockTime::Period(start, end) => {
+                let block_secs = average_block_secs(chain_id)
+                    .ok_or_else(|| anyhow!("Unsupported chain_id: {}", chain_id))?;
+                let seconds = (*end - *start).num_seconds().max(0) as f64;
+                (seconds / block_secs) as u64
+            }
         };
 
         Ok(start_block + blocks_to_add)
     }
 
+    /// Go back X blocks from the current block
+    ///
+    /// On chains with variable block times (Arbitrum) the start block is found by its timestamp,
+    /// on the other chains this is the same as [BlockTime::go_back]
+    pub async fn go_back_with_client<T, P, N>(
+        &self,
+        client: P,
+        chain_id: u64,
+        current_block: u64,
+    ) -> Result<u64, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        if let BlockTime::Period(start, _) = self {
+            let mut timestamps = BlockTimestamps::new();
+            return timestamps
+                .block_at(client, start.timestamp().max(0) as u64, current_block)
+                .await;
+        }
+
+        if !arbitrum::is_arbitrum(chain_id) {
+            return self.go_back(chain_id, current_block);
+        }
+
+        let seconds = match self {
+            BlockTime::Hours(hours) => hours * 3600,
+            BlockTime::Days(days) => days * 86400,
+            BlockTime::Block(block) => return Ok(*block),
+            BlockTime::Period(..) => unreachable!(),
+        };
+
+        let mut timestamps = BlockTimestamps::new();
+        let timestamp = timestamps.timestamp(client.clone(), current_block).await?;
+
+        if seconds > timestamp {
+            return Err(anyhow!("Starting block is greater than the current block"));
+        }
+
+        timestamps
+            .block_at(client, timestamp - seconds, current_block)
+            .await
+    }
+
+    /// The first and last block of the time range, the last block is `current_block` unless this is a [BlockTime::Period]
+    pub async fn block_range_with_client<T, P, N>(
+        &self,
+        client: P,
+        chain_id: u64,
+        current_block: u64,
+    ) -> Result<(u64, u64), anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let BlockTime::Period(start, end) = self else {
+            let from_block = self
+                .go_back_with_client(client, chain_id, current_block)
+                .await?;
+            return Ok((from_block, current_block));
+        };
+
+        if end <= start {
+            return Err(anyhow!("The period ends before it starts"));
+        }
+
+        let mut timestamps = BlockTimestamps::new();
+        let from_block = timestamps
+            .block_at(client.clone(), start.timestamp().max(0) as u64, current_block)
+            .await?;
+        let to_block = timestamps
+            .block_at(client, end.timestamp().max(0) as u64, current_block)
+            .await?;
+
+        // block_at returns the first block at or after the end
+        Ok((from_block, to_block.saturating_sub(1).max(from_block)))
+    }
+
+    /// The length of the time range in seconds, None for [BlockTime::Block]
+    pub fn seconds(&self) -> Option<u64> {
+        match self {
+            BlockTime::Hours(hours) => Some(hours * 3600),
+            BlockTime::Days(days) => Some(days * 86400),
+            BlockTime::Block(_) => None,
+            BlockTime::Period(start, end) => Some((*end - *start).num_seconds().max(0) as u64),
+        }
+    }
+
+    pub fn is_period(&self) -> bool {
+        matches!(self, BlockTime::Period(..))
+    }
+
     pub fn is_day(&self) -> bool {
         match self {
             BlockTime::Days(_) => true,
@@ -99,4 +201,19 @@ impl BlockTime {
             _ => false,
         }
     }
-}
\ No newline at end of file
+}
+
+/// The number of `block`, the latest block number if None
+///
+/// Used to tag a fetched state with the block it was read at
+pub async fn block_number<T, P, N>(client: P, block: Option<BlockId>) -> Result<u64, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    match block.and_then(|b| b.as_u64()) {
+        Some(block) => Ok(block),
+        None => Ok(client.get_block_number().await?),
+    }
+}
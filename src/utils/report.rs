@@ -0,0 +1,189 @@
+//! Shareable simulation results
+//!
+//! A [Report] bundles a result (eg. a [PositionResult](crate::defi::amm::uniswap::v3::lp_provider::PositionResult)
+//! or a [Quote](crate::defi::amm::quote::Quote)) with what is needed to reproduce it: the chain, the block range,
+//! the version of the crate and the config of the run.
+//!
+//! The report can be signed, anyone with the JSON can then check that it was not edited
+//! and who produced it with [Report::verify]
+//!
+//! ```ignore
+//! let report = Report::new(chain_id, from_block, to_block, &result)?
+//!     .with_config(&args_summary)?
+//!     .sign(&signer)
+//!     .await?;
+//! std::fs::write("report.json", report.to_json()?)?;
+//!
+//! let report = Report::from_json(&std::fs::read_to_string("report.json")?)?;
+//! let signer = report.verify()?;
+//! ```
+
+use alloy_primitives::{hex, keccak256, Address, Signature, B256};
+use alloy_signer::Signer;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where and how a result was produced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportMetadata {
+    pub chain_id: u64,
+    pub from_block: u64,
+    pub to_block: u64,
+
+    /// The name and version of the crate that produced the result
+    pub code_version: String,
+
+    /// Unix timestamp of the creation of the report
+    pub created_at: u64,
+
+    /// The config of the run, Null if not set
+    pub config: serde_json::Value,
+}
+
+/// The signature of a [Report]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportSignature {
+    pub signer: Address,
+
+    /// The hex encoded 65 bytes signature of [Report::hash]
+    pub signature: String,
+}
+
+/// A result with its metadata, see the [module](self) docs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub metadata: ReportMetadata,
+
+    /// The serialized result
+    pub result: serde_json::Value,
+
+    pub signature: Option<ReportSignature>,
+}
+
+/// The signed part of a [Report]
+#[derive(Serialize)]
+struct ReportBody<'a> {
+    metadata: &'a ReportMetadata,
+    result: &'a serde_json::Value,
+}
+
+impl Report {
+    /// Create an unsigned report
+    ///
+    /// ## Arguments
+    ///
+    /// * `chain_id` - The chain of the simulation
+    /// * `from_block` - The first block of the simulation
+    /// * `to_block` - The last block of the simulation
+    /// * `result` - The result, anything that implements [Serialize]
+    pub fn new<R: Serialize>(
+        chain_id: u64,
+        from_block: u64,
+        to_block: u64,
+        result: &R,
+    ) -> Result<Self, anyhow::Error> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        Ok(Self {
+            metadata: ReportMetadata {
+                chain_id,
+                from_block,
+                to_block,
+                code_version: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+                created_at,
+                config: serde_json::Value::Null,
+            },
+            result: serde_json::to_value(result)?,
+            signature: None,
+        })
+    }
+
+    /// Attach the config of the run, this removes any signature
+    pub fn with_config<C: Serialize>(mut self, config: &C) -> Result<Self, anyhow::Error> {
+        self.metadata.config = serde_json::to_value(config)?;
+        self.signature = None;
+        Ok(self)
+    }
+
+    /// Deserialize the result, eg. `report.result_as::<Quote>()`
+    pub fn result_as<R: DeserializeOwned>(&self) -> Result<R, anyhow::Error> {
+        Ok(serde_json::from_value(self.result.clone())?)
+    }
+
+    /// The keccak256 of the JSON of the metadata and the result
+    ///
+    /// The JSON objects have sorted keys so the hash doesn't depend on how the report was parsed
+    pub fn hash(&self) -> Result<B256, anyhow::Error> {
+        let body = ReportBody {
+            metadata: &self.metadata,
+            result: &self.result,
+        };
+        Ok(keccak256(serde_json::to_vec(&body)?))
+    }
+
+    /// Sign the [hash](Self::hash) of the report
+    pub async fn sign<S: Signer>(mut self, signer: &S) -> Result<Self, anyhow::Error> {
+        let signature = signer.sign_hash(&self.hash()?).await?;
+        self.signature = Some(ReportSignature {
+            signer: signer.address(),
+            signature: hex::encode_prefixed(signature.as_bytes()),
+        });
+        Ok(self)
+    }
+
+    /// Check the signature against the content of the report and return the signer
+    ///
+    /// Fails if the report is not signed or was changed after signing
+    pub fn verify(&self) -> Result<Address, anyhow::Error> {
+        let signed = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("The report is not signed"))?;
+
+        let bytes = hex::decode(&signed.signature)?;
+        let signature = Signature::try_from(bytes.as_slice())?;
+        let recovered = signature.recover_address_from_prehash(&self.hash()?)?;
+
+        if recovered != signed.signer {
+            return Err(anyhow::anyhow!(
+                "The report was signed by {} but recovered {}",
+                signed.signer,
+                recovered
+            ));
+        }
+        Ok(recovered)
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_signer_local::PrivateKeySigner;
+
+    #[tokio::test]
+    async fn test_signed_report_round_trip() {
+        let signer = PrivateKeySigner::random();
+        let report = Report::new(1, 100, 200, &vec![1u64, 2, 3])
+            .unwrap()
+            .sign(&signer)
+            .await
+            .unwrap();
+
+        let parsed = Report::from_json(&report.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.verify().unwrap(), signer.address());
+        assert_eq!(parsed.result_as::<Vec<u64>>().unwrap(), vec![1, 2, 3]);
+
+        let mut tampered = parsed.clone();
+        tampered.metadata.to_block = 300;
+        assert!(tampered.verify().is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Send many `eth_call`s in a single JSON-RPC batch request
+//!
+//! Each call still executes independently on the node and can fail on its own,
+//! but the whole batch costs a single round trip
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, Bytes};
+use alloy_provider::Provider;
+use alloy_rpc_client::BatchRequest;
+use alloy_rpc_types::{BlockId, TransactionInput, TransactionRequest};
+use alloy_transport::Transport;
+
+/// A batch of `eth_call`s executed at the same block
+///
+/// ## Example
+///
+/// ```ignore
+/// let mut batch = EthCallBatch::new(None);
+/// let symbol = batch.add(token, encode_symbol());
+/// let decimals = batch.add(token, encode_decimals());
+/// let results = batch.send(client).await?;
+/// let symbol = results[symbol].as_ref()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EthCallBatch {
+    pub block: BlockId,
+    pub calls: Vec<(Address, Bytes)>,
+}
+
+impl EthCallBatch {
+    /// Create an empty batch executed at `block`, None for the latest block
+    pub fn new(block: Option<BlockId>) -> Self {
+        Self {
+            block: block.unwrap_or(BlockId::latest()),
+            calls: Vec::new(),
+        }
+    }
+
+    /// Add a call to the batch and return its index in the results
+    pub fn add(&mut self, to: Address, data: Bytes) -> usize {
+        self.calls.push((to, data));
+        self.calls.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Send the batch
+    ///
+    /// The outer error is a transport error, the inner ones are the errors (eg. reverts) of the individual calls
+    pub async fn send<T, P, N>(
+        self,
+        client: P,
+    ) -> Result<Vec<Result<Bytes, anyhow::Error>>, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        if self.calls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut batch = BatchRequest::new(client.client());
+        let mut waiters = Vec::with_capacity(self.calls.len());
+
+        for (to, data) in self.calls {
+            let tx = TransactionRequest::default()
+                .to(to)
+                .input(TransactionInput::new(data));
+            let waiter = batch.add_call::<_, Bytes>("eth_call", &(tx, self.block))?;
+            waiters.push(waiter);
+        }
+
+        batch.send().await?;
+
+        let mut results = Vec::with_capacity(waiters.len());
+        for waiter in waiters {
+            results.push(waiter.await.map_err(anyhow::Error::from));
+        }
+
+        Ok(results)
+    }
+}
+
+/// Take the result of a call out of the batch results
+pub fn take_result(
+    results: &mut [Result<Bytes, anyhow::Error>],
+    index: usize,
+) -> Result<Bytes, anyhow::Error> {
+    std::mem::replace(&mut results[index], Ok(Bytes::new()))
+}
@@ -0,0 +1,429 @@
+//! Typed wrappers for the simulation RPC methods offered by some nodes
+//!
+//! * `eth_simulateV1` - geth, reth, nethermind and most hosted providers
+//! * `eth_callMany` - erigon and reth
+//! * `eth_callBundle` - flashbots compatible builders and relays
+//!
+//! These are an alternative to simulating with revm when consensus accurate results on recent blocks are needed
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, Bytes, TxHash, B256, I256, U256, U64};
+use alloy_provider::Provider;
+use alloy_rpc_types::{
+    state::StateOverride, BlockId, BlockNumberOrTag, BlockOverrides, Log, TransactionRequest,
+};
+use alloy_transport::Transport;
+use serde::{Deserialize, Serialize};
+
+/// A block of calls to simulate with `eth_simulateV1`
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimBlock {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_overrides: Option<BlockOverrides>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_overrides: Option<StateOverride>,
+
+    pub calls: Vec<TransactionRequest>,
+}
+
+impl SimBlock {
+    pub fn new(calls: Vec<TransactionRequest>) -> Self {
+        Self {
+            calls,
+            ..Default::default()
+        }
+    }
+}
+
+/// The payload of `eth_simulateV1`
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatePayload {
+    pub block_state_calls: Vec<SimBlock>,
+
+    /// Add ETH transfers as ERC20 like logs
+    pub trace_transfers: bool,
+
+    /// Run the same checks as a real transaction (nonce, balance, base fee)
+    pub validation: bool,
+
+    pub return_full_transactions: bool,
+}
+
+impl SimulatePayload {
+    pub fn new(block_state_calls: Vec<SimBlock>) -> Self {
+        Self {
+            block_state_calls,
+            ..Default::default()
+        }
+    }
+}
+
+/// A block returned by `eth_simulateV1`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedBlock {
+    pub number: U64,
+    pub hash: B256,
+    pub timestamp: U64,
+    pub gas_limit: U64,
+    pub gas_used: U64,
+    #[serde(default)]
+    pub base_fee_per_gas: Option<U256>,
+    pub calls: Vec<SimCallResult>,
+}
+
+/// The result of a single call in a [SimulatedBlock]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimCallResult {
+    pub return_data: Bytes,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+    pub gas_used: U64,
+
+    /// 1 on success, 0 on failure
+    pub status: U64,
+    #[serde(default)]
+    pub error: Option<SimError>,
+}
+
+impl SimCallResult {
+    pub fn is_success(&self) -> bool {
+        self.status == U64::from(1)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+/// A bundle of transactions for `eth_callMany`
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallManyBundle {
+    pub transactions: Vec<TransactionRequest>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_override: Option<BlockOverrides>,
+}
+
+/// Where the bundles of `eth_callMany` are executed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateContext {
+    pub block_number: BlockId,
+
+    /// Execute after this many transactions of the block, -1 for the end of the block
+    pub transaction_index: i64,
+}
+
+impl StateContext {
+    /// Execute at the end of `block`
+    pub fn new(block: BlockId) -> Self {
+        Self {
+            block_number: block,
+            transaction_index: -1,
+        }
+    }
+}
+
+/// The result of a call in `eth_callMany`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallManyResult {
+    #[serde(default)]
+    pub value: Option<Bytes>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// The request of `eth_callBundle`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleRequest {
+    /// Signed raw transactions
+    pub txs: Vec<Bytes>,
+
+    /// The block the bundle would be included in
+    pub block_number: U64,
+
+    /// The state the bundle is simulated on
+    pub state_block_number: BlockNumberOrTag,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+impl CallBundleRequest {
+    pub fn new(txs: Vec<Bytes>, block_number: u64) -> Self {
+        Self {
+            txs,
+            block_number: U64::from(block_number),
+            state_block_number: BlockNumberOrTag::Latest,
+            timestamp: None,
+        }
+    }
+}
+
+/// The response of `eth_callBundle`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleResponse {
+    pub bundle_hash: B256,
+    pub bundle_gas_price: U256,
+    pub coinbase_diff: U256,
+    pub eth_sent_to_coinbase: U256,
+    pub gas_fees: U256,
+    pub results: Vec<CallBundleTxResult>,
+    pub state_block_number: u64,
+    pub total_gas_used: u64,
+}
+
+impl CallBundleResponse {
+    /// The net profit of the bundle if it paid `priority_fee` per gas and `coinbase_payment` to the builder
+    ///
+    /// `total_gas_used` is the gas after refunds, so the costs are exact for the simulated state
+    ///
+    /// ## Arguments
+    ///
+    /// * `gross_profit` - The profit of the bundle before any cost, in wei
+    /// * `base_fee` - The base fee of the target block
+    /// * `priority_fee` - The priority fee per gas
+    /// * `coinbase_payment` - A direct payment to the builder, in wei
+    pub fn profit(
+        &self,
+        gross_profit: U256,
+        base_fee: U256,
+        priority_fee: U256,
+        coinbase_payment: U256,
+    ) -> Result<BundleProfit, anyhow::Error> {
+        BundleProfit::new(
+            gross_profit,
+            self.total_gas_used,
+            base_fee,
+            priority_fee,
+            coinbase_payment,
+        )
+    }
+}
+
+/// The costs and the net profit of a bundle, all amounts in wei
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BundleProfit {
+    pub gross_profit: U256,
+    pub gas_used: u64,
+    pub base_fee: U256,
+    pub priority_fee: U256,
+
+    /// `gas_used * (base_fee + priority_fee)`
+    pub gas_cost: U256,
+
+    pub coinbase_payment: U256,
+
+    /// `gross_profit - gas_cost - coinbase_payment`
+    pub net_profit: I256,
+
+    /// The highest priority fee per gas the bundle can pay and still break even with the same coinbase payment,
+    /// None if it is not profitable even without a tip
+    pub max_priority_fee_to_break_even: Option<U256>,
+}
+
+impl BundleProfit {
+    pub fn new(
+        gross_profit: U256,
+        gas_used: u64,
+        base_fee: U256,
+        priority_fee: U256,
+        coinbase_payment: U256,
+    ) -> Result<Self, anyhow::Error> {
+        let gas = U256::from(gas_used);
+        let gas_cost = gas.saturating_mul(base_fee.saturating_add(priority_fee));
+        let gas_cost_signed = I256::try_from(gas_cost)?;
+        let coinbase_payment_signed = I256::try_from(coinbase_payment)?;
+        let net_profit = I256::try_from(gross_profit)?
+            .checked_sub(gas_cost_signed)
+            .and_then(|profit| profit.checked_sub(coinbase_payment_signed))
+            .ok_or_else(|| anyhow::anyhow!("The net profit doesn't fit in an I256"))?;
+
+        // what is left for the tips once the base fee and the coinbase payment are paid
+        let max_priority_fee_to_break_even = gross_profit
+            .checked_sub(gas.saturating_mul(base_fee))
+            .and_then(|left| left.checked_sub(coinbase_payment))
+            .map(|left| if gas.is_zero() { U256::MAX } else { left / gas });
+
+        Ok(Self {
+            gross_profit,
+            gas_used,
+            base_fee,
+            priority_fee,
+            gas_cost,
+            coinbase_payment,
+            net_profit,
+            max_priority_fee_to_break_even,
+        })
+    }
+
+    pub fn is_profitable(&self) -> bool {
+        self.net_profit > I256::ZERO
+    }
+
+    /// The net profit at another priority fee
+    pub fn with_priority_fee(&self, priority_fee: U256) -> Result<Self, anyhow::Error> {
+        Self::new(
+            self.gross_profit,
+            self.gas_used,
+            self.base_fee,
+            priority_fee,
+            self.coinbase_payment,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallBundleTxResult {
+    pub tx_hash: TxHash,
+    pub from_address: Address,
+    #[serde(default)]
+    pub to_address: Option<Address>,
+    pub gas_used: u64,
+    pub gas_price: U256,
+    pub gas_fees: U256,
+    pub coinbase_diff: U256,
+    pub eth_sent_to_coinbase: U256,
+    #[serde(default)]
+    pub value: Option<Bytes>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub revert: Option<String>,
+}
+
+impl CallBundleTxResult {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Simulate blocks of calls with `eth_simulateV1`
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `payload` - See [SimulatePayload]
+/// * `block` - The block to simulate on top of
+pub async fn simulate_v1<T, P, N>(
+    client: P,
+    payload: SimulatePayload,
+    block: BlockId,
+) -> Result<Vec<SimulatedBlock>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let blocks = client
+        .raw_request::<_, Vec<SimulatedBlock>>("eth_simulateV1".into(), (payload, block))
+        .await?;
+    Ok(blocks)
+}
+
+/// Execute bundles of calls one after another with `eth_callMany`
+///
+/// Returns the results of each call grouped by bundle
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `bundles` - See [CallManyBundle]
+/// * `context` - See [StateContext]
+/// * `state_override` - Optional state overrides applied before the first bundle
+pub async fn call_many<T, P, N>(
+    client: P,
+    bundles: Vec<CallManyBundle>,
+    context: StateContext,
+    state_override: Option<StateOverride>,
+) -> Result<Vec<Vec<CallManyResult>>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let results = match state_override {
+        Some(state_override) => {
+            client
+                .raw_request::<_, Vec<Vec<CallManyResult>>>(
+                    "eth_callMany".into(),
+                    (bundles, context, state_override),
+                )
+                .await?
+        }
+        None => {
+            client
+                .raw_request::<_, Vec<Vec<CallManyResult>>>(
+                    "eth_callMany".into(),
+                    (bundles, context),
+                )
+                .await?
+        }
+    };
+    Ok(results)
+}
+
+/// Simulate a bundle of signed transactions with `eth_callBundle`
+///
+/// The client must be connected to an endpoint that supports it (eg. a flashbots compatible builder)
+pub async fn call_bundle<T, P, N>(
+    client: P,
+    request: CallBundleRequest,
+) -> Result<CallBundleResponse, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let response = client
+        .raw_request::<_, CallBundleResponse>("eth_callBundle".into(), (request,))
+        .await?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_profit() {
+        let gwei = U256::from(1_000_000_000u64);
+        let profit = BundleProfit::new(
+            U256::from(10_000_000u64) * gwei,
+            200_000,
+            U256::from(20) * gwei,
+            U256::from(5) * gwei,
+            U256::from(1_000_000u64) * gwei,
+        )
+        .unwrap();
+
+        assert_eq!(profit.gas_cost, U256::from(5_000_000u64) * gwei);
+        assert_eq!(profit.net_profit, I256::try_from(U256::from(4_000_000u64) * gwei).unwrap());
+        assert!(profit.is_profitable());
+        // 10M - 4M (base fee) - 1M (coinbase) = 5M gwei left for 200k gas
+        assert_eq!(profit.max_priority_fee_to_break_even, Some(U256::from(25) * gwei));
+
+        let at_break_even = profit.with_priority_fee(U256::from(25) * gwei).unwrap();
+        assert_eq!(at_break_even.net_profit, I256::ZERO);
+
+        let unprofitable = BundleProfit::new(gwei, 200_000, U256::from(20) * gwei, U256::ZERO, U256::ZERO).unwrap();
+        assert!(!unprofitable.is_profitable());
+        assert_eq!(unprofitable.max_priority_fee_to_break_even, None);
+
+        // amounts above I256::MAX can't be represented in the net profit
+        assert!(BundleProfit::new(U256::MAX, 200_000, U256::ZERO, U256::ZERO, U256::ZERO).is_err());
+    }
+}
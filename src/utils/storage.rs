@@ -0,0 +1,78 @@
+//! Read contract storage directly with `eth_getStorageAt`
+//!
+//! Useful with providers that block the deploy-bytecode `eth_call` batching of [batch_request](super::batch_request),
+//! as long as the storage layout of the contract is known
+
+use alloy_contract::private::Network;
+use alloy_primitives::{keccak256, Address, B256, U256};
+use alloy_provider::Provider;
+use alloy_rpc_client::BatchRequest;
+use alloy_rpc_types::BlockId;
+use alloy_transport::Transport;
+
+/// Read many storage slots in a single JSON-RPC batch
+///
+/// Returns the values in the same order as `slots`
+///
+/// ## Arguments
+///
+/// * `client` - The provider
+/// * `slots` - The (contract, slot) pairs to read
+/// * `block` - The block to read at, None for the latest
+pub async fn get_storage_batch<T, P, N>(
+    client: P,
+    slots: &[(Address, U256)],
+    block: Option<BlockId>,
+) -> Result<Vec<U256>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let block = block.unwrap_or(BlockId::latest());
+    let mut batch = BatchRequest::new(client.client());
+
+    let mut waiters = Vec::with_capacity(slots.len());
+    for (address, slot) in slots {
+        let waiter = batch.add_call::<_, U256>("eth_getStorageAt", &(address, slot, block))?;
+        waiters.push(waiter);
+    }
+
+    batch.send().await?;
+
+    let mut values = Vec::with_capacity(waiters.len());
+    for waiter in waiters {
+        values.push(waiter.await?);
+    }
+
+    Ok(values)
+}
+
+/// The storage slot of `mapping[key]` where the mapping is declared at `slot`
+///
+/// Integer keys must be sign extended (for signed types) to 32 bytes
+pub fn mapping_slot(key: B256, slot: U256) -> U256 {
+    let mut buf = [0u8; 64];
+    buf[..32].copy_from_slice(key.as_slice());
+    buf[32..].copy_from_slice(&slot.to_be_bytes::<32>());
+    keccak256(buf).into()
+}
+
+/// The 32 bytes key of a signed integer in a mapping
+pub fn signed_key(value: i64) -> B256 {
+    let fill = if value < 0 { 0xFF } else { 0x00 };
+    let mut key = [fill; 32];
+    key[24..].copy_from_slice(&value.to_be_bytes());
+    B256::from(key)
+}
+
+/// The 32 bytes key of an address in a mapping
+pub fn address_key(address: Address) -> B256 {
+    address.into_word()
+}
+
+/// Extract `bits` bits from a word starting at bit `offset`
+pub fn extract_bits(word: U256, offset: usize, bits: usize) -> U256 {
+    let mask = (U256::from(1) << bits) - U256::from(1);
+    (word >> offset) & mask
+}
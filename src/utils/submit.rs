@@ -0,0 +1,415 @@
+//! Submit a transaction so that retries can't spend the nonce twice
+//!
+//! [submit_transaction] fills and signs the transaction locally, so the hash of every broadcast is known before
+//! it is sent, and keeps the same nonce for the whole submission. A timeout or a failed broadcast re-sends the
+//! signed transaction, an unconfirmed one gets replaced with higher fees according to the [SubmitPolicy].
+//! Every signed version is tracked and the first one mined ends the submission, whichever it is.
+//!
+//! ```ignore
+//! let wallet = EthereumWallet::from(signer);
+//! let tx = TransactionRequest::default().with_to(router).with_input(call_data);
+//! let result = submit_transaction(client, &wallet, tx, &SubmitPolicy::default()).await?;
+//! println!("{} mined after {} broadcasts", result.tx_hash, result.broadcasts);
+//! ```
+
+use alloy_contract::private::Network;
+use alloy_eips::eip2718::Encodable2718;
+use alloy_network::{NetworkWallet, ReceiptResponse, TransactionBuilder};
+use alloy_primitives::{keccak256, Bytes, TxHash};
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+/// How a submission waits, re-broadcasts and bumps the fees
+#[derive(Debug, Clone)]
+pub struct SubmitPolicy {
+    /// How long to wait for a receipt before re-broadcasting
+    pub timeout: Duration,
+
+    /// The time between two receipt polls
+    pub poll_interval: Duration,
+
+    /// The maximum number of timeouts before giving up
+    pub max_attempts: u32,
+
+    /// The fee increase of a replacement in percent, 0 to only re-broadcast the same transaction
+    ///
+    /// Most nodes reject a replacement below 10%
+    pub fee_bump_percent: u64,
+
+    /// The max fee per gas (or gas price) the bumps can't go over, the transaction is re-broadcast as is once reached
+    pub max_fee_per_gas: Option<u128>,
+}
+
+impl SubmitPolicy {
+    pub fn new(timeout: Duration, max_attempts: u32, fee_bump_percent: u64) -> Self {
+        Self {
+            timeout,
+            max_attempts: max_attempts.max(1),
+            fee_bump_percent,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn with_max_fee_per_gas(mut self, max_fee_per_gas: u128) -> Self {
+        self.max_fee_per_gas = Some(max_fee_per_gas);
+        self
+    }
+
+    /// Increase a fee by [SubmitPolicy::fee_bump_percent], None if the cap is already reached
+    pub fn bump(&self, fee: u128) -> Option<u128> {
+        if self.fee_bump_percent == 0 {
+            return None;
+        }
+        let bumped = bump_by(fee, self.fee_bump_percent);
+        match self.max_fee_per_gas {
+            Some(cap) if fee >= cap => None,
+            Some(cap) => Some(bumped.min(cap)),
+            None => Some(bumped),
+        }
+    }
+}
+
+impl Default for SubmitPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            poll_interval: Duration::from_secs(2),
+            max_attempts: 5,
+            fee_bump_percent: 12,
+            max_fee_per_gas: None,
+        }
+    }
+}
+
+/// What a rejected broadcast means for the submission
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastError {
+    /// The node already has this exact transaction, it was sent before
+    AlreadyKnown,
+
+    /// The fees are too low to replace the pending transaction or to enter the mempool
+    Underpriced,
+
+    /// The nonce is used, by one of the signed versions or by another transaction
+    NonceTooLow,
+
+    /// Anything else, eg. a network error, the same transaction is sent again later
+    Other,
+}
+
+impl BroadcastError {
+    /// Classify the message of a rejected `eth_sendRawTransaction`, the messages of geth, erigon, nethermind and reth
+    pub fn from_message(message: &str) -> Self {
+        let message = message.to_lowercase();
+        if message.contains("already known")
+            || message.contains("known transaction")
+            || message.contains("already imported")
+            || message.contains("alreadyknown")
+        {
+            BroadcastError::AlreadyKnown
+        } else if message.contains("underpriced")
+            || message.contains("fee too low")
+            || message.contains("feetoolow")
+            || message.contains("max fee per gas less than block base fee")
+        {
+            BroadcastError::Underpriced
+        } else if message.contains("nonce too low") || message.contains("oldnonce") {
+            BroadcastError::NonceTooLow
+        } else {
+            BroadcastError::Other
+        }
+    }
+}
+
+/// A mined submission
+#[derive(Debug, Clone)]
+pub struct SubmitResult<R> {
+    /// The hash of the version that was mined
+    pub tx_hash: TxHash,
+
+    /// The hashes of every signed version, in order
+    pub signed: Vec<TxHash>,
+
+    /// The number of `eth_sendRawTransaction` made
+    pub broadcasts: u32,
+
+    pub nonce: u64,
+    pub receipt: R,
+}
+
+impl<R: ReceiptResponse> SubmitResult<R> {
+    /// Whether the mined transaction succeeded, a reverted one still used the nonce
+    pub fn status(&self) -> bool {
+        self.receipt.status()
+    }
+}
+
+/// A signed version of the transaction
+struct Signed {
+    hash: TxHash,
+    raw: Bytes,
+}
+
+/// Submit a transaction without risking a second one with another nonce, see the [module](self) docs
+///
+/// The nonce, the gas limit and the fees are filled if not set, the nonce is the pending nonce of the sender
+///
+/// ## Arguments
+///
+/// * `client` - The provider, it doesn't need a wallet
+/// * `wallet` - Signs the transaction, its default signer is the sender if `from` is not set
+/// * `tx` - The transaction
+/// * `policy` - See [SubmitPolicy]
+pub async fn submit_transaction<T, P, N, W>(
+    client: P,
+    wallet: &W,
+    tx: N::TransactionRequest,
+    policy: &SubmitPolicy,
+) -> Result<SubmitResult<N::ReceiptResponse>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+    W: NetworkWallet<N>,
+{
+    let mut tx = fill_transaction(client.clone(), wallet, tx).await?;
+    let nonce = tx
+        .nonce()
+        .ok_or_else(|| anyhow::anyhow!("The transaction has no nonce"))?;
+
+    let mut signed: Vec<Signed> = vec![sign::<N, W>(wallet, tx.clone()).await?];
+    let mut broadcasts = 0;
+    let mut attempt = 0;
+
+    loop {
+        let current = signed.last().expect("at least one signed version");
+        broadcasts += 1;
+        trace!("Broadcasting {} (nonce {})", current.hash, nonce);
+
+        match client.send_raw_transaction(&current.raw).await {
+            Ok(_) => {}
+            Err(e) => match BroadcastError::from_message(&e.to_string()) {
+                BroadcastError::AlreadyKnown => {}
+                BroadcastError::NonceTooLow => {
+                    // the nonce is used, hopefully by a version that was sent before
+                    if let Some(result) = find_receipt(client.clone(), &signed, broadcasts, nonce).await? {
+                        return Ok(result);
+                    }
+                    return Err(anyhow::anyhow!(
+                        "Nonce {} was used by another transaction, {} versions signed",
+                        nonce,
+                        signed.len()
+                    ));
+                }
+                BroadcastError::Underpriced => {
+                    trace!("{} is underpriced: {}", current.hash, e);
+                    attempt += 1;
+                    if attempt >= policy.max_attempts || !bump_fees::<N>(&mut tx, policy) {
+                        return Err(anyhow::anyhow!("Transaction underpriced and the fees can't be bumped: {}", e));
+                    }
+                    signed.push(sign::<N, W>(wallet, tx.clone()).await?);
+                    continue;
+                }
+                BroadcastError::Other => trace!("Broadcast of {} failed: {}", current.hash, e),
+            },
+        }
+
+        if let Some(result) = wait_for_receipt(client.clone(), &signed, broadcasts, nonce, policy).await? {
+            return Ok(result);
+        }
+
+        attempt += 1;
+        if attempt >= policy.max_attempts {
+            return Err(anyhow::anyhow!(
+                "No receipt for nonce {} after {} attempts, the last version is {}",
+                nonce,
+                attempt,
+                signed.last().map(|s| s.hash).unwrap_or_default()
+            ));
+        }
+
+        // replace with higher fees if the policy allows it, else re-broadcast the same transaction
+        if bump_fees::<N>(&mut tx, policy) {
+            signed.push(sign::<N, W>(wallet, tx.clone()).await?);
+        }
+    }
+}
+
+/// Set the sender, the pending nonce, the gas limit and the fees that are missing
+async fn fill_transaction<T, P, N, W>(
+    client: P,
+    wallet: &W,
+    mut tx: N::TransactionRequest,
+) -> Result<N::TransactionRequest, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+    W: NetworkWallet<N>,
+{
+    let from = match tx.from() {
+        Some(from) => from,
+        None => {
+            let from = wallet.default_signer_address();
+            tx.set_from(from);
+            from
+        }
+    };
+
+    if tx.chain_id().is_none() {
+        tx.set_chain_id(client.get_chain_id().await?);
+    }
+
+    if tx.nonce().is_none() {
+        tx.set_nonce(client.get_transaction_count(from).pending().await?);
+    }
+
+    if tx.gas_limit().is_none() {
+        tx.set_gas_limit(client.estimate_gas(&tx).await?);
+    }
+
+    if tx.gas_price().is_none() && tx.max_fee_per_gas().is_none() {
+        let fees = client.estimate_eip1559_fees(None).await?;
+        tx.set_max_fee_per_gas(fees.max_fee_per_gas);
+        tx.set_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+    }
+
+    Ok(tx)
+}
+
+/// Bump the fees of the transaction, false if the policy doesn't allow it anymore
+fn bump_fees<N: Network>(tx: &mut N::TransactionRequest, policy: &SubmitPolicy) -> bool {
+    if let Some(gas_price) = tx.gas_price() {
+        let Some(bumped) = policy.bump(gas_price) else {
+            return false;
+        };
+        tx.set_gas_price(bumped);
+        return true;
+    }
+
+    let (Some(max_fee), Some(priority_fee)) = (tx.max_fee_per_gas(), tx.max_priority_fee_per_gas()) else {
+        return false;
+    };
+    let Some(max_fee) = policy.bump(max_fee) else {
+        return false;
+    };
+
+    // both fees must increase for a replacement, the priority fee can't go over the max fee
+    let priority_fee = bump_by(priority_fee, policy.fee_bump_percent).min(max_fee);
+
+    tx.set_max_fee_per_gas(max_fee);
+    tx.set_max_priority_fee_per_gas(priority_fee);
+    true
+}
+
+/// Increase a fee by `percent`, rounded up so small fees still increase
+fn bump_by(fee: u128, percent: u64) -> u128 {
+    fee.saturating_add(fee.saturating_mul(percent as u128).div_ceil(100))
+}
+
+async fn sign<N, W>(wallet: &W, tx: N::TransactionRequest) -> Result<Signed, anyhow::Error>
+where
+    N: Network,
+    W: NetworkWallet<N>,
+{
+    let envelope = tx
+        .build(wallet)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to sign the transaction: {:?}", e))?;
+    let raw = envelope.encoded_2718();
+
+    Ok(Signed {
+        hash: keccak256(&raw),
+        raw: raw.into(),
+    })
+}
+
+/// Poll the receipts of every signed version until one is found or the policy times out
+async fn wait_for_receipt<T, P, N>(
+    client: P,
+    signed: &[Signed],
+    broadcasts: u32,
+    nonce: u64,
+    policy: &SubmitPolicy,
+) -> Result<Option<SubmitResult<N::ReceiptResponse>>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let start = Instant::now();
+    loop {
+        if let Some(result) = find_receipt(client.clone(), signed, broadcasts, nonce).await? {
+            return Ok(Some(result));
+        }
+        if start.elapsed() >= policy.timeout {
+            return Ok(None);
+        }
+        tokio::time::sleep(policy.poll_interval.min(policy.timeout)).await;
+    }
+}
+
+/// The receipt of the first signed version that was mined, failed lookups count as not mined
+async fn find_receipt<T, P, N>(
+    client: P,
+    signed: &[Signed],
+    broadcasts: u32,
+    nonce: u64,
+) -> Result<Option<SubmitResult<N::ReceiptResponse>>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    for version in signed.iter().rev() {
+        match client.get_transaction_receipt(version.hash).await {
+            Ok(Some(receipt)) => {
+                return Ok(Some(SubmitResult {
+                    tx_hash: version.hash,
+                    signed: signed.iter().map(|s| s.hash).collect(),
+                    broadcasts,
+                    nonce,
+                    receipt,
+                }))
+            }
+            Ok(None) => {}
+            Err(e) => trace!("Failed to get the receipt of {}: {:?}", version.hash, e),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broadcast_error() {
+        assert_eq!(BroadcastError::from_message("already known"), BroadcastError::AlreadyKnown);
+        assert_eq!(
+            BroadcastError::from_message("server returned an error response: error code -32000: replacement transaction underpriced"),
+            BroadcastError::Underpriced
+        );
+        assert_eq!(BroadcastError::from_message("nonce too low: next nonce 5, tx nonce 4"), BroadcastError::NonceTooLow);
+        assert_eq!(BroadcastError::from_message("connection reset by peer"), BroadcastError::Other);
+    }
+
+    #[test]
+    fn test_fee_bump() {
+        let policy = SubmitPolicy::default().with_max_fee_per_gas(120);
+        assert_eq!(policy.bump(100), Some(112));
+        assert_eq!(policy.bump(110), Some(120));
+        assert_eq!(policy.bump(120), None);
+
+        let rebroadcast = SubmitPolicy::new(Duration::from_secs(30), 3, 0);
+        assert_eq!(rebroadcast.bump(100), None);
+    }
+}
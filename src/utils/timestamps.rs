@@ -0,0 +1,150 @@
+//! Convert between block numbers, timestamps and dates
+//!
+//! Lookups hit the node once per block, [BlockTimestamps] caches them so repeated conversions
+//! (eg. bucketing thousands of swaps per day) stay cheap
+
+use alloy_contract::private::Network;
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+use chrono::{DateTime, NaiveDate, Utc};
+
+use std::collections::HashMap;
+
+use super::arbitrum::block_timestamp;
+
+/// A cache of block timestamps
+#[derive(Debug, Clone, Default)]
+pub struct BlockTimestamps {
+    timestamps: HashMap<u64, u64>,
+}
+
+impl BlockTimestamps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of cached blocks
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    /// Cache a known timestamp, eg. from a block header that was already fetched
+    pub fn insert(&mut self, block: u64, timestamp: u64) {
+        self.timestamps.insert(block, timestamp);
+    }
+
+    /// The timestamp of a block
+    pub async fn timestamp<T, P, N>(&mut self, client: P, block: u64) -> Result<u64, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        if let Some(timestamp) = self.timestamps.get(&block) {
+            return Ok(*timestamp);
+        }
+
+        let timestamp = block_timestamp(client, block).await?;
+        self.timestamps.insert(block, timestamp);
+        Ok(timestamp)
+    }
+
+    /// The UTC date and time of a block
+    pub async fn datetime<T, P, N>(
+        &mut self,
+        client: P,
+        block: u64,
+    ) -> Result<DateTime<Utc>, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let timestamp = self.timestamp(client, block).await?;
+        timestamp_to_datetime(timestamp)
+    }
+
+    /// The UTC day of a block
+    pub async fn date<T, P, N>(&mut self, client: P, block: u64) -> Result<NaiveDate, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        Ok(self.datetime(client, block).await?.date_naive())
+    }
+
+    /// Find the first block with a timestamp greater than or equal to `timestamp`
+    ///
+    /// ## Arguments
+    ///
+    /// * `client` - The provider
+    /// * `timestamp` - The target unix timestamp
+    /// * `latest_block` - The upper bound of the search
+    pub async fn block_at<T, P, N>(
+        &mut self,
+        client: P,
+        timestamp: u64,
+        latest_block: u64,
+    ) -> Result<u64, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        // start from the tightest cached bounds
+        let mut low = 0;
+        let mut high = latest_block;
+        for (block, block_timestamp) in &self.timestamps {
+            if *block > latest_block {
+                continue;
+            }
+            if *block_timestamp < timestamp {
+                low = low.max(*block + 1);
+            } else {
+                high = high.min(*block);
+            }
+        }
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.timestamp(client.clone(), mid).await? < timestamp {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        Ok(low)
+    }
+
+    /// The first block of a UTC day
+    pub async fn first_block_of<T, P, N>(
+        &mut self,
+        client: P,
+        date: NaiveDate,
+        latest_block: u64,
+    ) -> Result<u64, anyhow::Error>
+    where
+        T: Transport + Clone,
+        P: Provider<T, N> + Clone,
+        N: Network,
+    {
+        let timestamp = date
+            .and_hms_opt(0, 0, 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid date: {}", date))?
+            .and_utc()
+            .timestamp() as u64;
+        self.block_at(client, timestamp, latest_block).await
+    }
+}
+
+/// Convert a unix timestamp to a UTC date and time
+pub fn timestamp_to_datetime(timestamp: u64) -> Result<DateTime<Utc>, anyhow::Error> {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid timestamp: {}", timestamp))
+}
@@ -0,0 +1,305 @@
+//! Fetch call traces from nodes that support the `debug_*` or `trace_*` namespaces
+//!
+//! The results are converted into the same [CallTrace] produced by the [CallTraceInspector](crate::revm_utils::inspectors::call_trace::CallTraceInspector),
+//! so a transaction can be analyzed without replaying it locally
+
+use alloy_contract::private::Network;
+use alloy_primitives::{Address, Bytes, TxHash, U256, U64};
+use alloy_provider::Provider;
+use alloy_transport::Transport;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::revm_utils::inspectors::call_trace::{CallKind, CallTrace};
+
+/// The call trace of a transaction
+#[derive(Debug, Clone)]
+pub struct TransactionTrace {
+    pub tx_hash: TxHash,
+    pub trace: CallTrace,
+}
+
+/// A frame as returned by geth's `callTracer`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GethCallFrame {
+    #[serde(rename = "type")]
+    kind: String,
+    from: Address,
+    #[serde(default)]
+    to: Option<Address>,
+    #[serde(default)]
+    value: Option<U256>,
+    #[serde(default)]
+    gas_used: U64,
+    #[serde(default)]
+    input: Bytes,
+    #[serde(default)]
+    output: Option<Bytes>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    calls: Vec<GethCallFrame>,
+}
+
+impl From<GethCallFrame> for CallTrace {
+    fn from(frame: GethCallFrame) -> Self {
+        let kind = match frame.kind.as_str() {
+            "STATICCALL" => CallKind::StaticCall,
+            "DELEGATECALL" => CallKind::DelegateCall,
+            "CALLCODE" => CallKind::CallCode,
+            "CREATE" => CallKind::Create,
+            "CREATE2" => CallKind::Create2,
+            "SELFDESTRUCT" => CallKind::SelfDestruct,
+            _ => CallKind::Call,
+        };
+
+        CallTrace {
+            kind,
+            from: frame.from,
+            to: frame.to.unwrap_or_default(),
+            value: frame.value.unwrap_or_default(),
+            input: frame.input,
+            output: frame.output.unwrap_or_default(),
+            gas_used: frame.gas_used.to::<u64>(),
+            success: frame.error.is_none(),
+            error: frame.error,
+            calls: frame.calls.into_iter().map(CallTrace::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GethBlockTrace {
+    tx_hash: Option<TxHash>,
+    result: GethCallFrame,
+}
+
+/// A trace as returned by the parity style `trace_*` methods
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ParityTrace {
+    action: ParityAction,
+    #[serde(default)]
+    result: Option<ParityResult>,
+    #[serde(default)]
+    error: Option<String>,
+    trace_address: Vec<usize>,
+    transaction_hash: Option<TxHash>,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ParityAction {
+    #[serde(default)]
+    call_type: Option<String>,
+    #[serde(default)]
+    from: Option<Address>,
+    #[serde(default)]
+    to: Option<Address>,
+    #[serde(default)]
+    value: Option<U256>,
+    #[serde(default)]
+    input: Option<Bytes>,
+    #[serde(default)]
+    init: Option<Bytes>,
+
+    // selfdestruct
+    #[serde(default)]
+    address: Option<Address>,
+    #[serde(default)]
+    refund_address: Option<Address>,
+    #[serde(default)]
+    balance: Option<U256>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ParityResult {
+    #[serde(default)]
+    gas_used: U64,
+    #[serde(default)]
+    output: Option<Bytes>,
+    #[serde(default)]
+    address: Option<Address>,
+}
+
+impl ParityTrace {
+    /// Convert to a [CallTrace] without subcalls, None for block rewards
+    fn to_call_trace(&self) -> Option<CallTrace> {
+        let action = &self.action;
+
+        let (kind, from, to, value, input) = match self.kind.as_str() {
+            "call" => {
+                let kind = match action.call_type.as_deref() {
+                    Some("staticcall") => CallKind::StaticCall,
+                    Some("delegatecall") => CallKind::DelegateCall,
+                    Some("callcode") => CallKind::CallCode,
+                    _ => CallKind::Call,
+                };
+                (
+                    kind,
+                    action.from.unwrap_or_default(),
+                    action.to.unwrap_or_default(),
+                    action.value.unwrap_or_default(),
+                    action.input.clone().unwrap_or_default(),
+                )
+            }
+            "create" => (
+                CallKind::Create,
+                action.from.unwrap_or_default(),
+                self.result
+                    .as_ref()
+                    .and_then(|r| r.address)
+                    .unwrap_or_default(),
+                action.value.unwrap_or_default(),
+                action.init.clone().unwrap_or_default(),
+            ),
+            "suicide" | "selfdestruct" => (
+                CallKind::SelfDestruct,
+                action.address.unwrap_or_default(),
+                action.refund_address.unwrap_or_default(),
+                action.balance.unwrap_or_default(),
+                Bytes::new(),
+            ),
+            _ => return None,
+        };
+
+        let mut trace = CallTrace::new(kind, from, to, value, input);
+        if let Some(result) = &self.result {
+            trace.gas_used = result.gas_used.to::<u64>();
+            trace.output = result.output.clone().unwrap_or_default();
+        }
+        trace.success = self.error.is_none();
+        trace.error = self.error.clone();
+
+        Some(trace)
+    }
+}
+
+/// Trace a transaction with geth's `callTracer` using `debug_traceTransaction`
+pub async fn debug_trace_transaction<T, P, N>(
+    client: P,
+    tx_hash: TxHash,
+) -> Result<CallTrace, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let frame = client
+        .raw_request::<_, GethCallFrame>(
+            "debug_traceTransaction".into(),
+            (tx_hash, json!({ "tracer": "callTracer" })),
+        )
+        .await?;
+
+    Ok(frame.into())
+}
+
+/// Trace all the transactions of a block with geth's `callTracer` using `debug_traceBlockByNumber`
+pub async fn debug_trace_block<T, P, N>(
+    client: P,
+    block: u64,
+) -> Result<Vec<TransactionTrace>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let traces = client
+        .raw_request::<_, Vec<GethBlockTrace>>(
+            "debug_traceBlockByNumber".into(),
+            (U64::from(block), json!({ "tracer": "callTracer" })),
+        )
+        .await?;
+
+    let traces = traces
+        .into_iter()
+        .map(|t| TransactionTrace {
+            tx_hash: t.tx_hash.unwrap_or_default(),
+            trace: t.result.into(),
+        })
+        .collect();
+
+    Ok(traces)
+}
+
+/// Trace all the transactions of a block using the parity style `trace_block`
+///
+/// Block rewards are skipped
+pub async fn trace_block<T, P, N>(
+    client: P,
+    block: u64,
+) -> Result<Vec<TransactionTrace>, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let traces = client
+        .raw_request::<_, Vec<ParityTrace>>("trace_block".into(), (U64::from(block),))
+        .await?;
+
+    build_trees(traces)
+}
+
+/// Trace a transaction using the parity style `trace_transaction`
+pub async fn trace_transaction<T, P, N>(
+    client: P,
+    tx_hash: TxHash,
+) -> Result<CallTrace, anyhow::Error>
+where
+    T: Transport + Clone,
+    P: Provider<T, N> + Clone,
+    N: Network,
+{
+    let traces = client
+        .raw_request::<_, Vec<ParityTrace>>("trace_transaction".into(), (tx_hash,))
+        .await?;
+
+    build_trees(traces)?
+        .into_iter()
+        .next()
+        .map(|t| t.trace)
+        .ok_or_else(|| anyhow::anyhow!("No trace found for {}", tx_hash))
+}
+
+/// Rebuild the call trees from the flat parity traces using their trace address
+fn build_trees(traces: Vec<ParityTrace>) -> Result<Vec<TransactionTrace>, anyhow::Error> {
+    let mut trees: Vec<TransactionTrace> = Vec::new();
+
+    for trace in traces {
+        let Some(call) = trace.to_call_trace() else {
+            continue;
+        };
+
+        if trace.trace_address.is_empty() {
+            trees.push(TransactionTrace {
+                tx_hash: trace.transaction_hash.unwrap_or_default(),
+                trace: call,
+            });
+            continue;
+        }
+
+        let root = trees
+            .last_mut()
+            .ok_or_else(|| anyhow::anyhow!("Subtrace without a root trace"))?;
+
+        // traces are returned in execution order, so the parent is always the last call at each level
+        let depth = trace.trace_address.len() - 1;
+        let mut parent = &mut root.trace;
+        for _ in 0..depth {
+            parent = parent
+                .calls
+                .last_mut()
+                .ok_or_else(|| anyhow::anyhow!("Missing parent for {:?}", trace.trace_address))?;
+        }
+        parent.calls.push(call);
+    }
+
+    Ok(trees)
+}